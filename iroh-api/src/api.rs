@@ -1,20 +1,33 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use crate::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
+use crate::config::{Config, CHANGE_CACHE_FILE_NAME, CONFIG_FILE_NAME, ENV_PREFIX};
+#[cfg(feature = "testing")]
+use crate::files::MockFiles;
+use crate::files::{ClientFiles, Files};
 #[cfg(feature = "testing")]
 use crate::p2p::MockP2p;
 use crate::p2p::{ClientP2p, P2p};
 use crate::{AddEvent, IpfsPath};
-use anyhow::Result;
+use anyhow::{ensure, Result};
+use bytes::Bytes;
+use cid::Cid;
 use futures::future::{BoxFuture, LocalBoxFuture};
 use futures::stream::LocalBoxStream;
 use futures::FutureExt;
 use futures::StreamExt;
-use iroh_resolver::unixfs_builder;
+use iroh_resolver::change_cache::ChangeCache;
+use iroh_resolver::codecs::Codec;
+use iroh_resolver::dag;
+use iroh_resolver::patch;
+use iroh_resolver::resolver::Resolver;
+use iroh_resolver::unixfs::UnixTime;
+use iroh_resolver::unixfs_builder::{
+    self, Store, StoreAndProvideClient, StoreAndTryProvideClient, WalkOptions,
+};
 use iroh_rpc_client::Client;
 use iroh_rpc_client::StatusTable;
-use iroh_util::{iroh_config_path, make_config};
+use iroh_util::{iroh_config_path, iroh_data_path, make_config};
 #[cfg(feature = "testing")]
 use mockall::automock;
 use relative_path::RelativePathBuf;
@@ -30,39 +43,96 @@ pub enum OutType {
     Symlink(PathBuf),
 }
 
+/// Unix permissions and modification time of an entry, if they were recorded when
+/// it was added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutMetadata {
+    pub mode: Option<u32>,
+    pub mtime: Option<UnixTime>,
+}
+
 // Note: `#[async_trait]` is deliberately not in use for this trait, because it
 // became very hard to express what we wanted once streams were involved.
 // Instead we spell things out explicitly without magic.
 
-#[cfg_attr(feature= "testing", automock(type P = MockP2p;))]
+#[cfg_attr(feature= "testing", automock(type P = MockP2p; type F = MockFiles;))]
 pub trait Api {
     type P: P2p;
+    type F: Files;
 
     fn p2p(&self) -> Result<Self::P>;
 
+    /// Access to the mutable file system.
+    fn files(&self) -> Result<Self::F>;
+
     /// Produces a asynchronous stream of file descriptions
-    /// Each description is a tuple of a relative path, and either a `Directory` or a `Reader`
-    /// with the file contents.
+    /// Each description is a tuple of a relative path, either a `Directory` or a `Reader`
+    /// with the file contents, and the recorded unix metadata.
     fn get_stream(
         &self,
         ipfs_path: &IpfsPath,
-    ) -> LocalBoxStream<'_, Result<(RelativePathBuf, OutType)>>;
+    ) -> LocalBoxStream<'_, Result<(RelativePathBuf, OutType, OutMetadata)>>;
 
+    /// The `add_*` methods only compute the cids of the content, without storing
+    /// or providing it, if `only_hash` is set. The mode and mtime of paths are only
+    /// recorded when `options` ask to preserve them.
     fn add_file(
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>>;
+    /// Adds the directory at `path`, leaving out the entries excluded by `options`.
+    /// With `change_cache`, files that did not change since they were last added
+    /// are not read again.
     fn add_dir(
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
+        change_cache: bool,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>>;
     fn add_symlink(
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>>;
+    /// Adds the content of `reader` as a file named `name`. The content is
+    /// streamed, so it can be of any size.
+    fn add_reader(
+        &self,
+        reader: Box<dyn AsyncRead + Unpin>,
+        name: &str,
+        wrap: bool,
+        only_hash: bool,
+    ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>>;
+
+    /// Adds a link at `name` in the directory `root` pointing to `target`, replacing
+    /// an existing entry, and returns the new root. `name` may be a path of
+    /// several components, missing directories along it are only created with
+    /// `create`. The new directories are stored, and provided if p2p is running.
+    fn object_patch_add_link(
+        &self,
+        root: &Cid,
+        name: &str,
+        target: &Cid,
+        create: bool,
+    ) -> LocalBoxFuture<'_, Result<Cid>>;
+    /// Removes the entry at `name` from the directory `root` and returns the new root.
+    fn object_patch_rm_link(&self, root: &Cid, name: &str) -> LocalBoxFuture<'_, Result<Cid>>;
+
+    /// Stores the dag-json `input` encoded with `codec` and returns its cid.
+    fn dag_put(&self, input: Bytes, codec: Codec) -> LocalBoxFuture<'_, Result<Cid>>;
+    /// Returns the IPLD node at `ipfs_path`, encoded with `codec`. Links along
+    /// the path are followed.
+    fn dag_get(&self, ipfs_path: &IpfsPath, codec: Codec) -> LocalBoxFuture<'_, Result<Bytes>>;
+    /// Resolves `ipfs_path` to the cid of the last block on it and the
+    /// remaining path inside that block.
+    fn dag_resolve(&self, ipfs_path: &IpfsPath) -> LocalBoxFuture<'_, Result<(Cid, Vec<String>)>>;
 
     fn check(&self) -> BoxFuture<'_, StatusTable>;
     fn watch(&self) -> LocalBoxFuture<'static, LocalBoxStream<'static, StatusTable>>;
@@ -95,20 +165,34 @@ impl Iroh {
     fn from_client(client: Client) -> Self {
         Self { client }
     }
+
+    /// Where added content is stored and provided from, `None` if only the
+    /// cids are computed.
+    fn add_store(&self, only_hash: bool) -> Option<StoreAndProvideClient> {
+        (!only_hash).then(|| StoreAndProvideClient {
+            client: self.client.clone(),
+        })
+    }
 }
 
 impl Api for Iroh {
     type P = ClientP2p;
+    type F = ClientFiles;
 
     fn p2p(&self) -> Result<ClientP2p> {
         let p2p_client = self.client.try_p2p()?;
         Ok(ClientP2p::new(p2p_client.clone()))
     }
 
+    fn files(&self) -> Result<ClientFiles> {
+        self.client.try_store()?;
+        Ok(ClientFiles::new(self.client.clone()))
+    }
+
     fn get_stream(
         &self,
         ipfs_path: &IpfsPath,
-    ) -> LocalBoxStream<'_, Result<(RelativePathBuf, OutType)>> {
+    ) -> LocalBoxStream<'_, Result<(RelativePathBuf, OutType, OutMetadata)>> {
         tracing::debug!("get {:?}", ipfs_path);
         let resolver = iroh_resolver::resolver::Resolver::new(self.client.clone());
         let results = resolver.resolve_recursive_with_paths(ipfs_path.clone());
@@ -126,17 +210,21 @@ impl Api for Iroh {
                     continue;
                 }
                 let relative_path = relative_path.strip_prefix(&sub_path).expect("should be a prefix").to_owned();
+                let metadata = OutMetadata {
+                    mode: out.metadata().mode,
+                    mtime: out.metadata().mtime,
+                };
                 if out.is_dir() {
-                    yield (relative_path, OutType::Dir);
+                    yield (relative_path, OutType::Dir, metadata);
                 } else if out.is_symlink() {
                     let mut reader = out.pretty(resolver.clone(), Default::default(), iroh_resolver::resolver::ResponseClip::NoClip)?;
                     let mut target = String::new();
                     reader.read_to_string(&mut target).await?;
                     let target = PathBuf::from(target);
-                    yield (relative_path, OutType::Symlink(target));
+                    yield (relative_path, OutType::Symlink(target), metadata);
                 } else {
                     let reader = out.pretty(resolver.clone(), Default::default(), iroh_resolver::resolver::ResponseClip::NoClip)?;
-                    yield (relative_path, OutType::Reader(Box::new(reader)));
+                    yield (relative_path, OutType::Reader(Box::new(reader)), metadata);
                 }
             }
         }
@@ -147,13 +235,14 @@ impl Api for Iroh {
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>> {
-        let providing_client = iroh_resolver::unixfs_builder::StoreAndProvideClient {
-            client: self.client.clone(),
-        };
+        let store = self.add_store(only_hash);
         let path = path.to_path_buf();
+        let options = options.clone();
         async move {
-            unixfs_builder::add_file(Some(providing_client), &path, wrap)
+            unixfs_builder::add_file(store, &path, wrap, &options)
                 .await
                 .map(|s| s.boxed_local())
         }
@@ -164,15 +253,32 @@ impl Api for Iroh {
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
+        change_cache: bool,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>> {
-        let providing_client = iroh_resolver::unixfs_builder::StoreAndProvideClient {
-            client: self.client.clone(),
-        };
+        let store = self.add_store(only_hash);
         let path = path.to_path_buf();
+        let options = options.clone();
         async move {
-            unixfs_builder::add_dir(Some(providing_client), &path, wrap)
-                .await
-                .map(|s| s.boxed_local())
+            let cache = if change_cache {
+                Some(ChangeCache::load(iroh_data_path(CHANGE_CACHE_FILE_NAME)?).await?)
+            } else {
+                None
+            };
+            let events =
+                unixfs_builder::add_dir(store, &path, wrap, &options, cache.as_ref()).await?;
+            Ok(async_stream::try_stream! {
+                tokio::pin!(events);
+                while let Some(event) = events.next().await {
+                    let event = event?;
+                    if let (AddEvent::Done(_), Some(cache)) = (&event, &cache) {
+                        cache.save().await?;
+                    }
+                    yield event;
+                }
+            }
+            .boxed_local())
         }
         .boxed_local()
     }
@@ -181,19 +287,102 @@ impl Api for Iroh {
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
     ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>> {
-        let providing_client = iroh_resolver::unixfs_builder::StoreAndProvideClient {
-            client: self.client.clone(),
-        };
+        let store = self.add_store(only_hash);
         let path = path.to_path_buf();
+        let options = options.clone();
+        async move {
+            unixfs_builder::add_symlink(store, &path, wrap, &options)
+                .await
+                .map(|s| s.boxed_local())
+        }
+        .boxed_local()
+    }
+
+    fn add_reader(
+        &self,
+        reader: Box<dyn AsyncRead + Unpin>,
+        name: &str,
+        wrap: bool,
+        only_hash: bool,
+    ) -> LocalBoxFuture<'_, Result<LocalBoxStream<'static, Result<AddEvent>>>> {
+        let store = self.add_store(only_hash);
+        let name = name.to_string();
         async move {
-            unixfs_builder::add_symlink(Some(providing_client), &path, wrap)
+            unixfs_builder::add_reader(store, reader, &name, wrap)
                 .await
                 .map(|s| s.boxed_local())
         }
         .boxed_local()
     }
 
+    fn object_patch_add_link(
+        &self,
+        root: &Cid,
+        name: &str,
+        target: &Cid,
+        create: bool,
+    ) -> LocalBoxFuture<'_, Result<Cid>> {
+        let resolver = Resolver::new(self.client.clone());
+        let store = StoreAndTryProvideClient {
+            client: self.client.clone(),
+        };
+        let (root, target, name) = (*root, *target, name.to_string());
+        async move {
+            let tsize = patch::link_tsize(&resolver, target).await?;
+            let path = link_path(&name)?;
+            patch::add_link(&resolver, &store, root, &path, target, Some(tsize), create).await
+        }
+        .boxed_local()
+    }
+
+    fn object_patch_rm_link(&self, root: &Cid, name: &str) -> LocalBoxFuture<'_, Result<Cid>> {
+        let resolver = Resolver::new(self.client.clone());
+        let store = StoreAndTryProvideClient {
+            client: self.client.clone(),
+        };
+        let (root, name) = (*root, name.to_string());
+        async move {
+            let path = link_path(&name)?;
+            patch::rm_link(&resolver, &store, root, &path).await
+        }
+        .boxed_local()
+    }
+
+    fn dag_put(&self, input: Bytes, codec: Codec) -> LocalBoxFuture<'_, Result<Cid>> {
+        let store = StoreAndProvideClient {
+            client: self.client.clone(),
+        };
+        async move {
+            let (cid, data, links) = dag::from_json(&input, codec)?.into_parts();
+            store.put(cid, data, links).await?;
+            Ok(cid)
+        }
+        .boxed_local()
+    }
+
+    fn dag_get(&self, ipfs_path: &IpfsPath, codec: Codec) -> LocalBoxFuture<'_, Result<Bytes>> {
+        let resolver = Resolver::new(self.client.clone());
+        let ipfs_path = ipfs_path.clone();
+        async move {
+            let resolved = resolver.resolve_dag(ipfs_path).await?;
+            dag::encode_bytes(&resolved.node, codec)
+        }
+        .boxed_local()
+    }
+
+    fn dag_resolve(&self, ipfs_path: &IpfsPath) -> LocalBoxFuture<'_, Result<(Cid, Vec<String>)>> {
+        let resolver = Resolver::new(self.client.clone());
+        let ipfs_path = ipfs_path.clone();
+        async move {
+            let resolved = resolver.resolve_dag(ipfs_path).await?;
+            Ok((resolved.cid, resolved.rem_path))
+        }
+        .boxed_local()
+    }
+
     fn check(&self) -> BoxFuture<'_, StatusTable> {
         async { self.client.check().await }.boxed()
     }
@@ -205,3 +394,10 @@ impl Api for Iroh {
         async { client.watch().await.boxed_local() }.boxed_local()
     }
 }
+
+/// Splits a link name into its path components.
+fn link_path(name: &str) -> Result<Vec<&str>> {
+    let path: Vec<&str> = name.split('/').filter(|part| !part.is_empty()).collect();
+    ensure!(!path.is_empty(), "link name must not be empty");
+    Ok(path)
+}
@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use crate::{AddEvent, Api, Cid, IpfsPath, OutType};
+use crate::{AddEvent, Api, Cid, IpfsPath, OutMetadata, OutType, WalkOptions};
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use futures::stream::LocalBoxStream;
@@ -32,24 +32,41 @@ pub trait ApiExt: Api {
         Ok(root_path)
     }
 
+    /// Adds a file, symlink or directory. `options` select the entries of a directory
+    /// and the file system attributes that are recorded.
+    /// With `only_hash` the content is not stored, only its cids are computed.
+    /// With `change_cache` the unchanged files of a directory are not read again.
     async fn add_stream(
         &self,
         path: &Path,
         wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
+        change_cache: bool,
     ) -> Result<LocalBoxStream<'static, Result<AddEvent>>> {
         if path.is_dir() {
-            self.add_dir(path, wrap).await
+            self.add_dir(path, wrap, only_hash, options, change_cache)
+                .await
         } else if path.is_symlink() {
-            self.add_symlink(path, wrap).await
+            self.add_symlink(path, wrap, only_hash, options).await
         } else if path.is_file() {
-            self.add_file(path, wrap).await
+            self.add_file(path, wrap, only_hash, options).await
         } else {
             anyhow::bail!("can only add files or directories")
         }
     }
 
-    async fn add(&self, path: &Path, wrap: bool) -> Result<Cid> {
-        let add_events = self.add_stream(path, wrap).await?;
+    async fn add(
+        &self,
+        path: &Path,
+        wrap: bool,
+        only_hash: bool,
+        options: &WalkOptions,
+        change_cache: bool,
+    ) -> Result<Cid> {
+        let add_events = self
+            .add_stream(path, wrap, only_hash, options, change_cache)
+            .await?;
 
         add_events
             .try_fold(None, |acc, add_event| async move {
@@ -69,31 +86,63 @@ impl<T> ApiExt for T where T: Api {}
 /// take a stream of blocks as from `get_stream` and write them to the filesystem
 async fn save_get_stream(
     root_path: &Path,
-    blocks: impl Stream<Item = Result<(RelativePathBuf, OutType)>>,
+    blocks: impl Stream<Item = Result<(RelativePathBuf, OutType, OutMetadata)>>,
 ) -> Result<()> {
     tokio::pin!(blocks);
+    // directory metadata is restored last, as writing their entries would
+    // change the mtime, and the permissions might not allow writing at all
+    let mut dirs = Vec::new();
     while let Some(block) = blocks.next().await {
-        let (path, out) = block?;
+        let (path, out, metadata) = block?;
         let full_path = path.to_path(root_path);
         match out {
             OutType::Dir => {
-                tokio::fs::create_dir_all(full_path).await?;
+                tokio::fs::create_dir_all(&full_path).await?;
+                dirs.push((full_path, metadata));
             }
             OutType::Reader(mut reader) => {
                 if let Some(parent) = path.parent() {
                     tokio::fs::create_dir_all(parent.to_path(root_path)).await?;
                 }
-                let mut f = tokio::fs::File::create(full_path).await?;
+                let mut f = tokio::fs::File::create(&full_path).await?;
                 tokio::io::copy(&mut reader, &mut f).await?;
+                drop(f);
+                restore_metadata(&full_path, &metadata, false).await?;
             }
             OutType::Symlink(target) => {
                 if let Some(parent) = path.parent() {
                     tokio::fs::create_dir_all(parent.to_path(root_path)).await?;
                 }
-                tokio::fs::symlink(target, full_path).await?;
+                tokio::fs::symlink(target, &full_path).await?;
+                restore_metadata(&full_path, &metadata, true).await?;
             }
         }
     }
+    for (path, metadata) in dirs.into_iter().rev() {
+        restore_metadata(&path, &metadata, false).await?;
+    }
+    Ok(())
+}
+
+/// Apply the recorded permissions and modification time to a written file, directory or symlink.
+async fn restore_metadata(path: &Path, metadata: &OutMetadata, symlink: bool) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = metadata.mode {
+        // symlink permissions are meaningless and can not be set on most platforms
+        if !symlink {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(mode);
+            tokio::fs::set_permissions(path, permissions).await?;
+        }
+    }
+    if let Some(mtime) = metadata.mtime {
+        let mtime = filetime::FileTime::from_system_time(mtime.into());
+        if symlink {
+            filetime::set_symlink_file_times(path, mtime, mtime)?;
+        } else {
+            filetime::set_file_mtime(path, mtime)?;
+        }
+    }
     Ok(())
 }
 
@@ -120,14 +169,20 @@ mod tests {
     #[tokio::test]
     async fn test_save_get_stream() {
         let stream = Box::pin(futures::stream::iter(vec![
-            Ok((RelativePathBuf::from_path("a").unwrap(), OutType::Dir)),
+            Ok((
+                RelativePathBuf::from_path("a").unwrap(),
+                OutType::Dir,
+                OutMetadata::default(),
+            )),
             Ok((
                 RelativePathBuf::from_path("a/c").unwrap(),
                 OutType::Symlink(PathBuf::from("../b")),
+                OutMetadata::default(),
             )),
             Ok((
                 RelativePathBuf::from_path("b").unwrap(),
                 OutType::Reader(Box::new(std::io::Cursor::new("hello"))),
+                OutMetadata::default(),
             )),
         ]));
         let tmp_dir = TempDir::new("test_save_get_stream").unwrap();
@@ -144,6 +199,50 @@ mod tests {
         );
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_save_get_stream_metadata() {
+        use crate::UnixTime;
+        use std::os::unix::fs::PermissionsExt;
+
+        let mtime = UnixTime {
+            seconds: 1_600_000_000,
+            nanos: 0,
+        };
+        let metadata = OutMetadata {
+            mode: Some(0o750),
+            mtime: Some(mtime),
+        };
+        let stream = Box::pin(futures::stream::iter(vec![
+            Ok((
+                RelativePathBuf::from_path("a").unwrap(),
+                OutType::Dir,
+                metadata,
+            )),
+            Ok((
+                RelativePathBuf::from_path("a/b").unwrap(),
+                OutType::Reader(Box::new(std::io::Cursor::new("hello"))),
+                OutMetadata {
+                    mode: Some(0o600),
+                    mtime: Some(mtime),
+                },
+            )),
+        ]));
+        let tmp_dir = TempDir::new("test_save_get_stream_metadata").unwrap();
+        save_get_stream(tmp_dir.path(), stream).await.unwrap();
+
+        for (path, mode) in [("a", 0o750), ("a/b", 0o600)] {
+            let m = std::fs::metadata(tmp_dir.path().join(path)).unwrap();
+            assert_eq!(m.permissions().mode() & 0o7777, mode);
+            assert_eq!(
+                m.modified().unwrap(),
+                std::time::SystemTime::from(mtime),
+                "{}",
+                path
+            );
+        }
+    }
+
     #[test]
     fn test_get_root_path() {
         let ipfs_path =
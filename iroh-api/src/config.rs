@@ -9,6 +9,9 @@ pub const CONFIG_FILE_NAME: &str = "ctl.config.toml";
 /// ENV_PREFIX should be used along side the config field name to set a config field using
 /// environment variables
 pub const ENV_PREFIX: &str = "IROH_CTL";
+/// CHANGE_CACHE_FILE_NAME is the name of the file in the iroh data directory that remembers
+/// the CIDs of added files, see `iroh add --change-cache`
+pub const CHANGE_CACHE_FILE_NAME: &str = "add_change_cache";
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config {
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use cid::Cid;
+use iroh_resolver::mfs::{Entry, Mfs, Stat};
+use iroh_resolver::resolver::Resolver;
+use iroh_resolver::unixfs_builder::StoreAndTryProvideClient;
+use iroh_rpc_client::Client;
+#[cfg(feature = "testing")]
+use mockall::automock;
+use tokio::io::AsyncRead;
+
+/// How often a modification is applied again when the root was changed concurrently.
+const MAX_ATTEMPTS: usize = 10;
+const CONFLICT: &str = "the mutable file system keeps being changed concurrently, giving up";
+
+pub struct ClientFiles {
+    client: Client,
+}
+
+impl ClientFiles {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    async fn load(&self) -> Result<Mfs<Client, StoreAndTryProvideClient>> {
+        let resolver = Resolver::new(self.client.clone());
+        let store = StoreAndTryProvideClient {
+            client: self.client.clone(),
+        };
+        Mfs::load(resolver, store).await
+    }
+}
+
+/// Operations on the mutable file system (MFS).
+///
+/// Every modification is flushed right away, so the new root is persisted
+/// once the call returns. Modifications that race with others are applied again
+/// on top of the new root.
+#[cfg_attr(feature = "testing", automock)]
+#[async_trait(?Send)]
+pub trait Files: Sync {
+    async fn mkdir(&self, path: &str, parents: bool) -> Result<()>;
+    async fn write(
+        &self,
+        path: &str,
+        content: Box<dyn AsyncRead + Unpin>,
+        create: bool,
+        parents: bool,
+    ) -> Result<()>;
+    async fn cp(&self, src: &str, dst: &str) -> Result<()>;
+    async fn mv(&self, src: &str, dst: &str) -> Result<()>;
+    async fn rm(&self, path: &str, recursive: bool) -> Result<()>;
+    async fn ls(&self, path: &str) -> Result<Vec<Entry>>;
+    async fn stat(&self, path: &str) -> Result<Stat>;
+    /// Persists the root and returns the cid of the entry at `path`.
+    async fn flush(&self, path: &str) -> Result<Cid>;
+}
+
+#[async_trait(?Send)]
+impl Files for ClientFiles {
+    async fn mkdir(&self, path: &str, parents: bool) -> Result<()> {
+        let mut mfs = self.load().await?;
+        for _ in 0..MAX_ATTEMPTS {
+            mfs.mkdir(path, parents).await?;
+            if mfs.commit().await? {
+                return Ok(());
+            }
+            mfs.reload().await?;
+        }
+        bail!(CONFLICT)
+    }
+
+    async fn write(
+        &self,
+        path: &str,
+        content: Box<dyn AsyncRead + Unpin>,
+        create: bool,
+        parents: bool,
+    ) -> Result<()> {
+        let mut mfs = self.load().await?;
+        mfs.write(path, content, create, parents).await?;
+        if mfs.commit().await? {
+            return Ok(());
+        }
+        // the content can only be read once, link the stored file instead
+        let file = mfs.stat(path).await?.cid;
+        for _ in 1..MAX_ATTEMPTS {
+            mfs.reload().await?;
+            mfs.write_cid(path, file, create, parents).await?;
+            if mfs.commit().await? {
+                return Ok(());
+            }
+        }
+        bail!(CONFLICT)
+    }
+
+    async fn cp(&self, src: &str, dst: &str) -> Result<()> {
+        let mut mfs = self.load().await?;
+        for _ in 0..MAX_ATTEMPTS {
+            mfs.cp(src, dst).await?;
+            if mfs.commit().await? {
+                return Ok(());
+            }
+            mfs.reload().await?;
+        }
+        bail!(CONFLICT)
+    }
+
+    async fn mv(&self, src: &str, dst: &str) -> Result<()> {
+        let mut mfs = self.load().await?;
+        for _ in 0..MAX_ATTEMPTS {
+            mfs.mv(src, dst).await?;
+            if mfs.commit().await? {
+                return Ok(());
+            }
+            mfs.reload().await?;
+        }
+        bail!(CONFLICT)
+    }
+
+    async fn rm(&self, path: &str, recursive: bool) -> Result<()> {
+        let mut mfs = self.load().await?;
+        for _ in 0..MAX_ATTEMPTS {
+            mfs.rm(path, recursive).await?;
+            if mfs.commit().await? {
+                return Ok(());
+            }
+            mfs.reload().await?;
+        }
+        bail!(CONFLICT)
+    }
+
+    async fn ls(&self, path: &str) -> Result<Vec<Entry>> {
+        self.load().await?.ls(path).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Stat> {
+        self.load().await?.stat(path).await
+    }
+
+    async fn flush(&self, path: &str) -> Result<Cid> {
+        self.load().await?.flush(path).await
+    }
+}
@@ -1,20 +1,28 @@
 mod api;
 mod api_ext;
 mod config;
+mod files;
 mod p2p;
 
 #[cfg(feature = "testing")]
 pub use crate::api::MockApi;
-pub use crate::api::{Api, Iroh, OutType};
+pub use crate::api::{Api, Iroh, OutMetadata, OutType};
 pub use crate::api_ext::ApiExt;
+pub use crate::files::Files as FilesApi;
+#[cfg(feature = "testing")]
+pub use crate::files::MockFiles;
 #[cfg(feature = "testing")]
 pub use crate::p2p::MockP2p;
 pub use crate::p2p::P2p as P2pApi;
 pub use crate::p2p::{Lookup, PeerIdOrAddr};
 pub use bytes::Bytes;
 pub use cid::Cid;
+pub use iroh_resolver::codecs::Codec;
+pub use iroh_resolver::mfs::{Entry as MfsEntry, Stat as MfsStat};
 pub use iroh_resolver::resolver::Path as IpfsPath;
-pub use iroh_resolver::unixfs_builder::AddEvent;
+pub use iroh_resolver::resolver::UnixfsType;
+pub use iroh_resolver::unixfs::UnixTime;
+pub use iroh_resolver::unixfs_builder::{walk_dir, AddEvent, SymlinkMode, WalkOptions};
 pub use iroh_rpc_client::{ServiceStatus, StatusRow, StatusTable};
 pub use libp2p::gossipsub::MessageId;
 pub use libp2p::{Multiaddr, PeerId};
@@ -1,7 +1,12 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use iroh_resolver::resolver::Path as IpfsPath;
+pub use iroh_rpc_client::Lookup;
 use iroh_rpc_client::P2pClient;
-use libp2p::{Multiaddr, PeerId};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 #[cfg(feature = "testing")]
 use mockall::automock;
 
@@ -9,18 +14,34 @@ pub struct ClientP2p {
     client: P2pClient,
 }
 
-pub struct Lookup {
-    pub peer_id: PeerId,
-    pub listen_addrs: Vec<Multiaddr>,
-    pub local_addrs: Vec<Multiaddr>,
-}
-
 #[derive(Debug, Clone)]
 pub enum PeerIdOrAddr {
     PeerId(PeerId),
     Multiaddr(Multiaddr),
 }
 
+impl PeerIdOrAddr {
+    /// Splits the peer id off a `/p2p/` multiaddr, returning it along with the
+    /// address to dial, if any.
+    fn peer_id_and_addrs(&self) -> Result<(PeerId, Vec<Multiaddr>)> {
+        match self {
+            PeerIdOrAddr::PeerId(peer_id) => Ok((*peer_id, Vec::new())),
+            PeerIdOrAddr::Multiaddr(addr) => {
+                let mut addr = addr.clone();
+                let peer_id = match addr.pop() {
+                    Some(Protocol::P2p(hash)) => {
+                        PeerId::from_multihash(hash).map_err(|_| anyhow!("invalid peer id"))?
+                    }
+                    _ => return Err(anyhow!("multiaddr must end with /p2p/<peer id>")),
+                };
+                // a plain `/p2p/<peer id>` carries no address to dial
+                let addrs = if addr.is_empty() { vec![] } else { vec![addr] };
+                Ok((peer_id, addrs))
+            }
+        }
+    }
+}
+
 impl ClientP2p {
     pub fn new(client: P2pClient) -> Self {
         Self { client }
@@ -30,19 +51,108 @@ impl ClientP2p {
 #[cfg_attr(feature = "testing", automock)]
 #[async_trait]
 pub trait P2p: Sync {
+    /// Connects to a peer and returns its identify info. A bare peer id is looked up
+    /// in the DHT first.
     async fn lookup(&self, addr: &PeerIdOrAddr) -> Result<Lookup>;
+    /// Returns the info of the local node.
+    async fn lookup_local(&self) -> Result<Lookup>;
+    /// Connects to a peer. A bare peer id is looked up in the DHT first, a multiaddr
+    /// must end with the `/p2p/` id of the peer.
+    async fn connect(&self, addr: &PeerIdOrAddr) -> Result<PeerId>;
+    /// Closes all connections to a peer, fails if there are none.
+    async fn disconnect(&self, peer_id: &PeerId) -> Result<()>;
+    /// Returns the currently connected peers and their addresses.
+    async fn peers(&self) -> Result<HashMap<PeerId, Vec<Multiaddr>>>;
+    /// Publishes an IPNS record pointing to `path`, signed with the keychain key
+    /// `key`, and returns the name it was published under. The record is valid
+    /// for `lifetime` and republished by the node until then.
+    async fn publish_name(&self, key: &str, path: &IpfsPath, lifetime: Duration) -> Result<PeerId>;
+    /// Returns the fingerprint of the pre-shared key if the node is part of a private
+    /// network, `None` if it is on the public network.
+    async fn private_network(&self) -> Result<Option<String>>;
 }
 
 #[async_trait]
 impl P2p for ClientP2p {
-    /// XXX really should be an API that intos a peer id, and then also accepts
-    /// an address, or two separate methods, one for peer id, one for address
-    async fn lookup(&self, _addr: &PeerIdOrAddr) -> Result<Lookup> {
-        let (_, listen_addrs) = self.client.get_listening_addrs().await?;
+    async fn lookup(&self, addr: &PeerIdOrAddr) -> Result<Lookup> {
+        let (peer_id, addrs) = addr.peer_id_and_addrs()?;
+        self.client.lookup(peer_id, addrs).await
+    }
+
+    async fn lookup_local(&self) -> Result<Lookup> {
+        let (peer_id, listen_addrs) = self.client.get_listening_addrs().await?;
+        let version = self.client.version().await?;
         Ok(Lookup {
-            peer_id: self.client.local_peer_id().await?,
+            peer_id,
+            protocol_version: String::new(),
+            agent_version: format!("iroh/{}", version),
             listen_addrs,
-            local_addrs: self.client.external_addresses().await?,
+            protocols: Vec::new(),
+            observed_addrs: self.client.external_addresses().await?,
+            rtt: None,
         })
     }
+
+    async fn connect(&self, addr: &PeerIdOrAddr) -> Result<PeerId> {
+        let (peer_id, addrs) = addr.peer_id_and_addrs()?;
+        self.client.connect(peer_id, addrs).await?;
+        Ok(peer_id)
+    }
+
+    async fn disconnect(&self, peer_id: &PeerId) -> Result<()> {
+        self.client.disconnect(*peer_id).await
+    }
+
+    async fn peers(&self) -> Result<HashMap<PeerId, Vec<Multiaddr>>> {
+        self.client.get_peers().await
+    }
+
+    async fn publish_name(&self, key: &str, path: &IpfsPath, lifetime: Duration) -> Result<PeerId> {
+        self.client
+            .publish_ipns(key, &path.to_string(), lifetime)
+            .await
+    }
+
+    async fn private_network(&self) -> Result<Option<String>> {
+        self.client.private_network().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_id_and_addrs() {
+        let peer_id: PeerId = "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            PeerIdOrAddr::PeerId(peer_id).peer_id_and_addrs().unwrap(),
+            (peer_id, vec![])
+        );
+
+        let addr: Multiaddr =
+            "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            PeerIdOrAddr::Multiaddr(addr).peer_id_and_addrs().unwrap(),
+            (
+                peer_id,
+                vec!["/ip4/104.131.131.82/tcp/4001".parse().unwrap()]
+            )
+        );
+
+        let addr: Multiaddr = "/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            PeerIdOrAddr::Multiaddr(addr).peer_id_and_addrs().unwrap(),
+            (peer_id, vec![])
+        );
+
+        let addr: Multiaddr = "/ip4/104.131.131.82/tcp/4001".parse().unwrap();
+        assert!(PeerIdOrAddr::Multiaddr(addr).peer_id_and_addrs().is_err());
+    }
 }
@@ -6,6 +6,7 @@ use headers::{
     AccessControlAllowHeaders, AccessControlAllowMethods, AccessControlAllowOrigin, HeaderMapExt,
 };
 use iroh_metrics::config::Config as MetricsConfig;
+use iroh_resolver::cache::DEFAULT_BLOCK_CACHE_SIZE;
 use iroh_rpc_client::Config as RpcClientConfig;
 use iroh_rpc_types::{gateway::GatewayServerAddr, Addr};
 use iroh_util::insert_into_config_map;
@@ -28,6 +29,9 @@ pub struct Config {
     pub port: u16,
     /// flag to toggle whether the gateway should use denylist on requests
     pub denylist: bool,
+    /// size in bytes of the in-memory block cache, `0` turns it off
+    #[serde(default = "default_block_cache_size")]
+    pub block_cache_size: u64,
     /// rpc addresses for the gateway & addresses for the rpc client to dial
     pub rpc_client: RpcClientConfig,
     /// metrics configuration
@@ -48,6 +52,7 @@ impl Config {
             rpc_client,
             metrics: MetricsConfig::default(),
             denylist: false,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
         }
     }
 
@@ -76,6 +81,10 @@ impl Config {
     }
 }
 
+fn default_block_cache_size() -> u64 {
+    DEFAULT_BLOCK_CACHE_SIZE
+}
+
 fn default_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.typed_insert(AccessControlAllowOrigin::ANY);
@@ -115,6 +124,7 @@ impl Default for Config {
             rpc_client,
             metrics: MetricsConfig::default(),
             denylist: false,
+            block_cache_size: DEFAULT_BLOCK_CACHE_SIZE,
         };
         t.set_default_headers();
         t
@@ -131,6 +141,7 @@ impl Source for Config {
         let mut map: Map<String, Value> = Map::new();
         insert_into_config_map(&mut map, "public_url_base", self.public_url_base.clone());
         insert_into_config_map(&mut map, "denylist", self.denylist);
+        insert_into_config_map(&mut map, "block_cache_size", self.block_cache_size as i64);
         // Some issue between deserializing u64 & u16, converting this to
         // an signed int fixes the issue
         insert_into_config_map(&mut map, "port", self.port as i32);
@@ -201,6 +212,10 @@ mod tests {
         );
         expect.insert("port".to_string(), Value::new(None, default.port as i64));
         expect.insert("denylist".to_string(), Value::new(None, default.denylist));
+        expect.insert(
+            "block_cache_size".to_string(),
+            Value::new(None, default.block_cache_size as i64),
+        );
         expect.insert(
             "headers".to_string(),
             Value::new(None, collect_headers(&default.headers).unwrap()),
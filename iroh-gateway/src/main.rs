@@ -9,6 +9,7 @@ use iroh_gateway::{
     core::Core,
     metrics,
 };
+use iroh_resolver::cache::Cached;
 use iroh_rpc_client::Client as RpcClient;
 use iroh_util::{iroh_config_path, make_config};
 use tokio::sync::RwLock;
@@ -42,7 +43,10 @@ async fn main() -> Result<()> {
     let rpc_addr = config
         .server_rpc_addr()?
         .ok_or_else(|| anyhow!("missing gateway rpc addr"))?;
-    let content_loader = RpcClient::new(config.rpc_client.clone()).await?;
+    let content_loader = Cached::new(
+        RpcClient::new(config.rpc_client.clone()).await?,
+        config.block_cache_size,
+    );
     let handler = Core::new(
         Arc::new(config),
         rpc_addr,
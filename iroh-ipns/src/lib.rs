@@ -0,0 +1,369 @@
+//! IPNS records, as described in <https://specs.ipfs.tech/ipns/ipns-record/>.
+//!
+//! Shared by the resolver, which looks names up, and the p2p node, which
+//! publishes them and validates the records it receives.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bytes::Bytes;
+use cid::multihash::Multihash;
+use libipld::prelude::Codec as _;
+use libipld::{Ipld, IpldCodec};
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+use prost::Message;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+mod ipns_pb {
+    #![allow(clippy::all)]
+    include!(concat!(env!("OUT_DIR"), "/ipns_pb.rs"));
+}
+
+use ipns_pb::{ipns_entry::ValidityType, IpnsEntry};
+
+/// Prefix of the data signed by V2 signatures.
+const SIGNATURE_V2_PREFIX: &[u8] = b"ipns-signature:";
+/// Multihash code of identity hashes, used by peer ids which inline their public key.
+const IDENTITY_HASH_CODE: u64 = 0x00;
+
+/// A decoded and validated IPNS record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpnsRecord {
+    /// The path the name points to, e.g. `/ipfs/<cid>/dir`.
+    pub value: String,
+    pub sequence: u64,
+    /// The record is valid up to this point in time.
+    pub validity: OffsetDateTime,
+    /// How long the record may be cached for.
+    pub ttl: Option<Duration>,
+}
+
+/// The fields covered by the signature of a record.
+#[derive(Debug)]
+struct SignedFields {
+    value: Vec<u8>,
+    validity: Vec<u8>,
+    validity_type: i32,
+    sequence: u64,
+    ttl: Option<u64>,
+}
+
+/// Returns the DHT key the records of `peer_id` are stored under.
+pub fn record_key(peer_id: &PeerId) -> Vec<u8> {
+    let mut key = b"/ipns/".to_vec();
+    key.extend(peer_id.to_bytes());
+    key
+}
+
+impl IpnsRecord {
+    /// Decodes a record, checks that it is signed by `peer_id` and that it has
+    /// not expired at `now`.
+    pub fn decode(peer_id: &PeerId, data: &[u8], now: OffsetDateTime) -> Result<Self> {
+        let entry = IpnsEntry::decode(data).context("ipns: invalid record")?;
+        let public_key = public_key(peer_id, entry.pub_key.as_deref())?;
+
+        let fields = match (&entry.signature_v2, &entry.data) {
+            (Some(signature), Some(data)) => {
+                let mut signed = SIGNATURE_V2_PREFIX.to_vec();
+                signed.extend(data);
+                ensure!(
+                    public_key.verify(&signed, signature),
+                    "ipns: invalid V2 signature"
+                );
+                v2_fields(&entry, data)?
+            }
+            (Some(_), None) => bail!("ipns: record is missing its signed data"),
+            (None, _) => {
+                let signature = entry
+                    .signature_v1
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("ipns: record is not signed"))?;
+                let fields = v1_fields(&entry);
+                ensure!(
+                    public_key.verify(&fields.v1_signed_data(), signature),
+                    "ipns: invalid V1 signature"
+                );
+                fields
+            }
+        };
+
+        ensure!(
+            fields.validity_type == ValidityType::Eol as i32,
+            "ipns: unknown validity type {}",
+            fields.validity_type
+        );
+        let validity = std::str::from_utf8(&fields.validity)
+            .ok()
+            .and_then(|validity| OffsetDateTime::parse(validity, &Rfc3339).ok())
+            .ok_or_else(|| anyhow!("ipns: invalid validity"))?;
+        ensure!(validity > now, "ipns: record expired at {}", validity);
+
+        let value = String::from_utf8(fields.value).context("ipns: invalid value")?;
+
+        Ok(IpnsRecord {
+            value,
+            sequence: fields.sequence,
+            validity,
+            ttl: fields.ttl.map(Duration::from_nanos),
+        })
+    }
+
+    /// Picks the best record out of the ones found for `peer_id`: the one with the
+    /// highest sequence number, or the one valid for the longest among those.
+    /// Invalid records are skipped.
+    pub fn select(peer_id: &PeerId, records: &[Bytes], now: OffsetDateTime) -> Result<Self> {
+        let mut best: Option<IpnsRecord> = None;
+        let mut last_err = None;
+        for data in records {
+            match IpnsRecord::decode(peer_id, data, now) {
+                Ok(record) => {
+                    let is_better = best.as_ref().map_or(true, |best| {
+                        (record.sequence, record.validity) > (best.sequence, best.validity)
+                    });
+                    if is_better {
+                        best = Some(record);
+                    }
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        match (best, last_err) {
+            (Some(record), _) => Ok(record),
+            (None, Some(err)) => Err(err.context(format!("no valid ipns record for {}", peer_id))),
+            (None, None) => bail!("no ipns record found for {}", peer_id),
+        }
+    }
+
+    /// Encodes and signs a record with both V1 and V2 signatures.
+    pub fn encode(&self, keypair: &Keypair) -> Result<Vec<u8>> {
+        let fields = SignedFields {
+            value: self.value.clone().into_bytes(),
+            validity: self.validity.format(&Rfc3339)?.into_bytes(),
+            validity_type: ValidityType::Eol as i32,
+            sequence: self.sequence,
+            ttl: self.ttl.map(|ttl| ttl.as_nanos() as u64),
+        };
+        let data = fields.v2_data()?;
+        let mut signed = SIGNATURE_V2_PREFIX.to_vec();
+        signed.extend(&data);
+
+        // small keys, like ed25519 ones, are part of the peer id already
+        let public_key = keypair.public();
+        let pub_key = if inlined_public_key(&public_key.to_peer_id())?.is_some() {
+            None
+        } else {
+            Some(public_key.to_protobuf_encoding())
+        };
+        let entry = IpnsEntry {
+            signature_v1: Some(keypair.sign(&fields.v1_signed_data())?),
+            signature_v2: Some(keypair.sign(&signed)?),
+            data: Some(data),
+            value: Some(fields.value),
+            validity_type: Some(fields.validity_type),
+            validity: Some(fields.validity),
+            sequence: Some(fields.sequence),
+            ttl: fields.ttl,
+            pub_key,
+        };
+        Ok(entry.encode_to_vec())
+    }
+}
+
+impl SignedFields {
+    /// The data signed by V1 signatures.
+    fn v1_signed_data(&self) -> Vec<u8> {
+        let mut data = self.value.clone();
+        data.extend(&self.validity);
+        // the validity type is signed by its name
+        data.extend(b"EOL");
+        data
+    }
+
+    /// The dag-cbor document signed by V2 signatures.
+    fn v2_data(&self) -> Result<Vec<u8>> {
+        let mut map = BTreeMap::new();
+        map.insert("Value".to_string(), Ipld::Bytes(self.value.clone()));
+        map.insert("Validity".to_string(), Ipld::Bytes(self.validity.clone()));
+        map.insert(
+            "ValidityType".to_string(),
+            Ipld::Integer(self.validity_type.into()),
+        );
+        map.insert("Sequence".to_string(), Ipld::Integer(self.sequence.into()));
+        if let Some(ttl) = self.ttl {
+            map.insert("TTL".to_string(), Ipld::Integer(ttl.into()));
+        }
+        IpldCodec::DagCbor
+            .encode(&Ipld::Map(map))
+            .map_err(|e| anyhow!("ipns: failed to encode signed data: {:?}", e))
+    }
+}
+
+fn v1_fields(entry: &IpnsEntry) -> SignedFields {
+    SignedFields {
+        value: entry.value.clone().unwrap_or_default(),
+        validity: entry.validity.clone().unwrap_or_default(),
+        validity_type: entry.validity_type.unwrap_or_default(),
+        sequence: entry.sequence.unwrap_or_default(),
+        ttl: entry.ttl,
+    }
+}
+
+/// Reads the signed fields from the V2 data, which must agree with the
+/// unsigned copies in the protobuf.
+fn v2_fields(entry: &IpnsEntry, data: &[u8]) -> Result<SignedFields> {
+    let ipld: Ipld = IpldCodec::DagCbor
+        .decode(data)
+        .map_err(|e| anyhow!("ipns: invalid signed data: {:?}", e))?;
+    let bytes = |name: &str| match ipld.get(name) {
+        Ok(Ipld::Bytes(bytes)) => Ok(bytes.clone()),
+        _ => Err(anyhow!("ipns: missing {} in signed data", name)),
+    };
+    let integer = |name: &str| match ipld.get(name) {
+        Ok(Ipld::Integer(i)) => Ok(Some(*i)),
+        Ok(_) => Err(anyhow!("ipns: invalid {} in signed data", name)),
+        Err(_) => Ok(None),
+    };
+
+    let fields = SignedFields {
+        value: bytes("Value")?,
+        validity: bytes("Validity")?,
+        validity_type: integer("ValidityType")?
+            .unwrap_or_default()
+            .try_into()
+            .context("ipns: invalid ValidityType")?,
+        sequence: integer("Sequence")?
+            .unwrap_or_default()
+            .try_into()
+            .context("ipns: invalid Sequence")?,
+        ttl: integer("TTL")?
+            .map(|ttl| ttl.try_into().context("ipns: invalid TTL"))
+            .transpose()?,
+    };
+
+    let unsigned = v1_fields(entry);
+    ensure!(
+        unsigned.value == fields.value
+            && unsigned.validity == fields.validity
+            && unsigned.validity_type == fields.validity_type
+            && unsigned.sequence == fields.sequence
+            && unsigned.ttl == fields.ttl,
+        "ipns: record does not match its signed data"
+    );
+    Ok(fields)
+}
+
+/// Returns the public key of `peer_id`, either the one embedded in the record
+/// or the one inlined into the peer id.
+fn public_key(peer_id: &PeerId, embedded: Option<&[u8]>) -> Result<PublicKey> {
+    let public_key = match embedded {
+        Some(public_key) => PublicKey::from_protobuf_encoding(public_key)?,
+        None => inlined_public_key(peer_id)?
+            .ok_or_else(|| anyhow!("ipns: record is missing the public key of {}", peer_id))?,
+    };
+    ensure!(
+        public_key.to_peer_id() == *peer_id,
+        "ipns: public key does not belong to {}",
+        peer_id
+    );
+    Ok(public_key)
+}
+
+/// Returns the public key inlined into `peer_id`, if it is short enough to be.
+fn inlined_public_key(peer_id: &PeerId) -> Result<Option<PublicKey>> {
+    let multihash = Multihash::from_bytes(&peer_id.to_bytes())?;
+    if multihash.code() != IDENTITY_HASH_CODE {
+        return Ok(None);
+    }
+    let public_key = PublicKey::from_protobuf_encoding(multihash.digest())?;
+    Ok(Some(public_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sequence: u64, validity: OffsetDateTime) -> IpnsRecord {
+        IpnsRecord {
+            value: "/ipfs/QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn".into(),
+            sequence,
+            validity,
+            ttl: Some(Duration::from_secs(60)),
+        }
+    }
+
+    #[test]
+    fn test_ipns_record_roundtrip() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let now = OffsetDateTime::now_utc();
+        let expected = record(3, now + time::Duration::hours(1));
+
+        let data = expected.encode(&keypair)?;
+        let got = IpnsRecord::decode(&peer_id, &data, now)?;
+        assert_eq!(got.value, expected.value);
+        assert_eq!(got.sequence, 3);
+        assert_eq!(got.ttl, expected.ttl);
+
+        // expired
+        assert!(IpnsRecord::decode(&peer_id, &data, now + time::Duration::hours(2)).is_err());
+        // signed by someone else
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(IpnsRecord::decode(&other, &data, now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipns_record_tampered() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let now = OffsetDateTime::now_utc();
+        let data = record(1, now + time::Duration::hours(1)).encode(&keypair)?;
+
+        // the unsigned value does not match the V2 data
+        let mut entry = IpnsEntry::decode(&data[..])?;
+        entry.value = Some(b"/ipfs/QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH".to_vec());
+        assert!(IpnsRecord::decode(&peer_id, &entry.encode_to_vec(), now).is_err());
+
+        // a V1 only record is checked against its V1 signature
+        let mut entry = IpnsEntry::decode(&data[..])?;
+        entry.signature_v2 = None;
+        entry.data = None;
+        assert!(IpnsRecord::decode(&peer_id, &entry.encode_to_vec(), now).is_ok());
+        entry.sequence = Some(2);
+        entry.value = Some(b"/ipfs/QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH".to_vec());
+        assert!(IpnsRecord::decode(&peer_id, &entry.encode_to_vec(), now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipns_record_select() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let now = OffsetDateTime::now_utc();
+
+        let records: Vec<Bytes> = [
+            record(1, now + time::Duration::hours(5)),
+            record(2, now + time::Duration::hours(1)),
+            record(2, now + time::Duration::hours(2)),
+            // expired, even though it has the highest sequence
+            record(3, now - time::Duration::hours(1)),
+        ]
+        .iter()
+        .map(|record| record.encode(&keypair).map(Bytes::from))
+        .collect::<Result<_>>()?;
+
+        let best = IpnsRecord::select(&peer_id, &records, now)?;
+        assert_eq!(best.sequence, 2);
+        assert_eq!(
+            best.validity.unix_timestamp(),
+            (now + time::Duration::hours(2)).unix_timestamp()
+        );
+
+        assert!(IpnsRecord::select(&peer_id, &records[3..], now).is_err());
+        assert!(IpnsRecord::select(&peer_id, &[], now).is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt;
 
-use prometheus_client::{metrics::counter::Counter, registry::Registry};
+use prometheus_client::{
+    metrics::{counter::Counter, gauge::Gauge},
+    registry::Registry,
+};
 use tracing::error;
 
 use crate::{
@@ -17,6 +20,12 @@ pub(crate) struct Metrics {
     skipped_peer_bitswap: Counter,
     skipped_peer_kad: Counter,
     loops: Counter,
+    reprovide_runs: Counter,
+    reprovided_keys: Counter,
+    reprovide_failures: Counter,
+    reprovide_progress: Gauge,
+    reprovide_last_run_keys: Gauge,
+    reprovide_last_run_duration: Gauge,
 }
 
 impl fmt::Debug for Metrics {
@@ -54,12 +63,55 @@ impl Metrics {
         let loops = Counter::default();
         sub_registry.register(P2PMetrics::LoopCounter.name(), "", Box::new(loops.clone()));
 
+        let reprovide_runs = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideRuns.name(),
+            "Number of finished reprovider runs",
+            Box::new(reprovide_runs.clone()),
+        );
+        let reprovided_keys = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovidedKeys.name(),
+            "Number of keys announced by the reprovider",
+            Box::new(reprovided_keys.clone()),
+        );
+        let reprovide_failures = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideFailures.name(),
+            "Number of keys the reprovider failed to announce",
+            Box::new(reprovide_failures.clone()),
+        );
+        let reprovide_progress = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideProgress.name(),
+            "Keys announced so far by the running reprovider run",
+            Box::new(reprovide_progress.clone()),
+        );
+        let reprovide_last_run_keys = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideLastRunKeys.name(),
+            "Keys announced by the last reprovider run",
+            Box::new(reprovide_last_run_keys.clone()),
+        );
+        let reprovide_last_run_duration = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReprovideLastRunDuration.name(),
+            "Duration of the last reprovider run in seconds",
+            Box::new(reprovide_last_run_duration.clone()),
+        );
+
         Self {
             bad_peers,
             bad_peers_removed,
             skipped_peer_bitswap,
             skipped_peer_kad,
             loops,
+            reprovide_runs,
+            reprovided_keys,
+            reprovide_failures,
+            reprovide_progress,
+            reprovide_last_run_keys,
+            reprovide_last_run_duration,
         }
     }
 }
@@ -79,6 +131,18 @@ impl MetricsRecorder for Metrics {
             self.skipped_peer_kad.inc_by(value);
         } else if m.name() == P2PMetrics::LoopCounter.name() {
             self.loops.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovideRuns.name() {
+            self.reprovide_runs.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovidedKeys.name() {
+            self.reprovided_keys.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovideFailures.name() {
+            self.reprovide_failures.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovideProgress.name() {
+            self.reprovide_progress.set(value);
+        } else if m.name() == P2PMetrics::ReprovideLastRunKeys.name() {
+            self.reprovide_last_run_keys.set(value);
+        } else if m.name() == P2PMetrics::ReprovideLastRunDuration.name() {
+            self.reprovide_last_run_duration.set(value);
         } else {
             error!("record (bitswap): unknown metric {}", m.name());
         }
@@ -99,6 +163,12 @@ pub enum P2PMetrics {
     SkippedPeerBitswap,
     SkippedPeerKad,
     LoopCounter,
+    ReprovideRuns,
+    ReprovidedKeys,
+    ReprovideFailures,
+    ReprovideProgress,
+    ReprovideLastRunKeys,
+    ReprovideLastRunDuration,
 }
 
 impl MetricType for P2PMetrics {
@@ -109,6 +179,12 @@ impl MetricType for P2PMetrics {
             P2PMetrics::SkippedPeerBitswap => "skipped_peer_bitswap",
             P2PMetrics::SkippedPeerKad => "skipped_peer_kad",
             P2PMetrics::LoopCounter => "loop_counter",
+            P2PMetrics::ReprovideRuns => "reprovide_runs",
+            P2PMetrics::ReprovidedKeys => "reprovided_keys",
+            P2PMetrics::ReprovideFailures => "reprovide_failures",
+            P2PMetrics::ReprovideProgress => "reprovide_progress",
+            P2PMetrics::ReprovideLastRunKeys => "reprovide_last_run_keys",
+            P2PMetrics::ReprovideLastRunDuration => "reprovide_last_run_duration",
         }
     }
 }
@@ -12,6 +12,8 @@ use crate::{
 pub(crate) struct Metrics {
     cache_hit: Counter,
     cache_miss: Counter,
+    block_cache_hit: Counter,
+    block_cache_miss: Counter,
 }
 
 impl fmt::Debug for Metrics {
@@ -37,9 +39,25 @@ impl Metrics {
             Box::new(cache_miss.clone()),
         );
 
+        let block_cache_hit = Counter::default();
+        sub_registry.register(
+            METRICS_BLOCK_CACHE_HIT,
+            "Number of blocks served from the in-memory block cache",
+            Box::new(block_cache_hit.clone()),
+        );
+
+        let block_cache_miss = Counter::default();
+        sub_registry.register(
+            METRICS_BLOCK_CACHE_MISS,
+            "Number of blocks missing from the in-memory block cache",
+            Box::new(block_cache_miss.clone()),
+        );
+
         Self {
             cache_hit,
             cache_miss,
+            block_cache_hit,
+            block_cache_miss,
         }
     }
 }
@@ -60,6 +78,10 @@ impl MetricsRecorder for Metrics {
             self.cache_hit.inc_by(value);
         } else if m.name() == ResolverMetrics::CacheMiss.name() {
             self.cache_miss.inc_by(value);
+        } else if m.name() == ResolverMetrics::BlockCacheHit.name() {
+            self.block_cache_hit.inc_by(value);
+        } else if m.name() == ResolverMetrics::BlockCacheMiss.name() {
+            self.block_cache_miss.inc_by(value);
         } else {
             error!("record (resolver): unknown metric {}", m.name());
         }
@@ -77,6 +99,8 @@ impl MetricsRecorder for Metrics {
 pub enum ResolverMetrics {
     CacheHit,
     CacheMiss,
+    BlockCacheHit,
+    BlockCacheMiss,
 }
 
 impl MetricType for ResolverMetrics {
@@ -84,6 +108,8 @@ impl MetricType for ResolverMetrics {
         match self {
             ResolverMetrics::CacheHit => METRICS_CACHE_HIT,
             ResolverMetrics::CacheMiss => METRICS_CACHE_MISS,
+            ResolverMetrics::BlockCacheHit => METRICS_BLOCK_CACHE_HIT,
+            ResolverMetrics::BlockCacheMiss => METRICS_BLOCK_CACHE_MISS,
         }
     }
 }
@@ -102,3 +128,5 @@ impl std::fmt::Display for ResolverMetrics {
 
 const METRICS_CACHE_HIT: &str = "cache_hit";
 const METRICS_CACHE_MISS: &str = "cache_miss";
+const METRICS_BLOCK_CACHE_HIT: &str = "block_cache_hit";
+const METRICS_BLOCK_CACHE_MISS: &str = "block_cache_miss";
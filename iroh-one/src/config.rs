@@ -3,7 +3,7 @@ use axum::http::header::*;
 use config::{ConfigError, Map, Source, Value};
 
 use iroh_metrics::config::Config as MetricsConfig;
-use iroh_p2p::Libp2pConfig;
+use iroh_p2p::{Libp2pConfig, KAD_STORE_DIR_NAME, PEER_STORE_FILE_NAME};
 use iroh_rpc_client::Config as RpcClientConfig;
 use iroh_rpc_types::Addr;
 use iroh_store::config::config_data_path;
@@ -132,6 +132,8 @@ fn default_p2p_config(
         rpc_client: ipfsd,
         metrics,
         key_store_path,
+        peer_store_path: Some(iroh_util::iroh_data_path(PEER_STORE_FILE_NAME).unwrap()),
+        kad_store_path: Some(iroh_util::iroh_data_path(KAD_STORE_DIR_NAME).unwrap()),
     }
 }
 
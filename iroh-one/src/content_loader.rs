@@ -149,4 +149,8 @@ impl ContentLoader for RacingLoader {
     async fn has_cid(&self, cid: &Cid) -> Result<bool> {
         Ok(self.rpc_client.try_store()?.has(*cid).await?)
     }
+
+    async fn load_records(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        self.rpc_client.try_p2p()?.fetch_record_dht(key).await
+    }
 }
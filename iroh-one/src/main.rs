@@ -10,6 +10,7 @@ use iroh_one::{
     cli::Args,
     config::{Config, CONFIG_FILE_NAME, ENV_PREFIX},
 };
+use iroh_resolver::cache::Cached;
 use iroh_rpc_client::Client as RpcClient;
 use iroh_rpc_types::Addr;
 use iroh_util::lock::ProgramLock;
@@ -82,9 +83,12 @@ async fn main() -> Result<()> {
     };
 
     // let content_loader = RpcClient::new(config.rpc_client.clone()).await?;
-    let content_loader = iroh_one::content_loader::RacingLoader::new(
-        RpcClient::new(config.rpc_client.clone()).await?,
-        config.resolver_gateway.clone(),
+    let content_loader = Cached::new(
+        iroh_one::content_loader::RacingLoader::new(
+            RpcClient::new(config.rpc_client.clone()).await?,
+            config.resolver_gateway.clone(),
+        ),
+        config.gateway.block_cache_size,
     );
     let shared_state = Core::make_state(
         Arc::new(config.clone()),
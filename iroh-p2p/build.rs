@@ -0,0 +1,9 @@
+fn main() {
+    prost_build::Config::new()
+        .bytes(&[".fetch_pb.FetchResponse.data"])
+        .compile_protos(
+            &["src/behaviour/fetch.proto", "src/behaviour/dht.proto"],
+            &["src/behaviour"],
+        )
+        .unwrap();
+}
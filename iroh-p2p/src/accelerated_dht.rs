@@ -0,0 +1,632 @@
+//! Accelerated DHT client. Periodically crawls the whole DHT to keep a routing table of all
+//! reachable DHT servers, then looks up and announces providers by asking the closest peers
+//! of that table directly, in a single hop instead of walking the DHT.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::{anyhow, Result};
+use libp2p::kad::kbucket::Key as KBucketKey;
+use libp2p::kad::{record::Key, K_VALUE};
+use libp2p::request_response::{RequestId, RequestResponseEvent, RequestResponseMessage};
+use libp2p::{Multiaddr, PeerId};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot::Sender as OneShotSender;
+use tracing::{debug, info};
+
+use crate::behaviour::{DhtClient, DhtRequest, DhtResponse};
+
+pub(crate) struct AcceleratedDht {
+    local_peer_id: PeerId,
+    /// The DHT servers that answered during the last complete crawl.
+    peers: AHashMap<PeerId, Vec<Multiaddr>>,
+    last_crawl: Option<Instant>,
+    crawl: Option<Crawl>,
+    crawl_interval: Duration,
+    /// Maximum number of requests in flight.
+    parallelism: usize,
+    in_flight: usize,
+    requests: AHashMap<RequestId, PendingRequest>,
+    /// Number of requests in flight per peer, its addresses are kept by the client until
+    /// all of them finished.
+    peer_requests: AHashMap<PeerId, usize>,
+    provider_queries: AHashMap<Key, ProviderQuery>,
+    /// Provider lookups waiting to be sent, they go before crawl and announce requests.
+    lookup_queue: VecDeque<(PeerId, Key)>,
+    /// Announcements to single peers waiting to be sent, with the id of the announcement
+    /// someone waits for.
+    announce_queue: VecDeque<(PeerId, Key, Option<u64>)>,
+    /// Keys waiting to be announced to their closest peers.
+    provide_queue: VecDeque<(Key, Option<OneShotSender<Result<()>>>)>,
+    /// Announcements someone waits for, answered once all their requests finished.
+    announcements: AHashMap<u64, Announcement>,
+    next_announcement: u64,
+}
+
+enum PendingRequest {
+    Crawl(PeerId),
+    GetProviders(Key),
+    AddProvider(Option<u64>),
+}
+
+struct Crawl {
+    queue: VecDeque<PeerId>,
+    seen: AHashSet<PeerId>,
+    /// Addresses of all peers seen so far.
+    addrs: AHashMap<PeerId, Vec<Multiaddr>>,
+    /// Peers that answered.
+    reachable: AHashMap<PeerId, Vec<Multiaddr>>,
+    in_flight: usize,
+    started: Instant,
+}
+
+struct Announcement {
+    key: Key,
+    /// Number of peers the announcement was not sent to yet or that did not answer.
+    pending: usize,
+    /// Number of peers that accepted the announcement.
+    announced: usize,
+    channel: OneShotSender<Result<()>>,
+}
+
+struct ProviderQuery {
+    /// Number of peers that did not answer yet.
+    pending: usize,
+    found: HashSet<PeerId>,
+    channels: Vec<Sender<Result<HashSet<PeerId>, String>>>,
+    limit: usize,
+}
+
+impl AcceleratedDht {
+    pub fn new(local_peer_id: PeerId, crawl_interval: Duration, parallelism: usize) -> Self {
+        AcceleratedDht {
+            local_peer_id,
+            peers: Default::default(),
+            last_crawl: None,
+            crawl: None,
+            crawl_interval,
+            parallelism,
+            in_flight: 0,
+            requests: Default::default(),
+            peer_requests: Default::default(),
+            provider_queries: Default::default(),
+            lookup_queue: Default::default(),
+            announce_queue: Default::default(),
+            provide_queue: Default::default(),
+            announcements: Default::default(),
+            next_announcement: 0,
+        }
+    }
+
+    /// Whether a crawl completed, until then lookups go through kademlia.
+    pub fn is_ready(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    pub fn crawl_due(&self, now: Instant) -> bool {
+        self.crawl.is_none()
+            && self
+                .last_crawl
+                .map(|last| now.duration_since(last) >= self.crawl_interval)
+                .unwrap_or(true)
+    }
+
+    /// Starts crawling the DHT from `seeds` and the peers of the current routing table.
+    pub fn start_crawl(&mut self, seeds: impl IntoIterator<Item = (PeerId, Vec<Multiaddr>)>) {
+        let mut crawl = Crawl {
+            queue: Default::default(),
+            seen: Default::default(),
+            addrs: Default::default(),
+            reachable: Default::default(),
+            in_flight: 0,
+            started: Instant::now(),
+        };
+        let known = self.peers.iter().map(|(p, addrs)| (*p, addrs.clone()));
+        for (peer_id, addrs) in seeds.into_iter().chain(known) {
+            if peer_id != self.local_peer_id {
+                crawl.add_peer(peer_id, addrs);
+            }
+        }
+        if crawl.queue.is_empty() {
+            debug!("no peers to start crawling the dht from");
+            return;
+        }
+        debug!("crawling the dht from {} peers", crawl.queue.len());
+        self.crawl = Some(crawl);
+    }
+
+    pub fn peer_addrs(&self, peer_id: &PeerId) -> Option<&Vec<Multiaddr>> {
+        self.peers.get(peer_id)
+    }
+
+    /// The `n` peers of the routing table closest to `target`.
+    pub fn closest_peers<T>(&self, target: &KBucketKey<T>, n: usize) -> Vec<PeerId> {
+        let mut peers: Vec<_> = self
+            .peers
+            .keys()
+            .map(|peer_id| (KBucketKey::from(*peer_id).distance(target), *peer_id))
+            .collect();
+        if peers.len() > n && n > 0 {
+            peers.select_nth_unstable_by(n - 1, |a, b| a.0.cmp(&b.0));
+        }
+        peers.truncate(n);
+        peers.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        peers.into_iter().map(|(_, peer_id)| peer_id).collect()
+    }
+
+    /// Queues asking the closest peers of `key` for its providers, the requests are sent
+    /// by [`AcceleratedDht::poll`] ahead of all others.
+    pub fn get_providers(
+        &mut self,
+        key: Key,
+        channel: Sender<Result<HashSet<PeerId>, String>>,
+        limit: usize,
+    ) {
+        if let Some(query) = self.provider_queries.get_mut(&key) {
+            query.channels.push(channel);
+            return;
+        }
+
+        let peers = self.closest_peers(&KBucketKey::new(key.clone()), K_VALUE.get());
+        self.lookup_queue
+            .extend(peers.iter().map(|peer_id| (*peer_id, key.clone())));
+        self.provider_queries.insert(
+            key,
+            ProviderQuery {
+                pending: peers.len(),
+                found: Default::default(),
+                channels: vec![channel],
+                limit,
+            },
+        );
+    }
+
+    /// Queues `keys` to be announced to their closest peers.
+    pub fn provide(&mut self, keys: impl IntoIterator<Item = Key>) {
+        self.provide_queue
+            .extend(keys.into_iter().map(|key| (key, None)));
+    }
+
+    /// Queues `key` to be announced to its closest peers, `channel` is answered once all
+    /// of them accepted or failed the announcement. It fails if none accepted it.
+    pub fn reprovide(&mut self, key: Key, channel: OneShotSender<Result<()>>) {
+        self.provide_queue.push_back((key, Some(channel)));
+    }
+
+    /// Number of keys waiting to be announced.
+    pub fn provide_queue_len(&self) -> usize {
+        self.provide_queue.len()
+    }
+
+    /// Sends provider lookup, crawl and announce requests, as long as there is room.
+    /// Announcements advertise this node under `addrs`.
+    pub fn poll(&mut self, client: &mut DhtClient, addrs: &[Multiaddr]) {
+        while self.in_flight < self.parallelism {
+            if let Some((peer_id, key)) = self.lookup_queue.pop_front() {
+                let req = DhtRequest::GetProviders { key: key.clone() };
+                self.send(client, peer_id, req, PendingRequest::GetProviders(key));
+                continue;
+            }
+            if let Some(peer_id) = self.crawl.as_mut().and_then(|c| c.queue.pop_front()) {
+                let req = DhtRequest::FindNode {
+                    key: peer_id.to_bytes(),
+                };
+                self.send(client, peer_id, req, PendingRequest::Crawl(peer_id));
+                continue;
+            }
+            if !self.is_ready() {
+                break;
+            }
+            if let Some((peer_id, key, id)) = self.announce_queue.pop_front() {
+                let req = DhtRequest::AddProvider {
+                    key,
+                    provider: self.local_peer_id,
+                    addrs: addrs.to_vec(),
+                };
+                self.send(client, peer_id, req, PendingRequest::AddProvider(id));
+                continue;
+            }
+            match self.provide_queue.pop_front() {
+                Some((key, channel)) => {
+                    let peers = self.closest_peers(&KBucketKey::new(key.clone()), K_VALUE.get());
+                    let id = channel.map(|channel| {
+                        let id = self.next_announcement;
+                        self.next_announcement += 1;
+                        self.announcements.insert(
+                            id,
+                            Announcement {
+                                key: key.clone(),
+                                pending: peers.len(),
+                                announced: 0,
+                                channel,
+                            },
+                        );
+                        id
+                    });
+                    self.announce_queue
+                        .extend(peers.into_iter().map(|peer_id| (peer_id, key.clone(), id)));
+                }
+                None => break,
+            }
+        }
+        self.maybe_finish_crawl();
+    }
+
+    /// Handles the answers to our requests, `is_bad_peer` filters the providers found.
+    pub fn handle_event(
+        &mut self,
+        client: &mut DhtClient,
+        event: RequestResponseEvent<DhtRequest, DhtResponse>,
+        is_bad_peer: impl Fn(&PeerId) -> bool,
+    ) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+            } => {
+                self.handle_response(client, peer, request_id, Some(response), is_bad_peer);
+            }
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                debug!("dht request to {} failed: {:?}", peer, error);
+                self.handle_response(client, peer, request_id, None, is_bad_peer);
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_response(
+        &mut self,
+        client: &mut DhtClient,
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: Option<DhtResponse>,
+        is_bad_peer: impl Fn(&PeerId) -> bool,
+    ) {
+        let pending = match self.requests.remove(&request_id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        self.in_flight -= 1;
+        if let Some(count) = self.peer_requests.get_mut(&peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.peer_requests.remove(&peer_id);
+                self.forget_addresses(client, &peer_id);
+            }
+        }
+
+        match pending {
+            PendingRequest::Crawl(peer_id) => {
+                if let Some(crawl) = self.crawl.as_mut() {
+                    crawl.in_flight -= 1;
+                    if let Some(response) = response {
+                        let addrs = crawl.addrs.get(&peer_id).cloned().unwrap_or_default();
+                        crawl.reachable.insert(peer_id, addrs);
+                        for peer in response.closer_peers {
+                            if peer.peer_id != self.local_peer_id {
+                                crawl.add_peer(peer.peer_id, peer.addrs);
+                            }
+                        }
+                    }
+                }
+            }
+            PendingRequest::GetProviders(key) => {
+                let query = match self.provider_queries.get_mut(&key) {
+                    Some(query) => query,
+                    None => return,
+                };
+                query.pending -= 1;
+                let mut new_providers = HashSet::new();
+                for provider in response.map(|r| r.providers).unwrap_or_default() {
+                    if is_bad_peer(&provider.peer_id) || query.found.contains(&provider.peer_id) {
+                        continue;
+                    }
+                    // so the providers can be dialed
+                    for addr in &provider.addrs {
+                        client.add_address(&provider.peer_id, addr.clone());
+                    }
+                    new_providers.insert(provider.peer_id);
+                }
+                query.found.extend(new_providers.iter().copied());
+
+                if !new_providers.is_empty() {
+                    let channels = query.channels.clone();
+                    tokio::task::spawn(async move {
+                        for chan in channels.into_iter() {
+                            chan.send(Ok(new_providers.clone())).await.ok();
+                        }
+                    });
+                }
+                if query.pending == 0 || query.found.len() >= query.limit {
+                    debug!(
+                        "finish provider query {}/{}",
+                        query.found.len(),
+                        query.limit
+                    );
+                    self.provider_queries.remove(&key);
+                    // the query is done, no need to ask the remaining peers
+                    self.lookup_queue.retain(|(_, k)| k != &key);
+                }
+            }
+            PendingRequest::AddProvider(Some(id)) => {
+                if let Some(announcement) = self.announcements.get_mut(&id) {
+                    announcement.pending -= 1;
+                    if response.is_some() {
+                        announcement.announced += 1;
+                    }
+                    if announcement.pending == 0 {
+                        let announcement = self.announcements.remove(&id).expect("checked above");
+                        let res = if announcement.announced > 0 {
+                            Ok(())
+                        } else {
+                            Err(anyhow!(
+                                "no peer accepted the announcement of {:?}",
+                                announcement.key
+                            ))
+                        };
+                        announcement.channel.send(res).ok();
+                    }
+                }
+            }
+            PendingRequest::AddProvider(None) => {}
+        }
+        self.maybe_finish_crawl();
+    }
+
+    fn send(
+        &mut self,
+        client: &mut DhtClient,
+        peer_id: PeerId,
+        req: DhtRequest,
+        pending: PendingRequest,
+    ) {
+        for addr in self.addresses(&peer_id) {
+            client.add_address(&peer_id, addr);
+        }
+        self.in_flight += 1;
+        *self.peer_requests.entry(peer_id).or_default() += 1;
+        if let (PendingRequest::Crawl(_), Some(crawl)) = (&pending, self.crawl.as_mut()) {
+            crawl.in_flight += 1;
+        }
+        let request_id = client.send_request(&peer_id, req);
+        self.requests.insert(request_id, pending);
+    }
+
+    fn addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.crawl
+            .as_ref()
+            .and_then(|crawl| crawl.addrs.get(peer_id))
+            .or_else(|| self.peers.get(peer_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The addresses are only handed to the client while it talks to the peer.
+    fn forget_addresses(&self, client: &mut DhtClient, peer_id: &PeerId) {
+        for addr in self.addresses(peer_id) {
+            client.remove_address(peer_id, &addr);
+        }
+    }
+
+    fn maybe_finish_crawl(&mut self) {
+        let done = self
+            .crawl
+            .as_ref()
+            .map(|crawl| crawl.queue.is_empty() && crawl.in_flight == 0)
+            .unwrap_or_default();
+        if !done {
+            return;
+        }
+        let crawl = self.crawl.take().expect("checked above");
+        info!(
+            "crawled {} dht servers in {:?}, {} reachable",
+            crawl.seen.len(),
+            crawl.started.elapsed(),
+            crawl.reachable.len()
+        );
+        // keep the previous table if the network was not reachable at all
+        if !crawl.reachable.is_empty() {
+            self.peers = crawl.reachable;
+        }
+        self.last_crawl = Some(Instant::now());
+    }
+}
+
+impl Crawl {
+    fn add_peer(&mut self, peer_id: PeerId, addrs: Vec<Multiaddr>) {
+        let known = self.addrs.entry(peer_id).or_default();
+        for addr in addrs {
+            if !known.contains(&addr) {
+                known.push(addr);
+            }
+        }
+        if self.seen.insert(peer_id) {
+            self.queue.push_back(peer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+    use libp2p::request_response::OutboundFailure;
+    use libp2p::swarm::NetworkBehaviour;
+
+    use super::*;
+    use crate::behaviour::dht_client::{self, DhtPeer};
+
+    fn random_peer_id() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    fn respond(
+        dht: &mut AcceleratedDht,
+        client: &mut DhtClient,
+        peer_id: PeerId,
+        response: DhtResponse,
+    ) {
+        let request_id = *dht
+            .requests
+            .iter()
+            .find(|(_, pending)| matches!(pending, PendingRequest::Crawl(p) if *p == peer_id))
+            .unwrap()
+            .0;
+        let event = RequestResponseEvent::Message {
+            peer: peer_id,
+            message: RequestResponseMessage::Response {
+                request_id,
+                response,
+            },
+        };
+        dht.handle_event(client, event, |_| false);
+    }
+
+    /// Answers any of the requests to `peer_id`.
+    fn answer(dht: &mut AcceleratedDht, client: &mut DhtClient, peer_id: PeerId) {
+        let request_id = *dht.requests.keys().next().unwrap();
+        let event = RequestResponseEvent::Message {
+            peer: peer_id,
+            message: RequestResponseMessage::Response {
+                request_id,
+                response: DhtResponse::default(),
+            },
+        };
+        dht.handle_event(client, event, |_| false);
+    }
+
+    #[test]
+    fn test_crawl() {
+        let mut client = dht_client::new();
+        let local = random_peer_id();
+        let mut dht = AcceleratedDht::new(local, Duration::from_secs(3600), 16);
+        assert!(dht.crawl_due(Instant::now()));
+
+        let seed = random_peer_id();
+        let found = random_peer_id();
+        let addr: Multiaddr = "/ip4/192.168.1.10/tcp/4444".parse().unwrap();
+        // the local peer is never crawled
+        dht.start_crawl([(seed, vec![addr.clone()]), (local, Vec::new())]);
+        dht.poll(&mut client, &[]);
+        assert!(!dht.crawl_due(Instant::now()));
+        assert_eq!(dht.in_flight, 1);
+
+        respond(
+            &mut dht,
+            &mut client,
+            seed,
+            DhtResponse {
+                closer_peers: vec![DhtPeer {
+                    peer_id: found,
+                    addrs: vec![addr.clone()],
+                }],
+                providers: Vec::new(),
+            },
+        );
+        // the new peer is crawled next
+        dht.poll(&mut client, &[]);
+        assert!(!dht.is_ready());
+        respond(&mut dht, &mut client, found, DhtResponse::default());
+
+        assert!(dht.is_ready());
+        assert!(!dht.crawl_due(Instant::now()));
+        assert_eq!(dht.in_flight, 0);
+        assert_eq!(dht.peer_addrs(&found), Some(&vec![addr]));
+
+        let mut expected = vec![seed, found];
+        expected.sort_by_key(|p| KBucketKey::from(*p).distance(&KBucketKey::from(local)));
+        assert_eq!(dht.closest_peers(&KBucketKey::from(local), 20), expected);
+        assert_eq!(
+            dht.closest_peers(&KBucketKey::from(local), 1),
+            expected[..1]
+        );
+
+        // announcements go to all closest peers
+        dht.provide([Key::new(b"some-cid")]);
+        assert_eq!(dht.provide_queue_len(), 1);
+        dht.poll(&mut client, &[]);
+        assert_eq!(dht.provide_queue_len(), 0);
+        assert_eq!(dht.in_flight, 2);
+    }
+
+    #[test]
+    fn test_parallelism() {
+        let mut client = dht_client::new();
+        let mut dht = AcceleratedDht::new(random_peer_id(), Duration::from_secs(3600), 3);
+        let peer = random_peer_id();
+        let addr: Multiaddr = "/ip4/192.168.1.10/tcp/4444".parse().unwrap();
+        dht.peers.insert(peer, vec![addr.clone()]);
+
+        // one announcement per key, only three at a time
+        dht.provide((0..4u8).map(|i| Key::new(&[i])));
+        dht.poll(&mut client, &[]);
+        assert_eq!(dht.in_flight, 3);
+        assert_eq!(dht.requests.len(), 3);
+
+        // lookups wait for room as well
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        dht.get_providers(Key::new(b"some-cid"), tx, 10);
+        dht.poll(&mut client, &[]);
+        assert_eq!(dht.in_flight, 3);
+
+        // and go first once there is some
+        answer(&mut dht, &mut client, peer);
+        // the other requests to the peer still need its addresses
+        assert_eq!(client.addresses_of_peer(&peer), vec![addr]);
+        dht.poll(&mut client, &[]);
+        assert_eq!(dht.in_flight, 3);
+        assert!(dht
+            .requests
+            .values()
+            .any(|pending| matches!(pending, PendingRequest::GetProviders(_))));
+
+        while !dht.requests.is_empty() {
+            answer(&mut dht, &mut client, peer);
+            dht.poll(&mut client, &[]);
+            assert!(dht.in_flight <= 3);
+        }
+        assert_eq!(dht.in_flight, 0);
+        assert_eq!(dht.provide_queue_len(), 0);
+        assert!(dht.provider_queries.is_empty());
+        assert!(client.addresses_of_peer(&peer).is_empty());
+    }
+
+    #[test]
+    fn test_reprovide() {
+        let mut client = dht_client::new();
+        let mut dht = AcceleratedDht::new(random_peer_id(), Duration::from_secs(3600), 16);
+        let peer = random_peer_id();
+        dht.peers.insert(peer, Vec::new());
+
+        // answered once the peer accepted the announcement
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        dht.reprovide(Key::new(b"some-cid"), tx);
+        dht.poll(&mut client, &[]);
+        assert_eq!(dht.in_flight, 1);
+        assert!(rx.try_recv().is_err());
+        answer(&mut dht, &mut client, peer);
+        assert!(rx.try_recv().unwrap().is_ok());
+
+        // and fails if no peer did
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        dht.reprovide(Key::new(b"other-cid"), tx);
+        dht.poll(&mut client, &[]);
+        let request_id = *dht.requests.keys().next().unwrap();
+        let event = RequestResponseEvent::OutboundFailure {
+            peer,
+            request_id,
+            error: OutboundFailure::Timeout,
+        };
+        dht.handle_event(&mut client, event, |_| false);
+        assert!(rx.try_recv().unwrap().is_err());
+        assert!(dht.announcements.is_empty());
+    }
+}
@@ -1,46 +1,60 @@
+use std::path::Path;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use cid::Cid;
 use iroh_bitswap::{Bitswap, Block, Config as BitswapConfig, Store};
 use iroh_rpc_client::Client;
 use libp2p::core::identity::Keypair;
 use libp2p::core::PeerId;
-use libp2p::gossipsub::{Gossipsub, GossipsubConfig, MessageAuthenticity};
-use libp2p::identify::{Identify, IdentifyConfig};
+use libp2p::gossipsub::{Gossipsub, GossipsubConfigBuilder, MessageAuthenticity};
+use libp2p::identify::{Behaviour as Identify, Config as IdentifyConfig};
 use libp2p::kad::store::{MemoryStore, MemoryStoreConfig};
 use libp2p::kad::{Kademlia, KademliaConfig};
-use libp2p::mdns::TokioMdns as Mdns;
+use libp2p::mdns::tokio::Behaviour as Mdns;
 use libp2p::multiaddr::Protocol;
 use libp2p::ping::Behaviour as Ping;
 use libp2p::relay;
 use libp2p::swarm::behaviour::toggle::Toggle;
-use libp2p::NetworkBehaviour;
+use libp2p::swarm::NetworkBehaviour;
 use libp2p::{autonat, dcutr};
 use tracing::{info, warn};
 
+pub(crate) use self::dht_client::{DhtClient, DhtRequest, DhtResponse};
 pub(crate) use self::event::Event;
+use self::fetch::Fetch;
+pub(crate) use self::fetch::{FetchRequest, FetchResponse};
+pub(crate) use self::kad_store::KadStore;
+use self::kad_store::RocksStore;
+pub(crate) use self::peer_manager::Info as PeerInfo;
 use self::peer_manager::PeerManager;
 use crate::config::Libp2pConfig;
 
+pub(crate) mod dht_client;
 mod event;
+mod fetch;
+pub(crate) mod kad_store;
 mod peer_manager;
 
 /// Libp2p behaviour for the node.
 #[derive(NetworkBehaviour)]
-#[behaviour(out_event = "Event", event_process = false)]
+#[behaviour(out_event = "Event")]
 pub(crate) struct NodeBehaviour {
     ping: Ping,
     identify: Identify,
     pub(crate) bitswap: Toggle<Bitswap<BitswapStore>>,
-    pub(crate) kad: Toggle<Kademlia<MemoryStore>>,
+    pub(crate) kad: Toggle<Kademlia<KadStore>>,
+    /// Direct requests to DHT servers, for the accelerated DHT client.
+    pub(crate) dht_client: Toggle<DhtClient>,
     mdns: Toggle<Mdns>,
     pub(crate) autonat: Toggle<autonat::Behaviour>,
     relay: Toggle<relay::v2::relay::Relay>,
     relay_client: Toggle<relay::v2::client::Client>,
     dcutr: Toggle<dcutr::behaviour::Behaviour>,
     pub(crate) gossipsub: Toggle<Gossipsub>,
+    /// Answers requests for IPNS records, which are shared over gossipsub.
+    pub(crate) fetch: Toggle<Fetch>,
     pub(crate) peer_manager: PeerManager,
 }
 
@@ -81,6 +95,7 @@ impl NodeBehaviour {
     pub async fn new(
         local_key: &Keypair,
         config: &Libp2pConfig,
+        kad_store_path: Option<&Path>,
         relay_client: Option<relay::v2::client::Client>,
         rpc_client: Client,
     ) -> Result<Self> {
@@ -99,7 +114,7 @@ impl NodeBehaviour {
 
         let mdns = if config.mdns {
             info!("init mdns");
-            Some(Mdns::new(Default::default()).await?)
+            Some(Mdns::new(Default::default())?)
         } else {
             None
         }
@@ -107,20 +122,45 @@ impl NodeBehaviour {
 
         let kad = if config.kademlia {
             info!("init kademlia");
-            // TODO: persist to store
-            let mem_store_config = MemoryStoreConfig {
-                // enough for >10gb of unixfs files at the default chunk size
-                max_records: 1024 * 64,
-                max_provided_keys: 1024 * 64,
-                ..Default::default()
+            let store_config = MemoryStoreConfig {
+                max_records: config.kad_max_records,
+                max_value_bytes: config.kad_max_value_bytes,
+                max_providers_per_key: config.kad_max_providers_per_key,
+                max_provided_keys: config.kad_max_provided_keys,
+            };
+            let store = match kad_store_path {
+                Some(path) => {
+                    let path = path.to_path_buf();
+                    let store = tokio::task::spawn_blocking(move || {
+                        RocksStore::open(&path, peer_id, store_config)
+                            .with_context(|| format!("failed to open kad store {}", path.display()))
+                    })
+                    .await??;
+                    KadStore::RocksDb(store)
+                }
+                None => KadStore::Memory(MemoryStore::with_config(peer_id, store_config)),
             };
-            let store = MemoryStore::with_config(peer_id, mem_store_config);
 
             // TODO: make user configurable
             let mut kad_config = KademliaConfig::default();
             kad_config.set_parallelism(16usize.try_into().unwrap());
             // TODO: potentially lower (this is per query)
             kad_config.set_query_timeout(Duration::from_secs(60));
+            kad_config.set_record_ttl(Some(Duration::from_secs(config.kad_record_ttl)));
+            kad_config
+                .set_provider_record_ttl(Some(Duration::from_secs(config.kad_provider_record_ttl)));
+            kad_config.set_publication_interval(
+                (config.kad_publication_interval > 0)
+                    .then(|| Duration::from_secs(config.kad_publication_interval)),
+            );
+            // the reprovider or the accelerated client announce the provider records
+            // themselves, in batches
+            kad_config.set_provider_publication_interval(
+                (config.kad_provider_publication_interval > 0
+                    && config.reprovide_interval == 0
+                    && !config.accelerated_dht)
+                    .then(|| Duration::from_secs(config.kad_provider_publication_interval)),
+            );
 
             let mut kademlia = Kademlia::with_config(pub_key.to_peer_id(), store, kad_config);
             for multiaddr in &config.bootstrap_peers {
@@ -145,6 +185,14 @@ impl NodeBehaviour {
         }
         .into();
 
+        let dht_client = if config.kademlia && config.accelerated_dht {
+            info!("init accelerated dht client");
+            Some(dht_client::new())
+        } else {
+            None
+        }
+        .into();
+
         let autonat = if config.autonat {
             info!("init autonat");
             let pub_key = local_key.public();
@@ -189,18 +237,21 @@ impl NodeBehaviour {
             Identify::new(config)
         };
 
-        let gossipsub = if config.gossipsub {
+        let (gossipsub, fetch) = if config.gossipsub {
             info!("init gossipsub");
-            let gossipsub_config = GossipsubConfig::default();
+            // the node reports whether messages are valid, IPNS records are
+            // only forwarded once they are decoded and checked
+            let gossipsub_config = GossipsubConfigBuilder::default()
+                .validate_messages()
+                .build()
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
             let message_authenticity = MessageAuthenticity::Signed(local_key.clone());
-            Some(
-                Gossipsub::new(message_authenticity, gossipsub_config)
-                    .map_err(|e| anyhow::anyhow!("{}", e))?,
-            )
+            let gossipsub = Gossipsub::new(message_authenticity, gossipsub_config)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            (Some(gossipsub), Some(fetch::new()))
         } else {
-            None
-        }
-        .into();
+            (None, None)
+        };
 
         Ok(NodeBehaviour {
             ping: Ping::default(),
@@ -208,11 +259,13 @@ impl NodeBehaviour {
             bitswap,
             mdns,
             kad,
+            dht_client,
             autonat,
             relay,
             dcutr: dcutr.into(),
             relay_client: relay_client.into(),
-            gossipsub,
+            gossipsub: gossipsub.into(),
+            fetch: fetch.into(),
             peer_manager,
         })
     }
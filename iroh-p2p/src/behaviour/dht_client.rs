@@ -0,0 +1,300 @@
+//! Sends single kademlia requests to chosen peers, used by the accelerated DHT client to
+//! query the closest peers of its own routing table directly instead of walking the DHT.
+
+use std::io;
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::kad::record::Key;
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+};
+use libp2p::{Multiaddr, PeerId};
+use prost::Message;
+
+mod dht_pb {
+    #![allow(clippy::all)]
+    include!(concat!(env!("OUT_DIR"), "/dht_pb.rs"));
+}
+
+use dht_pb::message::{ConnectionType, MessageType, Peer};
+
+const PROTOCOL_NAME: &[u8] = b"/ipfs/kad/1.0.0";
+/// Responses carry up to 20 peers with all their addresses.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+pub(crate) type DhtClient = RequestResponse<DhtCodec>;
+
+pub(crate) fn new() -> DhtClient {
+    // inbound requests are answered by kademlia itself
+    RequestResponse::new(
+        DhtCodec::default(),
+        [(DhtProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhtRequest {
+    /// Asks for the peers closest to `key` the remote knows about.
+    FindNode {
+        key: Vec<u8>,
+    },
+    GetProviders {
+        key: Key,
+    },
+    /// Announces `provider` for `key`, the remote does not answer.
+    AddProvider {
+        key: Key,
+        provider: PeerId,
+        addrs: Vec<Multiaddr>,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DhtResponse {
+    pub closer_peers: Vec<DhtPeer>,
+    pub providers: Vec<DhtPeer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhtPeer {
+    pub peer_id: PeerId,
+    pub addrs: Vec<Multiaddr>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DhtProtocol;
+
+impl ProtocolName for DhtProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+/// A codec is cloned for every request, so it remembers whether the request it wrote
+/// gets an answer.
+#[derive(Debug, Clone)]
+pub(crate) struct DhtCodec {
+    expects_response: bool,
+}
+
+impl Default for DhtCodec {
+    fn default() -> Self {
+        DhtCodec {
+            expects_response: true,
+        }
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn encode_peer(peer: DhtPeer) -> Peer {
+    Peer {
+        id: peer.peer_id.to_bytes(),
+        addrs: peer.addrs.iter().map(|a| a.to_vec()).collect(),
+        connection: ConnectionType::NotConnected as i32,
+    }
+}
+
+/// Peers that fail to decode are skipped, as are addresses.
+fn decode_peers(peers: Vec<Peer>) -> Vec<DhtPeer> {
+    peers
+        .into_iter()
+        .filter_map(|peer| {
+            Some(DhtPeer {
+                peer_id: PeerId::from_bytes(&peer.id).ok()?,
+                addrs: peer
+                    .addrs
+                    .into_iter()
+                    .filter_map(|addr| Multiaddr::try_from(addr).ok())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl RequestResponseCodec for DhtCodec {
+    type Protocol = DhtProtocol;
+    type Request = DhtRequest;
+    type Response = DhtResponse;
+
+    async fn read_request<T>(&mut self, _: &DhtProtocol, io: &mut T) -> io::Result<DhtRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        let msg = dht_pb::Message::decode(&data[..]).map_err(invalid_data)?;
+        match MessageType::from_i32(msg.r#type) {
+            Some(MessageType::FindNode) => Ok(DhtRequest::FindNode { key: msg.key }),
+            Some(MessageType::GetProviders) => Ok(DhtRequest::GetProviders {
+                key: Key::from(msg.key),
+            }),
+            Some(MessageType::AddProvider) => {
+                let provider = decode_peers(msg.provider_peers)
+                    .pop()
+                    .ok_or_else(|| invalid_data("missing provider"))?;
+                Ok(DhtRequest::AddProvider {
+                    key: Key::from(msg.key),
+                    provider: provider.peer_id,
+                    addrs: provider.addrs,
+                })
+            }
+            _ => Err(invalid_data("unsupported message type")),
+        }
+    }
+
+    async fn read_response<T>(&mut self, _: &DhtProtocol, io: &mut T) -> io::Result<DhtResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        if !self.expects_response {
+            return Ok(DhtResponse::default());
+        }
+        let data = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        let msg = dht_pb::Message::decode(&data[..]).map_err(invalid_data)?;
+        Ok(DhtResponse {
+            closer_peers: decode_peers(msg.closer_peers),
+            providers: decode_peers(msg.provider_peers),
+        })
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &DhtProtocol,
+        io: &mut T,
+        req: DhtRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let msg = match req {
+            DhtRequest::FindNode { key } => dht_pb::Message {
+                r#type: MessageType::FindNode as i32,
+                key,
+                ..Default::default()
+            },
+            DhtRequest::GetProviders { key } => dht_pb::Message {
+                r#type: MessageType::GetProviders as i32,
+                key: key.to_vec(),
+                ..Default::default()
+            },
+            DhtRequest::AddProvider {
+                key,
+                provider,
+                addrs,
+            } => {
+                self.expects_response = false;
+                dht_pb::Message {
+                    r#type: MessageType::AddProvider as i32,
+                    key: key.to_vec(),
+                    provider_peers: vec![encode_peer(DhtPeer {
+                        peer_id: provider,
+                        addrs,
+                    })],
+                    ..Default::default()
+                }
+            }
+        };
+        write_length_prefixed(io, msg.encode_to_vec()).await?;
+        if !self.expects_response {
+            io.close().await?;
+        }
+        Ok(())
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &DhtProtocol,
+        io: &mut T,
+        res: DhtResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let msg = dht_pb::Message {
+            closer_peers: res.closer_peers.into_iter().map(encode_peer).collect(),
+            provider_peers: res.providers.into_iter().map(encode_peer).collect(),
+            ..Default::default()
+        };
+        write_length_prefixed(io, msg.encode_to_vec()).await?;
+        io.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+    use libp2p::identity::Keypair;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dht_codec_roundtrip() {
+        let peer = DhtPeer {
+            peer_id: Keypair::generate_ed25519().public().to_peer_id(),
+            addrs: vec!["/ip4/192.168.1.10/tcp/4444".parse().unwrap()],
+        };
+        let key = Key::new(b"some-cid");
+
+        for req in [
+            DhtRequest::FindNode {
+                key: peer.peer_id.to_bytes(),
+            },
+            DhtRequest::GetProviders { key: key.clone() },
+            DhtRequest::AddProvider {
+                key: key.clone(),
+                provider: peer.peer_id,
+                addrs: peer.addrs.clone(),
+            },
+        ] {
+            let mut codec = DhtCodec::default();
+            let mut buf = Vec::new();
+            codec
+                .write_request(&DhtProtocol, &mut Cursor::new(&mut buf), req.clone())
+                .await
+                .unwrap();
+            let decoded = DhtCodec::default()
+                .read_request(&DhtProtocol, &mut Cursor::new(&buf))
+                .await
+                .unwrap();
+            assert_eq!(decoded, req);
+            assert_eq!(
+                codec.expects_response,
+                !matches!(req, DhtRequest::AddProvider { .. })
+            );
+        }
+
+        let res = DhtResponse {
+            closer_peers: vec![peer.clone()],
+            providers: vec![peer],
+        };
+        let mut buf = Vec::new();
+        DhtCodec::default()
+            .write_response(&DhtProtocol, &mut Cursor::new(&mut buf), res.clone())
+            .await
+            .unwrap();
+        let decoded = DhtCodec::default()
+            .read_response(&DhtProtocol, &mut Cursor::new(&buf))
+            .await
+            .unwrap();
+        assert_eq!(decoded, res);
+
+        // announcements are not answered
+        let mut codec = DhtCodec {
+            expects_response: false,
+        };
+        let decoded = codec
+            .read_response(&DhtProtocol, &mut Cursor::new(Vec::<u8>::new()))
+            .await
+            .unwrap();
+        assert_eq!(decoded, DhtResponse::default());
+    }
+}
@@ -1,9 +1,12 @@
 use iroh_bitswap::BitswapEvent;
 use libp2p::{
-    autonat, dcutr, gossipsub::GossipsubEvent, identify::IdentifyEvent, kad::KademliaEvent,
-    mdns::MdnsEvent, ping::Event as PingEvent, relay,
+    autonat, dcutr, gossipsub::GossipsubEvent, identify::Event as IdentifyEvent,
+    kad::KademliaEvent, mdns::Event as MdnsEvent, ping::Event as PingEvent, relay,
+    request_response::RequestResponseEvent,
 };
 
+use super::dht_client::{DhtRequest, DhtResponse};
+use super::fetch::{FetchRequest, FetchResponse};
 use super::peer_manager::PeerManagerEvent;
 
 /// Event type which is emitted from the [`NodeBehaviour`].
@@ -19,6 +22,8 @@ pub enum Event {
     RelayClient(relay::v2::client::Event),
     Dcutr(dcutr::behaviour::Event),
     Gossipsub(GossipsubEvent),
+    Fetch(RequestResponseEvent<FetchRequest, FetchResponse>),
+    DhtClient(RequestResponseEvent<DhtRequest, DhtResponse>),
     PeerManager(PeerManagerEvent),
 }
 
@@ -87,3 +92,15 @@ impl From<PeerManagerEvent> for Event {
         Event::PeerManager(event)
     }
 }
+
+impl From<RequestResponseEvent<FetchRequest, FetchResponse>> for Event {
+    fn from(event: RequestResponseEvent<FetchRequest, FetchResponse>) -> Self {
+        Event::Fetch(event)
+    }
+}
+
+impl From<RequestResponseEvent<DhtRequest, DhtResponse>> for Event {
+    fn from(event: RequestResponseEvent<DhtRequest, DhtResponse>) -> Self {
+        Event::DhtClient(event)
+    }
+}
@@ -0,0 +1,172 @@
+//! The libp2p fetch protocol, used by IPNS over PubSub to get the current record
+//! from peers that join a topic. See <https://github.com/libp2p/specs/tree/master/fetch>.
+
+use std::io;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+};
+use prost::Message;
+
+mod fetch_pb {
+    #![allow(clippy::all)]
+    include!(concat!(env!("OUT_DIR"), "/fetch_pb.rs"));
+}
+
+use fetch_pb::fetch_response::StatusCode;
+
+const PROTOCOL_NAME: &[u8] = b"/libp2p/fetch/0.0.1";
+/// Records are limited to 10KiB by the IPNS spec, leave room for the framing.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+pub(crate) type Fetch = RequestResponse<FetchCodec>;
+
+pub(crate) fn new() -> Fetch {
+    RequestResponse::new(
+        FetchCodec,
+        [(FetchProtocol, ProtocolSupport::Full)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Asks for the value stored under `identifier`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchRequest {
+    pub identifier: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchResponse {
+    Found(Bytes),
+    NotFound,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FetchProtocol;
+
+impl ProtocolName for FetchProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FetchCodec;
+
+#[async_trait]
+impl RequestResponseCodec for FetchCodec {
+    type Protocol = FetchProtocol;
+    type Request = FetchRequest;
+    type Response = FetchResponse;
+
+    async fn read_request<T>(&mut self, _: &FetchProtocol, io: &mut T) -> io::Result<FetchRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        let req = fetch_pb::FetchRequest::decode(&data[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(FetchRequest {
+            identifier: req.identifier,
+        })
+    }
+
+    async fn read_response<T>(&mut self, _: &FetchProtocol, io: &mut T) -> io::Result<FetchResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        let res = fetch_pb::FetchResponse::decode(&data[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let res = match StatusCode::from_i32(res.status) {
+            Some(StatusCode::Ok) => FetchResponse::Found(res.data),
+            Some(StatusCode::NotFound) => FetchResponse::NotFound,
+            Some(StatusCode::Error) | None => FetchResponse::Error,
+        };
+        Ok(res)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &FetchProtocol,
+        io: &mut T,
+        req: FetchRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let req = fetch_pb::FetchRequest {
+            identifier: req.identifier,
+        };
+        write_length_prefixed(io, req.encode_to_vec()).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &FetchProtocol,
+        io: &mut T,
+        res: FetchResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let (status, data) = match res {
+            FetchResponse::Found(data) => (StatusCode::Ok, data),
+            FetchResponse::NotFound => (StatusCode::NotFound, Bytes::new()),
+            FetchResponse::Error => (StatusCode::Error, Bytes::new()),
+        };
+        let res = fetch_pb::FetchResponse {
+            status: status as i32,
+            data,
+        };
+        write_length_prefixed(io, res.encode_to_vec()).await?;
+        io.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_codec_roundtrip() {
+        let req = FetchRequest {
+            identifier: b"/ipns/\x00\x24".to_vec(),
+        };
+        let mut buf = Vec::new();
+        FetchCodec
+            .write_request(&FetchProtocol, &mut Cursor::new(&mut buf), req.clone())
+            .await
+            .unwrap();
+        let decoded = FetchCodec
+            .read_request(&FetchProtocol, &mut Cursor::new(&buf))
+            .await
+            .unwrap();
+        assert_eq!(decoded, req);
+
+        for res in [
+            FetchResponse::Found(Bytes::from_static(b"record")),
+            FetchResponse::NotFound,
+            FetchResponse::Error,
+        ] {
+            let mut buf = Vec::new();
+            FetchCodec
+                .write_response(&FetchProtocol, &mut Cursor::new(&mut buf), res.clone())
+                .await
+                .unwrap();
+            let decoded = FetchCodec
+                .read_response(&FetchProtocol, &mut Cursor::new(&buf))
+                .await
+                .unwrap();
+            assert_eq!(decoded, res);
+        }
+    }
+}
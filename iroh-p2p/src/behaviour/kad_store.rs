@@ -0,0 +1,641 @@
+//! Kademlia record store keeping value and provider records on disk, so a long running node
+//! keeps serving them across restarts and is not bounded by memory.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use libp2p::kad::record::Key;
+use libp2p::kad::store::{self, MemoryStore, MemoryStoreConfig, RecordStore};
+use libp2p::kad::{ProviderRecord, Record};
+use libp2p::{Multiaddr, PeerId};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB as RocksDb};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+/// Value records, keyed by the record key.
+const CF_RECORDS_V0: &str = "kad-records-v0";
+/// Provider records of all peers, keyed by [`provider_key`].
+const CF_PROVIDERS_V0: &str = "kad-providers-v0";
+/// Provider records of the local peer, keyed by the record key.
+const CF_PROVIDED_V0: &str = "kad-provided-v0";
+/// Number of entries checked by a single call to [`KadStore::remove_expired`].
+const GC_BATCH_SIZE: usize = 1024;
+
+/// The record store used by kademlia, on disk if the node has a path to keep it at.
+pub(crate) enum KadStore {
+    Memory(MemoryStore),
+    RocksDb(RocksStore),
+}
+
+impl KadStore {
+    /// Whether the records survive a restart.
+    pub fn is_persistent(&self) -> bool {
+        matches!(self, KadStore::RocksDb(_))
+    }
+
+    /// Drops the expired records of the next batch of entries. Kademlia only removes
+    /// expired value records while republishing, provider records of other peers would
+    /// otherwise be kept forever.
+    ///
+    /// Only [`GC_BATCH_SIZE`] entries are checked per call, so the swarm is not blocked
+    /// by a scan of the whole store. Repeated calls cycle through all entries.
+    pub fn remove_expired(&mut self) {
+        match self {
+            // expired entries are filtered out on read
+            KadStore::Memory(_) => {}
+            KadStore::RocksDb(store) => {
+                if let Err(err) = store.remove_expired(GC_BATCH_SIZE) {
+                    warn!("failed to remove expired kad records: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> RecordStore<'a> for KadStore {
+    type RecordsIter = Box<dyn Iterator<Item = Cow<'a, Record>> + 'a>;
+    type ProvidedIter = Box<dyn Iterator<Item = Cow<'a, ProviderRecord>> + 'a>;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'_, Record>> {
+        match self {
+            KadStore::Memory(store) => store.get(k),
+            KadStore::RocksDb(store) => store.get(k),
+        }
+    }
+
+    fn put(&'a mut self, r: Record) -> store::Result<()> {
+        match self {
+            KadStore::Memory(store) => store.put(r),
+            KadStore::RocksDb(store) => store.put(r),
+        }
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        match self {
+            KadStore::Memory(store) => store.remove(k),
+            KadStore::RocksDb(store) => store.remove(k),
+        }
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        match self {
+            KadStore::Memory(store) => Box::new(store.records()),
+            KadStore::RocksDb(store) => store.records(),
+        }
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> store::Result<()> {
+        match self {
+            KadStore::Memory(store) => store.add_provider(record),
+            KadStore::RocksDb(store) => store.add_provider(record),
+        }
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        match self {
+            KadStore::Memory(store) => store.providers(key),
+            KadStore::RocksDb(store) => store.providers(key),
+        }
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        match self {
+            KadStore::Memory(store) => Box::new(store.provided()),
+            KadStore::RocksDb(store) => store.provided(),
+        }
+    }
+
+    fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+        match self {
+            KadStore::Memory(store) => store.remove_provider(k, p),
+            KadStore::RocksDb(store) => store.remove_provider(k, p),
+        }
+    }
+}
+
+/// Record store backed by RocksDB, enforcing the same limits as the [`MemoryStore`].
+///
+/// Kademlia drives its store synchronously from the swarm, so all database access blocks.
+pub(crate) struct RocksStore {
+    db: RocksDb,
+    local_peer_id: PeerId,
+    config: MemoryStoreConfig,
+    /// Number of value records.
+    records: usize,
+    /// Number of keys provided by the local peer.
+    provided: usize,
+    /// Column family in which `remove_expired` continues.
+    gc_cf: &'static str,
+    /// Key from which `remove_expired` continues.
+    gc_from: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    value: Vec<u8>,
+    publisher: Option<Vec<u8>>,
+    expires: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredProvider {
+    addrs: Vec<Vec<u8>>,
+    expires: Option<u64>,
+}
+
+impl RocksStore {
+    /// Opens the store at `path`, creating it if it does not exist yet.
+    pub fn open(path: &Path, local_peer_id: PeerId, config: MemoryStoreConfig) -> Result<Self> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = RocksDb::open_cf(
+            &options,
+            path,
+            [CF_RECORDS_V0, CF_PROVIDERS_V0, CF_PROVIDED_V0],
+        )?;
+
+        let mut store = RocksStore {
+            db,
+            local_peer_id,
+            config,
+            records: 0,
+            provided: 0,
+            gc_cf: CF_RECORDS_V0,
+            gc_from: Vec::new(),
+        };
+        store.records = store.count(CF_RECORDS_V0)?;
+        store.provided = store.count(CF_PROVIDED_V0)?;
+        Ok(store)
+    }
+
+    fn cf(&self, name: &str) -> &ColumnFamily {
+        self.db
+            .cf_handle(name)
+            .expect("the store is opened with all column families")
+    }
+
+    fn count(&self, cf: &str) -> Result<usize> {
+        let mut count = 0;
+        for entry in self.db.iterator_cf(self.cf(cf), IteratorMode::Start) {
+            entry?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn contains(&self, cf: &str, key: &[u8]) -> Result<bool, rocksdb::Error> {
+        Ok(self.db.get_pinned_cf(self.cf(cf), key)?.is_some())
+    }
+
+    /// Iterates over the entries of the column family whose key starts with `prefix`.
+    fn prefix_iter<'a>(
+        &'a self,
+        cf: &str,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a {
+        self.db
+            .iterator_cf(self.cf(cf), IteratorMode::From(prefix, Direction::Forward))
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    warn!("failed to read kad store: {:?}", err);
+                    None
+                }
+            })
+            .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    fn get(&self, k: &Key) -> Option<Cow<'_, Record>> {
+        let value = match self.db.get_pinned_cf(self.cf(CF_RECORDS_V0), k) {
+            Ok(value) => value?,
+            Err(err) => {
+                warn!("failed to read kad record: {:?}", err);
+                return None;
+            }
+        };
+        decode_record(k.to_vec(), &value).map(Cow::Owned)
+    }
+
+    fn put(&mut self, r: Record) -> store::Result<()> {
+        if r.value.len() >= self.config.max_value_bytes {
+            return Err(store::Error::ValueTooLarge);
+        }
+        let exists = self
+            .contains(CF_RECORDS_V0, r.key.as_ref())
+            .map_err(|err| io_error(err, store::Error::MaxProvidedKeys))?;
+        if !exists && self.records >= self.config.max_records {
+            return Err(store::Error::MaxRecords);
+        }
+
+        let stored = StoredRecord {
+            value: r.value,
+            publisher: r.publisher.map(|p| p.to_bytes()),
+            expires: r.expires.map(expires_to_unix),
+        };
+        let value = bincode::serialize(&stored).expect("records serialize");
+        self.db
+            .put_cf(self.cf(CF_RECORDS_V0), &r.key, value)
+            .map_err(|err| io_error(err, store::Error::MaxProvidedKeys))?;
+        if !exists {
+            self.records += 1;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &Key) {
+        let res = self.contains(CF_RECORDS_V0, k.as_ref()).and_then(|exists| {
+            if exists {
+                self.db.delete_cf(self.cf(CF_RECORDS_V0), k)?;
+                self.records -= 1;
+            }
+            Ok(())
+        });
+        if let Err(err) = res {
+            warn!("failed to remove kad record: {:?}", err);
+        }
+    }
+
+    fn records(&self) -> Box<dyn Iterator<Item = Cow<'_, Record>> + '_> {
+        Box::new(
+            self.prefix_iter(CF_RECORDS_V0, &[])
+                .filter_map(|(key, value)| decode_record(key.into_vec(), &value))
+                .map(Cow::Owned),
+        )
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = provider_key(&record.key, &record.provider);
+        let exists = self
+            .contains(CF_PROVIDERS_V0, &key)
+            .map_err(|err| io_error(err, store::Error::MaxRecords))?;
+        let local = record.provider == self.local_peer_id;
+        if !exists {
+            if local {
+                if self.provided >= self.config.max_provided_keys {
+                    return Err(store::Error::MaxProvidedKeys);
+                }
+            } else if self.providers_count(&record.key) >= self.config.max_providers_per_key {
+                // same as the memory store, the key is well provided already
+                return Ok(());
+            }
+        }
+
+        let stored = StoredProvider {
+            addrs: record.addresses.iter().map(|a| a.to_vec()).collect(),
+            expires: record.expires.map(expires_to_unix),
+        };
+        let value = bincode::serialize(&stored).expect("records serialize");
+        let mut batch = WriteBatch::default();
+        batch.put_cf(self.cf(CF_PROVIDERS_V0), key, &value);
+        if local {
+            batch.put_cf(self.cf(CF_PROVIDED_V0), &record.key, &value);
+        }
+        self.db
+            .write(batch)
+            .map_err(|err| io_error(err, store::Error::MaxRecords))?;
+        if local && !exists {
+            self.provided += 1;
+        }
+        Ok(())
+    }
+
+    fn providers_count(&self, key: &Key) -> usize {
+        let prefix = provider_prefix(key);
+        self.prefix_iter(CF_PROVIDERS_V0, &prefix).count()
+    }
+
+    fn providers(&self, key: &Key) -> Vec<ProviderRecord> {
+        let prefix = provider_prefix(key);
+        self.prefix_iter(CF_PROVIDERS_V0, &prefix)
+            .filter_map(|(k, value)| {
+                let provider = PeerId::from_bytes(&k[prefix.len()..]).ok()?;
+                decode_provider(key.clone(), provider, &value)
+            })
+            .collect()
+    }
+
+    fn provided(&self) -> Box<dyn Iterator<Item = Cow<'_, ProviderRecord>> + '_> {
+        Box::new(
+            self.prefix_iter(CF_PROVIDED_V0, &[])
+                .filter_map(|(key, value)| {
+                    decode_provider(Key::from(key.into_vec()), self.local_peer_id, &value)
+                })
+                .map(Cow::Owned),
+        )
+    }
+
+    fn remove_provider(&mut self, k: &Key, p: &PeerId) {
+        let key = provider_key(k, p);
+        let res = self.contains(CF_PROVIDERS_V0, &key).and_then(|exists| {
+            if !exists {
+                return Ok(());
+            }
+            let mut batch = WriteBatch::default();
+            batch.delete_cf(self.cf(CF_PROVIDERS_V0), key);
+            if p == &self.local_peer_id {
+                batch.delete_cf(self.cf(CF_PROVIDED_V0), k);
+            }
+            self.db.write(batch)?;
+            if p == &self.local_peer_id {
+                self.provided -= 1;
+            }
+            Ok(())
+        });
+        if let Err(err) = res {
+            warn!("failed to remove kad provider record: {:?}", err);
+        }
+    }
+
+    /// Removes the expired entries among the next `limit` entries, continuing where the
+    /// previous call stopped. Once the value records are done the provider records are
+    /// checked, and the other way around.
+    fn remove_expired(&mut self, limit: usize) -> Result<()> {
+        let now = unix_now();
+        let is_expired = |expires: Option<u64>| matches!(expires, Some(expires) if expires <= now);
+        let cf = self.gc_cf;
+
+        let mut batch = WriteBatch::default();
+        let mut expired = 0;
+        let mut checked = 0;
+        let mut next = None;
+        let iter = self.db.iterator_cf(
+            self.cf(cf),
+            IteratorMode::From(&self.gc_from, Direction::Forward),
+        );
+        for entry in iter {
+            let (key, value) = entry?;
+            if checked == limit {
+                next = Some(key.into_vec());
+                break;
+            }
+            checked += 1;
+            // provider records of the local peer do not expire, they are republished instead
+            let expires = if cf == CF_RECORDS_V0 {
+                bincode::deserialize::<StoredRecord>(&value).map(|record| record.expires)
+            } else {
+                bincode::deserialize::<StoredProvider>(&value).map(|provider| provider.expires)
+            };
+            match expires {
+                Ok(expires) if !is_expired(expires) => {}
+                _ => {
+                    batch.delete_cf(self.cf(cf), key);
+                    expired += 1;
+                }
+            }
+        }
+        self.db.write(batch)?;
+        if cf == CF_RECORDS_V0 {
+            self.records -= expired;
+        }
+
+        match next {
+            Some(key) => self.gc_from = key,
+            None => {
+                self.gc_cf = if cf == CF_RECORDS_V0 {
+                    CF_PROVIDERS_V0
+                } else {
+                    CF_RECORDS_V0
+                };
+                self.gc_from = Vec::new();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The kad store errors have no variant for io failures. They are logged and reported as
+/// `reported`, a variant the failed operation does not return otherwise, so they can be told
+/// apart from the store hitting one of its limits.
+fn io_error(err: rocksdb::Error, reported: store::Error) -> store::Error {
+    error!("failed to access kad store: {:?}", err);
+    reported
+}
+
+/// Provider records are keyed by the length of the record key, the record key and
+/// the provider, so all providers of a key are next to each other.
+fn provider_prefix(key: &Key) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(4 + key.as_ref().len());
+    prefix.extend_from_slice(&(key.as_ref().len() as u32).to_be_bytes());
+    prefix.extend_from_slice(key.as_ref());
+    prefix
+}
+
+fn provider_key(key: &Key, provider: &PeerId) -> Vec<u8> {
+    let mut k = provider_prefix(key);
+    k.extend_from_slice(&provider.to_bytes());
+    k
+}
+
+fn decode_record(key: Vec<u8>, value: &[u8]) -> Option<Record> {
+    let stored: StoredRecord = bincode::deserialize(value).ok()?;
+    let publisher = match stored.publisher {
+        Some(publisher) => Some(PeerId::from_bytes(&publisher).ok()?),
+        None => None,
+    };
+    let expires = match stored.expires {
+        Some(expires) => Some(expires_from_unix(expires)?),
+        None => None,
+    };
+    Some(Record {
+        key: Key::from(key),
+        value: stored.value,
+        publisher,
+        expires,
+    })
+}
+
+fn decode_provider(key: Key, provider: PeerId, value: &[u8]) -> Option<ProviderRecord> {
+    let stored: StoredProvider = bincode::deserialize(value).ok()?;
+    let expires = match stored.expires {
+        Some(expires) => Some(expires_from_unix(expires)?),
+        None => None,
+    };
+    Some(ProviderRecord {
+        key,
+        provider,
+        expires,
+        addresses: stored
+            .addrs
+            .into_iter()
+            .filter_map(|addr| Multiaddr::try_from(addr).ok())
+            .collect(),
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Converts an expiry into seconds since the unix epoch, `Instant`s do not survive a restart.
+pub(crate) fn expires_to_unix(expires: Instant) -> u64 {
+    let remaining = expires.saturating_duration_since(Instant::now());
+    (SystemTime::now() + remaining)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Converts seconds since the unix epoch back into an expiry, `None` if it passed already.
+pub(crate) fn expires_from_unix(expires: u64) -> Option<Instant> {
+    let now = unix_now();
+    if expires <= now {
+        return None;
+    }
+    Some(Instant::now() + Duration::from_secs(expires - now))
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+
+    use super::*;
+
+    fn random_peer_id() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_rocks_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = random_peer_id();
+        let config = MemoryStoreConfig {
+            max_records: 2,
+            max_value_bytes: 16,
+            max_providers_per_key: 2,
+            max_provided_keys: 1,
+        };
+        let mut store = RocksStore::open(dir.path(), local, config.clone()).unwrap();
+
+        // value records
+        let record = Record::new(Key::new(b"a"), b"hello".to_vec());
+        store.put(record.clone()).unwrap();
+        assert_eq!(store.get(&record.key).unwrap().into_owned(), record);
+        // replacing a record does not count against the limit
+        store.put(record.clone()).unwrap();
+        store
+            .put(Record::new(Key::new(b"b"), b"world".to_vec()))
+            .unwrap();
+        assert!(matches!(
+            store.put(Record::new(Key::new(b"c"), b"full".to_vec())),
+            Err(store::Error::MaxRecords)
+        ));
+        assert!(matches!(
+            store.put(Record::new(Key::new(b"a"), vec![0u8; 16])),
+            Err(store::Error::ValueTooLarge)
+        ));
+        store.remove(&Key::new(b"b"));
+        assert!(store.get(&Key::new(b"b")).is_none());
+        assert_eq!(store.records().count(), 1);
+
+        // provider records
+        let addr: Multiaddr = "/ip4/192.168.1.10/tcp/4444".parse().unwrap();
+        let key = Key::new(b"some-cid");
+        let provided = ProviderRecord::new(key.clone(), local, vec![addr.clone()]);
+        store.add_provider(provided.clone()).unwrap();
+        assert!(matches!(
+            store.add_provider(ProviderRecord::new(Key::new(b"other-cid"), local, vec![])),
+            Err(store::Error::MaxProvidedKeys)
+        ));
+        let remote = ProviderRecord {
+            expires: Some(Instant::now() + Duration::from_secs(3600)),
+            ..ProviderRecord::new(key.clone(), random_peer_id(), vec![addr.clone()])
+        };
+        store.add_provider(remote.clone()).unwrap();
+        // the key has enough providers
+        store
+            .add_provider(ProviderRecord::new(key.clone(), random_peer_id(), vec![]))
+            .unwrap();
+        let expired = ProviderRecord {
+            expires: Some(Instant::now()),
+            ..ProviderRecord::new(Key::new(b"expired"), random_peer_id(), vec![])
+        };
+        store.add_provider(expired.clone()).unwrap();
+
+        let mut providers: Vec<_> = store
+            .providers(&key)
+            .into_iter()
+            .map(|p| p.provider)
+            .collect();
+        providers.sort();
+        let mut expected = vec![local, remote.provider];
+        expected.sort();
+        assert_eq!(providers, expected);
+        assert!(store.providers(&expired.key).is_empty());
+        assert_eq!(
+            store.provided().map(Cow::into_owned).collect::<Vec<_>>(),
+            vec![provided.clone()]
+        );
+
+        // everything survives a restart
+        drop(store);
+        let mut store = RocksStore::open(dir.path(), local, config).unwrap();
+        assert_eq!(store.records, 1);
+        assert_eq!(store.provided, 1);
+        assert_eq!(store.get(&record.key).unwrap().into_owned(), record);
+        assert_eq!(store.providers(&key).len(), 2);
+
+        // the value records, then the provider records
+        store.remove_expired(GC_BATCH_SIZE).unwrap();
+        store.remove_expired(GC_BATCH_SIZE).unwrap();
+        assert_eq!(store.providers(&key).len(), 2);
+        assert_eq!(store.count(CF_PROVIDERS_V0).unwrap(), 2);
+
+        store.remove_provider(&key, &local);
+        assert_eq!(store.provided().count(), 0);
+        let providers: Vec<_> = store
+            .providers(&key)
+            .into_iter()
+            .map(|p| p.provider)
+            .collect();
+        assert_eq!(providers, vec![remote.provider]);
+    }
+
+    #[test]
+    fn test_rocks_store_remove_expired_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store =
+            RocksStore::open(dir.path(), random_peer_id(), MemoryStoreConfig::default()).unwrap();
+
+        let expired = Instant::now();
+        for i in 0..4u8 {
+            let record = Record {
+                expires: (i % 2 == 0).then(|| expired),
+                ..Record::new(Key::new(&[i]), vec![i])
+            };
+            store.put(record).unwrap();
+            let provider = ProviderRecord {
+                expires: (i % 2 == 1).then(|| expired),
+                ..ProviderRecord::new(Key::new(&[i]), random_peer_id(), vec![])
+            };
+            store.add_provider(provider).unwrap();
+        }
+
+        // each call only looks at the next three entries
+        store.remove_expired(3).unwrap();
+        assert_eq!(store.records, 2);
+        assert_eq!(store.count(CF_RECORDS_V0).unwrap(), 2);
+        assert_eq!(store.count(CF_PROVIDERS_V0).unwrap(), 4);
+
+        store.remove_expired(3).unwrap();
+        assert_eq!(store.records, 2);
+        assert_eq!(store.count(CF_PROVIDERS_V0).unwrap(), 4);
+
+        store.remove_expired(3).unwrap();
+        store.remove_expired(3).unwrap();
+        assert_eq!(store.count(CF_PROVIDERS_V0).unwrap(), 2);
+        assert_eq!(store.count(CF_RECORDS_V0).unwrap(), 2);
+        for i in 0..4u8 {
+            assert_eq!(store.get(&Key::new(&[i])).is_some(), i % 2 == 1);
+            assert_eq!(
+                store.providers(&Key::new(&[i])).len(),
+                (i % 2 == 0) as usize
+            );
+        }
+    }
+}
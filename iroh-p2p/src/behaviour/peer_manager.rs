@@ -1,6 +1,6 @@
 use std::{
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use ahash::AHashMap;
@@ -8,7 +8,7 @@ use caches::{Cache, PutResult};
 use iroh_metrics::{core::MRecorder, inc, p2p::P2PMetrics};
 use libp2p::{
     core::{connection::ConnectionId, transport::ListenerId, ConnectedPoint},
-    identify::IdentifyInfo,
+    identify::Info as IdentifyInfo,
     ping::Success as PingSuccess,
     swarm::{
         handler::DummyConnectionHandler, ConnectionHandler, DialError, IntoConnectionHandler,
@@ -19,7 +19,8 @@ use libp2p::{
 
 pub struct PeerManager {
     info: AHashMap<PeerId, Info>,
-    bad_peers: caches::RawLRU<PeerId, ()>,
+    /// Peers that failed to dial, with the time they were marked bad.
+    bad_peers: caches::RawLRU<PeerId, Instant>,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -36,6 +37,8 @@ impl Info {
 }
 
 const DEFAULT_BAD_PEER_CAP: usize = 10 * 4096;
+/// Bad peers get another chance after this long, they might have just been offline.
+const BAD_PEER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
 
 impl Default for PeerManager {
     fn default() -> Self {
@@ -51,7 +54,10 @@ pub enum PeerManagerEvent {}
 
 impl PeerManager {
     pub fn is_bad_peer(&self, peer_id: &PeerId) -> bool {
-        self.bad_peers.contains(peer_id)
+        self.bad_peers
+            .peek(peer_id)
+            .map(|since| since.elapsed() < BAD_PEER_TIMEOUT)
+            .unwrap_or_default()
     }
 
     pub fn inject_identify_info(&mut self, peer_id: PeerId, new_info: IdentifyInfo) {
@@ -67,6 +73,15 @@ impl PeerManager {
     pub fn info_for_peer(&self, peer_id: &PeerId) -> Option<&Info> {
         self.info.get(peer_id)
     }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &Info)> {
+        self.info.iter()
+    }
+
+    /// Restores info persisted in an earlier run, newer info is kept.
+    pub fn restore_info(&mut self, peer_id: PeerId, info: Info) {
+        self.info.entry(peer_id).or_insert(info);
+    }
 }
 
 impl NetworkBehaviour for PeerManager {
@@ -150,7 +165,7 @@ impl NetworkBehaviour for PeerManager {
             match error {
                 DialError::ConnectionLimit(_) | DialError::DialPeerConditionFalse(_) => {}
                 _ => {
-                    if PutResult::Put == self.bad_peers.put(peer_id, ()) {
+                    if PutResult::Put == self.bad_peers.put(peer_id, Instant::now()) {
                         inc!(P2PMetrics::BadPeer);
                     }
 
@@ -1,3 +1,4 @@
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 use anyhow::{bail, Result};
@@ -8,12 +9,16 @@ use iroh_rpc_types::{
     p2p::{P2pClientAddr, P2pServerAddr},
     Addr,
 };
-use iroh_util::{insert_into_config_map, iroh_data_root};
+use iroh_util::{insert_into_config_map, iroh_data_path, iroh_data_root};
 use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
 
 /// CONFIG_FILE_NAME is the name of the optional config file located in the iroh home directory
 pub const CONFIG_FILE_NAME: &str = "p2p.config.toml";
+/// PEER_STORE_FILE_NAME is the name of the peer store located in the iroh data directory
+pub const PEER_STORE_FILE_NAME: &str = "p2p-peers";
+/// KAD_STORE_DIR_NAME is the name of the kademlia record store located in the iroh data directory
+pub const KAD_STORE_DIR_NAME: &str = "p2p-kad";
 /// ENV_PREFIX should be used along side the config field name to set a config field using
 /// environment variables
 /// For example, `IROH_P2P_MDNS=true` would set the value of the `Libp2pConfig.mdns` field
@@ -29,15 +34,89 @@ pub const DEFAULT_BOOTSTRAP: &[&str] = &[
     "/dnsaddr/bootstrap.libp2p.io/p2p/QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
     "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ", // mars.i.ipfs.io
 ];
-// no udp support yet
+// the `/quic` addresses of go-ipfs speak QUIC draft-29, the quic transport only QUIC v1
 
 // "/ip4/104.131.131.82/udp/4001/quic/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ", // mars.i.ipfs.io
 
+/// Stream multiplexers to negotiate on new connections.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Muxer {
+    Yamux,
+    Mplex,
+    /// Offer both, preferring yamux.
+    YamuxMplex,
+    /// Offer both, preferring mplex.
+    MplexYamux,
+}
+
+impl Muxer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Muxer::Yamux => "yamux",
+            Muxer::Mplex => "mplex",
+            Muxer::YamuxMplex => "yamux-mplex",
+            Muxer::MplexYamux => "mplex-yamux",
+        }
+    }
+}
+
+/// How `/dns*` multiaddrs get resolved.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnsResolver {
+    /// Use the resolvers configured for the system, e.g. in `/etc/resolv.conf`.
+    System,
+    Cloudflare,
+    Google,
+    /// Use `Libp2pConfig::dns_nameservers`.
+    Custom,
+    /// Do not resolve, dialing `/dns*` addresses fails.
+    None,
+}
+
+impl DnsResolver {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsResolver::System => "system",
+            DnsResolver::Cloudflare => "cloudflare",
+            DnsResolver::Google => "google",
+            DnsResolver::Custom => "custom",
+            DnsResolver::None => "none",
+        }
+    }
+}
+
+/// Which keys from the store the reprovider announces.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReprovideStrategy {
+    /// Every stored block.
+    All,
+    /// The named roots and every block reachable from them.
+    Pinned,
+    /// Only the named roots.
+    Roots,
+}
+
+impl ReprovideStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReprovideStrategy::All => "all",
+            ReprovideStrategy::Pinned => "pinned",
+            ReprovideStrategy::Roots => "roots",
+        }
+    }
+}
+
 /// Libp2p config for the node.
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
 pub struct Libp2pConfig {
-    /// Local address.
-    pub listening_multiaddr: Multiaddr,
+    /// Local addresses.
+    pub listening_multiaddrs: Vec<Multiaddr>,
+    /// Deprecated single local address, used instead of `listening_multiaddrs` when set,
+    /// so configs from before multiple addresses were supported keep working.
+    pub listening_multiaddr: Option<Multiaddr>,
     /// Bootstrap peer list.
     pub bootstrap_peers: Vec<Multiaddr>,
     /// Mdns discovery enabled.
@@ -62,6 +141,70 @@ pub struct Libp2pConfig {
     pub notify_handler_buffer_size: usize,
     pub connection_event_buffer_size: usize,
     pub dial_concurrency_factor: u8,
+    /// TCP transport enabled.
+    pub tcp: bool,
+    /// Websocket (over TCP) transport enabled.
+    pub websocket: bool,
+    /// QUIC transport enabled. It does not support private networks, so it is disabled
+    /// when `swarm_key` is set.
+    pub quic: bool,
+    /// Dial the QUIC addresses of a peer before its TCP and websocket addresses.
+    pub prefer_quic: bool,
+    /// Stream multiplexers to use and their preference.
+    pub muxer: Muxer,
+    pub yamux_max_buffer_size: usize,
+    pub yamux_receive_window_size: u32,
+    pub mplex_max_buffer_size: usize,
+    /// DNS resolver used for `/dns*` addresses.
+    pub dns: DnsResolver,
+    /// Nameservers used when `dns` is `custom`.
+    pub dns_nameservers: Vec<IpAddr>,
+    /// Timeout in seconds for establishing the raw transport connection.
+    pub transport_timeout: u64,
+    /// Timeout in seconds for the full connection setup, including security and muxer
+    /// negotiation.
+    pub connection_timeout: u64,
+    /// Path to a pre-shared key file in the go-ipfs `swarm.key` format. When set, the node
+    /// only connects to peers of the private network sharing this key.
+    pub swarm_key: Option<PathBuf>,
+    /// Maximum number of value records kept in the kademlia record store.
+    pub kad_max_records: usize,
+    /// Maximum size of a single value record.
+    pub kad_max_value_bytes: usize,
+    /// Maximum number of providers kept per key.
+    pub kad_max_providers_per_key: usize,
+    /// Maximum number of keys the node itself provides.
+    pub kad_max_provided_keys: usize,
+    /// Lifetime in seconds of the value records published by this node.
+    pub kad_record_ttl: u64,
+    /// Lifetime in seconds of the provider records published by this node.
+    pub kad_provider_record_ttl: u64,
+    /// Interval in seconds at which the value records published by this node are
+    /// republished, `0` disables republishing.
+    pub kad_publication_interval: u64,
+    /// Interval in seconds at which the provider records of this node are republished,
+    /// `0` disables republishing.
+    pub kad_provider_publication_interval: u64,
+    /// Crawl the whole DHT and look up and announce providers in a single hop, using
+    /// the routing table of the crawl.
+    pub accelerated_dht: bool,
+    /// Interval in seconds between crawls of the accelerated DHT client.
+    pub accelerated_dht_crawl_interval: u64,
+    /// Maximum number of requests the accelerated DHT client has in flight, including
+    /// provider lookups.
+    pub accelerated_dht_parallelism: usize,
+    /// Which keys from the store are announced again by the reprovider.
+    pub reprovide_strategy: ReprovideStrategy,
+    /// Interval in seconds between reprovider runs, `0` disables the reprovider.
+    ///
+    /// When enabled, it replaces the republishing of provider records by kademlia. Only the
+    /// keys of the store selected by `reprovide_strategy` are announced again, so keys
+    /// provided otherwise, for example by bitswap with the `pinned` or `roots` strategy,
+    /// expire from the DHT. Runs fail while the node has no store rpc connection.
+    pub reprovide_interval: u64,
+    /// Maximum number of keys announced per second by the reprovider, `0` for no limit.
+    /// Either way only a few announcements are in flight at once.
+    pub reprovide_rate_limit: u32,
 }
 
 /// Configuration for the node.
@@ -71,6 +214,12 @@ pub struct Config {
     pub rpc_client: RpcClientConfig,
     pub metrics: MetricsConfig,
     pub key_store_path: PathBuf,
+    /// Where known peers, the routing table and provider records are kept across
+    /// restarts, nothing is persisted if `None`.
+    pub peer_store_path: Option<PathBuf>,
+    /// Where the kademlia value and provider records are kept, they are only held in
+    /// memory if `None`.
+    pub kad_store_path: Option<PathBuf>,
 }
 
 impl Source for Libp2pConfig {
@@ -115,6 +264,27 @@ impl Source for Libp2pConfig {
             "dial_concurrency_factor",
             self.dial_concurrency_factor as i64,
         );
+        insert_into_config_map(
+            &mut map,
+            "yamux_max_buffer_size",
+            self.yamux_max_buffer_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "yamux_receive_window_size",
+            self.yamux_receive_window_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "mplex_max_buffer_size",
+            self.mplex_max_buffer_size as u64,
+        );
+        insert_into_config_map(&mut map, "transport_timeout", self.transport_timeout as i64);
+        insert_into_config_map(
+            &mut map,
+            "connection_timeout",
+            self.connection_timeout as i64,
+        );
 
         insert_into_config_map(&mut map, "kademlia", self.kademlia);
         insert_into_config_map(&mut map, "autonat", self.autonat);
@@ -125,10 +295,86 @@ impl Source for Libp2pConfig {
         insert_into_config_map(&mut map, "gossipsub", self.gossipsub);
         let peers: Vec<String> = self.bootstrap_peers.iter().map(|b| b.to_string()).collect();
         insert_into_config_map(&mut map, "bootstrap_peers", peers);
+        let addrs: Vec<String> = self
+            .listening_multiaddrs
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "listening_multiaddrs", addrs);
+        insert_into_config_map(&mut map, "tcp", self.tcp);
+        insert_into_config_map(&mut map, "websocket", self.websocket);
+        insert_into_config_map(&mut map, "quic", self.quic);
+        insert_into_config_map(&mut map, "prefer_quic", self.prefer_quic);
+        insert_into_config_map(&mut map, "muxer", self.muxer.as_str());
+        insert_into_config_map(&mut map, "dns", self.dns.as_str());
+        let nameservers: Vec<String> = self
+            .dns_nameservers
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "dns_nameservers", nameservers);
+        insert_into_config_map(
+            &mut map,
+            "swarm_key",
+            self.swarm_key.as_ref().and_then(|p| p.to_str()),
+        );
+        insert_into_config_map(&mut map, "kad_max_records", self.kad_max_records as i64);
+        insert_into_config_map(
+            &mut map,
+            "kad_max_value_bytes",
+            self.kad_max_value_bytes as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_max_providers_per_key",
+            self.kad_max_providers_per_key as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_max_provided_keys",
+            self.kad_max_provided_keys as i64,
+        );
+        insert_into_config_map(&mut map, "kad_record_ttl", self.kad_record_ttl as i64);
+        insert_into_config_map(
+            &mut map,
+            "kad_provider_record_ttl",
+            self.kad_provider_record_ttl as i64,
+        );
         insert_into_config_map(
             &mut map,
-            "listening_multiaddr",
-            self.listening_multiaddr.to_string(),
+            "kad_publication_interval",
+            self.kad_publication_interval as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_provider_publication_interval",
+            self.kad_provider_publication_interval as i64,
+        );
+        insert_into_config_map(&mut map, "accelerated_dht", self.accelerated_dht);
+        insert_into_config_map(
+            &mut map,
+            "accelerated_dht_crawl_interval",
+            self.accelerated_dht_crawl_interval as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "accelerated_dht_parallelism",
+            self.accelerated_dht_parallelism as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovide_strategy",
+            self.reprovide_strategy.as_str(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovide_interval",
+            self.reprovide_interval as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovide_rate_limit",
+            self.reprovide_rate_limit as i64,
         );
         Ok(map)
     }
@@ -146,10 +392,39 @@ impl Source for Config {
         insert_into_config_map(&mut map, "rpc_client", self.rpc_client.collect()?);
         insert_into_config_map(&mut map, "metrics", self.metrics.collect()?);
         insert_into_config_map(&mut map, "key_store_path", self.key_store_path.to_str());
+        insert_into_config_map(
+            &mut map,
+            "peer_store_path",
+            self.peer_store_path.as_ref().and_then(|p| p.to_str()),
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_store_path",
+            self.kad_store_path.as_ref().and_then(|p| p.to_str()),
+        );
         Ok(map)
     }
 }
 
+impl Libp2pConfig {
+    /// The addresses to listen on, without the QUIC ones when that transport is disabled.
+    pub fn listening_addrs(&self) -> Vec<Multiaddr> {
+        let addrs = match self.listening_multiaddr {
+            Some(ref addr) => vec![addr.clone()],
+            None => self.listening_multiaddrs.clone(),
+        };
+        addrs
+            .into_iter()
+            .filter(|addr| self.quic_enabled() || !crate::swarm::is_quic(addr))
+            .collect()
+    }
+
+    /// Whether the QUIC transport is used, see [`Libp2pConfig::quic`].
+    pub fn quic_enabled(&self) -> bool {
+        self.quic && self.swarm_key.is_none()
+    }
+}
+
 impl Default for Libp2pConfig {
     fn default() -> Self {
         let bootstrap_peers = DEFAULT_BOOTSTRAP
@@ -158,7 +433,11 @@ impl Default for Libp2pConfig {
             .collect();
 
         Self {
-            listening_multiaddr: "/ip4/0.0.0.0/tcp/4444".parse().unwrap(),
+            listening_multiaddrs: vec![
+                "/ip4/0.0.0.0/tcp/4444".parse().unwrap(),
+                "/ip4/0.0.0.0/udp/4444/quic".parse().unwrap(),
+            ],
+            listening_multiaddr: None,
             bootstrap_peers,
             mdns: false,
             kademlia: true,
@@ -175,6 +454,38 @@ impl Default for Libp2pConfig {
             notify_handler_buffer_size: 256,
             connection_event_buffer_size: 256,
             dial_concurrency_factor: 8,
+            tcp: true,
+            websocket: true,
+            quic: true,
+            // the QUIC transport of libp2p is still in alpha, and can not reach go-ipfs
+            // nodes, see `DEFAULT_BOOTSTRAP`
+            prefer_quic: false,
+            muxer: Muxer::YamuxMplex,
+            yamux_max_buffer_size: 16 * 1024 * 1024,
+            yamux_receive_window_size: 16 * 1024 * 1024,
+            mplex_max_buffer_size: usize::MAX,
+            dns: DnsResolver::Cloudflare,
+            dns_nameservers: Vec::new(),
+            transport_timeout: 10,
+            connection_timeout: 30,
+            swarm_key: None,
+            kad_max_records: 1024 * 64,
+            kad_max_value_bytes: 65 * 1024,
+            kad_max_providers_per_key: 20,
+            // enough for >10gb of unixfs files at the default chunk size
+            kad_max_provided_keys: 1024 * 64,
+            // kademlia defaults
+            kad_record_ttl: 36 * 60 * 60,
+            kad_provider_record_ttl: 24 * 60 * 60,
+            kad_publication_interval: 24 * 60 * 60,
+            kad_provider_publication_interval: 12 * 60 * 60,
+            accelerated_dht: false,
+            accelerated_dht_crawl_interval: 60 * 60,
+            accelerated_dht_parallelism: 64,
+            reprovide_strategy: ReprovideStrategy::All,
+            // kademlia republishes the provider records, see the field docs
+            reprovide_interval: 0,
+            reprovide_rate_limit: 50,
         }
     }
 }
@@ -189,6 +500,8 @@ impl Config {
             },
             metrics: MetricsConfig::default(),
             key_store_path: iroh_data_root().unwrap(),
+            peer_store_path: Some(iroh_data_path(PEER_STORE_FILE_NAME).unwrap()),
+            kad_store_path: Some(iroh_data_path(KAD_STORE_DIR_NAME).unwrap()),
         }
     }
 
@@ -200,6 +513,8 @@ impl Config {
             rpc_client,
             metrics: MetricsConfig::default(),
             key_store_path: iroh_data_root().unwrap(),
+            peer_store_path: Some(iroh_data_path(PEER_STORE_FILE_NAME).unwrap()),
+            kad_store_path: Some(iroh_data_path(KAD_STORE_DIR_NAME).unwrap()),
         }
     }
 
@@ -256,6 +571,14 @@ mod tests {
             "key_store_path".to_string(),
             Value::new(None, iroh_data_root().unwrap().to_str()),
         );
+        expect.insert(
+            "peer_store_path".to_string(),
+            Value::new(None, iroh_data_path(PEER_STORE_FILE_NAME).unwrap().to_str()),
+        );
+        expect.insert(
+            "kad_store_path".to_string(),
+            Value::new(None, iroh_data_path(KAD_STORE_DIR_NAME).unwrap().to_str()),
+        );
 
         let got = default.collect().unwrap();
         for key in got.keys() {
@@ -321,9 +644,104 @@ mod tests {
             "bootstrap_peers".to_string(),
             Value::new(None, bootstrap_peers),
         );
+        let listening_multiaddrs: Vec<String> = default
+            .listening_multiaddrs
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        expect.insert(
+            "listening_multiaddrs".to_string(),
+            Value::new(None, listening_multiaddrs),
+        );
+        expect.insert("tcp".to_string(), Value::new(None, default.tcp));
+        expect.insert("websocket".to_string(), Value::new(None, default.websocket));
+        expect.insert("quic".to_string(), Value::new(None, default.quic));
+        expect.insert(
+            "prefer_quic".to_string(),
+            Value::new(None, default.prefer_quic),
+        );
+        expect.insert("muxer".to_string(), Value::new(None, "yamux-mplex"));
+        expect.insert(
+            "yamux_max_buffer_size".to_string(),
+            Value::new(None, default.yamux_max_buffer_size as i64),
+        );
+        expect.insert(
+            "yamux_receive_window_size".to_string(),
+            Value::new(None, default.yamux_receive_window_size as i64),
+        );
+        expect.insert(
+            "mplex_max_buffer_size".to_string(),
+            Value::new(None, default.mplex_max_buffer_size as u64),
+        );
+        expect.insert("dns".to_string(), Value::new(None, "cloudflare"));
+        expect.insert(
+            "dns_nameservers".to_string(),
+            Value::new(None, Vec::<String>::new()),
+        );
+        expect.insert(
+            "transport_timeout".to_string(),
+            Value::new(None, default.transport_timeout as i64),
+        );
         expect.insert(
-            "listening_multiaddr".to_string(),
-            Value::new(None, default.listening_multiaddr.to_string()),
+            "connection_timeout".to_string(),
+            Value::new(None, default.connection_timeout as i64),
+        );
+        expect.insert(
+            "swarm_key".to_string(),
+            Value::new(None, default.swarm_key.as_ref().and_then(|p| p.to_str())),
+        );
+        expect.insert(
+            "kad_max_records".to_string(),
+            Value::new(None, default.kad_max_records as i64),
+        );
+        expect.insert(
+            "kad_max_value_bytes".to_string(),
+            Value::new(None, default.kad_max_value_bytes as i64),
+        );
+        expect.insert(
+            "kad_max_providers_per_key".to_string(),
+            Value::new(None, default.kad_max_providers_per_key as i64),
+        );
+        expect.insert(
+            "kad_max_provided_keys".to_string(),
+            Value::new(None, default.kad_max_provided_keys as i64),
+        );
+        expect.insert(
+            "kad_record_ttl".to_string(),
+            Value::new(None, default.kad_record_ttl as i64),
+        );
+        expect.insert(
+            "kad_provider_record_ttl".to_string(),
+            Value::new(None, default.kad_provider_record_ttl as i64),
+        );
+        expect.insert(
+            "kad_publication_interval".to_string(),
+            Value::new(None, default.kad_publication_interval as i64),
+        );
+        expect.insert(
+            "kad_provider_publication_interval".to_string(),
+            Value::new(None, default.kad_provider_publication_interval as i64),
+        );
+        expect.insert(
+            "accelerated_dht".to_string(),
+            Value::new(None, default.accelerated_dht),
+        );
+        expect.insert(
+            "accelerated_dht_crawl_interval".to_string(),
+            Value::new(None, default.accelerated_dht_crawl_interval as i64),
+        );
+        expect.insert(
+            "accelerated_dht_parallelism".to_string(),
+            Value::new(None, default.accelerated_dht_parallelism as i64),
+        );
+        expect.insert("reprovide_strategy".to_string(), Value::new(None, "all"));
+        expect.insert(
+            "reprovide_interval".to_string(),
+            Value::new(None, default.reprovide_interval as i64),
+        );
+        expect.insert(
+            "reprovide_rate_limit".to_string(),
+            Value::new(None, default.reprovide_rate_limit as i64),
         );
 
         let got = default.collect().unwrap();
@@ -347,4 +765,77 @@ mod tests {
 
         assert_eq!(expect, got);
     }
+
+    #[test]
+    fn test_deprecated_listening_multiaddr() {
+        let addr: Multiaddr = "/ip4/0.0.0.0/tcp/4001".parse().unwrap();
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.listening_multiaddr", addr.to_string())
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(got.libp2p.listening_multiaddr, Some(addr.clone()));
+        assert_eq!(got.libp2p.listening_addrs(), vec![addr]);
+        assert_eq!(
+            Libp2pConfig::default().listening_addrs(),
+            Libp2pConfig::default().listening_multiaddrs
+        );
+    }
+
+    #[test]
+    fn test_quic_listening_addrs() {
+        let tcp: Multiaddr = "/ip4/0.0.0.0/tcp/4444".parse().unwrap();
+        let quic: Multiaddr = "/ip4/0.0.0.0/udp/4444/quic".parse().unwrap();
+        let mut config = Libp2pConfig {
+            listening_multiaddrs: vec![tcp.clone(), quic.clone()],
+            ..Default::default()
+        };
+        assert_eq!(config.listening_addrs(), vec![tcp.clone(), quic]);
+
+        // private networks can not use QUIC
+        config.swarm_key = Some(PathBuf::from("/var/lib/iroh/swarm.key"));
+        assert!(!config.quic_enabled());
+        assert_eq!(config.listening_addrs(), vec![tcp.clone()]);
+
+        config.swarm_key = None;
+        config.quic = false;
+        assert_eq!(config.listening_addrs(), vec![tcp]);
+    }
+
+    #[test]
+    fn test_build_transport_config_from_struct() {
+        let expect = Config {
+            libp2p: Libp2pConfig {
+                listening_multiaddrs: vec![
+                    "/ip4/0.0.0.0/tcp/4444".parse().unwrap(),
+                    "/ip4/0.0.0.0/tcp/4445/ws".parse().unwrap(),
+                ],
+                prefer_quic: true,
+                muxer: Muxer::MplexYamux,
+                dns: DnsResolver::Custom,
+                dns_nameservers: vec!["9.9.9.9".parse().unwrap(), "::1".parse().unwrap()],
+                swarm_key: Some(PathBuf::from("/var/lib/iroh/swarm.key")),
+                kad_max_provided_keys: 10 * 1024 * 1024,
+                kad_provider_publication_interval: 0,
+                accelerated_dht: true,
+                reprovide_strategy: ReprovideStrategy::Pinned,
+                reprovide_interval: 6 * 60 * 60,
+                reprovide_rate_limit: 0,
+                ..Default::default()
+            },
+            ..Config::default_grpc()
+        };
+        let got: Config = ConfigBuilder::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
 }
@@ -1,5 +1,6 @@
 //! Handles storage and retrieval of public & private keys.
 
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
@@ -82,6 +83,11 @@ impl<S: Storage> Keychain<S> {
         Ok(())
     }
 
+    /// Returns the key stored under `name`, if there is one.
+    pub async fn get(&self, name: &str) -> Result<Option<Keypair>> {
+        self.storage.get(name).await
+    }
+
     /// Returns a stream of all keys stored.
     pub fn keys(&self) -> impl Stream<Item = Result<Keypair>> + '_ {
         self.storage.keys()
@@ -151,9 +157,7 @@ impl DiskStorage {
 
     async fn generate_name(&self, alg: ssh_key::Algorithm) -> Result<String> {
         let count = self.next_count_for_alg(alg).await?;
-        let name = format!("id_{}_{}", print_algorithm(alg), count);
-
-        Ok(name)
+        Ok(key_name(alg, count))
     }
 
     async fn next_count_for_alg(&self, alg: ssh_key::Algorithm) -> Result<usize> {
@@ -195,9 +199,11 @@ impl DiskStorage {
 }
 
 #[async_trait]
-pub trait Storage: std::fmt::Debug {
+pub trait Storage: std::fmt::Debug + Send + Sync + 'static {
     async fn put(&mut self, keypair: Keypair) -> Result<()>;
     async fn len(&self) -> Result<usize>;
+    /// Returns the key named `name`, keys are named `id_<algorithm>_<count>`.
+    async fn get(&self, name: &str) -> Result<Option<Keypair>>;
 
     fn keys(&self) -> Box<dyn Stream<Item = Result<Keypair>> + Unpin + Send + '_>;
 }
@@ -213,6 +219,18 @@ impl Storage for MemoryStorage {
         Ok(self.keys.len())
     }
 
+    async fn get(&self, name: &str) -> Result<Option<Keypair>> {
+        // names are handed out in insertion order, counting per algorithm
+        let key = self.keys.iter().enumerate().find(|(i, key)| {
+            let count = self.keys[..*i]
+                .iter()
+                .filter(|k| k.algorithm() == key.algorithm())
+                .count();
+            key_name(key.algorithm(), count) == name
+        });
+        Ok(key.map(|(_, key)| key.clone()))
+    }
+
     fn keys(&self) -> Box<dyn Stream<Item = Result<Keypair>> + Unpin + Send + '_> {
         let s = async_stream::stream! {
             for key in &self.keys {
@@ -240,6 +258,20 @@ impl Storage for DiskStorage {
         Ok(files.len())
     }
 
+    async fn get(&self, name: &str) -> Result<Option<Keypair>> {
+        // only plain file names, no paths
+        if Path::new(name).file_name() != Some(OsStr::new(name)) || !path_is_private_key(name) {
+            return Ok(None);
+        }
+        let path = self.path.join(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).await?;
+        let keypair = ssh_key::private::PrivateKey::from_openssh(&content)?;
+        Ok(Some(Keypair::try_from(&keypair)?))
+    }
+
     fn keys(&self) -> Box<dyn Stream<Item = Result<Keypair>> + Unpin + Send + '_> {
         let s = async_stream::try_stream! {
             let mut reader = fs::read_dir(&self.path).await?;
@@ -296,6 +328,10 @@ fn path_is_private_key<P: AsRef<Path>>(path: P) -> bool {
     false
 }
 
+fn key_name(alg: ssh_key::Algorithm, count: usize) -> String {
+    format!("id_{}_{}", print_algorithm(alg), count)
+}
+
 fn print_algorithm(alg: ssh_key::Algorithm) -> &'static str {
     match alg {
         ssh_key::Algorithm::Ed25519 => "ed25519",
@@ -320,6 +356,9 @@ mod tests {
 
         let keys: Vec<_> = kc.keys().try_collect().await.unwrap();
         assert_eq!(keys.len(), 2);
+
+        assert!(kc.get("id_ed25519_1").await.unwrap().is_some());
+        assert!(kc.get("id_ed25519_2").await.unwrap().is_none());
     }
 
     #[tokio::test]
@@ -359,5 +398,10 @@ mod tests {
 
         let keys: Vec<_> = kc.keys().try_collect().await.unwrap();
         assert_eq!(keys.len(), 2);
+
+        assert!(kc.get("id_ed25519_1").await.unwrap().is_some());
+        assert!(kc.get("id_ed25519_4").await.unwrap().is_none());
+        assert!(kc.get("id_foo").await.unwrap().is_none());
+        assert!(kc.get("../id_ed25519_1").await.unwrap().is_none());
     }
 }
@@ -1,9 +1,12 @@
+mod accelerated_dht;
 mod behaviour;
 pub mod cli;
 pub mod config;
 mod keys;
 pub mod metrics;
 mod node;
+mod peer_store;
+mod reprovider;
 pub mod rpc;
 mod swarm;
 
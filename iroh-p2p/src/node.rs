@@ -1,28 +1,39 @@
 use std::collections::{HashMap, HashSet};
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ahash::AHashMap;
 use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use caches::{Cache, RawLRU};
 use cid::Cid;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::FuturesUnordered;
 use futures_util::stream::StreamExt;
+use iroh_ipns::{self as ipns, IpnsRecord};
 use iroh_metrics::{core::MRecorder, inc, libp2p_metrics, p2p::P2PMetrics};
 use iroh_rpc_client::Client as RpcClient;
 use iroh_rpc_types::p2p::P2pServerAddr;
 use libp2p::core::Multiaddr;
-use libp2p::gossipsub::{GossipsubMessage, MessageId, TopicHash};
+use libp2p::gossipsub::{GossipsubMessage, MessageAcceptance, MessageId, TopicHash};
 pub use libp2p::gossipsub::{IdentTopic, Topic};
-use libp2p::identify::IdentifyEvent;
+use libp2p::identify::Event as IdentifyEvent;
 use libp2p::identity::Keypair;
 use libp2p::kad::kbucket::{Distance, NodeStatus};
+use libp2p::kad::store::RecordStore;
 use libp2p::kad::BootstrapOk;
 use libp2p::kad::{
-    self, record::Key, GetProvidersError, GetProvidersOk, KademliaEvent, QueryId, QueryResult,
+    self, record::Key, GetProvidersError, GetProvidersOk, GetRecordOk, KademliaEvent, PeerRecord,
+    ProviderRecord, PutRecordOk, QueryId, QueryResult, Quorum, Record,
 };
 use libp2p::metrics::Recorder;
 use libp2p::ping::Result as PingResult;
+use libp2p::request_response::{RequestId, RequestResponseEvent, RequestResponseMessage};
 use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
 use libp2p::swarm::{ConnectionHandler, IntoConnectionHandler, NetworkBehaviour, SwarmEvent};
 use libp2p::{PeerId, Swarm};
+use time::OffsetDateTime;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot::{self, Sender as OneShotSender};
 use tokio::task::JoinHandle;
@@ -30,11 +41,14 @@ use tracing::{debug, error, info, trace, warn};
 
 use iroh_bitswap::{BitswapEvent, Block};
 
+use crate::accelerated_dht::AcceleratedDht;
 use crate::keys::{Keychain, Storage};
+use crate::peer_store::PeerStore;
+use crate::reprovider::Reprovider;
 use crate::rpc::ProviderRequestKey;
-use crate::swarm::build_swarm;
+use crate::swarm::{build_swarm, ensure_private_bootstrap, load_swarm_key, sort_dial_addrs};
 use crate::{
-    behaviour::{Event, NodeBehaviour},
+    behaviour::{Event, FetchRequest, FetchResponse, NodeBehaviour, PeerInfo},
     rpc::{self, RpcMessage},
     Config,
 };
@@ -69,15 +83,40 @@ pub struct Node<KeyStorage: Storage> {
     net_receiver_in: Receiver<RpcMessage>,
     kad_queries: AHashMap<QueryKey, KadQueryChannel>,
     dial_queries: AHashMap<PeerId, Vec<OneShotSender<bool>>>,
+    /// Lookups waiting for the identify info of a peer.
+    lookup_queries: AHashMap<PeerId, Vec<OneShotSender<Result<PeerInfo, String>>>>,
+    /// Number of dials started by `dial_peer` that did not finish yet.
+    dials_in_flight: AHashMap<PeerId, usize>,
     network_events: Vec<Sender<NetworkEvent>>,
     #[allow(dead_code)]
     rpc_client: RpcClient,
-    _keychain: Keychain<KeyStorage>,
+    keychain: Arc<Keychain<KeyStorage>>,
+    /// The identity of this node, also available as the `self` key.
+    keypair: Keypair,
+    /// Publications waiting for their key to be read from the keychain.
+    key_loads: FuturesUnordered<BoxFuture<'static, Option<LoadedPublication>>>,
+    ipns_records: AHashMap<PeerId, IpnsPublication>,
+    /// The newest valid IPNS records seen over gossipsub or in the DHT, by record key.
+    ipns_cache: RawLRU<Key, Bytes>,
+    /// When the IPNS over PubSub topics this node follows were last looked up, by record key.
+    ipns_subscriptions: AHashMap<Key, Instant>,
+    /// Record keys of pending fetch requests.
+    ipns_fetches: AHashMap<RequestId, Key>,
     #[allow(dead_code)]
     kad_last_range: Option<(Distance, Distance)>,
     rpc_task: JoinHandle<()>,
     use_dht: bool,
     bitswap_sessions: BitswapSessions,
+    /// Fingerprint of the pre-shared key when running in a private network.
+    psk_fingerprint: Option<String>,
+    peer_store_path: Option<PathBuf>,
+    /// Dial the QUIC addresses of peers first.
+    prefer_quic: bool,
+    accelerated_dht: Option<AcceleratedDht>,
+    /// Interval at which the accelerated DHT client announces all provided keys again,
+    /// only used without the reprovider.
+    reprovide_interval: Option<Duration>,
+    reprovider: Option<Reprovider>,
 }
 
 type BitswapSessions = AHashMap<u64, Vec<(oneshot::Sender<()>, JoinHandle<()>)>>;
@@ -85,22 +124,87 @@ type BitswapSessions = AHashMap<u64, Vec<(oneshot::Sender<()>, JoinHandle<()>)>>
 enum KadQueryChannel {
     GetProviders {
         found_providers: HashSet<PeerId>,
-        #[allow(dead_code)]
         query_id: QueryId,
         channels: Vec<Sender<Result<HashSet<PeerId>, String>>>,
         limit: usize,
     },
+    GetRecord {
+        records: Vec<Bytes>,
+        channel: RecordChannel,
+        limit: usize,
+    },
+    PutRecord {
+        peer_id: PeerId,
+        channel: Option<OneShotSender<Result<PeerId, String>>>,
+    },
+    /// Looks up the addresses of a peer that should be dialed, the dial channels
+    /// live in `dial_queries`.
+    FindPeer { peer_id: PeerId },
+    /// An announcement of the reprovider.
+    Reprovide { channel: OneShotSender<Result<()>> },
+}
+
+enum RecordChannel {
+    Rpc(OneShotSender<Result<Vec<Bytes>, String>>),
+    /// The existing records of a name are fetched before publishing it for
+    /// the first time, to continue their sequence.
+    Publish {
+        keypair: Keypair,
+        value: String,
+        lifetime: Duration,
+        channel: OneShotSender<Result<PeerId, String>>,
+    },
+}
+
+/// The key, value, lifetime and response channel of a publication, once its key is loaded.
+type LoadedPublication = (
+    Keypair,
+    String,
+    Duration,
+    OneShotSender<Result<PeerId, String>>,
+);
+
+/// An IPNS record published by this node, it is republished before it expires.
+struct IpnsPublication {
+    keypair: Keypair,
+    value: String,
+    lifetime: Duration,
+    sequence: u64,
+    republish_at: Instant,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
 enum QueryKey {
     ProviderKey(Key),
+    /// Record queries are tracked by their id, as their last result does not carry the key.
+    RecordQuery(QueryId),
+    PeerQuery(QueryId),
+    ProviderQuery(QueryId),
 }
 
 pub(crate) const DEFAULT_PROVIDER_LIMIT: usize = 10;
+/// Number of records to collect before finishing a record query, same as go-ipfs.
+pub(crate) const DEFAULT_RECORD_LIMIT: usize = 16;
 const NICE_INTERVAL: Duration = Duration::from_secs(6);
 const BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
 const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+const PEER_STORE_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Each tick checks a batch of kad records for expiry, see [`KadStore::remove_expired`].
+const KAD_STORE_GC_INTERVAL: Duration = Duration::from_secs(5);
+/// How often to check whether the accelerated DHT client should crawl again.
+const DHT_CRAWL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Name of the key that refers to the node identity.
+const SELF_KEY: &str = "self";
+/// Published IPNS records are republished after this interval, or after half
+/// their lifetime if that is shorter. Same as go-ipfs.
+const IPNS_REPUBLISH_INTERVAL: Duration = Duration::from_secs(4 * 60 * 60);
+const IPNS_REPUBLISH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Prefix of the gossipsub topics IPNS records are shared on.
+const RECORD_TOPIC_PREFIX: &str = "/record/";
+/// Maximum number of cached IPNS records, and of IPNS over PubSub topics followed.
+const IPNS_CACHE_SIZE: usize = 1024;
+/// IPNS over PubSub topics are left when their name was not looked up for this long.
+const IPNS_SUBSCRIPTION_TTL: Duration = Duration::from_secs(60 * 60);
 
 impl<KeyStorage: Storage> Drop for Node<KeyStorage> {
     fn drop(&mut self) {
@@ -119,37 +223,108 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
         let Config {
             libp2p: libp2p_config,
             rpc_client,
+            peer_store_path,
+            kad_store_path,
             ..
         } = config;
 
-        let rpc_task = tokio::task::spawn(async move {
-            // TODO: handle error
-            rpc::new(rpc_addr, network_sender_in).await.unwrap()
+        let rpc_task = tokio::task::spawn({
+            let network_sender_in = network_sender_in.clone();
+            async move {
+                // TODO: handle error
+                rpc::new(rpc_addr, network_sender_in).await.unwrap()
+            }
         });
 
         let rpc_client = RpcClient::new(rpc_client)
             .await
             .context("failed to create rpc client")?;
 
+        let psk = match libp2p_config.swarm_key {
+            Some(ref path) => {
+                ensure_private_bootstrap(&libp2p_config.bootstrap_peers)?;
+                let psk = load_swarm_key(path).await?;
+                info!(
+                    "private network, swarm key fingerprint {}",
+                    psk.fingerprint()
+                );
+                Some(psk)
+            }
+            None => None,
+        };
+
         let keypair = load_identity(&mut keychain).await?;
-        let mut swarm = build_swarm(&libp2p_config, &keypair, rpc_client.clone()).await?;
+        let mut swarm = build_swarm(
+            &libp2p_config,
+            &keypair,
+            psk,
+            kad_store_path.as_deref(),
+            rpc_client.clone(),
+        )
+        .await?;
+
+        if libp2p_config.listening_multiaddr.is_some() {
+            warn!("libp2p.listening_multiaddr is deprecated, use libp2p.listening_multiaddrs");
+        }
+        for addr in &libp2p_config.listening_addrs() {
+            Swarm::listen_on(&mut swarm, addr.clone())
+                .with_context(|| format!("failed to listen on {}", addr))?;
+            println!("{}", addr);
+        }
 
-        Swarm::listen_on(&mut swarm, libp2p_config.listening_multiaddr.clone()).unwrap();
-        println!("{}", libp2p_config.listening_multiaddr);
+        let accelerated_dht =
+            (libp2p_config.kademlia && libp2p_config.accelerated_dht).then(|| {
+                AcceleratedDht::new(
+                    keypair.public().to_peer_id(),
+                    Duration::from_secs(libp2p_config.accelerated_dht_crawl_interval),
+                    libp2p_config.accelerated_dht_parallelism,
+                )
+            });
+        let reprovider =
+            (libp2p_config.kademlia && libp2p_config.reprovide_interval > 0).then(|| {
+                Reprovider::new(
+                    libp2p_config.reprovide_strategy,
+                    Duration::from_secs(libp2p_config.reprovide_interval),
+                    libp2p_config.reprovide_rate_limit,
+                    rpc_client.clone(),
+                    network_sender_in,
+                )
+            });
+        let reprovide_interval = (accelerated_dht.is_some()
+            && reprovider.is_none()
+            && libp2p_config.kad_provider_publication_interval > 0)
+            .then(|| Duration::from_secs(libp2p_config.kad_provider_publication_interval));
 
-        Ok(Node {
+        let mut node = Node {
             swarm,
             net_receiver_in: network_receiver_in,
             kad_queries: Default::default(),
             dial_queries: Default::default(),
+            lookup_queries: Default::default(),
+            dials_in_flight: Default::default(),
             network_events: Vec::new(),
             rpc_client,
-            _keychain: keychain,
+            keychain: Arc::new(keychain),
+            keypair,
+            key_loads: Default::default(),
+            ipns_records: Default::default(),
+            ipns_cache: RawLRU::new(IPNS_CACHE_SIZE).unwrap(),
+            ipns_subscriptions: Default::default(),
+            ipns_fetches: Default::default(),
             kad_last_range: None,
             rpc_task,
             use_dht: libp2p_config.kademlia,
             bitswap_sessions: Default::default(),
-        })
+            psk_fingerprint: psk.map(|psk| psk.fingerprint().to_string()),
+            peer_store_path,
+            prefer_quic: libp2p_config.prefer_quic,
+            accelerated_dht,
+            reprovide_interval,
+            reprovider,
+        };
+        node.restore_peer_store().await?;
+
+        Ok(node)
     }
 
     /// Starts the libp2p service networking stack. This Future resolves when shutdown occurs.
@@ -163,6 +338,16 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
         };
         let mut bootstrap_interval = tokio::time::interval(BOOTSTRAP_INTERVAL);
         let mut expiry_interval = tokio::time::interval(EXPIRY_INTERVAL);
+        let mut republish_interval = tokio::time::interval(IPNS_REPUBLISH_CHECK_INTERVAL);
+        let mut peer_store_interval = tokio::time::interval(PEER_STORE_SAVE_INTERVAL);
+        // the first tick completes immediately, there is nothing new to save yet
+        peer_store_interval.tick().await;
+        let mut kad_store_gc_interval = tokio::time::interval(KAD_STORE_GC_INTERVAL);
+        let mut dht_crawl_interval = tokio::time::interval(DHT_CRAWL_CHECK_INTERVAL);
+        let mut reprovide_interval = self.reprovide_interval.map(|interval| {
+            // the first announcements happen when providing
+            tokio::time::interval_at(tokio::time::Instant::now() + interval, interval)
+        });
 
         loop {
             inc!(P2PMetrics::LoopCounter);
@@ -177,9 +362,10 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 rpc_message = self.net_receiver_in.recv() => {
                     match rpc_message {
                         Some(rpc_message) => {
-                            match self.handle_rpc_message(rpc_message) {
+                            match self.handle_rpc_message(rpc_message).await {
                                 Ok(true) => {
                                     // shutdown
+                                    self.save_peer_store().await;
                                     return Ok(());
                                 }
                                 Ok(false) => {
@@ -192,6 +378,7 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         }
                         None => {
                             // shutdown
+                            self.save_peer_store().await;
                             return Ok(());
                         }
                     }
@@ -216,11 +403,107 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         warn!("expiry error {:?}", err);
                     }
                 }
+                Some(loaded) = self.key_loads.next(), if !self.key_loads.is_empty() => {
+                    if let Some((keypair, value, lifetime, channel)) = loaded {
+                        self.publish_ipns(keypair, value, lifetime, channel);
+                    }
+                }
+                _ = republish_interval.tick() => {
+                    self.republish_ipns_records();
+                    self.expire_ipns_subscriptions();
+                }
+                _ = peer_store_interval.tick() => {
+                    self.save_peer_store().await;
+                }
+                _ = kad_store_gc_interval.tick() => {
+                    if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                        kad.store_mut().remove_expired();
+                    }
+                }
+                _ = dht_crawl_interval.tick(), if self.accelerated_dht.is_some() => {
+                    self.maybe_crawl_dht();
+                }
+                _ = async {
+                    if let Some(ref mut reprovide_interval) = reprovide_interval {
+                        reprovide_interval.tick().await
+                    } else {
+                        unreachable!()
+                    }
+                }, if reprovide_interval.is_some() => {
+                    self.reprovide_dht();
+                }
+            }
+        }
+    }
+
+    /// Reloads the peers, routing table and provider records persisted by an earlier run.
+    async fn restore_peer_store(&mut self) -> Result<()> {
+        let path = match self.peer_store_path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let store = PeerStore::load(path).await?;
+
+        let behaviour = self.swarm.behaviour_mut();
+        for (peer_id, info) in store.peers() {
+            behaviour.peer_manager.restore_info(peer_id, info);
+        }
+        if let Some(kad) = behaviour.kad.as_mut() {
+            for (peer_id, addrs) in store.kbuckets() {
+                for addr in addrs {
+                    kad.add_address(&peer_id, addr);
+                }
+            }
+            // a persistent kad store keeps its provider records itself
+            if !kad.store_mut().is_persistent() {
+                for record in store.providers() {
+                    if let Err(err) = kad.store_mut().add_provider(record) {
+                        warn!("failed to restore provider record: {:?}", err);
+                    }
+                }
             }
         }
+        Ok(())
+    }
+
+    /// Persists the known peers, the routing table and, unless the kad store is
+    /// persistent, the provider records of this node. Provider records of other peers
+    /// are not persisted, as they can not be listed from the in memory store.
+    async fn save_peer_store(&mut self) {
+        let path = match self.peer_store_path {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+        let mut store = PeerStore::default();
+
+        let behaviour = self.swarm.behaviour_mut();
+        for (peer_id, info) in behaviour.peer_manager.peers() {
+            store.add_peer(peer_id, info);
+        }
+        if let Some(kad) = behaviour.kad.as_mut() {
+            for kbucket in kad.kbuckets() {
+                for entry in kbucket.iter() {
+                    store.add_kbucket_entry(entry.node.key.preimage(), entry.node.value.iter());
+                }
+            }
+            if !kad.store_mut().is_persistent() {
+                for record in kad.store_mut().provided() {
+                    store.add_provider(&record);
+                }
+            }
+        }
+
+        if let Err(err) = store.save(&path).await {
+            warn!("failed to save peer store {}: {:?}", path.display(), err);
+        }
     }
 
     fn expiry(&mut self) -> Result<()> {
+        // lookups whose rpc call timed out
+        self.lookup_queries.retain(|_, channels| {
+            channels.retain(|channel| !channel.is_closed());
+            !channels.is_empty()
+        });
         Ok(())
     }
 
@@ -239,27 +522,28 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 // find the first disconnected node
                 for entry in kbucket.iter() {
                     if entry.status == NodeStatus::Disconnected {
-                        let peer_id = entry.node.key.preimage();
-
-                        let dial_opts = DialOpts::peer_id(*peer_id)
-                            .condition(PeerCondition::Disconnected)
-                            .addresses(entry.node.value.clone().into_vec())
-                            .extend_addresses_through_behaviour()
-                            .build();
-                        to_dial = Some((dial_opts, kbucket.range()));
+                        let peer_id = *entry.node.key.preimage();
+                        let addrs = entry.node.value.clone().into_vec();
+                        to_dial = Some((peer_id, addrs, kbucket.range()));
                         break;
                     }
                 }
             }
         }
 
-        if let Some((dial_opts, range)) = to_dial {
-            trace!(
-                "checking node {:?} in bucket range ({:?})",
-                dial_opts.get_peer_id().unwrap(),
-                range
-            );
+        if let Some((peer_id, mut addrs, range)) = to_dial {
+            trace!("checking node {:?} in bucket range ({:?})", peer_id, range);
 
+            for addr in self.swarm.behaviour_mut().addresses_of_peer(&peer_id) {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+            sort_dial_addrs(&mut addrs, self.prefer_quic);
+            let dial_opts = DialOpts::peer_id(peer_id)
+                .condition(PeerCondition::Disconnected)
+                .addresses(addrs)
+                .build();
             if let Err(e) = self.swarm.dial(dial_opts) {
                 warn!("failed to dial: {:?}", e);
             }
@@ -360,8 +644,12 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             SwarmEvent::ConnectionEstablished {
                 peer_id,
                 num_established,
+                endpoint,
                 ..
             } => {
+                if endpoint.is_dialer() {
+                    self.finish_dial(&peer_id);
+                }
                 if let Some(channels) = self.dial_queries.get_mut(&peer_id) {
                     while let Some(channel) = channels.pop() {
                         channel.send(true).ok();
@@ -390,10 +678,13 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 trace!("failed to dial: {:?}, {:?}", peer_id, error);
 
                 if let Some(peer_id) = peer_id {
-                    if let Some(channels) = self.dial_queries.get_mut(&peer_id) {
-                        while let Some(channel) = channels.pop() {
-                            channel.send(false).ok();
-                        }
+                    // other dials or a DHT lookup of the peer might still succeed, or a
+                    // connection exists already and its identify info is pending
+                    if !self.finish_dial(&peer_id)
+                        && !self.is_finding_peer(&peer_id)
+                        && !self.swarm.is_connected(&peer_id)
+                    {
+                        self.fail_dial(&peer_id);
                     }
                 }
                 Ok(())
@@ -422,14 +713,9 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 match e {
                     BitswapEvent::Provide { key } => {
                         info!("bitswap provide {}", key);
-                        if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
-                            match kad.start_providing(key.hash().to_bytes().into()) {
-                                Ok(_query_id) => {
-                                    // TODO: track query?
-                                }
-                                Err(err) => {
-                                    error!("failed to provide {}: {:?}", key, err);
-                                }
+                        if self.swarm.behaviour().kad.is_enabled() {
+                            if let Err(err) = self.start_providing(key.hash().to_bytes().into()) {
+                                error!("failed to provide {}: {:?}", key, err);
                             }
                         }
                     }
@@ -439,11 +725,7 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         limit,
                     } => {
                         info!("bitswap find providers {}", key);
-                        self.handle_rpc_message(RpcMessage::ProviderRequest {
-                            key: ProviderRequestKey::Dht(key.hash().to_bytes().into()),
-                            response_channel: response,
-                            limit,
-                        })?;
+                        self.fetch_providers_dht(key.hash().to_bytes().into(), response, limit);
                     }
                     BitswapEvent::Ping { peer, response } => {
                         match self.swarm.behaviour().peer_manager.info_for_peer(&peer) {
@@ -464,8 +746,9 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 } = e
                 {
                     match result {
-                        QueryResult::GetProviders(Ok(GetProvidersOk {
-                            key, providers, ..
+                        QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders {
+                            key,
+                            providers,
                         })) => {
                             debug!("provider results for {:?} last: {}", key, step.last);
                             if step.last {
@@ -516,6 +799,15 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                             }
                         }
 
+                        QueryResult::GetProviders(Ok(
+                            GetProvidersOk::FinishedWithNoAdditionalRecord { .. },
+                        )) => {
+                            // the last step does not carry the key
+                            self.kad_queries.retain(|_, query| match query {
+                                KadQueryChannel::GetProviders { query_id, .. } => *query_id != id,
+                                _ => true,
+                            });
+                        }
                         QueryResult::GetProviders(Err(err)) => {
                             let key = match err {
                                 GetProvidersError::Timeout { key, .. } => key,
@@ -531,6 +823,79 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                                 });
                             }
                         }
+                        QueryResult::GetRecord(Ok(GetRecordOk::FoundRecord(PeerRecord {
+                            record,
+                            ..
+                        }))) => {
+                            debug!("found record for {:?}, last: {}", record.key, step.last);
+                            if ipns_name(&record.key).is_some() {
+                                if let Err(err) = self.cache_ipns_record(
+                                    record.key.clone(),
+                                    record.value.clone().into(),
+                                ) {
+                                    debug!("ignoring ipns record from the dht: {:?}", err);
+                                }
+                            }
+                            let mut done = step.last;
+                            if let Some(KadQueryChannel::GetRecord { records, limit, .. }) =
+                                self.kad_queries.get_mut(&QueryKey::RecordQuery(id))
+                            {
+                                records.push(record.value.into());
+                                if records.len() >= *limit {
+                                    // Finish query if we have enough records.
+                                    self.swarm.behaviour_mut().finish_query(&id);
+                                    done = true;
+                                }
+                            }
+                            if done {
+                                self.finish_record_query(id, None);
+                            }
+                        }
+                        QueryResult::GetRecord(Ok(
+                            GetRecordOk::FinishedWithNoAdditionalRecord { .. },
+                        )) => {
+                            self.finish_record_query(id, None);
+                        }
+                        QueryResult::GetRecord(Err(err)) => {
+                            debug!("GetRecord error {:?}", err);
+                            self.finish_record_query(id, Some(err.to_string()));
+                        }
+                        QueryResult::PutRecord(res) => {
+                            if let Some(KadQueryChannel::PutRecord { peer_id, channel }) =
+                                self.kad_queries.remove(&QueryKey::RecordQuery(id))
+                            {
+                                let res = match res {
+                                    Ok(PutRecordOk { .. }) => Ok(peer_id),
+                                    Err(err) => {
+                                        warn!(
+                                            "failed to publish ipns record for {}: {:?}",
+                                            peer_id, err
+                                        );
+                                        Err(err.to_string())
+                                    }
+                                };
+                                if let Some(channel) = channel {
+                                    channel.send(res).ok();
+                                }
+                            }
+                        }
+                        QueryResult::StartProviding(res) => {
+                            if let Some(KadQueryChannel::Reprovide { channel }) =
+                                self.kad_queries.remove(&QueryKey::ProviderQuery(id))
+                            {
+                                channel.send(res.map(|_| ()).map_err(Into::into)).ok();
+                            }
+                        }
+                        QueryResult::GetClosestPeers(res) => {
+                            if let Some(KadQueryChannel::FindPeer { peer_id }) =
+                                self.kad_queries.remove(&QueryKey::PeerQuery(id))
+                            {
+                                if let Err(err) = res {
+                                    debug!("GetClosestPeers error {:?}", err);
+                                }
+                                self.dial_found_peer(peer_id);
+                            }
+                        }
                         QueryResult::Bootstrap(Ok(BootstrapOk {
                             peer,
                             num_remaining,
@@ -580,6 +945,12 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         .behaviour_mut()
                         .peer_manager
                         .inject_identify_info(peer_id, info);
+                    if let Some(channels) = self.lookup_queries.remove(&peer_id) {
+                        let info = self.swarm.behaviour().peer_manager.info_for_peer(&peer_id);
+                        for channel in channels {
+                            channel.send(Ok(info.cloned().unwrap_or_default())).ok();
+                        }
+                    }
                 }
             }
             Event::Ping(e) => {
@@ -605,12 +976,51 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                     message,
                 } = e
                 {
+                    // messages are only forwarded once they are reported as accepted
+                    let acceptance = match topic_record_key(&message.topic) {
+                        Some(key) => match self.cache_ipns_record(key, message.data.clone().into())
+                        {
+                            Ok(true) => MessageAcceptance::Accept,
+                            // known already, or older than the record we have
+                            Ok(false) => MessageAcceptance::Ignore,
+                            Err(err) => {
+                                debug!(
+                                    "rejecting ipns record from {}: {:?}",
+                                    propagation_source, err
+                                );
+                                MessageAcceptance::Reject
+                            }
+                        },
+                        None => MessageAcceptance::Accept,
+                    };
+                    if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+                        gossipsub
+                            .report_message_validation_result(
+                                &message_id,
+                                &propagation_source,
+                                acceptance,
+                            )
+                            .ok();
+                    }
                     self.emit_network_event(NetworkEvent::Gossipsub(GossipsubEvent::Message {
                         from: propagation_source,
                         id: message_id,
                         message,
                     }));
                 } else if let libp2p::gossipsub::GossipsubEvent::Subscribed { peer_id, topic } = e {
+                    if let Some(key) = topic_record_key(&topic) {
+                        // ask peers joining a topic we follow for the current record
+                        let subscribed = self
+                            .swarm
+                            .behaviour()
+                            .gossipsub
+                            .as_ref()
+                            .map(|gossipsub| gossipsub.topics().any(|t| t == &topic))
+                            .unwrap_or_default();
+                        if subscribed && !self.ipns_cache.contains(&key) {
+                            self.fetch_ipns_record(&peer_id, key);
+                        }
+                    }
                     self.emit_network_event(NetworkEvent::Gossipsub(GossipsubEvent::Subscribed {
                         peer_id,
                         topic,
@@ -622,6 +1032,54 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                     ));
                 }
             }
+            Event::Fetch(e) => match e {
+                RequestResponseEvent::Message { peer, message } => match message {
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    } => {
+                        let key = Key::from(request.identifier);
+                        trace!("fetch request from {} for {:?}", peer, key);
+                        let res = match self.ipns_cache.get(&key) {
+                            Some(data) => FetchResponse::Found(data.clone()),
+                            None => FetchResponse::NotFound,
+                        };
+                        if let Some(fetch) = self.swarm.behaviour_mut().fetch.as_mut() {
+                            fetch.send_response(channel, res).ok();
+                        }
+                    }
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    } => {
+                        if let Some(key) = self.ipns_fetches.remove(&request_id) {
+                            if let FetchResponse::Found(data) = response {
+                                if let Err(err) = self.cache_ipns_record(key, data) {
+                                    debug!("ignoring fetched ipns record: {:?}", err);
+                                }
+                            }
+                        }
+                    }
+                },
+                RequestResponseEvent::OutboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                } => {
+                    debug!("fetch from {} failed: {:?}", peer, error);
+                    self.ipns_fetches.remove(&request_id);
+                }
+                _ => {}
+            },
+            Event::DhtClient(e) => {
+                if let Some(dht) = self.accelerated_dht.as_mut() {
+                    let behaviour = self.swarm.behaviour_mut();
+                    if let Some(client) = behaviour.dht_client.as_mut() {
+                        let peer_manager = &behaviour.peer_manager;
+                        dht.handle_event(client, e, |peer| peer_manager.is_bad_peer(peer));
+                    }
+                }
+                self.poll_accelerated_dht();
+            }
             _ => {
                 // TODO: check all important events are handled
             }
@@ -630,8 +1088,622 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
         Ok(())
     }
 
+    /// Keeps `data` as the record for `key`, if it is a valid IPNS record newer
+    /// than the one cached already. Returns whether it was newer.
+    fn cache_ipns_record(&mut self, key: Key, data: Bytes) -> Result<bool> {
+        let name = ipns_name(&key).context("not an ipns record key")?;
+        let now = OffsetDateTime::now_utc();
+        let record = IpnsRecord::decode(&name, &data, now)?;
+        if let Some(cached) = self.ipns_cache.peek(&key) {
+            if let Ok(cached) = IpnsRecord::decode(&name, cached, now) {
+                if (cached.sequence, cached.validity) >= (record.sequence, record.validity) {
+                    return Ok(false);
+                }
+            }
+        }
+        debug!("caching ipns record {} for {}", record.sequence, name);
+        self.ipns_cache.put(key, data);
+        Ok(true)
+    }
+
+    /// Returns the cached record for `key`, as long as it is still valid.
+    fn cached_ipns_record(&mut self, key: &Key) -> Option<Bytes> {
+        let name = ipns_name(key)?;
+        let data = self.ipns_cache.get(key)?;
+        if IpnsRecord::decode(&name, data, OffsetDateTime::now_utc()).is_ok() {
+            return Some(data.clone());
+        }
+        self.ipns_cache.remove(key);
+        None
+    }
+
+    /// Follows the IPNS over PubSub topic of `key`. When joining the topic the
+    /// current record is fetched from the peers already in it.
+    fn subscribe_ipns(&mut self, key: &Key) {
+        if let Some(last_used) = self.ipns_subscriptions.get_mut(key) {
+            *last_used = Instant::now();
+            return;
+        }
+        if self.swarm.behaviour().gossipsub.as_ref().is_none() {
+            return;
+        }
+        if self.ipns_subscriptions.len() >= IPNS_CACHE_SIZE {
+            debug!("not following more than {} ipns names", IPNS_CACHE_SIZE);
+            return;
+        }
+        self.ipns_subscriptions.insert(key.clone(), Instant::now());
+        let topic = record_topic(key);
+        let peers: Vec<PeerId> = match self.swarm.behaviour_mut().gossipsub.as_mut() {
+            Some(gossipsub) => match gossipsub.subscribe(&topic) {
+                Ok(true) => gossipsub
+                    .all_peers()
+                    .filter(|(_, topics)| topics.contains(&&topic.hash()))
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect(),
+                Ok(false) => Vec::new(),
+                Err(err) => {
+                    warn!("failed to subscribe to {}: {:?}", topic, err);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        for peer_id in peers {
+            self.fetch_ipns_record(&peer_id, key.clone());
+        }
+    }
+
+    /// Leaves the IPNS over PubSub topics of names that were not looked up for a
+    /// while, except for the names published by this node.
+    fn expire_ipns_subscriptions(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<Key> = self
+            .ipns_subscriptions
+            .iter()
+            .filter(|(key, last_used)| {
+                now.duration_since(**last_used) > IPNS_SUBSCRIPTION_TTL
+                    && !ipns_name(key).map_or(false, |name| self.ipns_records.contains_key(&name))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            debug!("leaving ipns topic for {:?}", key);
+            self.ipns_subscriptions.remove(&key);
+            // the record is not kept up to date anymore
+            self.ipns_cache.remove(&key);
+            if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+                if let Err(err) = gossipsub.unsubscribe(&record_topic(&key)) {
+                    warn!("failed to unsubscribe from ipns topic: {:?}", err);
+                }
+            }
+        }
+    }
+
+    fn fetch_ipns_record(&mut self, peer_id: &PeerId, key: Key) {
+        if let Some(fetch) = self.swarm.behaviour_mut().fetch.as_mut() {
+            let request_id = fetch.send_request(
+                peer_id,
+                FetchRequest {
+                    identifier: key.to_vec(),
+                },
+            );
+            self.ipns_fetches.insert(request_id, key);
+        }
+    }
+
+    /// Shares a record published by this node over gossipsub.
+    fn publish_ipns_pubsub(&mut self, key: &Key, data: Bytes) {
+        self.cache_ipns_record(key.clone(), data.clone()).ok();
+        self.subscribe_ipns(key);
+        if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+            if let Err(err) = gossipsub.publish(record_topic(key), data.to_vec()) {
+                // fails if there are no other subscribers yet
+                debug!("failed to publish ipns record over pubsub: {:?}", err);
+            }
+        }
+    }
+
+    /// Answers a record query with the records found so far, and with `err`
+    /// only if there are none.
+    fn finish_record_query(&mut self, id: QueryId, err: Option<String>) {
+        if let Some(KadQueryChannel::GetRecord {
+            records, channel, ..
+        }) = self.kad_queries.remove(&QueryKey::RecordQuery(id))
+        {
+            match channel {
+                RecordChannel::Rpc(channel) => {
+                    let res = match err {
+                        Some(err) if records.is_empty() => Err(err),
+                        _ => Ok(records),
+                    };
+                    channel.send(res).ok();
+                }
+                RecordChannel::Publish {
+                    keypair,
+                    value,
+                    lifetime,
+                    channel,
+                } => {
+                    // not finding any records is expected for new names
+                    let peer_id = keypair.public().to_peer_id();
+                    let sequence =
+                        match IpnsRecord::select(&peer_id, &records, OffsetDateTime::now_utc()) {
+                            Ok(record) => record.sequence + 1,
+                            Err(_) => 0,
+                        };
+                    self.put_ipns_record(keypair, value, lifetime, sequence, Some(channel));
+                }
+            }
+        }
+    }
+
+    /// Returns the keychain key named `name`.
+    async fn load_key(keychain: &Keychain<KeyStorage>, name: &str) -> Result<Keypair> {
+        let keypair = keychain
+            .get(name)
+            .await?
+            .with_context(|| format!("unknown key: {}", name))?;
+        Ok(keypair.into())
+    }
+
+    /// Publishes `value` with the key `key`, `self` is the node identity. Other
+    /// keys are read from the keychain first, without blocking the swarm.
+    fn load_key_and_publish_ipns(
+        &mut self,
+        key: String,
+        value: String,
+        lifetime: Duration,
+        channel: OneShotSender<Result<PeerId, String>>,
+    ) {
+        if key == SELF_KEY {
+            let keypair = self.keypair.clone();
+            self.publish_ipns(keypair, value, lifetime, channel);
+            return;
+        }
+        let keychain = self.keychain.clone();
+        self.key_loads.push(
+            async move {
+                match Self::load_key(&keychain, &key).await {
+                    Ok(keypair) => Some((keypair, value, lifetime, channel)),
+                    Err(err) => {
+                        channel.send(Err(err.to_string())).ok();
+                        None
+                    }
+                }
+            }
+            .boxed(),
+        );
+    }
+
+    fn publish_ipns(
+        &mut self,
+        keypair: Keypair,
+        value: String,
+        lifetime: Duration,
+        channel: OneShotSender<Result<PeerId, String>>,
+    ) {
+        let peer_id = keypair.public().to_peer_id();
+        if let Some(publication) = self.ipns_records.get(&peer_id) {
+            let sequence = publication.sequence + 1;
+            self.put_ipns_record(keypair, value, lifetime, sequence, Some(channel));
+        } else if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+            let query_id = kad.get_record(ipns::record_key(&peer_id).into());
+            self.kad_queries.insert(
+                QueryKey::RecordQuery(query_id),
+                KadQueryChannel::GetRecord {
+                    records: Vec::new(),
+                    channel: RecordChannel::Publish {
+                        keypair,
+                        value,
+                        lifetime,
+                        channel,
+                    },
+                    limit: DEFAULT_RECORD_LIMIT,
+                },
+            );
+        } else {
+            channel.send(Err("kademlia is not available".into())).ok();
+        }
+    }
+
+    /// Signs a record pointing to `value` and puts it into the DHT. The record
+    /// is remembered, so it gets republished.
+    fn put_ipns_record(
+        &mut self,
+        keypair: Keypair,
+        value: String,
+        lifetime: Duration,
+        sequence: u64,
+        channel: Option<OneShotSender<Result<PeerId, String>>>,
+    ) {
+        let peer_id = keypair.public().to_peer_id();
+        let record = IpnsRecord {
+            value: value.clone(),
+            sequence,
+            validity: OffsetDateTime::now_utc() + lifetime,
+            ttl: None,
+        };
+        let key = Key::new(&ipns::record_key(&peer_id));
+        let res = record
+            .encode(&keypair)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                self.publish_ipns_pubsub(&key, data.clone().into());
+                let kad = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .as_mut()
+                    .ok_or_else(|| "kademlia is not available".to_string())?;
+                let mut record = Record::new(key, data);
+                record.expires = Some(Instant::now() + lifetime);
+                kad.put_record(record, Quorum::One)
+                    .map_err(|e| e.to_string())
+            });
+
+        match res {
+            Ok(query_id) => {
+                debug!("publishing ipns record {} for {}", sequence, peer_id);
+                self.kad_queries.insert(
+                    QueryKey::RecordQuery(query_id),
+                    KadQueryChannel::PutRecord { peer_id, channel },
+                );
+                let republish_at = Instant::now() + (lifetime / 2).min(IPNS_REPUBLISH_INTERVAL);
+                self.ipns_records.insert(
+                    peer_id,
+                    IpnsPublication {
+                        keypair,
+                        value,
+                        lifetime,
+                        sequence,
+                        republish_at,
+                    },
+                );
+            }
+            Err(err) => {
+                warn!("failed to publish ipns record for {}: {}", peer_id, err);
+                if let Some(channel) = channel {
+                    channel.send(Err(err)).ok();
+                }
+            }
+        }
+    }
+
+    fn republish_ipns_records(&mut self) {
+        let now = Instant::now();
+        let due: Vec<PeerId> = self
+            .ipns_records
+            .iter()
+            .filter(|(_, publication)| publication.republish_at <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in due {
+            if let Some(publication) = self.ipns_records.remove(&peer_id) {
+                self.put_ipns_record(
+                    publication.keypair,
+                    publication.value,
+                    publication.lifetime,
+                    publication.sequence + 1,
+                    None,
+                );
+            }
+        }
+    }
+
+    fn fetch_providers_dht(
+        &mut self,
+        key: Key,
+        response_channel: Sender<Result<HashSet<PeerId>, String>>,
+        limit: usize,
+    ) {
+        debug!("fetching providers for: {:?}", key);
+        let has_dht_client = self.swarm.behaviour().dht_client.is_enabled();
+        if let Some(dht) = self
+            .accelerated_dht
+            .as_mut()
+            .filter(|dht| has_dht_client && dht.is_ready())
+        {
+            dht.get_providers(key, response_channel, limit);
+            self.poll_accelerated_dht();
+            return;
+        }
+        if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+            match self.kad_queries.entry(QueryKey::ProviderKey(key.clone())) {
+                std::collections::hash_map::Entry::Occupied(mut entry) => {
+                    if let KadQueryChannel::GetProviders { channels, .. } = entry.get_mut() {
+                        channels.push(response_channel);
+                    }
+                }
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    let query_id = kad.get_providers(key);
+                    entry.insert(KadQueryChannel::GetProviders {
+                        found_providers: Default::default(),
+                        query_id,
+                        channels: vec![response_channel],
+                        limit,
+                    });
+                }
+            }
+        } else {
+            tokio::task::spawn(async move {
+                response_channel
+                    .send(Err("kademlia is not available".into()))
+                    .await
+                    .ok();
+            });
+        }
+    }
+
+    /// Announces this node as a provider of `key`, through the accelerated DHT client
+    /// once it crawled the DHT.
+    fn start_providing(&mut self, key: Key) -> Result<()> {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let kad = match self.swarm.behaviour_mut().kad.as_mut() {
+            Some(kad) => kad,
+            None => bail!("kademlia is not available"),
+        };
+        match self.accelerated_dht.as_mut().filter(|dht| dht.is_ready()) {
+            Some(dht) => {
+                // kademlia answers lookups for the key from its store
+                let record = ProviderRecord::new(key.clone(), local_peer_id, Vec::new());
+                kad.store_mut().add_provider(record)?;
+                dht.provide([key]);
+                self.poll_accelerated_dht();
+            }
+            None => {
+                kad.start_providing(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Announces `key` for the reprovider, answering `channel` once done. Unlike
+    /// [`Node::start_providing`] the key is not added to the keys kademlia republishes,
+    /// which keeps the blocks of the store out of the kad store.
+    fn reprovide(&mut self, key: Key, channel: OneShotSender<Result<()>>) {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let kad = match self.swarm.behaviour_mut().kad.as_mut() {
+            Some(kad) => kad,
+            None => {
+                channel.send(Err(anyhow!("kademlia is not available"))).ok();
+                return;
+            }
+        };
+        if let Some(dht) = self.accelerated_dht.as_mut().filter(|dht| dht.is_ready()) {
+            dht.reprovide(key, channel);
+            self.poll_accelerated_dht();
+            return;
+        }
+
+        let provided = kad
+            .store_mut()
+            .providers(&key)
+            .iter()
+            .any(|record| record.provider == local_peer_id);
+        match kad.start_providing(key.clone()) {
+            Ok(query_id) => {
+                // kademlia only announces the key and this node, so the provider record
+                // it added for the announcement can be removed right away
+                if !provided {
+                    kad.store_mut().remove_provider(&key, &local_peer_id);
+                }
+                self.kad_queries.insert(
+                    QueryKey::ProviderQuery(query_id),
+                    KadQueryChannel::Reprovide { channel },
+                );
+            }
+            Err(err) => {
+                channel.send(Err(err.into())).ok();
+            }
+        }
+    }
+
+    /// Lets the accelerated DHT client send the requests it has room for.
+    fn poll_accelerated_dht(&mut self) {
+        let dht = match self.accelerated_dht.as_mut() {
+            Some(dht) => dht,
+            None => return,
+        };
+        let addrs: Vec<_> = Swarm::external_addresses(&self.swarm)
+            .map(|r| r.addr.clone())
+            .collect();
+        if let Some(client) = self.swarm.behaviour_mut().dht_client.as_mut() {
+            dht.poll(client, &addrs);
+        }
+    }
+
+    /// Crawls the DHT again when the routing table of the accelerated DHT client is
+    /// out of date, starting from the peers known to kademlia.
+    fn maybe_crawl_dht(&mut self) {
+        let due = self
+            .accelerated_dht
+            .as_ref()
+            .map(|dht| dht.crawl_due(Instant::now()))
+            .unwrap_or_default();
+        if !due {
+            return;
+        }
+        let mut seeds = Vec::new();
+        if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+            for kbucket in kad.kbuckets() {
+                for entry in kbucket.iter() {
+                    seeds.push((
+                        *entry.node.key.preimage(),
+                        entry.node.value.iter().cloned().collect(),
+                    ));
+                }
+            }
+        }
+        if let Some(dht) = self.accelerated_dht.as_mut() {
+            dht.start_crawl(seeds);
+        }
+        self.poll_accelerated_dht();
+    }
+
+    /// Announces all keys provided by this node again, in batches.
+    fn reprovide_dht(&mut self) {
+        let keys: Vec<Key> = match self.swarm.behaviour_mut().kad.as_mut() {
+            Some(kad) => kad
+                .store_mut()
+                .provided()
+                .map(|record| record.key.clone())
+                .collect(),
+            None => return,
+        };
+        match self.accelerated_dht.as_mut().filter(|dht| dht.is_ready()) {
+            Some(dht) => {
+                info!("reproviding {} keys", keys.len());
+                dht.provide(keys);
+                self.poll_accelerated_dht();
+            }
+            None => {
+                // no routing table yet, fall back to walking the DHT
+                if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                    for key in keys {
+                        if let Err(err) = kad.start_providing(key) {
+                            warn!("failed to reprovide: {:?}", err);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dials `peer_id`, answering its pending `dial_queries` once connected. Without
+    /// `addresses`, the ones known to the behaviours are used.
+    fn dial_peer(&mut self, peer_id: PeerId, mut addresses: Vec<Multiaddr>) {
+        if addresses.is_empty() {
+            addresses = self.swarm.behaviour_mut().addresses_of_peer(&peer_id);
+        }
+        sort_dial_addrs(&mut addresses, self.prefer_quic);
+        let dial_opts = DialOpts::peer_id(peer_id)
+            .addresses(addresses)
+            .condition(PeerCondition::Always)
+            .build();
+        match Swarm::dial(&mut self.swarm, dial_opts) {
+            Ok(()) => *self.dials_in_flight.entry(peer_id).or_default() += 1,
+            Err(e) => {
+                warn!("invalid dial options: {:?}", e);
+                if !self.dials_in_flight.contains_key(&peer_id) {
+                    self.fail_dial(&peer_id);
+                }
+            }
+        }
+    }
+
+    /// Counts a dial to `peer_id` as finished, returns whether others are still in flight.
+    fn finish_dial(&mut self, peer_id: &PeerId) -> bool {
+        match self.dials_in_flight.get_mut(peer_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                true
+            }
+            _ => {
+                self.dials_in_flight.remove(peer_id);
+                false
+            }
+        }
+    }
+
+    fn fail_dial(&mut self, peer_id: &PeerId) {
+        if let Some(channels) = self.dial_queries.get_mut(peer_id) {
+            while let Some(channel) = channels.pop() {
+                channel.send(false).ok();
+            }
+        }
+        if let Some(channels) = self.lookup_queries.remove(peer_id) {
+            for channel in channels {
+                channel
+                    .send(Err(format!("failed to connect to {}", peer_id)))
+                    .ok();
+            }
+        }
+    }
+
+    /// Connects to `peer_id`, looking up its addresses in the DHT if none are known.
+    fn connect_peer(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        if addresses.is_empty()
+            && self
+                .swarm
+                .behaviour_mut()
+                .addresses_of_peer(&peer_id)
+                .is_empty()
+        {
+            self.find_peer(peer_id);
+        } else {
+            self.dial_peer(peer_id, addresses);
+        }
+    }
+
+    /// Looks for the addresses of an unknown peer in the DHT before dialing it.
+    fn find_peer(&mut self, peer_id: PeerId) {
+        let addrs = self
+            .accelerated_dht
+            .as_ref()
+            .and_then(|dht| dht.peer_addrs(&peer_id))
+            .cloned();
+        if let Some(addrs) = addrs {
+            self.dial_peer(peer_id, addrs);
+            return;
+        }
+        match self.swarm.behaviour_mut().kad.as_mut() {
+            Some(kad) => {
+                let query_id = kad.get_closest_peers(peer_id);
+                self.kad_queries.insert(
+                    QueryKey::PeerQuery(query_id),
+                    KadQueryChannel::FindPeer { peer_id },
+                );
+            }
+            None => {
+                warn!(
+                    "no addresses known for {} and kademlia is disabled",
+                    peer_id
+                );
+                self.fail_dial(&peer_id);
+            }
+        }
+    }
+
+    /// Returns whether the addresses of `peer_id` are being looked up in the DHT.
+    fn is_finding_peer(&self, peer_id: &PeerId) -> bool {
+        self.kad_queries.values().any(
+            |query| matches!(query, KadQueryChannel::FindPeer { peer_id: id } if id == peer_id),
+        )
+    }
+
+    /// Called once the DHT lookup for `peer_id` finished. The lookup usually connects
+    /// to the peer itself, otherwise dial it with whatever addresses were learned.
+    fn dial_found_peer(&mut self, peer_id: PeerId) {
+        let pending = self
+            .dial_queries
+            .get(&peer_id)
+            .map(|channels| !channels.is_empty())
+            .unwrap_or_default()
+            || self.lookup_queries.contains_key(&peer_id);
+        if !pending {
+            return;
+        }
+        if self.swarm.is_connected(&peer_id) {
+            if let Some(channels) = self.dial_queries.get_mut(&peer_id) {
+                while let Some(channel) = channels.pop() {
+                    channel.send(true).ok();
+                }
+            }
+        } else if self
+            .swarm
+            .behaviour_mut()
+            .addresses_of_peer(&peer_id)
+            .is_empty()
+        {
+            debug!("could not find addresses for {}", peer_id);
+            self.fail_dial(&peer_id);
+        } else {
+            self.dial_peer(peer_id, Vec::new());
+        }
+    }
+
     #[tracing::instrument(skip(self))]
-    fn handle_rpc_message(&mut self, message: RpcMessage) -> Result<bool> {
+    async fn handle_rpc_message(&mut self, message: RpcMessage) -> Result<bool> {
         // Inbound messages
         match message {
             RpcMessage::ExternalAddrs(response_channel) => {
@@ -647,6 +1719,9 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             RpcMessage::LocalPeerId(response_channel) => {
                 response_channel.send(*self.swarm.local_peer_id()).ok();
             }
+            RpcMessage::NetworkInfo(response_channel) => {
+                response_channel.send(self.psk_fingerprint.clone()).ok();
+            }
             RpcMessage::BitswapRequest {
                 ctx,
                 cids,
@@ -678,32 +1753,7 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                 response_channel,
             } => match key {
                 ProviderRequestKey::Dht(key) => {
-                    debug!("fetching providers for: {:?}", key);
-                    if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
-                        match self.kad_queries.entry(QueryKey::ProviderKey(key.clone())) {
-                            std::collections::hash_map::Entry::Occupied(mut entry) => {
-                                let KadQueryChannel::GetProviders { channels, .. } =
-                                    entry.get_mut();
-                                channels.push(response_channel);
-                            }
-                            std::collections::hash_map::Entry::Vacant(entry) => {
-                                let query_id = kad.get_providers(key);
-                                entry.insert(KadQueryChannel::GetProviders {
-                                    found_providers: Default::default(),
-                                    query_id,
-                                    channels: vec![response_channel],
-                                    limit,
-                                });
-                            }
-                        }
-                    } else {
-                        tokio::task::spawn(async move {
-                            response_channel
-                                .send(Err("kademlia is not available".into()))
-                                .await
-                                .ok();
-                        });
-                    }
+                    self.fetch_providers_dht(key, response_channel, limit);
                 }
                 ProviderRequestKey::Bitswap(_, _) => {
                     debug!(
@@ -714,17 +1764,57 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                     // TODO
                 }
             },
-            RpcMessage::StartProviding(response_channel, key) => {
+            RpcMessage::RecordRequest {
+                key,
+                limit,
+                response_channel,
+            } => {
+                debug!("fetching records for: {:?}", key);
+                let cached = if ipns_name(&key).is_some() {
+                    self.subscribe_ipns(&key);
+                    self.cached_ipns_record(&key)
+                } else {
+                    None
+                };
                 if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
-                    let res: Result<QueryId> = kad.start_providing(key).map_err(|e| e.into());
-                    // TODO: wait for kad to process the query request before returning
-                    response_channel.send(res).ok();
+                    // the DHT might hold a newer record than the one seen over
+                    // pubsub, the resolver picks the highest sequence number
+                    let query_id = kad.get_record(key);
+                    self.kad_queries.insert(
+                        QueryKey::RecordQuery(query_id),
+                        KadQueryChannel::GetRecord {
+                            records: cached.into_iter().collect(),
+                            channel: RecordChannel::Rpc(response_channel),
+                            limit,
+                        },
+                    );
+                } else if let Some(record) = cached {
+                    response_channel.send(Ok(vec![record])).ok();
                 } else {
                     response_channel
-                        .send(Err(anyhow!("kademlia is not available")))
+                        .send(Err("kademlia is not available".into()))
                         .ok();
                 }
             }
+            RpcMessage::PublishIpns {
+                key,
+                value,
+                lifetime,
+                response_channel,
+            } => {
+                self.load_key_and_publish_ipns(key, value, lifetime, response_channel);
+            }
+            RpcMessage::StartProviding(response_channel, key) => {
+                // TODO: wait for kad to process the query request before returning
+                response_channel.send(self.start_providing(key)).ok();
+            }
+            RpcMessage::Reprovide(response_channel, key) => {
+                self.reprovide(key, response_channel);
+            }
+            RpcMessage::ReproviderStatus(response_channel) => {
+                let status = self.reprovider.as_ref().map(|r| r.status());
+                response_channel.send(status).ok();
+            }
             RpcMessage::StopProviding(response_channel, key) => {
                 if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
                     kad.stop_providing(&key);
@@ -757,25 +1847,49 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                     .map_err(|_| anyhow!("Failed to get Libp2p peers"))?;
             }
             RpcMessage::NetConnect(response_channel, peer_id, addresses) => {
-                let channels = self.dial_queries.entry(peer_id).or_default();
-                channels.push(response_channel);
-
-                let dial_opts = DialOpts::peer_id(peer_id)
-                    .addresses(addresses)
-                    .condition(libp2p::swarm::dial_opts::PeerCondition::Always)
-                    .build();
-                if let Err(e) = Swarm::dial(&mut self.swarm, dial_opts) {
-                    warn!("invalid dial options: {:?}", e);
-                    while let Some(channel) = channels.pop() {
-                        channel.send(false).ok();
+                self.dial_queries
+                    .entry(peer_id)
+                    .or_default()
+                    .push(response_channel);
+                self.connect_peer(peer_id, addresses);
+            }
+            RpcMessage::LookupPeerInfo {
+                peer_id,
+                addrs,
+                response_channel,
+            } => {
+                let info = self
+                    .swarm
+                    .behaviour()
+                    .peer_manager
+                    .info_for_peer(&peer_id)
+                    .filter(|info| info.last_info.is_some());
+                if let (true, Some(info)) = (self.swarm.is_connected(&peer_id), info) {
+                    response_channel.send(Ok(info.clone())).ok();
+                } else {
+                    let pending = self.lookup_queries.entry(peer_id).or_default();
+                    // lookups whose rpc call timed out do not wait for the dial anymore
+                    pending.retain(|channel| !channel.is_closed());
+                    pending.push(response_channel);
+                    // identify runs on every new connection, so a single connection
+                    // answers all lookups
+                    if !self.swarm.is_connected(&peer_id) {
+                        if pending.len() == 1 {
+                            self.connect_peer(peer_id, addrs);
+                        } else if !addrs.is_empty() {
+                            // a lookup is running already, also try the addresses of this one
+                            self.dial_peer(peer_id, addrs);
+                        }
                     }
                 }
             }
-            RpcMessage::NetDisconnect(response_channel, _peer_id) => {
-                warn!("NetDisconnect API not yet implemented"); // TODO: implement NetDisconnect
+            RpcMessage::NetDisconnect(response_channel, peer_id) => {
+                // like go-ipfs, disconnecting from a peer that is not connected is an error
+                let res = Swarm::disconnect_peer_id(&mut self.swarm, peer_id)
+                    .map_err(|_| anyhow!("not connected to {}", peer_id));
 
                 response_channel
-                    .send(())
+                    .send(res)
                     .map_err(|_| anyhow!("sender dropped"))?;
             }
             RpcMessage::Gossipsub(g) => {
@@ -856,6 +1970,27 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
     }
 }
 
+/// Returns the gossipsub topic records for `key` are shared on, as described
+/// in the IPNS over PubSub spec.
+fn record_topic(key: &Key) -> IdentTopic {
+    let encoded = base64::encode_config(key.to_vec(), base64::URL_SAFE_NO_PAD);
+    IdentTopic::new(format!("{}{}", RECORD_TOPIC_PREFIX, encoded))
+}
+
+/// Returns the record key of a record topic.
+fn topic_record_key(topic: &TopicHash) -> Option<Key> {
+    let encoded = topic.as_str().strip_prefix(RECORD_TOPIC_PREFIX)?;
+    let key = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?;
+    Some(key.into())
+}
+
+/// Returns the IPNS name a record key belongs to.
+fn ipns_name(key: &Key) -> Option<PeerId> {
+    let key = key.to_vec();
+    let name = key.strip_prefix(b"/ipns/")?;
+    PeerId::from_bytes(name).ok()
+}
+
 async fn load_identity<S: Storage>(kc: &mut Keychain<S>) -> Result<Keypair> {
     if kc.is_empty().await? {
         info!("no identity found, creating",);
@@ -934,13 +2069,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_record_topic() {
+        let peer_id = PeerId::random();
+        let key = Key::new(&ipns::record_key(&peer_id));
+        let topic = record_topic(&key);
+        // base64url of `/ipns/`
+        assert!(topic.hash().as_str().starts_with("/record/L2lwbnMv"));
+        assert_eq!(topic_record_key(&topic.hash()), Some(key.clone()));
+        assert_eq!(ipns_name(&key), Some(peer_id));
+
+        assert_eq!(
+            topic_record_key(&TopicHash::from_raw("/other/L2lwbnMv")),
+            None
+        );
+        assert_eq!(ipns_name(&Key::new(b"/pk/foo")), None);
+    }
+
     async fn fetch_providers(
         addr: Multiaddr,
         rpc_server_addr: P2pServerAddr,
         rpc_client_addr: P2pClientAddr,
     ) -> Result<()> {
         let mut network_config = Config::default_with_rpc(rpc_client_addr.clone());
-        network_config.libp2p.listening_multiaddr = addr;
+        network_config.libp2p.listening_multiaddrs = vec![addr];
+        network_config.peer_store_path = None;
+        network_config.kad_store_path = None;
 
         let kc = Keychain::<MemoryStorage>::new();
         let mut p2p = Node::new(network_config, rpc_server_addr, kc).await?;
@@ -0,0 +1,254 @@
+//! Persists what the node learned about the network across restarts: known peers with
+//! their identify info and round trip times, the kademlia routing table, and the
+//! provider records of the local node.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use libp2p::identify::Info as IdentifyInfo;
+use libp2p::identity::PublicKey;
+use libp2p::kad::{record::Key, ProviderRecord};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::behaviour::kad_store::{expires_from_unix, expires_to_unix};
+use crate::behaviour::PeerInfo;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct PeerStore {
+    peers: Vec<StoredPeer>,
+    /// Peers of the kademlia routing table.
+    kbuckets: Vec<StoredAddrs>,
+    providers: Vec<StoredProvider>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredPeer {
+    peer_id: Vec<u8>,
+    rtt_micros: Option<u64>,
+    identify: Option<StoredIdentify>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredIdentify {
+    /// Protobuf encoding of the public key.
+    public_key: Vec<u8>,
+    protocol_version: String,
+    agent_version: String,
+    listen_addrs: Vec<Vec<u8>>,
+    protocols: Vec<String>,
+    observed_addr: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredAddrs {
+    peer_id: Vec<u8>,
+    addrs: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredProvider {
+    key: Vec<u8>,
+    provider: Vec<u8>,
+    addrs: Vec<Vec<u8>>,
+    /// Expiry as seconds since the unix epoch, `Instant`s do not survive a restart.
+    expires: Option<u64>,
+}
+
+impl PeerStore {
+    /// Loads the peer store at `path`. The store starts out empty if there is no such
+    /// file yet, or if it can not be read.
+    pub async fn load(path: &Path) -> Result<Self> {
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(bincode::deserialize(&data).unwrap_or_else(|err| {
+                warn!("ignoring broken peer store {}: {}", path.display(), err);
+                PeerStore::default()
+            })),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(PeerStore::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("failed to read peer store {}", path.display()))
+            }
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let data = bincode::serialize(self)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // an interrupted save must not leave a truncated store behind
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+
+    pub fn add_peer(&mut self, peer_id: &PeerId, info: &PeerInfo) {
+        let identify = info.last_info.as_ref().map(|info| StoredIdentify {
+            public_key: info.public_key.to_protobuf_encoding(),
+            protocol_version: info.protocol_version.clone(),
+            agent_version: info.agent_version.clone(),
+            listen_addrs: info.listen_addrs.iter().map(|a| a.to_vec()).collect(),
+            protocols: info.protocols.clone(),
+            observed_addr: info.observed_addr.to_vec(),
+        });
+        self.peers.push(StoredPeer {
+            peer_id: peer_id.to_bytes(),
+            rtt_micros: info.last_rtt.map(|rtt| rtt.as_micros() as u64),
+            identify,
+        });
+    }
+
+    pub fn add_kbucket_entry<'a>(
+        &mut self,
+        peer_id: &PeerId,
+        addrs: impl Iterator<Item = &'a Multiaddr>,
+    ) {
+        self.kbuckets.push(StoredAddrs {
+            peer_id: peer_id.to_bytes(),
+            addrs: addrs.map(|a| a.to_vec()).collect(),
+        });
+    }
+
+    pub fn add_provider(&mut self, record: &ProviderRecord) {
+        self.providers.push(StoredProvider {
+            key: record.key.to_vec(),
+            provider: record.provider.to_bytes(),
+            addrs: record.addresses.iter().map(|a| a.to_vec()).collect(),
+            expires: record.expires.map(expires_to_unix),
+        });
+    }
+
+    /// The stored peers, entries that fail to decode are skipped.
+    pub fn peers(&self) -> impl Iterator<Item = (PeerId, PeerInfo)> + '_ {
+        self.peers.iter().filter_map(|peer| {
+            let peer_id = PeerId::from_bytes(&peer.peer_id).ok()?;
+            let last_info = match peer.identify {
+                Some(ref identify) => Some(IdentifyInfo {
+                    public_key: PublicKey::from_protobuf_encoding(&identify.public_key).ok()?,
+                    protocol_version: identify.protocol_version.clone(),
+                    agent_version: identify.agent_version.clone(),
+                    listen_addrs: decode_addrs(&identify.listen_addrs),
+                    protocols: identify.protocols.clone(),
+                    observed_addr: Multiaddr::try_from(identify.observed_addr.clone()).ok()?,
+                }),
+                None => None,
+            };
+            let info = PeerInfo {
+                last_rtt: peer.rtt_micros.map(Duration::from_micros),
+                last_info,
+            };
+            Some((peer_id, info))
+        })
+    }
+
+    pub fn kbuckets(&self) -> impl Iterator<Item = (PeerId, Vec<Multiaddr>)> + '_ {
+        self.kbuckets.iter().filter_map(|entry| {
+            let peer_id = PeerId::from_bytes(&entry.peer_id).ok()?;
+            Some((peer_id, decode_addrs(&entry.addrs)))
+        })
+    }
+
+    /// The stored provider records that did not expire yet.
+    pub fn providers(&self) -> impl Iterator<Item = ProviderRecord> + '_ {
+        self.providers.iter().filter_map(|provider| {
+            let expires = match provider.expires {
+                Some(expires) => Some(expires_from_unix(expires)?),
+                None => None,
+            };
+            Some(ProviderRecord {
+                key: Key::from(provider.key.clone()),
+                provider: PeerId::from_bytes(&provider.provider).ok()?,
+                expires,
+                addresses: decode_addrs(&provider.addrs),
+            })
+        })
+    }
+}
+
+fn decode_addrs(addrs: &[Vec<u8>]) -> Vec<Multiaddr> {
+    addrs
+        .iter()
+        .filter_map(|addr| Multiaddr::try_from(addr.clone()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use libp2p::identity::Keypair;
+
+    use super::*;
+    use crate::PEER_STORE_FILE_NAME;
+
+    #[tokio::test]
+    async fn test_peer_store_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PEER_STORE_FILE_NAME);
+
+        // a missing store is empty
+        let store = PeerStore::load(&path).await.unwrap();
+        assert_eq!(store.peers().count(), 0);
+
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let addr: Multiaddr = "/ip4/192.168.1.10/tcp/4444".parse().unwrap();
+        let info = PeerInfo {
+            last_rtt: Some(Duration::from_millis(42)),
+            last_info: Some(IdentifyInfo {
+                public_key: keypair.public(),
+                protocol_version: "ipfs/0.1.0".to_string(),
+                agent_version: "iroh/0.1.0".to_string(),
+                listen_addrs: vec![addr.clone()],
+                protocols: vec!["/ipfs/id/1.0.0".to_string()],
+                observed_addr: "/ip4/203.0.113.7/tcp/4444".parse().unwrap(),
+            }),
+        };
+        let provider = ProviderRecord {
+            key: Key::new(b"some-cid"),
+            provider: peer_id,
+            expires: Some(Instant::now() + Duration::from_secs(3600)),
+            addresses: vec![addr.clone()],
+        };
+        let expired = ProviderRecord {
+            expires: Some(Instant::now()),
+            ..provider.clone()
+        };
+
+        let mut store = PeerStore::default();
+        store.add_peer(&peer_id, &info);
+        store.add_kbucket_entry(&peer_id, [addr.clone()].iter());
+        store.add_provider(&provider);
+        store.add_provider(&expired);
+        store.save(&path).await.unwrap();
+
+        let store = PeerStore::load(&path).await.unwrap();
+        let peers: Vec<_> = store.peers().collect();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].0, peer_id);
+        assert_eq!(peers[0].1.last_rtt, info.last_rtt);
+        let identify = peers[0].1.last_info.as_ref().unwrap();
+        assert_eq!(identify.public_key, keypair.public());
+        assert_eq!(identify.listen_addrs, vec![addr.clone()]);
+        assert_eq!(identify.agent_version, "iroh/0.1.0");
+
+        assert_eq!(
+            store.kbuckets().collect::<Vec<_>>(),
+            vec![(peer_id, vec![addr.clone()])]
+        );
+
+        let providers: Vec<_> = store.providers().collect();
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].key, provider.key);
+        assert_eq!(providers[0].addresses, vec![addr]);
+        assert!(providers[0].expires.unwrap() > Instant::now());
+
+        // broken stores are ignored
+        tokio::fs::write(&path, b"garbage").await.unwrap();
+        let store = PeerStore::load(&path).await.unwrap();
+        assert_eq!(store.peers().count(), 0);
+    }
+}
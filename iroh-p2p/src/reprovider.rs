@@ -0,0 +1,540 @@
+//! Announces the keys of the store again at a fixed interval, so the provider records of
+//! this node do not expire from the DHT.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use cid::multihash::Multihash;
+use cid::Cid;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use iroh_metrics::{core::MRecorder, inc, p2p::P2PMetrics, record};
+use iroh_rpc_client::Client as RpcClient;
+use libp2p::kad::record::Key;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::config::ReprovideStrategy;
+use crate::rpc::RpcMessage;
+
+/// Delay before the first run, so the routing table can fill up after starting.
+const INITIAL_DELAY: Duration = Duration::from_secs(60);
+/// Number of block hashes listed from the store at once.
+const PAGE_SIZE: u32 = 1024;
+/// Maximum number of announcements a run waits for at once.
+const MAX_IN_FLIGHT: usize = 32;
+
+/// Progress and results of the reprovider, shared with the node for status requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReproviderStatus {
+    pub strategy: ReprovideStrategy,
+    /// Whether a run is in progress.
+    pub running: bool,
+    /// Keys announced so far by the run in progress.
+    pub progress: u64,
+    pub last_run: Option<ReprovideRun>,
+}
+
+/// Results of a finished run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReprovideRun {
+    pub started: SystemTime,
+    pub duration: Duration,
+    /// Number of keys announced.
+    pub keys: u64,
+    /// Number of keys that could not be announced.
+    pub failures: u64,
+    /// Why the run was aborted, if it did not list all keys.
+    pub error: Option<String>,
+}
+
+pub(crate) struct Reprovider {
+    status: Arc<Mutex<ReproviderStatus>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Reprovider {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Reprovider {
+    /// Spawns the reprovider, keys are announced by sending `Reprovide` messages to the
+    /// node through `sender`, at most `rate_limit` per second unless it is `0`.
+    pub(crate) fn new(
+        strategy: ReprovideStrategy,
+        interval: Duration,
+        rate_limit: u32,
+        rpc_client: RpcClient,
+        sender: Sender<RpcMessage>,
+    ) -> Self {
+        let status = Arc::new(Mutex::new(ReproviderStatus {
+            strategy,
+            running: false,
+            progress: 0,
+            last_run: None,
+        }));
+        let task = tokio::task::spawn({
+            let status = status.clone();
+            async move {
+                let mut interval =
+                    tokio::time::interval_at(tokio::time::Instant::now() + INITIAL_DELAY, interval);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    let mut run = Run::new(sender.clone(), rate_limit, status.clone());
+                    let res = run.reprovide(&rpc_client, strategy).await;
+                    run.wait().await;
+                    let finished = run.finish(res);
+                    info!(
+                        "reprovided {} keys in {:?}, {} failed",
+                        finished.keys, finished.duration, finished.failures
+                    );
+                    if let Some(ref err) = finished.error {
+                        warn!("reprovide run aborted: {}", err);
+                    }
+                }
+            }
+        });
+
+        Reprovider { status, task }
+    }
+
+    pub(crate) fn status(&self) -> ReproviderStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// The store operations the reprovider needs.
+#[async_trait]
+trait Store: Sync {
+    async fn get_block_hashes(
+        &self,
+        after: Option<Multihash>,
+        limit: u32,
+    ) -> Result<Vec<Multihash>>;
+    async fn get_roots(&self) -> Result<Vec<(String, Cid)>>;
+    async fn get_links(&self, cid: Cid) -> Result<Option<Vec<Cid>>>;
+    async fn has(&self, cid: Cid) -> Result<bool>;
+}
+
+#[async_trait]
+impl Store for RpcClient {
+    async fn get_block_hashes(
+        &self,
+        after: Option<Multihash>,
+        limit: u32,
+    ) -> Result<Vec<Multihash>> {
+        self.try_store()?.get_block_hashes(after, limit).await
+    }
+
+    async fn get_roots(&self) -> Result<Vec<(String, Cid)>> {
+        self.try_store()?.get_roots().await
+    }
+
+    async fn get_links(&self, cid: Cid) -> Result<Option<Vec<Cid>>> {
+        self.try_store()?.get_links(cid).await
+    }
+
+    async fn has(&self, cid: Cid) -> Result<bool> {
+        self.try_store()?.has(cid).await
+    }
+}
+
+/// A single pass over the keys selected by the strategy.
+struct Run {
+    sender: Sender<RpcMessage>,
+    limiter: Option<Interval>,
+    /// Announcements sent to the node that did not finish yet.
+    in_flight: FuturesUnordered<BoxFuture<'static, (Key, Result<()>)>>,
+    status: Arc<Mutex<ReproviderStatus>>,
+    started: SystemTime,
+    start: Instant,
+    keys: u64,
+    failures: u64,
+}
+
+impl Run {
+    fn new(
+        sender: Sender<RpcMessage>,
+        rate_limit: u32,
+        status: Arc<Mutex<ReproviderStatus>>,
+    ) -> Self {
+        let limiter = (rate_limit > 0)
+            .then(|| Duration::from_secs(1) / rate_limit)
+            // the period of rates above one key per nanosecond rounds to zero, which
+            // intervals do not support, they are the same as no limit
+            .filter(|period| !period.is_zero())
+            .map(|period| {
+                let mut limiter = tokio::time::interval(period);
+                limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                limiter
+            });
+        {
+            let mut status = status.lock().unwrap();
+            status.running = true;
+            status.progress = 0;
+        }
+        record!(P2PMetrics::ReprovideProgress, 0);
+        Run {
+            sender,
+            limiter,
+            in_flight: Default::default(),
+            status,
+            started: SystemTime::now(),
+            start: Instant::now(),
+            keys: 0,
+            failures: 0,
+        }
+    }
+
+    /// Announces the keys selected by `strategy`, the last announcements might still be
+    /// in flight when this returns.
+    async fn reprovide(&mut self, store: &impl Store, strategy: ReprovideStrategy) -> Result<()> {
+        match strategy {
+            ReprovideStrategy::All => {
+                let mut after = None;
+                loop {
+                    let hashes = store.get_block_hashes(after, PAGE_SIZE).await?;
+                    for hash in &hashes {
+                        self.provide(hash.to_bytes().into()).await?;
+                    }
+                    if hashes.len() < PAGE_SIZE as usize {
+                        return Ok(());
+                    }
+                    after = hashes.last().copied();
+                }
+            }
+            ReprovideStrategy::Pinned => {
+                let roots = stored_roots(store).await?;
+                let mut seen: HashSet<Cid> = roots.iter().copied().collect();
+                let mut queue = roots;
+                while let Some(cid) = queue.pop() {
+                    self.provide(cid.hash().to_bytes().into()).await?;
+                    for link in store.get_links(cid).await?.unwrap_or_default() {
+                        if seen.insert(link) && store.has(link).await? {
+                            queue.push(link);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ReprovideStrategy::Roots => {
+                for cid in stored_roots(store).await? {
+                    self.provide(cid.hash().to_bytes().into()).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts announcing `key` once less than [`MAX_IN_FLIGHT`] announcements are in
+    /// flight, failing only if the node is gone.
+    async fn provide(&mut self, key: Key) -> Result<()> {
+        while self.in_flight.len() >= MAX_IN_FLIGHT {
+            self.wait_one().await;
+        }
+        if let Some(ref mut limiter) = self.limiter {
+            limiter.tick().await;
+        }
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::Reprovide(s, key.clone()))
+            .await
+            .map_err(|_| anyhow!("node is gone"))?;
+        self.in_flight.push(
+            async move {
+                let res = r.await.unwrap_or_else(|_| Err(anyhow!("node is gone")));
+                (key, res)
+            }
+            .boxed(),
+        );
+        Ok(())
+    }
+
+    /// Waits for all announcements in flight.
+    async fn wait(&mut self) {
+        while !self.in_flight.is_empty() {
+            self.wait_one().await;
+        }
+    }
+
+    async fn wait_one(&mut self) {
+        let (key, res) = match self.in_flight.next().await {
+            Some(finished) => finished,
+            None => return,
+        };
+        match res {
+            Ok(()) => {
+                self.keys += 1;
+                inc!(P2PMetrics::ReprovidedKeys);
+            }
+            Err(err) => {
+                debug!("failed to reprovide {:?}: {:?}", key, err);
+                self.failures += 1;
+                inc!(P2PMetrics::ReprovideFailures);
+            }
+        }
+        self.status.lock().unwrap().progress = self.keys;
+        record!(P2PMetrics::ReprovideProgress, self.keys);
+    }
+
+    fn finish(self, res: Result<()>) -> ReprovideRun {
+        let run = ReprovideRun {
+            started: self.started,
+            duration: self.start.elapsed(),
+            keys: self.keys,
+            failures: self.failures,
+            error: res.err().map(|err| format!("{:#}", err)),
+        };
+        inc!(P2PMetrics::ReprovideRuns);
+        record!(P2PMetrics::ReprovideLastRunKeys, run.keys);
+        record!(P2PMetrics::ReprovideLastRunDuration, run.duration.as_secs());
+
+        let mut status = self.status.lock().unwrap();
+        status.running = false;
+        status.last_run = Some(run.clone());
+        run
+    }
+}
+
+/// Returns the named roots that are in the store, each root only once.
+async fn stored_roots(store: &impl Store) -> Result<Vec<Cid>> {
+    let mut roots = Vec::new();
+    for (name, cid) in store.get_roots().await? {
+        if roots.contains(&cid) {
+            continue;
+        }
+        if store.has(cid).await? {
+            roots.push(cid);
+        } else {
+            debug!("root {} ({}) is not stored, skipping", name, cid);
+        }
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    /// Blocks with their links and named roots.
+    #[derive(Default)]
+    struct FakeStore {
+        blocks: BTreeMap<Cid, Vec<Cid>>,
+        roots: Vec<(String, Cid)>,
+    }
+
+    #[async_trait]
+    impl Store for FakeStore {
+        async fn get_block_hashes(
+            &self,
+            after: Option<Multihash>,
+            limit: u32,
+        ) -> Result<Vec<Multihash>> {
+            let mut hashes: Vec<_> = self.blocks.keys().map(|cid| *cid.hash()).collect();
+            hashes.sort_by_key(|hash| hash.to_bytes());
+            Ok(hashes
+                .into_iter()
+                .filter(|hash| {
+                    after
+                        .map(|a| hash.to_bytes() > a.to_bytes())
+                        .unwrap_or(true)
+                })
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn get_roots(&self) -> Result<Vec<(String, Cid)>> {
+            Ok(self.roots.clone())
+        }
+
+        async fn get_links(&self, cid: Cid) -> Result<Option<Vec<Cid>>> {
+            Ok(self.blocks.get(&cid).cloned())
+        }
+
+        async fn has(&self, cid: Cid) -> Result<bool> {
+            Ok(self.blocks.contains_key(&cid))
+        }
+    }
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, Code::Sha2_256.digest(data))
+    }
+
+    /// The keys of `cids`, sorted.
+    fn sorted_keys(cids: &[Cid]) -> Vec<Key> {
+        let mut keys: Vec<_> = cids
+            .iter()
+            .map(|cid| Key::from(cid.hash().to_bytes()))
+            .collect();
+        keys.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        keys
+    }
+
+    /// Runs `strategy` against `store`, returning the announced keys sorted.
+    async fn reprovided(store: &FakeStore, strategy: ReprovideStrategy) -> Vec<Key> {
+        let (sender, mut receiver) = channel(8);
+        let node = tokio::task::spawn(async move {
+            let mut keys = Vec::new();
+            while let Some(msg) = receiver.recv().await {
+                if let RpcMessage::Reprovide(s, key) = msg {
+                    keys.push(key);
+                    s.send(Ok(())).unwrap();
+                }
+            }
+            keys
+        });
+        let status = Arc::new(Mutex::new(ReproviderStatus {
+            strategy,
+            running: false,
+            progress: 0,
+            last_run: None,
+        }));
+
+        let mut run = Run::new(sender, 0, status);
+        run.reprovide(store, strategy).await.unwrap();
+        run.wait().await;
+        let finished = run.finish(Ok(()));
+        let mut keys = node.await.unwrap();
+        assert_eq!(finished.keys, keys.len() as u64);
+        keys.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let (sender, mut receiver) = channel(8);
+        let status = Arc::new(Mutex::new(ReproviderStatus {
+            strategy: ReprovideStrategy::Roots,
+            running: false,
+            progress: 0,
+            last_run: None,
+        }));
+        // fails to provide every other key
+        let node = tokio::task::spawn(async move {
+            let mut i = 0;
+            while let Some(msg) = receiver.recv().await {
+                if let RpcMessage::Reprovide(s, _) = msg {
+                    i += 1;
+                    let res = if i % 2 == 0 {
+                        Err(anyhow!("kademlia is not available"))
+                    } else {
+                        Ok(())
+                    };
+                    s.send(res).unwrap();
+                }
+            }
+        });
+
+        let mut run = Run::new(sender, 0, status.clone());
+        assert!(status.lock().unwrap().running);
+        for i in 0..5u8 {
+            run.provide(Key::new(&[i])).await.unwrap();
+        }
+        run.wait().await;
+        assert_eq!(status.lock().unwrap().progress, 3);
+
+        let finished = run.finish(Err(anyhow!("missing rpc store connection")));
+        assert_eq!(finished.keys, 3);
+        assert_eq!(finished.failures, 2);
+        assert_eq!(
+            finished.error.as_deref(),
+            Some("missing rpc store connection")
+        );
+        let status = status.lock().unwrap().clone();
+        assert!(!status.running);
+        assert_eq!(status.last_run, Some(finished));
+
+        // the run dropped the sender
+        node.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_rate_limit() {
+        let (sender, _receiver) = channel(1);
+        let status = Arc::new(Mutex::new(ReproviderStatus {
+            strategy: ReprovideStrategy::All,
+            running: false,
+            progress: 0,
+            last_run: None,
+        }));
+        assert!(Run::new(sender.clone(), 0, status.clone())
+            .limiter
+            .is_none());
+        assert!(Run::new(sender.clone(), 50, status.clone())
+            .limiter
+            .is_some());
+        // the period would be zero
+        assert!(Run::new(sender, u32::MAX, status).limiter.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reprovide_all() {
+        let mut store = FakeStore::default();
+        let cids: Vec<_> = (0..(PAGE_SIZE * 2 + 3))
+            .map(|i| cid(&i.to_be_bytes()))
+            .collect();
+        for cid in &cids {
+            store.blocks.insert(*cid, Vec::new());
+        }
+
+        // all pages are listed
+        let keys = reprovided(&store, ReprovideStrategy::All).await;
+        assert_eq!(keys, sorted_keys(&cids));
+    }
+
+    #[tokio::test]
+    async fn test_reprovide_pinned() {
+        let mut store = FakeStore::default();
+        let leaf = cid(b"leaf");
+        let missing = cid(b"missing");
+        let dir = cid(b"dir");
+        let file = cid(b"file");
+        let unpinned = cid(b"unpinned");
+        store.blocks.insert(leaf, Vec::new());
+        // linked from both the file and the directory
+        store.blocks.insert(file, vec![leaf, missing]);
+        store.blocks.insert(dir, vec![file, leaf]);
+        store.blocks.insert(unpinned, Vec::new());
+        store.roots = vec![
+            ("dir".to_string(), dir),
+            ("file".to_string(), file),
+            ("gone".to_string(), missing),
+        ];
+
+        // every stored block reachable from a root, once
+        let keys = reprovided(&store, ReprovideStrategy::Pinned).await;
+        assert_eq!(keys, sorted_keys(&[dir, file, leaf]));
+    }
+
+    #[tokio::test]
+    async fn test_reprovide_roots() {
+        let mut store = FakeStore::default();
+        let a = cid(b"a");
+        let b = cid(b"b");
+        store.blocks.insert(a, vec![b]);
+        store.blocks.insert(b, Vec::new());
+        store.roots = vec![
+            ("a".to_string(), a),
+            ("also-a".to_string(), a),
+            ("gone".to_string(), cid(b"gone")),
+        ];
+
+        // only the stored roots, each once
+        let keys = reprovided(&store, ReprovideStrategy::Roots).await;
+        assert_eq!(keys, sorted_keys(&[a]));
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::pin::Pin;
+use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{anyhow, ensure, Context, Result};
 use bytes::Bytes;
@@ -24,11 +25,18 @@ use iroh_rpc_types::p2p::{
     GetListeningAddrsResponse, GetPeersResponse, GossipsubAllPeersResponse, GossipsubPeerAndTopics,
     GossipsubPeerIdMsg, GossipsubPeersResponse, GossipsubPublishRequest, GossipsubPublishResponse,
     GossipsubSubscribeResponse, GossipsubTopicHashMsg, GossipsubTopicsResponse, Key as ProviderKey,
-    Multiaddrs, NotifyNewBlocksBitswapRequest, P2p as RpcP2p, P2pServerAddr, PeerIdResponse,
-    Providers, StopSessionBitswapRequest, VersionResponse,
+    LookupRequest, LookupResponse, Multiaddrs, NetworkInfoResponse, NotifyNewBlocksBitswapRequest,
+    P2p as RpcP2p, P2pServerAddr, PeerIdResponse, Providers, PublishIpnsRequest,
+    PublishIpnsResponse, Records, ReprovideRun, ReproviderStatusResponse,
+    StopSessionBitswapRequest, VersionResponse,
 };
 
-use super::node::DEFAULT_PROVIDER_LIMIT;
+use super::node::{DEFAULT_PROVIDER_LIMIT, DEFAULT_RECORD_LIMIT};
+use crate::behaviour::PeerInfo;
+use crate::reprovider::ReproviderStatus;
+
+/// How long a lookup waits for connecting to the peer and receiving its identify info.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
 
 struct P2p {
     sender: Sender<RpcMessage>,
@@ -80,6 +88,20 @@ impl RpcP2p for P2p {
         })
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn network_info(&self, _: ()) -> Result<NetworkInfoResponse> {
+        trace!("received NetworkInfo request");
+
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::NetworkInfo(s);
+
+        self.sender.send(msg).await?;
+
+        let psk_fingerprint = r.await?;
+
+        Ok(NetworkInfoResponse { psk_fingerprint })
+    }
+
     // TODO: expand to handle multiple cids at once. Probably not a tough fix, just want to push
     // forward right now
     #[tracing::instrument(skip(self, req))]
@@ -192,6 +214,47 @@ impl RpcP2p for P2p {
         })))
     }
 
+    #[tracing::instrument(skip(self, req))]
+    async fn fetch_record_dht(&self, req: ProviderKey) -> Result<Records> {
+        trace!("received fetch_record_dht: {:?}", req.key);
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::RecordRequest {
+            key: req.key.into(),
+            response_channel: s,
+            limit: DEFAULT_RECORD_LIMIT,
+        };
+
+        self.sender.send(msg).await?;
+        let records = r.await?.map_err(|e| anyhow!(e))?;
+
+        Ok(Records { records })
+    }
+
+    #[tracing::instrument(skip(self, req))]
+    async fn publish_ipns(&self, req: PublishIpnsRequest) -> Result<PublishIpnsResponse> {
+        trace!("received publish_ipns: {} -> {}", req.key, req.value);
+        ensure!(
+            req.value.starts_with("/ipfs/") || req.value.starts_with("/ipns/"),
+            "invalid path: {}",
+            req.value
+        );
+        ensure!(req.lifetime > 0, "lifetime must not be zero");
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::PublishIpns {
+            key: req.key,
+            value: req.value,
+            lifetime: Duration::from_millis(req.lifetime),
+            response_channel: s,
+        };
+
+        self.sender.send(msg).await?;
+        let peer_id = r.await?.map_err(|e| anyhow!(e))?;
+
+        Ok(PublishIpnsResponse {
+            peer_id: peer_id.to_bytes(),
+        })
+    }
+
     #[tracing::instrument(skip(self, req))]
     async fn start_providing(&self, req: ProviderKey) -> Result<()> {
         trace!("received StartProviding request: {:?}", req.key);
@@ -200,9 +263,8 @@ impl RpcP2p for P2p {
 
         self.sender.send(msg).await?;
 
-        let query_id = r.await??;
+        r.await??;
 
-        tracing::debug!("StartProviding query_id: {:?}", query_id);
         Ok(())
     }
 
@@ -218,6 +280,16 @@ impl RpcP2p for P2p {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn reprovider_status(&self, _: ()) -> Result<ReproviderStatusResponse> {
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::ReproviderStatus(s);
+
+        self.sender.send(msg).await?;
+
+        Ok(reprovider_status_response(r.await?))
+    }
+
     #[tracing::instrument(skip(self))]
     async fn get_listening_addrs(&self, _: ()) -> Result<GetListeningAddrsResponse> {
         let (s, r) = oneshot::channel();
@@ -269,9 +341,38 @@ impl RpcP2p for P2p {
         let (s, r) = oneshot::channel();
         let msg = RpcMessage::NetDisconnect(s, peer_id);
         self.sender.send(msg).await?;
-        let ack = r.await?;
+        r.await?
+    }
 
-        Ok(ack)
+    #[tracing::instrument(skip(self, req))]
+    async fn lookup(&self, req: LookupRequest) -> Result<LookupResponse> {
+        let peer_id = peer_id_from_bytes(req.peer_id)?;
+        let addrs = addrs_from_bytes(req.addrs)?;
+        let (s, r) = oneshot::channel();
+        let msg = RpcMessage::LookupPeerInfo {
+            peer_id,
+            addrs,
+            response_channel: s,
+        };
+        self.sender.send(msg).await?;
+
+        let info = tokio::time::timeout(LOOKUP_TIMEOUT, r)
+            .await
+            .map_err(|_| anyhow!("lookup of {} timed out", peer_id))??
+            .map_err(|e| anyhow!("{}", e))?;
+        let rtt = info.last_rtt.map(|rtt| rtt.as_millis() as u64);
+        let info = info
+            .last_info
+            .ok_or_else(|| anyhow!("no identify info for {}", peer_id))?;
+        Ok(LookupResponse {
+            peer_id: info.public_key.to_peer_id().to_bytes(),
+            protocol_version: info.protocol_version,
+            agent_version: info.agent_version,
+            listen_addrs: info.listen_addrs.iter().map(|a| a.to_vec()).collect(),
+            protocols: info.protocols,
+            observed_addr: info.observed_addr.to_vec(),
+            rtt,
+        })
     }
 
     #[tracing::instrument(skip(self, req))]
@@ -427,6 +528,32 @@ fn addrs_from_bytes(a: Vec<Vec<u8>>) -> Result<Vec<Multiaddr>> {
     a.into_iter().map(addr_from_bytes).collect()
 }
 
+/// A disabled reprovider is reported with `enabled` unset.
+fn reprovider_status_response(status: Option<ReproviderStatus>) -> ReproviderStatusResponse {
+    let status = match status {
+        Some(status) => status,
+        None => return ReproviderStatusResponse::default(),
+    };
+    let last_run = status.last_run.map(|run| ReprovideRun {
+        started: run
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        duration: run.duration.as_millis() as u64,
+        keys: run.keys,
+        failures: run.failures,
+        error: run.error,
+    });
+    ReproviderStatusResponse {
+        enabled: true,
+        strategy: status.strategy.as_str().to_string(),
+        running: status.running,
+        progress: status.progress,
+        last_run,
+    }
+}
+
 #[derive(Debug)]
 pub enum ProviderRequestKey {
     // TODO: potentially change this to Cid, as that is the only key we use for providers
@@ -439,6 +566,8 @@ pub enum ProviderRequestKey {
 pub enum RpcMessage {
     ExternalAddrs(oneshot::Sender<Vec<Multiaddr>>),
     LocalPeerId(oneshot::Sender<PeerId>),
+    /// Responds with the fingerprint of the pre-shared key, if the network is private.
+    NetworkInfo(oneshot::Sender<Option<String>>),
     BitswapRequest {
         ctx: u64,
         cids: Vec<Cid>,
@@ -458,12 +587,35 @@ pub enum RpcMessage {
         response_channel: Sender<Result<HashSet<PeerId>, String>>,
         limit: usize,
     },
-    StartProviding(oneshot::Sender<Result<libp2p::kad::QueryId>>, Key),
+    RecordRequest {
+        key: Key,
+        response_channel: oneshot::Sender<Result<Vec<Bytes>, String>>,
+        limit: usize,
+    },
+    /// Publishes an IPNS record signed with the keychain key `key`.
+    PublishIpns {
+        key: String,
+        value: String,
+        lifetime: Duration,
+        response_channel: oneshot::Sender<Result<PeerId, String>>,
+    },
+    StartProviding(oneshot::Sender<Result<()>>, Key),
+    /// Announces the key without adding it to the keys this node provides, answered once
+    /// the announcement finished. Sent by the reprovider.
+    Reprovide(oneshot::Sender<Result<()>>, Key),
     StopProviding(oneshot::Sender<Result<()>>, Key),
+    /// Answered with `None` if the reprovider is disabled.
+    ReproviderStatus(oneshot::Sender<Option<ReproviderStatus>>),
     NetListeningAddrs(oneshot::Sender<(PeerId, Vec<Multiaddr>)>),
     NetPeers(oneshot::Sender<HashMap<PeerId, Vec<Multiaddr>>>),
     NetConnect(oneshot::Sender<bool>, PeerId, Vec<Multiaddr>),
-    NetDisconnect(oneshot::Sender<()>, PeerId),
+    NetDisconnect(oneshot::Sender<Result<()>>, PeerId),
+    /// Connects to the peer and responds with its info once identify ran.
+    LookupPeerInfo {
+        peer_id: PeerId,
+        addrs: Vec<Multiaddr>,
+        response_channel: oneshot::Sender<Result<PeerInfo, String>>,
+    },
     Gossipsub(GossipsubMessage),
     Shutdown,
 }
@@ -484,3 +636,50 @@ pub enum GossipsubMessage {
     Topics(oneshot::Sender<Vec<TopicHash>>),
     Unsubscribe(oneshot::Sender<Result<bool, PublishError>>, TopicHash),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ReprovideStrategy;
+    use crate::reprovider::ReprovideRun as Run;
+
+    #[test]
+    fn test_reprovider_status_response() {
+        let disabled = reprovider_status_response(None);
+        assert!(!disabled.enabled);
+        assert_eq!(
+            iroh_rpc_client::ReproviderStatus::from_response(disabled),
+            None
+        );
+
+        let status = ReproviderStatus {
+            strategy: ReprovideStrategy::Pinned,
+            running: true,
+            progress: 3,
+            last_run: Some(Run {
+                started: UNIX_EPOCH + Duration::from_secs(1_000),
+                duration: Duration::from_millis(1_500),
+                keys: 10,
+                failures: 2,
+                error: Some("node is gone".to_string()),
+            }),
+        };
+        let res = reprovider_status_response(Some(status));
+        assert!(res.enabled);
+        assert_eq!(
+            iroh_rpc_client::ReproviderStatus::from_response(res),
+            Some(iroh_rpc_client::ReproviderStatus {
+                strategy: "pinned".to_string(),
+                running: true,
+                progress: 3,
+                last_run: Some(iroh_rpc_client::ReprovideRun {
+                    started: UNIX_EPOCH + Duration::from_secs(1_000),
+                    duration: Duration::from_millis(1_500),
+                    keys: 10,
+                    failures: 2,
+                    error: Some("node is gone".to_string()),
+                }),
+            })
+        );
+    }
+}
@@ -1,44 +1,123 @@
+use std::path::Path;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
 use iroh_rpc_client::Client;
 use libp2p::{
     core::{
         self,
+        either::{EitherOutput, EitherTransport},
         muxing::StreamMuxerBox,
-        transport::{timeout::TransportTimeout, Boxed, OrTransport},
+        transport::{timeout::TransportTimeout, Boxed, OptionalTransport, OrTransport},
     },
     dns,
     identity::Keypair,
-    mplex, noise,
+    mplex,
+    multiaddr::Protocol,
+    noise,
+    pnet::{PnetConfig, PreSharedKey},
+    quic,
     swarm::{ConnectionLimits, SwarmBuilder},
     yamux::{self, WindowUpdateMode},
-    PeerId, Swarm, Transport,
+    Multiaddr, PeerId, Swarm, Transport,
 };
+use tracing::warn;
+use trust_dns_resolver::config::NameServerConfigGroup;
 
-use crate::{behaviour::NodeBehaviour, Libp2pConfig};
+use crate::{behaviour::NodeBehaviour, DnsResolver, Libp2pConfig, Muxer, DEFAULT_BOOTSTRAP};
+
+/// Reads a pre-shared key in the go-ipfs `swarm.key` format.
+pub(crate) async fn load_swarm_key(path: &Path) -> Result<PreSharedKey> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read swarm key {}", path.display()))?;
+    let psk = content
+        .parse()
+        .with_context(|| format!("invalid swarm key {}", path.display()))?;
+    Ok(psk)
+}
+
+/// Peers of a private network can not reach the public bootstrap nodes, and dialing them
+/// would leak that the node exists, so refuse to use any of them.
+///
+/// Public nodes are recognized by the peer ids and hosts of [`DEFAULT_BOOTSTRAP`], under
+/// any address. This is best-effort: other public peers can not be told apart from
+/// private ones.
+pub(crate) fn ensure_private_bootstrap(bootstrap_peers: &[Multiaddr]) -> Result<()> {
+    let public: Vec<Multiaddr> = DEFAULT_BOOTSTRAP
+        .iter()
+        .map(|node| node.parse().unwrap())
+        .collect();
+    let public_peers: Vec<PeerId> = public.iter().filter_map(addr_peer_id).collect();
+    for addr in bootstrap_peers {
+        let public_peer = addr_peer_id(addr).map_or(false, |peer| public_peers.contains(&peer));
+        let public_host = addr.iter().any(|p| {
+            matches!(p, Protocol::Dnsaddr(_))
+                && public.iter().any(|node| node.iter().any(|q| q == p))
+        });
+        if public_peer || public_host {
+            bail!(
+                "public bootstrap peer {} configured in a private network, remove it from bootstrap_peers",
+                addr
+            );
+        }
+    }
+    Ok(())
+}
+
+fn addr_peer_id(addr: &Multiaddr) -> Option<PeerId> {
+    addr.iter().find_map(|p| match p {
+        Protocol::P2p(mh) => PeerId::from_multihash(mh).ok(),
+        _ => None,
+    })
+}
+
+fn yamux_config(config: &Libp2pConfig) -> yamux::YamuxConfig {
+    let mut yamux_config = yamux::YamuxConfig::default();
+    yamux_config.set_max_buffer_size(config.yamux_max_buffer_size);
+    yamux_config.set_receive_window_size(config.yamux_receive_window_size);
+    yamux_config.set_window_update_mode(WindowUpdateMode::on_receive());
+    yamux_config
+}
+
+fn mplex_config(config: &Libp2pConfig) -> mplex::MplexConfig {
+    let mut mplex_config = mplex::MplexConfig::new();
+    mplex_config.set_max_buffer_size(config.mplex_max_buffer_size);
+    mplex_config
+}
 
 /// Builds the transport stack that LibP2P will communicate over.
 async fn build_transport(
     keypair: &Keypair,
     config: &Libp2pConfig,
-) -> (
+    psk: Option<PreSharedKey>,
+) -> Result<(
     Boxed<(PeerId, StreamMuxerBox)>,
     Option<libp2p::relay::v2::client::Client>,
-) {
-    // TODO: make transports configurable
-
-    let tcp_config = libp2p::tcp::GenTcpConfig::default().port_reuse(true);
-    let transport = libp2p::tcp::TokioTcpTransport::new(tcp_config.clone());
-    let transport =
-        libp2p::websocket::WsConfig::new(libp2p::tcp::TokioTcpTransport::new(tcp_config))
-            .or_transport(transport);
-
-    // TODO: configurable
-    let transport = TransportTimeout::new(transport, Duration::from_secs(10));
-    let dns_cfg = dns::ResolverConfig::cloudflare();
-    let dns_opts = dns::ResolverOpts::default();
-    let transport = dns::TokioDnsConfig::custom(transport, dns_cfg, dns_opts).unwrap();
+)> {
+    ensure!(
+        config.tcp || config.websocket || config.quic_enabled(),
+        "at least one of the tcp, websocket and quic transports must be enabled"
+    );
+    if config.quic && psk.is_some() {
+        warn!("the quic transport does not support private networks, it is disabled");
+    }
+
+    let tcp_config = libp2p::tcp::Config::default().port_reuse(true);
+    let tcp_transport = if config.tcp {
+        OptionalTransport::some(libp2p::tcp::tokio::Transport::new(tcp_config.clone()))
+    } else {
+        OptionalTransport::none()
+    };
+    let ws_transport = if config.websocket {
+        OptionalTransport::some(libp2p::websocket::WsConfig::new(
+            libp2p::tcp::tokio::Transport::new(tcp_config),
+        ))
+    } else {
+        OptionalTransport::none()
+    };
+    let transport = ws_transport.or_transport(tcp_transport);
+    let transport = TransportTimeout::new(transport, Duration::from_secs(config.transport_timeout));
 
     let auth_config = {
         let dh_keys = noise::Keypair::<noise::X25519Spec>::new()
@@ -48,55 +127,118 @@ async fn build_transport(
         noise::NoiseConfig::xx(dh_keys).into_authenticated()
     };
 
-    let muxer_config = {
-        let mut mplex_config = mplex::MplexConfig::new();
-        mplex_config.set_max_buffer_size(usize::MAX);
-
-        let mut yamux_config = yamux::YamuxConfig::default();
-        yamux_config.set_max_buffer_size(16 * 1024 * 1024); // TODO: configurable
-        yamux_config.set_receive_window_size(16 * 1024 * 1024); // TODO: configurable
-        yamux_config.set_window_update_mode(WindowUpdateMode::on_receive());
-        core::upgrade::SelectUpgrade::new(yamux_config, mplex_config)
-    };
-
-    // TODO: configurable
-    let connection_timeout = Duration::from_secs(30);
-    if config.relay_client {
+    let (relay_transport, relay_client) = if config.relay_client {
         let (relay_transport, relay_client) =
             libp2p::relay::v2::client::Client::new_transport_and_behaviour(
                 keypair.public().to_peer_id(),
             );
+        (OptionalTransport::some(relay_transport), Some(relay_client))
+    } else {
+        (OptionalTransport::none(), None)
+    };
 
-        let transport = OrTransport::new(relay_transport, transport);
-        let transport = transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
+    let connection_timeout = Duration::from_secs(config.connection_timeout);
+    let transport = OrTransport::new(relay_transport, transport);
+    // the pnet handshake wraps the raw connection, before any of the libp2p upgrades
+    let transport = match psk {
+        Some(psk) => EitherTransport::Left(
+            transport.and_then(move |socket, _| PnetConfig::new(psk).handshake(socket)),
+        ),
+        None => EitherTransport::Right(transport),
+    };
+    let transport = transport
+        .upgrade(core::upgrade::Version::V1Lazy)
+        .authenticate(auth_config);
+    let transport = match config.muxer {
+        Muxer::Yamux => transport
+            .multiplex(yamux_config(config))
+            .timeout(connection_timeout)
+            .boxed(),
+        Muxer::Mplex => transport
+            .multiplex(mplex_config(config))
             .timeout(connection_timeout)
-            .boxed();
+            .boxed(),
+        Muxer::YamuxMplex => transport
+            .multiplex(core::upgrade::SelectUpgrade::new(
+                yamux_config(config),
+                mplex_config(config),
+            ))
+            .timeout(connection_timeout)
+            .boxed(),
+        Muxer::MplexYamux => transport
+            .multiplex(core::upgrade::SelectUpgrade::new(
+                mplex_config(config),
+                yamux_config(config),
+            ))
+            .timeout(connection_timeout)
+            .boxed(),
+    };
 
-        (transport, Some(relay_client))
+    // QUIC brings its own encryption and multiplexing, so it skips the upgrades
+    let quic_transport = if config.quic_enabled() {
+        let quic_config = quic::Config::new(keypair);
+        OptionalTransport::some(
+            quic::tokio::Transport::new(quic_config)
+                .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer))),
+        )
     } else {
-        let transport = transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
-            .timeout(connection_timeout)
-            .boxed();
+        OptionalTransport::none()
+    };
+    let transport = quic_transport
+        .or_transport(transport)
+        .map(|output, _| match output {
+            EitherOutput::First(output) => output,
+            EitherOutput::Second(output) => output,
+        });
 
-        (transport, None)
-    }
+    let dns_opts = dns::ResolverOpts::default();
+    let transport = match config.dns {
+        DnsResolver::System => dns::TokioDnsConfig::system(transport)?.boxed(),
+        DnsResolver::Cloudflare => {
+            dns::TokioDnsConfig::custom(transport, dns::ResolverConfig::cloudflare(), dns_opts)?
+                .boxed()
+        }
+        DnsResolver::Google => {
+            dns::TokioDnsConfig::custom(transport, dns::ResolverConfig::google(), dns_opts)?.boxed()
+        }
+        DnsResolver::Custom => {
+            ensure!(
+                !config.dns_nameservers.is_empty(),
+                "the custom dns resolver needs at least one entry in dns_nameservers"
+            );
+            let nameservers =
+                NameServerConfigGroup::from_ips_clear(&config.dns_nameservers, 53, true);
+            let dns_cfg = dns::ResolverConfig::from_parts(None, Vec::new(), nameservers);
+            dns::TokioDnsConfig::custom(transport, dns_cfg, dns_opts)?.boxed()
+        }
+        DnsResolver::None => transport.boxed(),
+    };
+
+    Ok((transport, relay_client))
+}
+
+/// Orders `addrs` for dialing, the ones of the preferred transport first. The order of
+/// the other addresses is kept.
+pub(crate) fn sort_dial_addrs(addrs: &mut [Multiaddr], prefer_quic: bool) {
+    addrs.sort_by_key(|addr| is_quic(addr) != prefer_quic);
+}
+
+pub(crate) fn is_quic(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| matches!(p, Protocol::Quic))
 }
 
 pub(crate) async fn build_swarm(
     config: &Libp2pConfig,
     keypair: &Keypair,
+    psk: Option<PreSharedKey>,
+    kad_store_path: Option<&Path>,
     rpc_client: Client,
 ) -> Result<Swarm<NodeBehaviour>> {
     let peer_id = keypair.public().to_peer_id();
 
-    let (transport, relay_client) = build_transport(keypair, config).await;
-    let behaviour = NodeBehaviour::new(keypair, config, relay_client, rpc_client).await?;
+    let (transport, relay_client) = build_transport(keypair, config, psk).await?;
+    let behaviour =
+        NodeBehaviour::new(keypair, config, kad_store_path, relay_client, rpc_client).await?;
 
     let limits = ConnectionLimits::default()
         .with_max_pending_incoming(Some(config.max_conns_pending_in))
@@ -104,15 +246,71 @@ pub(crate) async fn build_swarm(
         .with_max_established_incoming(Some(config.max_conns_in))
         .with_max_established_outgoing(Some(config.max_conns_out))
         .with_max_established_per_peer(Some(config.max_conns_per_peer));
-    let swarm = SwarmBuilder::new(transport, behaviour, peer_id)
+    let swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, peer_id)
         .connection_limits(limits)
         .notify_handler_buffer_size(config.notify_handler_buffer_size.try_into()?)
         .connection_event_buffer_size(config.connection_event_buffer_size)
         .dial_concurrency_factor(config.dial_concurrency_factor.try_into().unwrap())
-        .executor(Box::new(|fut| {
-            tokio::task::spawn(fut);
-        }))
         .build();
 
     Ok(swarm)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_swarm_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("swarm.key");
+        let key = "/key/swarm/psk/1.0.0/\n/base16/\n\
+            6189c5cf0b87fb800c1a9feeda73c6ab5e998db48fb9e6a978575c770ceef683\n";
+        tokio::fs::write(&path, key).await.unwrap();
+        let psk = load_swarm_key(&path).await.unwrap();
+        // `Display` writes the key back in the same file format
+        assert_eq!(psk.to_string(), key);
+
+        tokio::fs::write(&path, "/key/swarm/psk/1.0.0/\n/base64/\nfoo\n")
+            .await
+            .unwrap();
+        assert!(load_swarm_key(&path).await.is_err());
+        assert!(load_swarm_key(&dir.path().join("missing.key"))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_ensure_private_bootstrap() {
+        let private: Multiaddr = "/ip4/10.0.0.1/tcp/4444".parse().unwrap();
+        assert!(ensure_private_bootstrap(&[]).is_ok());
+        assert!(ensure_private_bootstrap(&[private.clone()]).is_ok());
+
+        let public: Multiaddr = DEFAULT_BOOTSTRAP[0].parse().unwrap();
+        assert!(ensure_private_bootstrap(&[private.clone(), public]).is_err());
+
+        // the same peers under other addresses
+        let public_peer: Multiaddr =
+            "/ip4/127.0.0.1/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+                .parse()
+                .unwrap();
+        assert!(ensure_private_bootstrap(&[public_peer]).is_err());
+        let public_host: Multiaddr = "/dnsaddr/bootstrap.libp2p.io".parse().unwrap();
+        assert!(ensure_private_bootstrap(&[public_host]).is_err());
+        let private_peer = private.clone().with(Protocol::P2p(PeerId::random().into()));
+        assert!(ensure_private_bootstrap(&[private, private_peer]).is_ok());
+    }
+
+    #[test]
+    fn test_sort_dial_addrs() {
+        let tcp: Multiaddr = "/ip4/10.0.0.1/tcp/4444".parse().unwrap();
+        let ws: Multiaddr = "/ip4/10.0.0.1/tcp/4445/ws".parse().unwrap();
+        let quic: Multiaddr = "/ip4/10.0.0.1/udp/4444/quic".parse().unwrap();
+
+        let mut addrs = vec![tcp.clone(), quic.clone(), ws.clone()];
+        sort_dial_addrs(&mut addrs, true);
+        assert_eq!(addrs, vec![quic.clone(), tcp.clone(), ws.clone()]);
+        sort_dial_addrs(&mut addrs, false);
+        assert_eq!(addrs, vec![tcp, ws, quic]);
+    }
+}
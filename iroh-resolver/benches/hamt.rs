@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use cid::Cid;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::{StreamExt, TryStreamExt};
+use iroh_resolver::resolver::{
+    ContentLoader, ContextId, LoadedCid, LoaderContext, OutMetrics, Resolver, Source,
+};
+use tokio::runtime::Runtime;
+
+/// Hamt sharded directory with 10000 files and a nested directory,
+/// created with `ipfs add --recursive`.
+const ROOT_CID: &str = "QmUu8pzQ5yjhDrg4GiHYLeko2oT76vcmYX5bw6sjiEJ82k";
+
+#[derive(Debug, Clone)]
+struct MemLoader(Arc<HashMap<Cid, Bytes>>);
+
+#[async_trait]
+impl ContentLoader for MemLoader {
+    async fn load_cid(&self, cid: &Cid, _ctx: &LoaderContext) -> Result<LoadedCid> {
+        match self.0.get(cid) {
+            Some(data) => Ok(LoadedCid {
+                data: data.clone(),
+                source: Source::Store("mem"),
+            }),
+            None => bail!("not found"),
+        }
+    }
+
+    async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+        Ok(())
+    }
+
+    async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+        Ok(self.0.contains_key(cid))
+    }
+}
+
+async fn load_fixture() -> MemLoader {
+    let file = tokio::fs::File::open("./fixtures/big-foo.car")
+        .await
+        .unwrap();
+    let car_reader = iroh_car::CarReader::new(tokio::io::BufReader::new(file))
+        .await
+        .unwrap();
+    let blocks = car_reader
+        .stream()
+        .map(|r| r.map(|(k, v)| (k, Bytes::from(v))))
+        .try_collect()
+        .await
+        .unwrap();
+    MemLoader(Arc::new(blocks))
+}
+
+pub fn hamt_benchmark(c: &mut Criterion) {
+    let executor = Runtime::new().unwrap();
+    // the resolver spawns its session worker, so it needs the runtime
+    let resolver = executor.block_on(async { Resolver::new(load_fixture().await) });
+    let resolver = &resolver;
+
+    let mut group = c.benchmark_group("hamt");
+    for name in ["1.txt", "9999.txt", "bar/bar.txt"] {
+        group.bench_with_input(BenchmarkId::new("lookup", name), name, |b, name| {
+            let path = &format!("/ipfs/{ROOT_CID}/{name}");
+            b.to_async(&executor).iter(|| async move {
+                let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+                black_box(out)
+            });
+        });
+    }
+    group.bench_function("list", |b| {
+        let path = &format!("/ipfs/{ROOT_CID}");
+        b.to_async(&executor).iter(|| async move {
+            let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+            let links: Vec<_> = out
+                .unixfs_read_dir(resolver, OutMetrics::default())
+                .unwrap()
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            black_box(links)
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, hamt_benchmark);
+criterion_main!(benches);
@@ -53,10 +53,14 @@ pub fn add_benchmark(c: &mut Criterion) {
                 });
                 let rpc_ref = &rpc;
                 b.to_async(&executor).iter(|| async move {
-                    let stream =
-                        iroh_resolver::unixfs_builder::add_file(Some(rpc_ref), path, false)
-                            .await
-                            .unwrap();
+                    let stream = iroh_resolver::unixfs_builder::add_file(
+                        Some(rpc_ref),
+                        path,
+                        false,
+                        &Default::default(),
+                    )
+                    .await
+                    .unwrap();
                     // we have to consume the stream here, otherwise we are
                     // not actually benchmarking anything
                     // TODO(faassen) rewrite the benchmark in terms of the iroh-api which
@@ -0,0 +1,173 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use cid::Cid;
+use iroh_metrics::{core::MRecorder, inc, resolver::ResolverMetrics};
+use lru::LruCache;
+
+use crate::resolver::{ContentLoader, ContextId, LoadedCid, LoaderContext, Source};
+
+/// Default size of the block cache in bytes.
+pub const DEFAULT_BLOCK_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A [`ContentLoader`] that keeps recently loaded blocks in memory, so hot
+/// blocks like directory roots and HAMT shards are not fetched again.
+///
+/// The cache holds at most `capacity` bytes of block data, evicting the least
+/// recently used blocks first. A capacity of `0` turns the cache off.
+#[derive(Debug, Clone)]
+pub struct Cached<T: ContentLoader> {
+    loader: T,
+    cache: Arc<Mutex<BlockCache>>,
+}
+
+#[derive(Debug)]
+struct BlockCache {
+    blocks: LruCache<Cid, (Bytes, Source)>,
+    size: u64,
+    capacity: u64,
+}
+
+impl BlockCache {
+    fn get(&mut self, cid: &Cid) -> Option<LoadedCid> {
+        self.blocks.get(cid).map(|(data, source)| LoadedCid {
+            data: data.clone(),
+            source: source.clone(),
+        })
+    }
+
+    fn insert(&mut self, cid: Cid, loaded: &LoadedCid) {
+        let len = loaded.data.len() as u64;
+        if len > self.capacity {
+            return;
+        }
+        if let Some((data, _)) = self
+            .blocks
+            .put(cid, (loaded.data.clone(), loaded.source.clone()))
+        {
+            self.size -= data.len() as u64;
+        }
+        self.size += len;
+        while self.size > self.capacity {
+            match self.blocks.pop_lru() {
+                Some((_, (data, _))) => self.size -= data.len() as u64,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T: ContentLoader> Cached<T> {
+    /// Wraps `loader` in a cache holding up to `capacity` bytes.
+    pub fn new(loader: T, capacity: u64) -> Self {
+        Cached {
+            loader,
+            cache: Arc::new(Mutex::new(BlockCache {
+                blocks: LruCache::unbounded(),
+                size: 0,
+                capacity,
+            })),
+        }
+    }
+
+    pub fn loader(&self) -> &T {
+        &self.loader
+    }
+
+    /// Returns the number of bytes currently cached.
+    pub fn size(&self) -> u64 {
+        self.cache.lock().unwrap().size
+    }
+}
+
+#[async_trait]
+impl<T: ContentLoader> ContentLoader for Cached<T> {
+    async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
+        if let Some(loaded) = self.cache.lock().unwrap().get(cid) {
+            inc!(ResolverMetrics::BlockCacheHit);
+            return Ok(loaded);
+        }
+        inc!(ResolverMetrics::BlockCacheMiss);
+        let loaded = self.loader.load_cid(cid, ctx).await?;
+        self.cache.lock().unwrap().insert(*cid, &loaded);
+        Ok(loaded)
+    }
+
+    async fn stop_session(&self, ctx: ContextId) -> Result<()> {
+        self.loader.stop_session(ctx).await
+    }
+
+    async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+        if self.cache.lock().unwrap().blocks.contains(cid) {
+            return Ok(true);
+        }
+        self.loader.has_cid(cid).await
+    }
+
+    async fn load_records(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        self.loader.load_records(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::patch::tests::MemStore;
+    use crate::resolver::{Path, Resolver};
+    use crate::unixfs::UnixfsNode;
+
+    fn put_raw(store: &MemStore, data: &'static [u8]) -> Cid {
+        let block = UnixfsNode::Raw(Bytes::from_static(data)).encode().unwrap();
+        let cid = *block.cid();
+        store
+            .blocks
+            .lock()
+            .unwrap()
+            .insert(cid, block.data().clone());
+        cid
+    }
+
+    #[tokio::test]
+    async fn test_cached_loader() -> Result<()> {
+        let store = MemStore::default();
+        let cids = [
+            put_raw(&store, b"hello"),
+            put_raw(&store, b"world"),
+            put_raw(&store, b"iroh!"),
+        ];
+        let resolver = Resolver::new(Cached::new(store.clone(), 10));
+
+        for cid in &cids {
+            resolver.resolve(Path::from_cid(*cid)).await?;
+        }
+        assert_eq!(store.loaded.load(Ordering::SeqCst), 3);
+        // only the two most recent blocks fit
+        assert_eq!(resolver.loader().size(), 10);
+
+        for cid in &cids[1..] {
+            resolver.resolve(Path::from_cid(*cid)).await?;
+        }
+        assert_eq!(store.loaded.load(Ordering::SeqCst), 3);
+
+        resolver.resolve(Path::from_cid(cids[0])).await?;
+        assert_eq!(store.loaded.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_loader_disabled() -> Result<()> {
+        let store = MemStore::default();
+        let cid = put_raw(&store, b"hello");
+        let resolver = Resolver::new(Cached::new(store.clone(), 0));
+
+        resolver.resolve(Path::from_cid(cid)).await?;
+        resolver.resolve(Path::from_cid(cid)).await?;
+        assert_eq!(store.loaded.load(Ordering::SeqCst), 2);
+        assert_eq!(resolver.loader().size(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::unixfs_builder::WalkOptions;
+
+/// Remembers the root cid and tsize of files added from disk, so adding them
+/// again can skip chunking and hashing the files that did not change.
+///
+/// A file counts as unchanged as long as its size, modification time, inode and
+/// permissions are the same, and it is added with the same options to preserve
+/// its mode and mtime. The cached cids are only reused while their blocks are
+/// still in the store.
+#[derive(Debug, Clone)]
+pub struct ChangeCache {
+    path: PathBuf,
+    /// Relative paths are resolved against the working directory at load time.
+    base: PathBuf,
+    files: Arc<Mutex<HashMap<PathBuf, CachedFile>>>,
+}
+
+impl PartialEq for ChangeCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.files, &other.files)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileKey {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    inode: u64,
+    mode: u32,
+    /// The recorded attributes are part of the encoding of the file.
+    preserve_mode: bool,
+    preserve_mtime: bool,
+}
+
+impl FileKey {
+    fn new(metadata: &Metadata, options: &WalkOptions) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        #[cfg(unix)]
+        let (inode, mode) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.ino(), metadata.mode())
+        };
+        #[cfg(not(unix))]
+        let (inode, mode) = (0, 0);
+        FileKey {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            inode,
+            mode,
+            preserve_mode: options.preserve_mode,
+            preserve_mtime: options.preserve_mtime,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFile {
+    key: FileKey,
+    cid: Vec<u8>,
+    tsize: u64,
+}
+
+impl ChangeCache {
+    /// Loads the cache stored at `path`. The cache starts out empty if there is
+    /// no such file yet, or if it can not be read.
+    pub async fn load<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let files = match tokio::fs::read(&path).await {
+            Ok(data) => bincode::deserialize(&data).unwrap_or_else(|err| {
+                warn!("ignoring broken change cache {}: {}", path.display(), err);
+                HashMap::new()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read change cache {}", path.display()))
+            }
+        };
+        Ok(ChangeCache {
+            path,
+            base: std::env::current_dir()?,
+            files: Arc::new(Mutex::new(files)),
+        })
+    }
+
+    /// Writes the cache back to the path it was loaded from.
+    pub async fn save(&self) -> Result<()> {
+        let data = bincode::serialize(&*self.files.lock().unwrap())?;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // an interrupted save must not leave a truncated cache behind
+        let tmp_path = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+        Ok(())
+    }
+
+    /// Returns the root cid and tsize of the file at `path` if it was added
+    /// before with the same `options` and did not change since.
+    pub fn get(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        options: &WalkOptions,
+    ) -> Option<(Cid, u64)> {
+        let files = self.files.lock().unwrap();
+        let file = files.get(&self.base.join(path))?;
+        if file.key != FileKey::new(metadata, options) {
+            return None;
+        }
+        let cid = Cid::try_from(file.cid.as_slice()).ok()?;
+        Some((cid, file.tsize))
+    }
+
+    /// Records the root cid and tsize of the file at `path`, as it was described
+    /// by `metadata` before it was read and encoded with `options`.
+    pub fn insert(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        options: &WalkOptions,
+        cid: Cid,
+        tsize: u64,
+    ) {
+        self.pending(path, metadata, options).insert(cid, tsize);
+    }
+
+    /// Prepares an entry for the file at `path`, to be inserted once it is encoded.
+    pub(crate) fn pending(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        options: &WalkOptions,
+    ) -> PendingFile {
+        PendingFile {
+            cache: self.clone(),
+            path: self.base.join(path),
+            key: FileKey::new(metadata, options),
+        }
+    }
+}
+
+/// A file that is recorded in the [`ChangeCache`] once its root is known.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PendingFile {
+    cache: ChangeCache,
+    path: PathBuf,
+    key: FileKey,
+}
+
+impl PendingFile {
+    pub(crate) fn insert(self, cid: Cid, tsize: u64) {
+        let file = CachedFile {
+            key: self.key,
+            cid: cid.to_bytes(),
+            tsize,
+        };
+        self.cache.files.lock().unwrap().insert(self.path, file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_change_cache() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("data.bin");
+        std::fs::write(&file, b"hello")?;
+        let cid: Cid = "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn".parse()?;
+
+        let cache_path = dir.path().join("cache").join("change_cache");
+        let cache = ChangeCache::load(&cache_path).await?;
+        let metadata = std::fs::metadata(&file)?;
+        let options = WalkOptions::default();
+        assert_eq!(cache.get(&file, &metadata, &options), None);
+        cache.insert(&file, &metadata, &options, cid, 13);
+        cache.save().await?;
+
+        let cache = ChangeCache::load(&cache_path).await?;
+        assert_eq!(cache.get(&file, &metadata, &options), Some((cid, 13)));
+        let preserve = WalkOptions {
+            preserve_mtime: true,
+            ..Default::default()
+        };
+        assert_eq!(cache.get(&file, &metadata, &preserve), None);
+
+        std::fs::write(&file, b"hello world")?;
+        let metadata = std::fs::metadata(&file)?;
+        assert_eq!(cache.get(&file, &metadata, &options), None);
+        Ok(())
+    }
+}
@@ -35,38 +35,29 @@ impl Chunker {
                 let chunk_size = *chunk_size;
                 async_stream::stream! {
                     let mut buffer = BytesMut::with_capacity(chunk_size);
-                    let mut current_len = 0;
 
                     loop {
-                        if current_len == 0 {
-                            buffer.clear();
-                        }
-                        match source.read_buf(&mut buffer).await {
-                            Ok(len) => {
-                                current_len += len;
-                                if current_len == chunk_size {
+                        // never read beyond the current chunk, so at most a single
+                        // chunk is buffered, no matter how large the source is
+                        let remaining = (chunk_size - buffer.len()) as u64;
+                        match (&mut source).take(remaining).read_buf(&mut buffer).await {
+                            Ok(0) => {
+                                // finished reading
+                                if !buffer.is_empty() {
+                                    yield Ok(buffer.split());
+                                }
+                                break;
+                            }
+                            Ok(_) => {
+                                if buffer.len() == chunk_size {
                                     // read a full chunk
-                                    current_len = 0;
-                                    yield Ok(buffer.clone());
-                                } else if current_len < chunk_size && len > 0 {
-                                    // not done reading, read again
-                                    continue;
-                                } else if current_len > chunk_size {
-                                    // read more than a chunk, emit only a single chunk
-                                    let out = buffer.split_to(chunk_size);
-                                    current_len -= chunk_size;
-                                    yield Ok(out);
-                                } else {
-                                    // finished reading
-                                    debug_assert!(len == 0);
-                                    if current_len > 0 {
-                                        yield Ok(buffer.clone());
-                                    }
-                                    break;
+                                    yield Ok(buffer.split());
+                                    buffer.reserve(chunk_size);
                                 }
                             }
                             Err(err) => {
                                 yield Err(err);
+                                break;
                             }
                         }
                     }
@@ -78,7 +69,13 @@ impl Chunker {
 
 #[cfg(test)]
 mod tests {
-    use futures::TryStreamExt;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures::{StreamExt, TryStreamExt};
+    use tokio::io::ReadBuf;
 
     use super::*;
 
@@ -123,4 +120,33 @@ mod tests {
             assert_eq!(&chunks[4], &[5u8; 2][..]);
         }
     }
+
+    #[tokio::test]
+    async fn test_fixed_chunker_bounded_reads() {
+        struct CountingReader {
+            read: Arc<AtomicUsize>,
+        }
+
+        impl AsyncRead for CountingReader {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                _cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                let len = buf.remaining();
+                buf.put_slice(&vec![1u8; len]);
+                self.read.fetch_add(len, Ordering::SeqCst);
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        // the source never ends, only the consumed chunks are read from it
+        let read = Arc::new(AtomicUsize::default());
+        let source = CountingReader { read: read.clone() };
+        let chunker = Chunker::fixed_with_size(256);
+        let chunks: Vec<_> = chunker.chunks(source).take(3).try_collect().await.unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() == 256));
+        assert_eq!(read.load(Ordering::SeqCst), 3 * 256);
+    }
 }
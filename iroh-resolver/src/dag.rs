@@ -0,0 +1,143 @@
+//! Generic IPLD data, encoded with any of the supported codecs.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use libipld::prelude::Codec as _;
+use libipld::{Ipld, IpldCodec};
+
+use crate::codecs::Codec;
+use crate::resolver::Block;
+
+/// The result of resolving a path through generic IPLD data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagResolved {
+    /// The last block reached.
+    pub cid: Cid,
+    /// The part of the path that was resolved inside of that block.
+    pub rem_path: Vec<String>,
+    /// The node at the end of the path.
+    pub node: Ipld,
+}
+
+/// Maps a codec to its IPLD implementation, if it is supported.
+pub fn ipld_codec(codec: Codec) -> Result<IpldCodec> {
+    match codec {
+        Codec::DagPb => Ok(IpldCodec::DagPb),
+        Codec::DagCbor => Ok(IpldCodec::DagCbor),
+        Codec::DagJson => Ok(IpldCodec::DagJson),
+        Codec::Raw => Ok(IpldCodec::Raw),
+        _ => bail!("unsupported codec {:?}", codec),
+    }
+}
+
+/// Decodes `data` as IPLD, using the codec of `cid`.
+pub fn decode(cid: &Cid, data: &[u8]) -> Result<Ipld> {
+    let codec = Codec::try_from(cid.codec()).context("unknown codec")?;
+    ipld_codec(codec)?
+        .decode(data)
+        .map_err(|e| anyhow!("invalid {:?}: {:?}", codec, e))
+}
+
+/// Encodes `ipld` with `codec`, without creating a block.
+pub fn encode_bytes(ipld: &Ipld, codec: Codec) -> Result<Bytes> {
+    let bytes = ipld_codec(codec)?
+        .encode(ipld)
+        .map_err(|e| anyhow!("cannot encode as {:?}: {:?}", codec, e))?;
+    Ok(bytes.into())
+}
+
+/// Encodes `ipld` with `codec` into a block, addressed by a CIDv1 sha2-256.
+pub fn encode(ipld: &Ipld, codec: Codec) -> Result<Block> {
+    let data = encode_bytes(ipld, codec)?;
+    let cid = Cid::new_v1(codec.into(), Code::Sha2_256.digest(&data));
+    let mut links = Vec::new();
+    ipld.references(&mut links);
+    Ok(Block::new(cid, data, links))
+}
+
+/// Parses dag-json `input` and encodes it with `codec` into a block.
+///
+/// For dag-pb the input has to follow the dag-pb data model, a map with a
+/// `Links` list and optional `Data` bytes.
+pub fn from_json(input: &[u8], codec: Codec) -> Result<Block> {
+    let ipld: Ipld = IpldCodec::DagJson
+        .decode(input)
+        .map_err(|e| anyhow!("invalid dag json: {:?}", e))?;
+    encode(&ipld, codec)
+}
+
+/// Returns the child of `node` named `part`.
+///
+/// Links of dag-pb nodes can also be addressed by their name, as in unixfs
+/// paths, if `node` is the root of its block.
+pub(crate) fn child(node: Ipld, codec: Codec, part: &str, block_root: bool) -> Result<Ipld> {
+    if codec == Codec::DagPb && block_root && part != "Data" && part != "Links" {
+        if let Ipld::List(links) = node.get("Links")? {
+            for link in links {
+                if link.get("Name").ok() == Some(&Ipld::String(part.to_string())) {
+                    return Ok(link.get("Hash")?.clone());
+                }
+            }
+        }
+        bail!("no link named {:?}", part);
+    }
+
+    let index: libipld::ipld::IpldIndex = match part.parse::<usize>() {
+        Ok(i) => i.into(),
+        Err(_) => part.to_string().into(),
+    };
+    Ok(node.take(index)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json() {
+        let input = br#"{"hello": "world", "list": [1, 2], "link": {"/": "bafkqaaa"}}"#;
+        let cbor = from_json(input, Codec::DagCbor).unwrap();
+        let json = from_json(input, Codec::DagJson).unwrap();
+        assert_eq!(cbor.cid().codec(), u64::from(Codec::DagCbor));
+        assert_eq!(json.cid().codec(), u64::from(Codec::DagJson));
+        assert_eq!(cbor.links(), &["bafkqaaa".parse::<Cid>().unwrap()]);
+
+        let decoded = decode(cbor.cid(), cbor.data()).unwrap();
+        assert_eq!(decoded, decode(json.cid(), json.data()).unwrap());
+        assert_eq!(
+            child(decoded.clone(), Codec::DagCbor, "hello", true).unwrap(),
+            Ipld::String("world".into())
+        );
+        assert_eq!(
+            child(decoded, Codec::DagCbor, "list", true)
+                .and_then(|list| child(list, Codec::DagCbor, "1", false))
+                .unwrap(),
+            Ipld::Integer(2)
+        );
+
+        let pb = from_json(
+            br#"{"Links": [{"Hash": {"/": "bafkqaaa"}, "Name": "a", "Tsize": 0}]}"#,
+            Codec::DagPb,
+        )
+        .unwrap();
+        let decoded = decode(pb.cid(), pb.data()).unwrap();
+        assert_eq!(
+            child(decoded.clone(), Codec::DagPb, "a", true).unwrap(),
+            Ipld::Link("bafkqaaa".parse().unwrap())
+        );
+        // below the root, fields are addressed as in any other codec
+        let link = child(decoded, Codec::DagPb, "Links", true)
+            .and_then(|links| child(links, Codec::DagPb, "0", false))
+            .unwrap();
+        assert_eq!(
+            child(link.clone(), Codec::DagPb, "Name", false).unwrap(),
+            Ipld::String("a".into())
+        );
+        assert!(child(link, Codec::DagPb, "a", false).is_err());
+
+        assert!(from_json(b"{", Codec::DagCbor).is_err());
+        assert!(from_json(b"{}", Codec::Sha2256).is_err());
+    }
+}
@@ -1,18 +1,20 @@
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use async_recursion::async_recursion;
 use futures::{stream::BoxStream, Stream, StreamExt};
 use once_cell::sync::OnceCell;
 
 use crate::{
-    resolver::{ContentLoader, LoaderContext, OutContent, Path, Resolver},
+    resolver::{ContentLoader, LoaderContext, Resolver},
     unixfs::{self, HamtHashFunction, Link, Links, PbLinks, UnixfsNode},
 };
 
+pub(crate) use self::patch::{find_link, patch_link};
 use self::{bitfield::Bitfield, hash_bits::HashBits};
 
 #[allow(dead_code)]
 mod bitfield;
 mod hash_bits;
+mod patch;
 
 const HASH_BIT_LENGTH: usize = 8;
 
@@ -84,39 +86,30 @@ impl Hamt {
 
 impl InnerNode {
     pub async fn load_from_link<C: ContentLoader>(
-        ctx: crate::resolver::LoaderContext,
+        mut ctx: crate::resolver::LoaderContext,
         link: &Link,
         loader: &Resolver<C>,
     ) -> Result<Self> {
-        let path = Path::from_cid(link.cid);
-        let out = loader.resolve_with_ctx(ctx, path).await?;
-
-        match out.content {
-            OutContent::Unixfs(value) => match value {
-                UnixfsNode::HamtShard(_, ref hamt) => Ok(InnerNode::Node {
-                    node: hamt.root.clone(),
-                    value,
-                }),
-                UnixfsNode::RawNode(_)
-                | UnixfsNode::File(_)
-                | UnixfsNode::Directory(_)
-                | UnixfsNode::Raw(_)
-                | UnixfsNode::Symlink(_) => Ok(InnerNode::Leaf {
-                    link: link.clone(),
-                    value,
-                }),
-            },
-            OutContent::Raw(_, bytes) => {
-                let node = UnixfsNode::decode(&link.cid, bytes)?;
-
-                Ok(InnerNode::Leaf {
-                    link: link.clone(),
-                    value: node,
-                })
-            }
-            _ => bail!("unexpected node: {:?}", out.content.typ()),
+        let loaded_cid = loader.load_cid(&link.cid, &mut ctx).await?;
+        let value = UnixfsNode::decode(&link.cid, loaded_cid.data)?;
+
+        match value {
+            UnixfsNode::HamtShard(_, ref hamt) => Ok(InnerNode::Node {
+                node: hamt.root.clone(),
+                value,
+            }),
+            UnixfsNode::RawNode(_)
+            | UnixfsNode::File(_)
+            | UnixfsNode::Directory(_)
+            | UnixfsNode::Raw(_)
+            | UnixfsNode::Symlink(_)
+            | UnixfsNode::Metadata(_) => Ok(InnerNode::Leaf {
+                link: link.clone(),
+                value,
+            }),
         }
     }
+
     fn children<'a, 'b: 'a, C: ContentLoader>(
         &'a self,
         ctx: LoaderContext,
@@ -162,7 +155,9 @@ impl Node {
         let fanout = node.fanout().unwrap_or(DEFAULT_FANOUT);
         ensure!(fanout > 0, "fanout must be non zero");
 
-        let data = node.data().as_ref().unwrap().clone();
+        let data = node
+            .data()
+            .ok_or_else(|| anyhow!("hamt: missing bitfield data"))?;
         let bitfield = Bitfield::from_slice(&data[..])?;
 
         let links = Links::HamtShard(PbLinks::new(&node.outer));
@@ -218,35 +213,24 @@ impl Node {
         }
 
         let cindex = self.index_for_bit_pos(idx);
-        let child = self.get_child(cindex);
-        let cached_node = self.load_child(ctx.clone(), loader, child).await?;
-        match cached_node {
-            InnerNode::Node { node, value } => {
-                let name = child
-                    .link
-                    .name
-                    .as_ref()
-                    .map(|s| &s.as_bytes()[self.padding_len..])
-                    .unwrap_or_default();
-
-                if key == name {
-                    Ok(Some((&child.link, value)))
-                } else {
+        let child = self.get_child(cindex)?;
+        if self.is_shard(&child.link) {
+            match self.load_child(ctx.clone(), loader, child).await? {
+                InnerNode::Node { node, .. } => {
                     node.get_value(ctx, loader, hashed_key, key, depth + 1)
                         .await
                 }
+                InnerNode::Leaf { .. } => bail!("hamt: expected a shard at {:?}", child.link),
             }
-            InnerNode::Leaf { link, value } => {
-                let name = link
-                    .name
-                    .as_ref()
-                    .map(|s| &s.as_bytes()[self.padding_len..])
-                    .unwrap_or_default();
-                if key == name {
-                    Ok(Some((link, value)))
-                } else {
-                    Ok(None)
-                }
+        } else {
+            // Only the entry with the matching name is loaded, the other entries
+            // of the bucket are never touched.
+            if self.strip_padding(&child.link)? != key {
+                return Ok(None);
+            }
+            match self.load_child(ctx, loader, child).await? {
+                InnerNode::Node { value, .. } => Ok(Some((&child.link, value))),
+                InnerNode::Leaf { link, value } => Ok(Some((link, value))),
             }
         }
     }
@@ -271,8 +255,30 @@ impl Node {
         mask.and(&self.bitfield).count_ones()
     }
 
-    fn get_child(&self, i: usize) -> &NodeLink {
-        &self.pointers[i]
+    fn get_child(&self, i: usize) -> Result<&NodeLink> {
+        self.pointers
+            .get(i)
+            .ok_or_else(|| anyhow!("hamt: missing link for index {}", i))
+    }
+
+    /// Returns true if the link points to a nested shard, whose name only consists of
+    /// the hex prefix.
+    fn is_shard(&self, link: &Link) -> bool {
+        match link.name {
+            Some(ref name) => name.len() <= self.padding_len,
+            None => true,
+        }
+    }
+
+    /// Returns the name of the link, without the hex prefix used for hamt shards.
+    fn strip_padding<'a>(&self, link: &'a Link) -> Result<&'a [u8]> {
+        match link.name {
+            Some(ref name) => name
+                .as_bytes()
+                .get(self.padding_len..)
+                .ok_or_else(|| anyhow!("hamt: invalid link name {:?}", name)),
+            None => Ok(&[]),
+        }
     }
 
     fn children<'a, 'b: 'a, C: ContentLoader>(
@@ -285,11 +291,12 @@ impl Node {
             for pointer in &self.pointers {
                 if let Some(ref name) = pointer.link.name {
                     if name.len() > padding_len {
+                        let name = name
+                            .get(padding_len..)
+                            .ok_or_else(|| anyhow!("hamt: invalid link name {:?}", name))?;
                         yield Link {
                             cid: pointer.link.cid,
-                            name: pointer.link.name.as_ref().map(|n| {
-                                std::str::from_utf8(&n.as_bytes()[padding_len..]).unwrap().to_string()
-                            }),
+                            name: Some(name.to_string()),
                             tsize: pointer.link.tsize,
                         };
                     } else {
@@ -0,0 +1,258 @@
+//! Modification of hamt sharded directories.
+//!
+//! Only the shards on the path to the modified entry are loaded and re-encoded,
+//! all other shards are kept as they are.
+
+use anyhow::{anyhow, bail, Result};
+use async_recursion::async_recursion;
+use bytes::Bytes;
+use cid::Cid;
+
+use super::{bitfield::Bitfield, hash_bits::HashBits, hash_key, Hamt, HASH_BIT_LENGTH};
+use crate::{
+    patch::{block_link, link_cid},
+    resolver::{Block, ContentLoader, OutContent, Path, Resolver},
+    unixfs::{self, dag_pb, unixfs_pb, DataType, HamtHashFunction, UnixfsNode},
+    unixfs_builder::{encode_unixfs_pb, Store},
+};
+
+/// A single shard, decoded for modification.
+struct Shard {
+    inner: unixfs_pb::Data,
+    links: Vec<dag_pb::PbLink>,
+    bitfield: Bitfield,
+    bit_width: u32,
+    padding_len: usize,
+}
+
+/// What a link in a shard points to.
+enum Pointer {
+    /// A shard one level down.
+    Shard(Cid),
+    /// A directory entry, with the hex prefix stripped from its name.
+    Value(String),
+}
+
+impl Shard {
+    fn from_node(node: unixfs::Node) -> Result<Self> {
+        let hamt = Hamt::from_node(&node)?;
+        Ok(Shard {
+            inner: node.inner,
+            links: node.outer.links,
+            bitfield: hamt.root.bitfield,
+            bit_width: hamt.root.bit_width,
+            padding_len: hamt.root.padding_len,
+        })
+    }
+
+    async fn load<C: ContentLoader>(resolver: &Resolver<C>, cid: Cid) -> Result<Self> {
+        let out = resolver.resolve(Path::from_cid(cid)).await?;
+        match out.content {
+            OutContent::Unixfs(UnixfsNode::HamtShard(node, _)) => Shard::from_node(node),
+            _ => bail!("hamt: {} is not a shard", cid),
+        }
+    }
+
+    /// An empty shard with the same layout as this one.
+    fn empty_child(&self) -> Self {
+        Shard {
+            inner: unixfs_pb::Data {
+                r#type: DataType::HamtShard as i32,
+                hash_type: Some(HamtHashFunction::Murmur3.into()),
+                fanout: self.inner.fanout,
+                ..Default::default()
+            },
+            links: Vec::new(),
+            bitfield: Bitfield::zero(),
+            bit_width: self.bit_width,
+            padding_len: self.padding_len,
+        }
+    }
+
+    /// The hex prefix of link names at `idx`.
+    fn prefix(&self, idx: u32) -> String {
+        format!("{:0width$X}", idx, width = self.padding_len)
+    }
+
+    /// The position in `links` of the link at `idx`.
+    fn position(&self, idx: u32) -> usize {
+        Bitfield::zero()
+            .set_bits_le(idx)
+            .and(&self.bitfield)
+            .count_ones()
+    }
+
+    fn pointer(&self, pos: usize) -> Result<Pointer> {
+        let link = self
+            .links
+            .get(pos)
+            .ok_or_else(|| anyhow!("hamt: missing link for index {}", pos))?;
+        let name = link.name.as_deref().unwrap_or_default();
+        let value = name
+            .get(self.padding_len..)
+            .ok_or_else(|| anyhow!("hamt: invalid link name {:?}", name))?;
+        if value.is_empty() {
+            Ok(Pointer::Shard(link_cid(link)?))
+        } else {
+            Ok(Pointer::Value(value.to_string()))
+        }
+    }
+
+    fn remove(&mut self, idx: u32, pos: usize) {
+        self.links.remove(pos);
+        self.bitfield.clear_bit(idx);
+    }
+
+    fn encode(&self) -> Result<Block> {
+        let fanout = self.inner.fanout.unwrap_or(super::DEFAULT_FANOUT as u64) as usize;
+        let bitfield = self.bitfield.as_bytes();
+        let len = ((fanout + 7) / 8).min(bitfield.len());
+
+        let mut inner = self.inner.clone();
+        inner.data = Some(Bytes::copy_from_slice(&bitfield[bitfield.len() - len..]));
+        let outer = encode_unixfs_pb(&inner, self.links.clone())?;
+        let node = unixfs::Node { outer, inner };
+        let hamt = Hamt::from_node(&node)?;
+
+        UnixfsNode::HamtShard(node, hamt).encode()
+    }
+}
+
+/// Returns the link for the entry `name` in the sharded directory `node`,
+/// with the hex prefix stripped from its name.
+pub(crate) async fn find_link<C: ContentLoader>(
+    resolver: &Resolver<C>,
+    node: unixfs::Node,
+    name: &str,
+) -> Result<Option<dag_pb::PbLink>> {
+    let hash = hash_key(name.as_bytes());
+    let mut bits = HashBits::new(&hash);
+    let mut shard = Shard::from_node(node)?;
+    loop {
+        let idx = bits.next(shard.bit_width)?;
+        if !shard.bitfield.test_bit(idx) {
+            return Ok(None);
+        }
+        let pos = shard.position(idx);
+        match shard.pointer(pos)? {
+            Pointer::Shard(cid) => shard = Shard::load(resolver, cid).await?,
+            Pointer::Value(value) if value == name => {
+                let mut link = shard.links.swap_remove(pos);
+                link.name = Some(value);
+                return Ok(Some(link));
+            }
+            Pointer::Value(_) => return Ok(None),
+        }
+    }
+}
+
+/// Sets the entry `name` in the sharded directory `node` to `link`, or removes it
+/// if `link` is `None`. All modified shards are stored, the new root shard is
+/// returned.
+pub(crate) async fn patch_link<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    node: unixfs::Node,
+    name: &str,
+    link: Option<dag_pb::PbLink>,
+) -> Result<Block> {
+    let mut shard = Shard::from_node(node)?;
+    let hash = hash_key(name.as_bytes());
+    patch_shard(resolver, store, &mut shard, hash, 0, name, link).await?;
+
+    put_shard(store, &shard).await
+}
+
+/// Sets or removes the entry `name` in `shard`, which is located after `consumed`
+/// bits of `hash`.
+#[async_recursion]
+async fn patch_shard<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    shard: &mut Shard,
+    hash: [u8; HASH_BIT_LENGTH],
+    consumed: u32,
+    name: &str,
+    link: Option<dag_pb::PbLink>,
+) -> Result<()> {
+    let idx = HashBits::new_at_index(&hash, consumed).next(shard.bit_width)?;
+    let consumed = consumed + shard.bit_width;
+    let pos = shard.position(idx);
+    let prefix = shard.prefix(idx);
+
+    if !shard.bitfield.test_bit(idx) {
+        let link = link.ok_or_else(|| anyhow!("no link named {:?}", name))?;
+        shard.bitfield.set_bit(idx);
+        shard.links.insert(pos, named(link, prefix + name));
+        return Ok(());
+    }
+
+    match shard.pointer(pos)? {
+        Pointer::Shard(cid) => {
+            let mut child = Shard::load(resolver, cid).await?;
+            patch_shard(resolver, store, &mut child, hash, consumed, name, link).await?;
+
+            if child.links.is_empty() {
+                shard.remove(idx, pos);
+            } else if let (1, Pointer::Value(value)) = (child.links.len(), child.pointer(0)?) {
+                // a shard holding a single entry is collapsed into its parent
+                let value_link = child.links.remove(0);
+                shard.links[pos] = named(value_link, prefix + &value);
+            } else {
+                let block = put_shard(store, &child).await?;
+                shard.links[pos] = block_link(&prefix, &block);
+            }
+        }
+        Pointer::Value(value) if value == name => match link {
+            Some(link) => shard.links[pos] = named(link, prefix + name),
+            None => shard.remove(idx, pos),
+        },
+        Pointer::Value(value) => {
+            let link = link.ok_or_else(|| anyhow!("no link named {:?}", name))?;
+            // both entries end up in a new shard one level down
+            let existing = shard.links[pos].clone();
+            let existing_hash = hash_key(value.as_bytes());
+            let mut child = shard.empty_child();
+            patch_shard(
+                resolver,
+                store,
+                &mut child,
+                existing_hash,
+                consumed,
+                &value,
+                Some(existing),
+            )
+            .await?;
+            patch_shard(
+                resolver,
+                store,
+                &mut child,
+                hash,
+                consumed,
+                name,
+                Some(link),
+            )
+            .await?;
+
+            let block = put_shard(store, &child).await?;
+            shard.links[pos] = block_link(&prefix, &block);
+        }
+    }
+
+    Ok(())
+}
+
+async fn put_shard<S: Store>(store: &S, shard: &Shard) -> Result<Block> {
+    let block = shard.encode()?;
+    store
+        .put(*block.cid(), block.data().clone(), block.links().to_vec())
+        .await?;
+    Ok(block)
+}
+
+fn named(link: dag_pb::PbLink, name: String) -> dag_pb::PbLink {
+    dag_pb::PbLink {
+        name: Some(name),
+        ..link
+    }
+}
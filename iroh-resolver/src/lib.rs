@@ -1,7 +1,12 @@
 pub mod balanced_tree;
+pub mod cache;
+pub mod change_cache;
 pub mod chunker;
 pub mod codecs;
+pub mod dag;
 pub mod hamt;
+pub mod mfs;
+pub mod patch;
 pub mod resolver;
 pub mod unixfs;
 pub mod unixfs_builder;
@@ -0,0 +1,646 @@
+//! Mutable File System (MFS)
+//!
+//! A mutable namespace of unixfs files and directories, layered on top of the
+//! immutable dag. Modifications are copy on write: every change writes new nodes
+//! for the modified directory and all of its ancestors, which yields a new root.
+//! The root is persisted in the store under [`MFS_ROOT`] on [`Mfs::commit`], unless
+//! another writer persisted a different root in the meantime.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use async_trait::async_trait;
+use cid::Cid;
+use futures::{StreamExt, TryStreamExt};
+use tokio::io::AsyncRead;
+
+use crate::{
+    patch::{self, block_link, empty_dir, link_cid, link_tsize},
+    resolver::{Block, ContentLoader, OutMetrics, OutType, Path, Resolver, UnixfsType},
+    unixfs::{dag_pb, UnixTime},
+    unixfs_builder::{FileBuilder, Store, StoreAndTryProvideClient},
+};
+
+/// Name under which the root of the mutable file system is stored.
+pub const MFS_ROOT: &str = "mfs";
+
+/// Storage for named roots, used to persist the root of the mutable file system.
+#[async_trait]
+pub trait RootStore {
+    async fn get_root(&self, name: &str) -> Result<Option<Cid>>;
+    /// Sets the root `name` to `cid` if it currently is `expected`, where `None`
+    /// means that it is not set yet. Returns whether the root was set.
+    async fn compare_and_set_root(
+        &self,
+        name: &str,
+        expected: Option<Cid>,
+        cid: Cid,
+    ) -> Result<bool>;
+}
+
+#[async_trait]
+impl RootStore for StoreAndTryProvideClient {
+    async fn get_root(&self, name: &str) -> Result<Option<Cid>> {
+        self.client.try_store()?.get_root(name).await
+    }
+
+    async fn compare_and_set_root(
+        &self,
+        name: &str,
+        expected: Option<Cid>,
+        cid: Cid,
+    ) -> Result<bool> {
+        self.client
+            .try_store()?
+            .compare_and_set_root(name, expected, cid)
+            .await
+    }
+}
+
+/// Information about a file or directory in the mutable file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stat {
+    pub cid: Cid,
+    pub typ: UnixfsType,
+    /// Size in bytes, for files and symlinks.
+    pub size: Option<u64>,
+    pub mode: Option<u32>,
+    pub mtime: Option<UnixTime>,
+}
+
+/// A single directory entry, as returned by [`Mfs::ls`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub cid: Cid,
+}
+
+#[derive(Debug)]
+pub struct Mfs<T: ContentLoader, S: Store + RootStore + Sync> {
+    resolver: Resolver<T>,
+    store: S,
+    root: Cid,
+    /// The persisted root the current root is based on.
+    base: Option<Cid>,
+}
+
+impl<T: ContentLoader, S: Store + RootStore + Sync> Mfs<T, S> {
+    /// Loads the mutable file system from the store, starting out with an empty
+    /// directory if no root was stored yet.
+    pub async fn load(resolver: Resolver<T>, store: S) -> Result<Self> {
+        let (base, root) = load_root(&store).await?;
+        Ok(Mfs {
+            resolver,
+            store,
+            root,
+            base,
+        })
+    }
+
+    /// Loads the persisted root again, dropping all changes that were not committed.
+    pub async fn reload(&mut self) -> Result<()> {
+        (self.base, self.root) = load_root(&self.store).await?;
+        Ok(())
+    }
+
+    /// The current root, which is only persisted on [`Mfs::commit`].
+    pub fn root(&self) -> Cid {
+        self.root
+    }
+
+    /// Persists the current root, unless the persisted root was changed since it was
+    /// loaded. Returns whether it was persisted; if not, [`Mfs::reload`] and apply the
+    /// changes again.
+    pub async fn commit(&mut self) -> Result<bool> {
+        if self.base == Some(self.root) {
+            return Ok(true);
+        }
+        let updated = self
+            .store
+            .compare_and_set_root(MFS_ROOT, self.base, self.root)
+            .await?;
+        if updated {
+            self.base = Some(self.root);
+        }
+        Ok(updated)
+    }
+
+    /// Persists the current root and returns the cid of the entry at `path`. Fails
+    /// if the persisted root was changed since it was loaded.
+    pub async fn flush(&mut self, path: &str) -> Result<Cid> {
+        ensure!(
+            self.commit().await?,
+            "the mutable file system was changed concurrently"
+        );
+        self.cid_at(&components(path)?).await
+    }
+
+    pub async fn stat(&self, path: &str) -> Result<Stat> {
+        let cid = self.cid_at(&components(path)?).await?;
+        self.stat_cid(cid).await
+    }
+
+    /// Lists the entries of the directory at `path`, or the file itself if `path`
+    /// is not a directory.
+    pub async fn ls(&self, path: &str) -> Result<Vec<Entry>> {
+        let parts = components(path)?;
+        let cid = self.cid_at(&parts).await?;
+        let out = self.resolver.resolve(Path::from_cid(cid)).await?;
+        let children = out.unixfs_read_dir(&self.resolver, OutMetrics::default())?;
+        match children {
+            Some(children) => {
+                children
+                    .map_ok(|link| Entry {
+                        name: link.name.unwrap_or_default(),
+                        cid: link.cid,
+                    })
+                    .try_collect()
+                    .await
+            }
+            None => Ok(vec![Entry {
+                name: parts.last().map(ToString::to_string).unwrap_or_default(),
+                cid,
+            }]),
+        }
+    }
+
+    /// Creates a directory at `path`. With `parents` any missing parent directories
+    /// are created as well, and an already existing directory is not an error.
+    pub async fn mkdir(&mut self, path: &str, parents: bool) -> Result<()> {
+        let parts = components(path)?;
+        let (name, dir) = match parts.split_last() {
+            Some(split) => split,
+            None if parents => return Ok(()),
+            None => bail!("cannot create the root directory"),
+        };
+
+        if let Some(existing) = self.find_entry(dir, name).await? {
+            let stat = self.stat_cid(link_cid(&existing)?).await?;
+            if parents && stat.typ == UnixfsType::Dir {
+                return Ok(());
+            }
+            bail!("file already exists: {}", path);
+        }
+
+        let block = empty_dir()?;
+        let link = block_link(name, &block);
+        self.put_block(block).await?;
+        self.set_entry(&parts, Some(link), parents).await
+    }
+
+    /// Writes `content` to the file at `path`, replacing the previous content.
+    ///
+    /// With `create` the file is created if it does not exist yet, with `parents`
+    /// any missing parent directories are created as well.
+    pub async fn write<R: AsyncRead + 'static>(
+        &mut self,
+        path: &str,
+        content: R,
+        create: bool,
+        parents: bool,
+    ) -> Result<()> {
+        let parts = components(path)?;
+        let name = self.check_write(path, &parts, create).await?;
+
+        let mut file = FileBuilder::new();
+        file.name(*name).content_reader(content);
+        let blocks = file.build().await?.encode().await?;
+        tokio::pin!(blocks);
+
+        let mut root = None;
+        while let Some(block) = blocks.next().await {
+            let block = block?;
+            root = Some(block_link(name, &block));
+            self.put_block(block).await?;
+        }
+        let link = root.ok_or_else(|| anyhow!("missing root"))?;
+
+        self.set_entry(&parts, Some(link), parents).await
+    }
+
+    /// Like [`Mfs::write`], but sets the file at `path` to the already stored file
+    /// `cid`.
+    pub async fn write_cid(
+        &mut self,
+        path: &str,
+        cid: Cid,
+        create: bool,
+        parents: bool,
+    ) -> Result<()> {
+        let parts = components(path)?;
+        let name = self.check_write(path, &parts, create).await?;
+        let link = dag_pb::PbLink {
+            hash: Some(cid.to_bytes()),
+            name: Some(name.to_string()),
+            tsize: Some(link_tsize(&self.resolver, cid).await?),
+        };
+        self.set_entry(&parts, Some(link), parents).await
+    }
+
+    /// Checks that the file at `path` can be written, and returns its name.
+    async fn check_write<'a>(
+        &self,
+        path: &str,
+        parts: &[&'a str],
+        create: bool,
+    ) -> Result<&'a str> {
+        let (name, dir) = parts
+            .split_last()
+            .ok_or_else(|| anyhow!("cannot write to the root directory"))?;
+
+        match self.find_entry(dir, name).await? {
+            Some(existing) => {
+                let stat = self.stat_cid(link_cid(&existing)?).await?;
+                ensure!(stat.typ != UnixfsType::Dir, "{} is a directory", path);
+            }
+            None => ensure!(create, "file does not exist: {}", path),
+        }
+        Ok(name)
+    }
+
+    /// Copies `src` to `dst`.
+    ///
+    /// `src` can either be a path in the mutable file system, or an `/ipfs/` or
+    /// `/ipns/` path. If `dst` ends in a `/`, the entry is copied into that
+    /// directory under the name of the source.
+    pub async fn cp(&mut self, src: &str, dst: &str) -> Result<()> {
+        let (src_name, link) = if src.starts_with("/ipfs/") || src.starts_with("/ipns/") {
+            let path: Path = src.parse()?;
+            let out = self.resolver.resolve(path.clone()).await?;
+            let cid = *out
+                .metadata()
+                .resolved_path
+                .last()
+                .ok_or_else(|| anyhow!("could not resolve {}", src))?;
+            let name = match path.tail().last() {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => cid.to_string(),
+            };
+            let link = dag_pb::PbLink {
+                hash: Some(cid.to_bytes()),
+                name: None,
+                tsize: Some(link_tsize(&self.resolver, cid).await?),
+            };
+            (name, link)
+        } else {
+            let parts = components(src)?;
+            let (name, dir) = parts
+                .split_last()
+                .ok_or_else(|| anyhow!("cannot copy the root directory"))?;
+            let link = self
+                .find_entry(dir, name)
+                .await?
+                .ok_or_else(|| anyhow!("file does not exist: {}", src))?;
+            (name.to_string(), link)
+        };
+
+        let dst = destination(dst, &src_name)?;
+        self.link_new_entry(&dst, link).await
+    }
+
+    /// Moves `src` to `dst`. If `dst` ends in a `/`, the entry is moved into that
+    /// directory under its current name.
+    pub async fn mv(&mut self, src: &str, dst: &str) -> Result<()> {
+        let src_parts = components(src)?;
+        let (src_name, src_dir) = src_parts
+            .split_last()
+            .ok_or_else(|| anyhow!("cannot move the root directory"))?;
+        let dst_parts = destination(dst, src_name)?;
+        let into_itself = dst_parts.len() >= src_parts.len()
+            && dst_parts.iter().zip(&src_parts).all(|(d, s)| d == s);
+        ensure!(!into_itself, "cannot move {} into itself", src);
+
+        let link = self
+            .find_entry(src_dir, src_name)
+            .await?
+            .ok_or_else(|| anyhow!("file does not exist: {}", src))?;
+        self.link_new_entry(&dst_parts, link).await?;
+        self.set_entry(&src_parts, None, false).await
+    }
+
+    /// Removes the entry at `path`. Directories are only removed with `recursive`.
+    pub async fn rm(&mut self, path: &str, recursive: bool) -> Result<()> {
+        let parts = components(path)?;
+        let (name, dir) = parts
+            .split_last()
+            .ok_or_else(|| anyhow!("cannot remove the root directory"))?;
+
+        let existing = self
+            .find_entry(dir, name)
+            .await?
+            .ok_or_else(|| anyhow!("file does not exist: {}", path))?;
+        if !recursive {
+            let stat = self.stat_cid(link_cid(&existing)?).await?;
+            ensure!(
+                stat.typ != UnixfsType::Dir,
+                "{} is a directory, use recursive to remove it",
+                path
+            );
+        }
+
+        self.set_entry(&parts, None, false).await
+    }
+
+    /// Adds `link` under the path `dst`, which must not exist yet.
+    async fn link_new_entry(&mut self, dst: &[String], mut link: dag_pb::PbLink) -> Result<()> {
+        let dst: Vec<&str> = dst.iter().map(String::as_str).collect();
+        let (name, dir) = dst
+            .split_last()
+            .ok_or_else(|| anyhow!("destination must not be the root directory"))?;
+        if self.find_entry(dir, name).await?.is_some() {
+            bail!("file already exists: /{}", dst.join("/"));
+        }
+
+        link.name = Some(name.to_string());
+        self.set_entry(&dst, Some(link), false).await
+    }
+
+    /// Sets the entry at `path` to `link`, or removes it if `link` is `None`,
+    /// writing new versions of all its ancestors up to a new root.
+    async fn set_entry(
+        &mut self,
+        path: &[&str],
+        link: Option<dag_pb::PbLink>,
+        parents: bool,
+    ) -> Result<()> {
+        let block =
+            patch::set_entry(&self.resolver, &self.store, self.root, path, link, parents).await?;
+        self.root = *block.cid();
+        Ok(())
+    }
+
+    /// Returns the link named `name` in the directory at `dir`, if the directory
+    /// exists and contains it.
+    async fn find_entry(&self, dir: &[&str], name: &str) -> Result<Option<dag_pb::PbLink>> {
+        let mut path = dir.to_vec();
+        path.push(name);
+        patch::find_entry(&self.resolver, self.root, &path).await
+    }
+
+    async fn cid_at(&self, parts: &[&str]) -> Result<Cid> {
+        match parts.split_last() {
+            Some((name, dir)) => {
+                let link = self
+                    .find_entry(dir, name)
+                    .await?
+                    .ok_or_else(|| anyhow!("file does not exist: /{}", parts.join("/")))?;
+                link_cid(&link)
+            }
+            None => Ok(self.root),
+        }
+    }
+
+    async fn stat_cid(&self, cid: Cid) -> Result<Stat> {
+        let out = self.resolver.resolve(Path::from_cid(cid)).await?;
+        let metadata = out.metadata();
+        let typ = match (metadata.unixfs_type, metadata.typ) {
+            (Some(typ), _) => typ,
+            // raw leaves are files on their own
+            (None, OutType::Raw) => UnixfsType::File,
+            _ => bail!("{} is not unixfs content", cid),
+        };
+
+        Ok(Stat {
+            cid,
+            typ,
+            size: metadata.size,
+            mode: metadata.mode,
+            mtime: metadata.mtime,
+        })
+    }
+
+    async fn put_block(&self, block: Block) -> Result<()> {
+        let (cid, data, links) = block.into_parts();
+        self.store.put(cid, data, links).await
+    }
+}
+
+/// Returns the persisted root, and the root to start out with, which is an empty
+/// directory if no root was persisted yet.
+async fn load_root<S: Store + RootStore>(store: &S) -> Result<(Option<Cid>, Cid)> {
+    let base = store.get_root(MFS_ROOT).await?;
+    let root = match base {
+        Some(root) => root,
+        None => {
+            let (cid, data, links) = empty_dir()?.into_parts();
+            store.put(cid, data, links).await?;
+            cid
+        }
+    };
+    Ok((base, root))
+}
+
+/// Splits an absolute path in the mutable file system into its components.
+fn components(path: &str) -> Result<Vec<&str>> {
+    ensure!(path.starts_with('/'), "paths must be absolute: {}", path);
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            ensure!(
+                part != "." && part != "..",
+                "invalid path component {:?} in {}",
+                part,
+                path
+            );
+            Ok(part)
+        })
+        .collect()
+}
+
+/// The components of the destination of a copy or move of an entry named `name`.
+fn destination(dst: &str, name: &str) -> Result<Vec<String>> {
+    let mut parts: Vec<String> = components(dst)?.into_iter().map(Into::into).collect();
+    if dst.ends_with('/') {
+        parts.push(name.to_string());
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::tests::MemStore;
+    use crate::resolver::ResponseClip;
+    use tokio::io::AsyncReadExt;
+
+    #[async_trait]
+    impl RootStore for MemStore {
+        async fn get_root(&self, name: &str) -> Result<Option<Cid>> {
+            Ok(self.roots.lock().unwrap().get(name).copied())
+        }
+
+        async fn compare_and_set_root(
+            &self,
+            name: &str,
+            expected: Option<Cid>,
+            cid: Cid,
+        ) -> Result<bool> {
+            let mut roots = self.roots.lock().unwrap();
+            if roots.get(name).copied() != expected {
+                return Ok(false);
+            }
+            roots.insert(name.to_string(), cid);
+            Ok(true)
+        }
+    }
+
+    async fn load(store: &MemStore) -> Mfs<MemStore, MemStore> {
+        Mfs::load(Resolver::new(store.clone()), store.clone())
+            .await
+            .unwrap()
+    }
+
+    async fn read(mfs: &Mfs<MemStore, MemStore>, path: &str) -> String {
+        let cid = mfs.stat(path).await.unwrap().cid;
+        let out = mfs.resolver.resolve(Path::from_cid(cid)).await.unwrap();
+        let mut reader = out
+            .pretty(
+                mfs.resolver.clone(),
+                OutMetrics::default(),
+                ResponseClip::NoClip,
+            )
+            .unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await.unwrap();
+        content
+    }
+
+    fn names(entries: Vec<Entry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_mfs_mkdir_write_ls() {
+        let store = MemStore::default();
+        let mut mfs = load(&store).await;
+        let empty = mfs.root();
+        assert_eq!(mfs.stat("/").await.unwrap().typ, UnixfsType::Dir);
+        assert!(mfs.ls("/").await.unwrap().is_empty());
+
+        mfs.mkdir("/a", false).await.unwrap();
+        assert!(mfs.mkdir("/a", false).await.is_err());
+        mfs.mkdir("/a", true).await.unwrap();
+        assert!(mfs.mkdir("/b/c", false).await.is_err());
+        mfs.mkdir("/b/c", true).await.unwrap();
+
+        assert!(mfs
+            .write("/a/hello.txt", &b"hello"[..], false, false)
+            .await
+            .is_err());
+        mfs.write("/a/hello.txt", &b"hello"[..], true, false)
+            .await
+            .unwrap();
+        mfs.write("/d/e/f.txt", &b"deep"[..], true, true)
+            .await
+            .unwrap();
+        assert!(mfs.write("/a", &b"nope"[..], true, false).await.is_err());
+
+        assert_eq!(names(mfs.ls("/").await.unwrap()), vec!["a", "b", "d"]);
+        assert_eq!(names(mfs.ls("/a").await.unwrap()), vec!["hello.txt"]);
+        assert_eq!(
+            names(mfs.ls("/a/hello.txt").await.unwrap()),
+            vec!["hello.txt"]
+        );
+        assert_eq!(read(&mfs, "/a/hello.txt").await, "hello");
+        assert_eq!(read(&mfs, "/d/e/f.txt").await, "deep");
+
+        let stat = mfs.stat("/a/hello.txt").await.unwrap();
+        assert_eq!(stat.typ, UnixfsType::File);
+        assert_eq!(stat.size, Some(5));
+
+        // overwrite replaces the content
+        mfs.write("/a/hello.txt", &b"bye"[..], false, false)
+            .await
+            .unwrap();
+        assert_eq!(read(&mfs, "/a/hello.txt").await, "bye");
+
+        // nothing is persisted before flushing
+        assert_eq!(load(&store).await.root(), empty);
+        let root = mfs.flush("/").await.unwrap();
+        assert_eq!(root, mfs.root());
+        let mfs = load(&store).await;
+        assert_eq!(mfs.root(), root);
+        assert_eq!(read(&mfs, "/a/hello.txt").await, "bye");
+    }
+
+    #[tokio::test]
+    async fn test_mfs_cp_mv_rm() {
+        let store = MemStore::default();
+        let mut mfs = load(&store).await;
+        mfs.write("/a/hello.txt", &b"hello"[..], true, true)
+            .await
+            .unwrap();
+        mfs.mkdir("/b", false).await.unwrap();
+
+        mfs.cp("/a/hello.txt", "/b/").await.unwrap();
+        mfs.cp("/a/hello.txt", "/b/copy.txt").await.unwrap();
+        assert!(mfs.cp("/a/hello.txt", "/b/copy.txt").await.is_err());
+        assert_eq!(
+            names(mfs.ls("/b").await.unwrap()),
+            vec!["copy.txt", "hello.txt"]
+        );
+        assert_eq!(read(&mfs, "/b/copy.txt").await, "hello");
+
+        let file = mfs.stat("/a/hello.txt").await.unwrap().cid;
+        mfs.cp(&format!("/ipfs/{}", file), "/c.txt").await.unwrap();
+        assert_eq!(mfs.stat("/c.txt").await.unwrap().cid, file);
+        let dir = mfs.stat("/b").await.unwrap().cid;
+        mfs.cp(&format!("/ipfs/{}/copy.txt", dir), "/")
+            .await
+            .unwrap();
+        assert_eq!(read(&mfs, "/copy.txt").await, "hello");
+        mfs.cp(&format!("/ipfs/{}", dir), "/b2").await.unwrap();
+        let b = mfs.find_entry(&[], "b").await.unwrap().unwrap();
+        let b2 = mfs.find_entry(&[], "b2").await.unwrap().unwrap();
+        assert!(b.tsize.is_some());
+        assert_eq!(b2.tsize, b.tsize);
+        mfs.rm("/b2", true).await.unwrap();
+
+        mfs.mv("/a/hello.txt", "/a/moved.txt").await.unwrap();
+        assert_eq!(names(mfs.ls("/a").await.unwrap()), vec!["moved.txt"]);
+        mfs.mv("/a", "/b/").await.unwrap();
+        assert_eq!(read(&mfs, "/b/a/moved.txt").await, "hello");
+        assert!(mfs.stat("/a").await.is_err());
+        assert!(mfs.mv("/b", "/b/inner").await.is_err());
+
+        assert!(mfs.rm("/b", false).await.is_err());
+        mfs.rm("/b/copy.txt", false).await.unwrap();
+        assert_eq!(names(mfs.ls("/b").await.unwrap()), vec!["a", "hello.txt"]);
+        mfs.rm("/b", true).await.unwrap();
+        assert!(mfs.rm("/b", true).await.is_err());
+        assert!(mfs.rm("/", true).await.is_err());
+        assert_eq!(names(mfs.ls("/").await.unwrap()), vec!["c.txt", "copy.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_mfs_commit_conflict() {
+        let store = MemStore::default();
+        let mut a = load(&store).await;
+        let mut b = load(&store).await;
+        a.mkdir("/a", false).await.unwrap();
+        b.write("/b.txt", &b"b"[..], true, false).await.unwrap();
+        let file = b.stat("/b.txt").await.unwrap().cid;
+
+        assert!(a.commit().await.unwrap());
+        assert!(!b.commit().await.unwrap());
+        assert!(b.flush("/").await.is_err());
+
+        // apply the change again on top of the new root
+        b.reload().await.unwrap();
+        assert_eq!(names(b.ls("/").await.unwrap()), vec!["a"]);
+        b.write_cid("/b.txt", file, true, false).await.unwrap();
+        assert!(b.commit().await.unwrap());
+
+        let mfs = load(&store).await;
+        assert_eq!(names(mfs.ls("/").await.unwrap()), vec!["a", "b.txt"]);
+        assert_eq!(read(&mfs, "/b.txt").await, "b");
+    }
+
+    #[test]
+    fn test_components() {
+        assert_eq!(components("/").unwrap(), Vec::<&str>::new());
+        assert_eq!(components("/a//b/").unwrap(), vec!["a", "b"]);
+        assert!(components("a/b").is_err());
+        assert!(components("/a/../b").is_err());
+        assert_eq!(destination("/a/", "b").unwrap(), vec!["a", "b"]);
+        assert_eq!(destination("/a", "b").unwrap(), vec!["a"]);
+    }
+}
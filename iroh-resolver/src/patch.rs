@@ -0,0 +1,497 @@
+//! Modification of existing unixfs directories.
+//!
+//! Setting or removing an entry only loads and re-encodes the directories on the
+//! path to that entry, including the shards of sharded directories. Everything
+//! else is referenced by its existing cid and never fetched.
+
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use async_recursion::async_recursion;
+use cid::Cid;
+
+use crate::{
+    hamt,
+    resolver::{Block, ContentLoader, OutContent, Path, Resolver},
+    unixfs::{dag_pb, unixfs_pb, DataType, Link, Node, UnixfsNode},
+    unixfs_builder::{encode_unixfs_pb, Store},
+};
+
+/// Adds a link to `cid` under `path` in the directory `root`, replacing any
+/// existing entry of that name, and returns the new root.
+///
+/// With `create` missing intermediate directories are created, otherwise they
+/// have to exist.
+pub async fn add_link<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    root: Cid,
+    path: &[&str],
+    cid: Cid,
+    tsize: Option<u64>,
+    create: bool,
+) -> Result<Cid> {
+    let link = dag_pb::PbLink {
+        hash: Some(cid.to_bytes()),
+        name: None,
+        tsize,
+    };
+    let block = set_entry(resolver, store, root, path, Some(link), create).await?;
+    Ok(*block.cid())
+}
+
+/// The tsize of a link to `cid`, which is the size of its root block, the same as
+/// for the links of added directories.
+pub async fn link_tsize<C: ContentLoader>(resolver: &Resolver<C>, cid: Cid) -> Result<u64> {
+    let data = resolver.load_block(cid).await?;
+    Ok(data.len() as u64)
+}
+
+/// Removes the entry at `path` in the directory `root` and returns the new root.
+pub async fn rm_link<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    root: Cid,
+    path: &[&str],
+) -> Result<Cid> {
+    let block = set_entry(resolver, store, root, path, None, false).await?;
+    Ok(*block.cid())
+}
+
+/// Returns the link at `path` in the directory `root`, if it exists.
+pub async fn get_link<C: ContentLoader>(
+    resolver: &Resolver<C>,
+    root: Cid,
+    path: &[&str],
+) -> Result<Option<Link>> {
+    match find_entry(resolver, root, path).await? {
+        Some(link) => Ok(Some(Link {
+            cid: link_cid(&link)?,
+            name: link.name,
+            tsize: link.tsize,
+        })),
+        None => Ok(None),
+    }
+}
+
+/// Returns the entry at `path` in the directory `root`, or `None` if it or
+/// any of its parent directories do not exist. An empty `path` refers to `root`
+/// itself.
+pub(crate) async fn find_entry<C: ContentLoader>(
+    resolver: &Resolver<C>,
+    root: Cid,
+    path: &[&str],
+) -> Result<Option<dag_pb::PbLink>> {
+    let mut current = root;
+    for (i, name) in path.iter().enumerate() {
+        let dir = load_dir(resolver, current)
+            .await
+            .with_context(|| format!("invalid path component: /{}", path[..i].join("/")))?;
+        match dir_entry(resolver, dir, name).await? {
+            Some(link) if i + 1 == path.len() => return Ok(Some(link)),
+            Some(link) => current = link_cid(&link)?,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(dag_pb::PbLink {
+        hash: Some(root.to_bytes()),
+        name: None,
+        tsize: None,
+    }))
+}
+
+/// Sets the entry at `path` in the directory `root` to `link`, or removes it if
+/// `link` is `None`. All modified directories are stored, the new root is
+/// returned.
+pub(crate) async fn set_entry<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    root: Cid,
+    path: &[&str],
+    link: Option<dag_pb::PbLink>,
+    create: bool,
+) -> Result<Block> {
+    ensure!(!path.is_empty(), "path must not be empty");
+    let dir = load_dir(resolver, root).await?;
+    patch_dir(resolver, store, dir, path, link, create).await
+}
+
+#[async_recursion]
+async fn patch_dir<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    dir: UnixfsNode,
+    path: &[&str],
+    link: Option<dag_pb::PbLink>,
+    create: bool,
+) -> Result<Block> {
+    let (name, rest) = path
+        .split_first()
+        .ok_or_else(|| anyhow!("path must not be empty"))?;
+    if rest.is_empty() {
+        return set_dir_entry(resolver, store, dir, name, link).await;
+    }
+
+    let child = match dir_entry(resolver, dir.clone(), name).await? {
+        Some(existing) => load_dir(resolver, link_cid(&existing)?)
+            .await
+            .with_context(|| format!("invalid path component: {}", name))?,
+        None if create && link.is_some() => UnixfsNode::Directory(empty_dir_node()?),
+        None => bail!("directory does not exist: {}", name),
+    };
+    let block = patch_dir(resolver, store, child, rest, link, create).await?;
+    set_dir_entry(resolver, store, dir, name, Some(block_link(name, &block))).await
+}
+
+/// Sets or removes a single entry of `dir` and stores the result.
+async fn set_dir_entry<C: ContentLoader, S: Store + Sync>(
+    resolver: &Resolver<C>,
+    store: &S,
+    dir: UnixfsNode,
+    name: &str,
+    link: Option<dag_pb::PbLink>,
+) -> Result<Block> {
+    let link = link.map(|link| dag_pb::PbLink {
+        name: Some(name.to_string()),
+        ..link
+    });
+    match dir {
+        UnixfsNode::Directory(mut node) => {
+            match link {
+                Some(link) => set_link(&mut node.outer.links, link),
+                None => {
+                    let pos = node
+                        .outer
+                        .links
+                        .iter()
+                        .position(|l| l.name.as_deref() == Some(name))
+                        .ok_or_else(|| anyhow!("no link named {:?}", name))?;
+                    node.outer.links.remove(pos);
+                }
+            }
+            let block = UnixfsNode::Directory(node).encode()?;
+            put_block(store, &block).await?;
+            Ok(block)
+        }
+        UnixfsNode::HamtShard(node, _) => hamt::patch_link(resolver, store, node, name, link).await,
+        _ => bail!("not a directory"),
+    }
+}
+
+/// Returns the entry `name` of `dir`.
+async fn dir_entry<C: ContentLoader>(
+    resolver: &Resolver<C>,
+    dir: UnixfsNode,
+    name: &str,
+) -> Result<Option<dag_pb::PbLink>> {
+    match dir {
+        UnixfsNode::Directory(node) => Ok(node
+            .outer
+            .links
+            .into_iter()
+            .find(|link| link.name.as_deref() == Some(name))),
+        UnixfsNode::HamtShard(node, _) => hamt::find_link(resolver, node, name).await,
+        _ => bail!("not a directory"),
+    }
+}
+
+async fn load_dir<C: ContentLoader>(resolver: &Resolver<C>, cid: Cid) -> Result<UnixfsNode> {
+    let out = resolver.resolve(Path::from_cid(cid)).await?;
+    match out.content {
+        OutContent::Unixfs(node @ UnixfsNode::Directory(_))
+        | OutContent::Unixfs(node @ UnixfsNode::HamtShard(_, _)) => Ok(node),
+        _ => bail!("{} is not a directory", cid),
+    }
+}
+
+async fn put_block<S: Store>(store: &S, block: &Block) -> Result<()> {
+    store
+        .put(*block.cid(), block.data().clone(), block.links().to_vec())
+        .await
+}
+
+pub(crate) fn empty_dir_node() -> Result<Node> {
+    let inner = unixfs_pb::Data {
+        r#type: DataType::Directory as i32,
+        ..Default::default()
+    };
+    let outer = encode_unixfs_pb(&inner, Vec::new())?;
+    Ok(Node { outer, inner })
+}
+
+pub(crate) fn empty_dir() -> Result<Block> {
+    UnixfsNode::Directory(empty_dir_node()?).encode()
+}
+
+pub(crate) fn link_cid(link: &dag_pb::PbLink) -> Result<Cid> {
+    let hash = link
+        .hash
+        .as_ref()
+        .ok_or_else(|| anyhow!("link without hash"))?;
+    Cid::read_bytes(Cursor::new(hash)).context("invalid cid in link")
+}
+
+pub(crate) fn block_link(name: &str, block: &Block) -> dag_pb::PbLink {
+    dag_pb::PbLink {
+        hash: Some(block.cid().to_bytes()),
+        name: Some(name.to_string()),
+        tsize: Some(block.data().len() as u64),
+    }
+}
+
+/// Inserts `link`, replacing any link of the same name. New links are inserted
+/// in name order.
+fn set_link(links: &mut Vec<dag_pb::PbLink>, link: dag_pb::PbLink) {
+    if let Some(existing) = links.iter_mut().find(|l| l.name == link.name) {
+        *existing = link;
+        return;
+    }
+    let name = link.name.as_deref().unwrap_or_default();
+    let pos = links
+        .iter()
+        .position(|l| l.name.as_deref().unwrap_or_default() > name)
+        .unwrap_or(links.len());
+    links.insert(pos, link);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::resolver::{ContextId, LoadedCid, LoaderContext, OutMetrics, Source};
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures::{StreamExt, TryStreamExt};
+
+    /// In memory block store, which counts the blocks loaded through it.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct MemStore {
+        pub(crate) blocks: Arc<Mutex<HashMap<Cid, Bytes>>>,
+        pub(crate) roots: Arc<Mutex<HashMap<String, Cid>>>,
+        pub(crate) loaded: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ContentLoader for MemStore {
+        async fn load_cid(&self, cid: &Cid, _ctx: &LoaderContext) -> Result<LoadedCid> {
+            self.loaded.fetch_add(1, Ordering::SeqCst);
+            match self.blocks.lock().unwrap().get(cid) {
+                Some(data) => Ok(LoadedCid {
+                    data: data.clone(),
+                    source: Source::Store("mem"),
+                }),
+                None => bail!("not found"),
+            }
+        }
+
+        async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+            Ok(())
+        }
+
+        async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+            Ok(self.blocks.lock().unwrap().contains_key(cid))
+        }
+    }
+
+    #[async_trait]
+    impl Store for MemStore {
+        async fn put(&self, cid: Cid, blob: Bytes, _links: Vec<Cid>) -> Result<()> {
+            self.blocks.lock().unwrap().insert(cid, blob);
+            Ok(())
+        }
+
+        async fn has(&self, cid: Cid) -> Result<bool> {
+            Ok(self.blocks.lock().unwrap().contains_key(&cid))
+        }
+    }
+
+    async fn put_raw(store: &MemStore, data: &'static [u8]) -> Cid {
+        let block = UnixfsNode::Raw(Bytes::from_static(data)).encode().unwrap();
+        put_block(store, &block).await.unwrap();
+        *block.cid()
+    }
+
+    async fn names(resolver: &Resolver<MemStore>, root: Cid) -> Vec<String> {
+        let out = resolver.resolve(Path::from_cid(root)).await.unwrap();
+        out.unixfs_read_dir(resolver, OutMetrics::default())
+            .unwrap()
+            .unwrap()
+            .map_ok(|link| link.name.unwrap())
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_patch_dir() {
+        let store = MemStore::default();
+        let resolver = Resolver::new(store.clone());
+        let empty = empty_dir().unwrap();
+        put_block(&store, &empty).await.unwrap();
+        let hello = put_raw(&store, b"hello").await;
+        let world = put_raw(&store, b"world").await;
+        assert_eq!(link_tsize(&resolver, hello).await.unwrap(), 5);
+        assert_eq!(
+            link_tsize(&resolver, *empty.cid()).await.unwrap(),
+            empty.data().len() as u64
+        );
+
+        let root = add_link(&resolver, &store, *empty.cid(), &["b"], hello, None, false)
+            .await
+            .unwrap();
+        let root = add_link(&resolver, &store, root, &["a"], hello, None, false)
+            .await
+            .unwrap();
+        assert_eq!(names(&resolver, root).await, vec!["a", "b"]);
+
+        // missing parents are only created on request
+        assert!(
+            add_link(&resolver, &store, root, &["c", "d"], hello, None, false)
+                .await
+                .is_err()
+        );
+        let root = add_link(&resolver, &store, root, &["c", "d"], hello, None, true)
+            .await
+            .unwrap();
+        let link = get_link(&resolver, root, &["c", "d"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.cid, hello);
+        assert_eq!(link.name.as_deref(), Some("d"));
+        assert!(get_link(&resolver, root, &["c", "e"])
+            .await
+            .unwrap()
+            .is_none());
+        assert!(get_link(&resolver, root, &["e", "d"])
+            .await
+            .unwrap()
+            .is_none());
+
+        // replace
+        let replaced = add_link(&resolver, &store, root, &["c", "d"], world, None, false)
+            .await
+            .unwrap();
+        let link = get_link(&resolver, replaced, &["c", "d"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.cid, world);
+
+        // remove
+        let removed = rm_link(&resolver, &store, replaced, &["c", "d"])
+            .await
+            .unwrap();
+        assert!(names(&resolver, link_cid_at(&resolver, removed, "c").await)
+            .await
+            .is_empty());
+        assert!(rm_link(&resolver, &store, removed, &["c", "d"])
+            .await
+            .is_err());
+        let removed = rm_link(&resolver, &store, removed, &["c"]).await.unwrap();
+        let removed = rm_link(&resolver, &store, removed, &["b"]).await.unwrap();
+        assert_eq!(names(&resolver, removed).await, vec!["a"]);
+
+        // patching a file fails
+        assert!(
+            add_link(&resolver, &store, root, &["a", "b"], hello, None, true)
+                .await
+                .is_err()
+        );
+    }
+
+    async fn link_cid_at(resolver: &Resolver<MemStore>, root: Cid, name: &str) -> Cid {
+        get_link(resolver, root, &[name])
+            .await
+            .unwrap()
+            .unwrap()
+            .cid
+    }
+
+    #[tokio::test]
+    async fn test_patch_hamt_dir() {
+        // see resolver::tests::test_unixfs_hamt_dir for the content
+        let root: Cid = "QmUu8pzQ5yjhDrg4GiHYLeko2oT76vcmYX5bw6sjiEJ82k"
+            .parse()
+            .unwrap();
+        let reader = tokio::io::BufReader::new(
+            tokio::fs::File::open("./fixtures/big-foo.car")
+                .await
+                .unwrap(),
+        );
+        let car_reader = iroh_car::CarReader::new(reader).await.unwrap();
+        let blocks: HashMap<Cid, Bytes> = car_reader
+            .stream()
+            .map(|r| r.map(|(k, v)| (k, Bytes::from(v))))
+            .try_collect()
+            .await
+            .unwrap();
+        let store = MemStore {
+            blocks: Arc::new(Mutex::new(blocks)),
+            ..Default::default()
+        };
+        let resolver = Resolver::new(store.clone());
+        let hello = put_raw(&store, b"hello").await;
+        let world = put_raw(&store, b"world").await;
+
+        // only the shards on the path are loaded
+        store.loaded.store(0, Ordering::SeqCst);
+        let added = add_link(&resolver, &store, root, &["new.txt"], hello, None, false)
+            .await
+            .unwrap();
+        assert!(store.loaded.load(Ordering::SeqCst) < 10);
+
+        assert_eq!(link_cid_at(&resolver, added, "new.txt").await, hello);
+        let unchanged = link_cid_at(&resolver, root, "9999.txt").await;
+        assert_eq!(link_cid_at(&resolver, added, "9999.txt").await, unchanged);
+        let path = format!("/ipfs/{}/new.txt", added);
+        let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+        assert_eq!(out.metadata().resolved_path.last(), Some(&hello));
+        assert_eq!(names(&resolver, added).await.len(), 10003);
+
+        // replace an existing entry
+        let replaced = add_link(&resolver, &store, added, &["42.txt"], world, None, false)
+            .await
+            .unwrap();
+        assert_eq!(link_cid_at(&resolver, replaced, "42.txt").await, world);
+        assert_eq!(names(&resolver, replaced).await.len(), 10003);
+
+        // removal is the inverse of adding
+        let removed = rm_link(&resolver, &store, added, &["new.txt"])
+            .await
+            .unwrap();
+        assert!(get_link(&resolver, removed, &["new.txt"])
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(names(&resolver, removed).await.len(), 10002);
+        let readded = add_link(&resolver, &store, removed, &["new.txt"], hello, None, false)
+            .await
+            .unwrap();
+        assert_eq!(readded, added);
+
+        // nested below the sharded directory
+        let nested = add_link(
+            &resolver,
+            &store,
+            root,
+            &["bar", "new.txt"],
+            hello,
+            None,
+            false,
+        )
+        .await
+        .unwrap();
+        let link = get_link(&resolver, nested, &["bar", "new.txt"])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.cid, hello);
+        assert!(rm_link(&resolver, &store, root, &["missing.txt"])
+            .await
+            .is_err());
+    }
+}
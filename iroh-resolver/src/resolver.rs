@@ -12,13 +12,16 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
-use futures::{Future, Stream};
+use futures::{Future, Stream, StreamExt};
+use iroh_ipns::{self as ipns, IpnsRecord};
 use iroh_metrics::inc;
 use iroh_rpc_client::Client;
 use libipld::codec::{Decode, Encode};
 use libipld::error::{InvalidMultihash, UnsupportedMultihash};
 use libipld::prelude::Codec as _;
 use libipld::{Ipld, IpldCodec};
+use libp2p::PeerId;
+use time::OffsetDateTime;
 use tokio::io::{AsyncRead, AsyncSeek};
 use tokio::sync::{mpsc, oneshot, Mutex};
 use tokio::task::JoinHandle;
@@ -32,12 +35,18 @@ use iroh_metrics::{
 };
 
 use crate::codecs::Codec;
+use crate::dag::{self, DagResolved};
 use crate::unixfs::{
-    poll_read_buf_at_pos, DataType, UnixfsChildStream, UnixfsContentReader, UnixfsNode,
+    poll_read_buf_at_pos, DataType, UnixTime, UnixfsChildStream, UnixfsContentReader, UnixfsNode,
 };
 
 pub const IROH_STORE: &str = "iroh-store";
 
+/// Default number of blocks that are loaded at once when resolving recursively.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+/// Default number of blocks of a file that are loaded ahead of reading them.
+pub const DEFAULT_READ_AHEAD: usize = 4;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Block {
     cid: Cid,
@@ -63,7 +72,7 @@ impl Block {
     }
 
     pub fn raw_data_size(&self) -> Option<u64> {
-        let codec = Codec::try_from(self.cid.codec()).unwrap();
+        let codec = Codec::try_from(self.cid.codec()).ok()?;
         match codec {
             Codec::Raw => Some(self.data.len() as u64),
             _ => None,
@@ -130,6 +139,15 @@ impl Path {
         self.tail.push(str.as_ref().to_owned());
     }
 
+    /// Appends `tail` to the tail of this path, dropping a trailing slash of this path.
+    fn join(mut self, tail: Vec<String>) -> Self {
+        if !tail.is_empty() && self.has_trailing_slash() {
+            self.tail.pop();
+        }
+        self.tail.extend(tail);
+        self
+    }
+
     // Empty path segments in the *middle* shouldn't occur,
     // though they can occur at the end, which `join` handles.
     // TODO(faassen): it would make sense to return a `RelativePathBuf` here at some
@@ -460,6 +478,10 @@ pub struct Metadata {
     /// to a block.
     pub resolved_path: Vec<Cid>,
     pub source: Source,
+    /// Unix permission bits, only recorded for UnixFS content.
+    pub mode: Option<u32>,
+    /// Modification time, only recorded for UnixFS content.
+    pub mtime: Option<UnixTime>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -654,6 +676,8 @@ pub enum Source {
 #[derive(Debug, Clone)]
 pub struct Resolver<T: ContentLoader> {
     loader: T,
+    concurrency: usize,
+    read_ahead: usize,
     next_id: Arc<AtomicU64>,
     worker: Option<Arc<(oneshot::Sender<()>, JoinHandle<()>)>>,
     session_closer: mpsc::Sender<ContextId>,
@@ -746,6 +770,10 @@ pub trait ContentLoader: Sync + Send + std::fmt::Debug + Clone + 'static {
     async fn stop_session(&self, ctx: ContextId) -> Result<()>;
     /// Checks if the given cid is present in the local storage.
     async fn has_cid(&self, cid: &Cid) -> Result<bool>;
+    /// Loads the records stored under the given key in the DHT.
+    async fn load_records(&self, _key: &[u8]) -> Result<Vec<Bytes>> {
+        bail!("loading records is not supported")
+    }
 }
 
 #[async_trait]
@@ -761,6 +789,10 @@ impl<T: ContentLoader> ContentLoader for Arc<T> {
     async fn has_cid(&self, cid: &Cid) -> Result<bool> {
         self.as_ref().has_cid(cid).await
     }
+
+    async fn load_records(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        self.as_ref().load_records(key).await
+    }
 }
 
 #[async_trait]
@@ -770,6 +802,10 @@ impl ContentLoader for Client {
         Ok(())
     }
 
+    async fn load_records(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        self.try_p2p()?.fetch_record_dht(key).await
+    }
+
     async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
         trace!("{:?} loading {}", ctx.id(), cid);
 
@@ -884,6 +920,8 @@ impl<T: ContentLoader> Resolver<T> {
 
         Resolver {
             loader,
+            concurrency: DEFAULT_CONCURRENCY,
+            read_ahead: DEFAULT_READ_AHEAD,
             next_id: Arc::new(AtomicU64::new(0)),
             worker: Some(Arc::new((closer_s, worker))),
             session_closer: session_closer_s,
@@ -895,10 +933,27 @@ impl<T: ContentLoader> Resolver<T> {
         ContextId(id)
     }
 
+    /// Sets how many blocks are loaded at once when resolving recursively.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Sets how many blocks of a file are loaded ahead of reading them, `0`
+    /// turns reading ahead off.
+    pub fn with_read_ahead(mut self, blocks: usize) -> Self {
+        self.read_ahead = blocks;
+        self
+    }
+
     pub fn loader(&self) -> &T {
         &self.loader
     }
 
+    pub(crate) fn read_ahead(&self) -> usize {
+        self.read_ahead
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn resolve_recursive_with_paths(
         &self,
@@ -913,25 +968,21 @@ impl<T: ContentLoader> Resolver<T> {
                 if let Some((current_output_path, current_out)) = blocks.pop_front() {
                     let current = current_out?;
                     let links = current.named_links()?;
-                    // TODO: configurable limit
-                    for link_chunk in links.chunks(8) {
-                        let next = futures::future::join_all(
-                            link_chunk.iter().map(|(link_name, link)| {
-                                let this = this.clone();
-                                let mut this_path = current_output_path.clone();
-                                match link_name {
-                                    None => this_path.push(link.to_string()),
-                                    Some(p) =>  this_path.push(p),
-                                };
-                                async move {
-                                    (this_path, this.resolve(Path::from_cid(*link)).await)
-                                }
-                            })
-                        ).await;
-                        for res in next.into_iter() {
-                            blocks.push_back(res);
+                    let next = futures::stream::iter(links.into_iter().map(|(link_name, link)| {
+                        let this = this.clone();
+                        let mut this_path = current_output_path.clone();
+                        match link_name {
+                            None => this_path.push(link.to_string()),
+                            Some(p) =>  this_path.push(p),
+                        };
+                        async move {
+                            (this_path, this.resolve(Path::from_cid(link)).await)
                         }
-                    }
+                    }))
+                    .buffered(this.concurrency)
+                    .collect::<Vec<_>>()
+                    .await;
+                    blocks.extend(next);
                     yield (current_output_path, current);
                 } else {
                     // no links left to resolve
@@ -987,9 +1038,8 @@ impl<T: ContentLoader> Resolver<T> {
         let mut cids = VecDeque::new();
         let this = self.clone();
         let mut counter = 0;
-        let chunk_size = 8;
         async_stream::try_stream! {
-            let root_cid = this.resolve_path_to_cid(&root, &mut ctx).await?;
+            let (root_cid, _) = this.resolve_path_to_cid(&root, &mut ctx).await?;
             let root_block = resolve(root_cid, ctx.clone()).await?;
             cids.push_back(root_block);
             loop {
@@ -1002,21 +1052,16 @@ impl<T: ContentLoader> Resolver<T> {
                         }
                     }
 
-                    // TODO: configurable limit
-                    for link_chunk in links.chunks(chunk_size) {
-                        let next = futures::future::join_all(
-                            link_chunk.iter().map(|link| {
-                                let resolve = resolve.clone();
-                                let ctx = ctx.clone();
-                                async move {
-                                    resolve(*link, ctx).await
-                                }
-                            })
-                        ).await;
-                        for res in next.into_iter() {
-                            let res = res?;
-                            cids.push_back(res);
+                    let mut next = futures::stream::iter(links.into_iter().map(|link| {
+                        let resolve = resolve.clone();
+                        let ctx = ctx.clone();
+                        async move {
+                            resolve(link, ctx).await
                         }
+                    }))
+                    .buffered(this.concurrency);
+                    while let Some(res) = next.next().await {
+                        cids.push_back(res?);
                     }
                     yield current;
 
@@ -1037,26 +1082,110 @@ impl<T: ContentLoader> Resolver<T> {
         self.resolve_with_ctx(ctx, path).await
     }
 
+    /// Resolves `path` through generic IPLD data, following links into blocks of
+    /// any supported codec. Unlike [`Resolver::resolve`], dag-pb blocks are not
+    /// interpreted as unixfs. A link at the end of the path is followed as well.
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve_dag(&self, path: Path) -> Result<DagResolved> {
+        let mut ctx =
+            LoaderContext::from_path(self.next_id(), self.session_closer.clone(), path.clone());
+        let (mut cid, tail, loaded_cid) = self.resolve_root(&path, &mut ctx).await?;
+        let mut node = dag::decode(&cid, &loaded_cid.data)?;
+        let mut rem_path = Vec::new();
+
+        for part in &tail {
+            if let Some((link, linked)) = self.load_dag_link(&node, &mut ctx).await? {
+                cid = link;
+                node = linked;
+                rem_path.clear();
+            }
+            let codec = Codec::try_from(cid.codec()).context("unknown codec")?;
+            node = dag::child(node, codec, part, rem_path.is_empty())
+                .with_context(|| format!("cannot resolve {} in {}", part, cid))?;
+            rem_path.push(part.clone());
+        }
+        if let Some((link, linked)) = self.load_dag_link(&node, &mut ctx).await? {
+            cid = link;
+            node = linked;
+            rem_path.clear();
+        }
+
+        Ok(DagResolved {
+            cid,
+            rem_path,
+            node,
+        })
+    }
+
+    /// Loads and decodes the block `node` points to, if it is a link.
+    async fn load_dag_link(
+        &self,
+        node: &Ipld,
+        ctx: &mut LoaderContext,
+    ) -> Result<Option<(Cid, Ipld)>> {
+        match node {
+            Ipld::Link(link) => {
+                let loaded_cid = self.load_cid(link, ctx).await?;
+                let linked = dag::decode(link, &loaded_cid.data)?;
+                Ok(Some((*link, linked)))
+            }
+            _ => Ok(None),
+        }
+    }
+
     pub async fn resolve_with_ctx(&self, mut ctx: LoaderContext, path: Path) -> Result<Out> {
         // Resolve the root block.
-        let (root_cid, loaded_cid) = self.resolve_root(&path, &mut ctx).await?;
+        let (root_cid, tail, loaded_cid) = self.resolve_root(&path, &mut ctx).await?;
         match loaded_cid.source {
             Source::Store(_) => inc!(ResolverMetrics::CacheHit),
             _ => inc!(ResolverMetrics::CacheMiss),
         }
 
         let codec = Codec::try_from(root_cid.codec()).context("unknown codec")?;
+        // walk the tail below the resolved root, but report the requested path
+        let resolved = Path {
+            typ: PathType::Ipfs,
+            root: CidOrDomain::Cid(root_cid),
+            tail,
+        };
 
-        match codec {
+        let mut out = match codec {
             Codec::DagPb => {
-                self.resolve_dag_pb_or_unixfs(path, root_cid, loaded_cid, ctx)
-                    .await
+                self.resolve_dag_pb_or_unixfs(resolved, root_cid, loaded_cid, ctx)
+                    .await?
+            }
+            Codec::DagCbor => {
+                self.resolve_dag_cbor(resolved, root_cid, loaded_cid, ctx)
+                    .await?
+            }
+            Codec::DagJson => {
+                self.resolve_dag_json(resolved, root_cid, loaded_cid, ctx)
+                    .await?
+            }
+            Codec::Raw => {
+                self.resolve_raw(resolved, root_cid, loaded_cid, ctx)
+                    .await?
             }
-            Codec::DagCbor => self.resolve_dag_cbor(path, root_cid, loaded_cid, ctx).await,
-            Codec::DagJson => self.resolve_dag_json(path, root_cid, loaded_cid, ctx).await,
-            Codec::Raw => self.resolve_raw(path, root_cid, loaded_cid, ctx).await,
             _ => bail!("unsupported codec {:?}", codec),
+        };
+        out.metadata.path = path;
+        Ok(out)
+    }
+
+    /// Replaces legacy metadata nodes with the content they wrap.
+    async fn unwrap_unixfs_metadata(
+        &self,
+        current: &mut UnixfsNode,
+        resolved_path: &mut Vec<Cid>,
+        ctx: &mut LoaderContext,
+    ) -> Result<()> {
+        while let Some(target) = current.metadata_target()? {
+            let cid = target.cid;
+            let loaded_cid = self.load_cid(&cid, ctx).await?;
+            resolved_path.push(cid);
+            *current = UnixfsNode::decode(&cid, loaded_cid.data)?;
         }
+        Ok(())
     }
 
     async fn inner_resolve(
@@ -1066,6 +1195,8 @@ impl<T: ContentLoader> Resolver<T> {
         part: &str,
         ctx: &mut LoaderContext,
     ) -> Result<()> {
+        self.unwrap_unixfs_metadata(current, resolved_path, ctx)
+            .await?;
         match current {
             UnixfsNode::Directory(_) => {
                 let next_link = current
@@ -1115,6 +1246,8 @@ impl<T: ContentLoader> Resolver<T> {
                 self.inner_resolve(&mut current, &mut resolved_path, part, &mut ctx)
                     .await?;
             }
+            self.unwrap_unixfs_metadata(&mut current, &mut resolved_path, &mut ctx)
+                .await?;
 
             let unixfs_type = match current.typ() {
                 Some(DataType::Directory) => Some(UnixfsType::Dir),
@@ -1134,6 +1267,8 @@ impl<T: ContentLoader> Resolver<T> {
                 unixfs_type,
                 resolved_path,
                 source: loaded_cid.source,
+                mode: current.mode(),
+                mtime: current.mtime(),
             };
             Ok(Out {
                 metadata,
@@ -1184,6 +1319,8 @@ impl<T: ContentLoader> Resolver<T> {
             unixfs_type: None,
             resolved_path: vec![cid],
             source: loaded_cid.source,
+            mode: None,
+            mtime: None,
         };
         Ok(Out {
             metadata,
@@ -1231,6 +1368,8 @@ impl<T: ContentLoader> Resolver<T> {
             unixfs_type: None,
             resolved_path: vec![cid],
             source: loaded_cid.source,
+            mode: None,
+            mtime: None,
         };
         Ok(Out {
             metadata,
@@ -1278,6 +1417,8 @@ impl<T: ContentLoader> Resolver<T> {
             unixfs_type: None,
             resolved_path: vec![cid],
             source: loaded_cid.source,
+            mode: None,
+            mtime: None,
         };
         Ok(Out {
             metadata,
@@ -1316,6 +1457,8 @@ impl<T: ContentLoader> Resolver<T> {
             unixfs_type: None,
             resolved_path: vec![cid],
             source: loaded_cid.source,
+            mode: None,
+            mtime: None,
         };
         Ok(Out {
             metadata,
@@ -1367,7 +1510,15 @@ impl<T: ContentLoader> Resolver<T> {
     }
 
     #[tracing::instrument(skip(self))]
-    async fn resolve_path_to_cid(&self, root: &Path, ctx: &mut LoaderContext) -> Result<Cid> {
+    /// Follows IPNS names and DNSLink domains in `root` until it reaches an `/ipfs/` path.
+    ///
+    /// Returns the root cid of that path and the tail to resolve below it, which is the
+    /// tail of every followed record value, followed by the tail of `root`.
+    async fn resolve_path_to_cid(
+        &self,
+        root: &Path,
+        ctx: &mut LoaderContext,
+    ) -> Result<(Cid, Vec<String>)> {
         let mut current = root.clone();
 
         // maximum cursion of ipns lookups
@@ -1377,21 +1528,29 @@ impl<T: ContentLoader> Resolver<T> {
             match current.typ {
                 PathType::Ipfs => match current.root {
                     CidOrDomain::Cid(ref c) => {
-                        return Ok(*c);
+                        return Ok((*c, current.tail));
                     }
                     CidOrDomain::Domain(_) => bail!("invalid domain encountered"),
                 },
                 PathType::Ipns => match current.root {
                     CidOrDomain::Cid(ref c) => {
-                        let c = self.load_ipns_record(c).await?;
-                        current = Path::from_cid(c);
+                        let peer_id = PeerId::from_bytes(&c.hash().to_bytes())
+                            .with_context(|| format!("invalid ipns name {}", c))?;
+                        let value = self.load_ipns_record(peer_id).await?;
+                        current = value.join(current.tail);
                     }
                     CidOrDomain::Domain(ref domain) => {
+                        // peer ids with inlined keys are not valid cids
+                        if let Ok(peer_id) = domain.parse::<PeerId>() {
+                            let value = self.load_ipns_record(peer_id).await?;
+                            current = value.join(current.tail);
+                            continue;
+                        }
                         let mut records = resolve_dnslink(domain).await?;
                         if records.is_empty() {
                             bail!("no valid dnslink records found for {}", domain);
                         }
-                        current = records.remove(0);
+                        current = records.remove(0).join(current.tail);
                     }
                 },
             }
@@ -1401,25 +1560,50 @@ impl<T: ContentLoader> Resolver<T> {
     }
 
     #[tracing::instrument(skip(self))]
-    async fn resolve_root(&self, root: &Path, ctx: &mut LoaderContext) -> Result<(Cid, LoadedCid)> {
-        let cid = self.resolve_path_to_cid(root, ctx).await?;
+    async fn resolve_root(
+        &self,
+        root: &Path,
+        ctx: &mut LoaderContext,
+    ) -> Result<(Cid, Vec<String>, LoadedCid)> {
+        let (cid, tail) = self.resolve_path_to_cid(root, ctx).await?;
         let loaded_cid = self.load_cid(&cid, ctx).await?;
-        Ok((cid, loaded_cid))
+        Ok((cid, tail, loaded_cid))
     }
 
     #[tracing::instrument(skip(self))]
-    async fn load_cid(&self, cid: &Cid, ctx: &mut LoaderContext) -> Result<LoadedCid> {
+    pub(crate) async fn load_cid(&self, cid: &Cid, ctx: &mut LoaderContext) -> Result<LoadedCid> {
         self.loader.load_cid(cid, ctx).await
     }
 
+    /// Loads the data of the block `cid`, without decoding it.
+    pub(crate) async fn load_block(&self, cid: Cid) -> Result<Bytes> {
+        let mut ctx = LoaderContext::from_path(
+            self.next_id(),
+            self.session_closer.clone(),
+            Path::from_cid(cid),
+        );
+        Ok(self.load_cid(&cid, &mut ctx).await?.data)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn has_cid(&self, cid: &Cid) -> Result<bool> {
         self.loader.has_cid(cid).await
     }
 
+    /// Looks up the records published by `peer_id` and returns the path of the
+    /// most recent valid one.
     #[tracing::instrument(skip(self))]
-    async fn load_ipns_record(&self, cid: &Cid) -> Result<Cid> {
-        todo!()
+    async fn load_ipns_record(&self, peer_id: PeerId) -> Result<Path> {
+        let records = self
+            .loader
+            .load_records(&ipns::record_key(&peer_id))
+            .await?;
+        let record = IpnsRecord::select(&peer_id, &records, OffsetDateTime::now_utc())?;
+        debug!("resolved /ipns/{} to {}", peer_id, record.value);
+        record
+            .value
+            .parse()
+            .with_context(|| format!("invalid ipns value for {}", peer_id))
     }
 }
 
@@ -1505,6 +1689,32 @@ mod tests {
         }
     }
 
+    /// Loader which serves IPNS records next to blocks.
+    #[derive(Debug, Clone, Default)]
+    struct RecordLoader {
+        blocks: HashMap<Cid, Bytes>,
+        records: HashMap<Vec<u8>, Vec<Bytes>>,
+    }
+
+    #[async_trait]
+    impl ContentLoader for RecordLoader {
+        async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
+            self.blocks.load_cid(cid, ctx).await
+        }
+
+        async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+            Ok(())
+        }
+
+        async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+            Ok(self.blocks.contains_key(cid))
+        }
+
+        async fn load_records(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+            Ok(self.records.get(key).cloned().unwrap_or_default())
+        }
+    }
+
     async fn load_fixture(p: &str) -> Bytes {
         Bytes::from(tokio::fs::read(format!("./fixtures/{p}")).await.unwrap())
     }
@@ -1727,6 +1937,80 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_resolve_dag() {
+        // a dag-json node linking to a dag-cbor node, linked from a dag-pb node
+        let child = dag::from_json(br#"{"value": 42}"#, Codec::DagCbor).unwrap();
+        let parent = dag::from_json(
+            format!(r#"{{"child": {{"/": "{}"}}, "name": "x"}}"#, child.cid()).as_bytes(),
+            Codec::DagJson,
+        )
+        .unwrap();
+        let pb = dag::from_json(
+            format!(
+                r#"{{"Links": [{{"Hash": {{"/": "{}"}}, "Name": "parent", "Tsize": 1}}]}}"#,
+                parent.cid()
+            )
+            .as_bytes(),
+            Codec::DagPb,
+        )
+        .unwrap();
+        let loader: Arc<HashMap<_, _>> = Arc::new(
+            [&child, &parent, &pb]
+                .into_iter()
+                .map(|block| (*block.cid(), block.data().clone()))
+                .collect(),
+        );
+        let resolver = Resolver::new(loader);
+
+        let resolved = resolver
+            .resolve_dag(
+                format!("/ipfs/{}/child/value", parent.cid())
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resolved.cid, *child.cid());
+        assert_eq!(resolved.rem_path, vec!["value".to_string()]);
+        assert_eq!(resolved.node, Ipld::Integer(42));
+
+        // a link at the end of the path is followed
+        let resolved = resolver
+            .resolve_dag(format!("/ipfs/{}/parent/child", pb.cid()).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resolved.cid, *child.cid());
+        assert!(resolved.rem_path.is_empty());
+
+        // dag-pb links are only looked up by name at the root of the block
+        let resolved = resolver
+            .resolve_dag(format!("/ipfs/{}/Links/0/Name", pb.cid()).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resolved.cid, *pb.cid());
+        assert_eq!(resolved.rem_path, vec!["Links", "0", "Name"]);
+        assert_eq!(resolved.node, Ipld::String("parent".to_string()));
+        let resolved = resolver
+            .resolve_dag(format!("/ipfs/{}/Links/0/Hash", pb.cid()).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resolved.cid, *parent.cid());
+        assert!(resolved.rem_path.is_empty());
+
+        let resolved = resolver
+            .resolve_dag(format!("/ipfs/{}/name", parent.cid()).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resolved.cid, *parent.cid());
+        assert_eq!(resolved.node, Ipld::String("x".to_string()));
+
+        assert!(resolver
+            .resolve_dag(format!("/ipfs/{}/missing", parent.cid()).parse().unwrap())
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_unixfs_basics_cid_v0() {
         // Test content
@@ -2061,6 +2345,30 @@ mod tests {
                 panic!("invalid result: {:?}", ipld_readme);
             }
         }
+
+        {
+            // reading ahead returns the same content
+            let resolver = resolver.with_read_ahead(2);
+            let path = format!("/ipfs/{root_cid_str}");
+            let ipld_readme = resolver.resolve(path.parse().unwrap()).await.unwrap();
+            let size = ipld_readme.metadata().size.unwrap();
+
+            let ctx = ipld_readme.context.clone();
+            if let OutContent::Unixfs(node) = ipld_readme.content {
+                let cr = seek_and_clip(ctx.clone(), &node, resolver.clone(), 0..size).await;
+                let content = read_to_string(cr).await;
+                assert_eq!(content.len(), size as usize);
+                assert!(content.starts_with("# iroh"));
+
+                let cr = seek_and_clip(ctx, &node, resolver.clone(), 101..size - 101).await;
+                let content = read_to_string(cr).await;
+                assert_eq!(content.len(), (size - 202) as usize);
+                assert!(content.starts_with("2.0</a>"));
+                assert!(content.ends_with("the Apac"));
+            } else {
+                panic!("invalid result: {:?}", ipld_readme);
+            }
+        }
     }
 
     #[tokio::test]
@@ -2396,6 +2704,125 @@ mod tests {
             assert_eq!(parts[4].metadata().path, pieces_cid_str[3].parse().unwrap());
             assert_eq!(parts[5].metadata().path, pieces_cid_str[4].parse().unwrap());
         }
+
+        {
+            // loading links concurrently keeps their order
+            let resolver = resolver.with_concurrency(3);
+            let path = format!("/ipfs/{root_cid_str}");
+            let parts: Vec<_> = resolver
+                .resolve_recursive(path.parse().unwrap())
+                .try_collect()
+                .await
+                .unwrap();
+            let paths: Vec<_> = parts.iter().map(|p| p.metadata().path.clone()).collect();
+            let expected: Vec<Path> = std::iter::once(Path::from_cid(root_cid))
+                .chain(pieces_cid_str.iter().map(|c| c.parse().unwrap()))
+                .collect();
+            assert_eq!(paths, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unixfs_raw_and_metadata_nodes() {
+        use crate::unixfs::{dag_pb, unixfs_pb, Node};
+        use crate::unixfs_builder::encode_unixfs_pb;
+        use prost::Message;
+
+        fn link(block: &Block, name: Option<&str>) -> dag_pb::PbLink {
+            dag_pb::PbLink {
+                hash: Some(block.cid().to_bytes()),
+                name: name.map(ToString::to_string),
+                tsize: Some(block.data().len() as u64),
+            }
+        }
+
+        // leaf as created by older go-ipfs versions: a dag-pb node of type raw
+        let inner = unixfs_pb::Data {
+            r#type: DataType::Raw as i32,
+            data: Some(Bytes::from("hello")),
+            filesize: Some(5),
+            ..Default::default()
+        };
+        let outer = encode_unixfs_pb(&inner, Vec::new()).unwrap();
+        let leaf = UnixfsNode::RawNode(Node { outer, inner }).encode().unwrap();
+
+        let inner = unixfs_pb::Data {
+            r#type: DataType::File as i32,
+            filesize: Some(10),
+            blocksizes: vec![5, 5],
+            ..Default::default()
+        };
+        let outer = encode_unixfs_pb(&inner, vec![link(&leaf, None), link(&leaf, None)]).unwrap();
+        let file = UnixfsNode::File(Node { outer, inner }).encode().unwrap();
+
+        let mime = unixfs_pb::Metadata {
+            mime_type: Some("text/plain".into()),
+        };
+        let inner = unixfs_pb::Data {
+            r#type: DataType::Metadata as i32,
+            data: Some(mime.encode_to_vec().into()),
+            ..Default::default()
+        };
+        let outer = encode_unixfs_pb(&inner, vec![link(&file, None)]).unwrap();
+        let metadata = UnixfsNode::Metadata(Node { outer, inner })
+            .encode()
+            .unwrap();
+
+        let inner = unixfs_pb::Data {
+            r#type: DataType::Directory as i32,
+            ..Default::default()
+        };
+        let outer = encode_unixfs_pb(&inner, vec![link(&metadata, Some("hello.txt"))]).unwrap();
+        let dir = UnixfsNode::Directory(Node { outer, inner })
+            .encode()
+            .unwrap();
+
+        let decoded = UnixfsNode::decode(metadata.cid(), metadata.data().clone()).unwrap();
+        assert_eq!(decoded.typ(), Some(DataType::Metadata));
+        assert_eq!(decoded.mime_type().unwrap(), Some("text/plain".to_string()));
+        assert_eq!(decoded.metadata_target().unwrap().unwrap().cid, *file.cid());
+
+        let loader: HashMap<Cid, Bytes> = [&leaf, &file, &metadata, &dir]
+            .into_iter()
+            .map(|b| (*b.cid(), b.data().clone()))
+            .collect();
+        let resolver = Resolver::new(Arc::new(loader));
+
+        for (path, content) in [
+            (format!("/ipfs/{}", leaf.cid()), "hello"),
+            (format!("/ipfs/{}", file.cid()), "hellohello"),
+            (format!("/ipfs/{}", metadata.cid()), "hellohello"),
+            (format!("/ipfs/{}/hello.txt", dir.cid()), "hellohello"),
+        ] {
+            let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+            assert_eq!(out.metadata().unixfs_type, Some(UnixfsType::File));
+            let reader = out
+                .pretty(
+                    resolver.clone(),
+                    OutMetrics::default(),
+                    ResponseClip::NoClip,
+                )
+                .unwrap();
+            assert_eq!(read_to_string(reader).await, content, "{}", path);
+        }
+
+        let out = resolver
+            .resolve(format!("/ipfs/{}", metadata.cid()).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            out.metadata().resolved_path,
+            vec![*metadata.cid(), *file.cid()]
+        );
+
+        // a metadata node must wrap exactly one node
+        let inner = unixfs_pb::Data {
+            r#type: DataType::Metadata as i32,
+            ..Default::default()
+        };
+        let outer = encode_unixfs_pb(&inner, vec![link(&file, None), link(&file, None)]).unwrap();
+        let bytes: Bytes = outer.encode_to_vec().into();
+        assert!(UnixfsNode::decode(dir.cid(), bytes).is_err());
     }
 
     #[tokio::test]
@@ -2709,6 +3136,120 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_resolve_ipns() {
+        use libp2p::identity::Keypair;
+
+        let data = Bytes::from_static(b"hello ipns");
+        let cid = Cid::new_v1(Codec::Raw as _, Code::Sha2_256.digest(&data));
+        let now = OffsetDateTime::now_utc();
+        let publish = |keypair: &Keypair, value: String, sequence: u64| {
+            let record = IpnsRecord {
+                value,
+                sequence,
+                validity: now + time::Duration::hours(1),
+                ttl: None,
+            };
+            Bytes::from(record.encode(keypair).unwrap())
+        };
+
+        let inner = Keypair::generate_ed25519();
+        let inner_id = inner.public().to_peer_id();
+        let outer = Keypair::generate_ed25519();
+        let outer_id = outer.public().to_peer_id();
+
+        let mut loader = RecordLoader::default();
+        loader.blocks.insert(cid, data);
+        loader.records.insert(
+            ipns::record_key(&inner_id),
+            vec![
+                publish(&inner, format!("/ipfs/{cid}"), 2),
+                publish(
+                    &inner,
+                    "/ipfs/QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn".into(),
+                    1,
+                ),
+            ],
+        );
+        // points to the other name
+        loader.records.insert(
+            ipns::record_key(&outer_id),
+            vec![publish(&outer, format!("/ipns/{inner_id}"), 1)],
+        );
+        let resolver = Resolver::new(loader);
+
+        for name in [inner_id, outer_id] {
+            let path = format!("/ipns/{name}");
+            let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+            assert_eq!(out.metadata().resolved_path, vec![cid]);
+        }
+
+        let unknown = Keypair::generate_ed25519().public().to_peer_id();
+        let path = format!("/ipns/{unknown}");
+        assert!(resolver.resolve(path.parse().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_ipns_sub_path() {
+        use libp2p::identity::Keypair;
+
+        let ipld = Ipld::Map(
+            [(
+                "a".to_string(),
+                Ipld::Map([("b".to_string(), Ipld::String("hello".into()))].into()),
+            )]
+            .into(),
+        );
+        let mut data = Vec::new();
+        ipld.encode(IpldCodec::DagCbor, &mut data).unwrap();
+        let cid = Cid::new_v1(Codec::DagCbor as _, Code::Sha2_256.digest(&data));
+        let now = OffsetDateTime::now_utc();
+        let publish = |keypair: &Keypair, value: String| {
+            let record = IpnsRecord {
+                value,
+                sequence: 1,
+                validity: now + time::Duration::hours(1),
+                ttl: None,
+            };
+            Bytes::from(record.encode(keypair).unwrap())
+        };
+
+        let inner = Keypair::generate_ed25519();
+        let inner_id = inner.public().to_peer_id();
+        let outer = Keypair::generate_ed25519();
+        let outer_id = outer.public().to_peer_id();
+
+        let mut loader = RecordLoader::default();
+        loader.blocks.insert(cid, data.into());
+        loader.records.insert(
+            ipns::record_key(&inner_id),
+            vec![publish(&inner, format!("/ipfs/{cid}/a"))],
+        );
+        loader.records.insert(
+            ipns::record_key(&outer_id),
+            vec![publish(&outer, format!("/ipns/{inner_id}/b"))],
+        );
+        let resolver = Resolver::new(loader);
+
+        // the tail of the record value comes before the tail of the requested path
+        for path in [format!("/ipns/{inner_id}/b"), format!("/ipns/{outer_id}")] {
+            let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+            assert_eq!(out.metadata().path.to_string(), path);
+            assert_eq!(out.metadata().resolved_path, vec![cid]);
+            match out.content {
+                OutContent::DagCbor(ipld, _) => assert_eq!(ipld, Ipld::String("hello".into())),
+                content => panic!("invalid content: {:?}", content),
+            }
+        }
+
+        let path = format!("/ipns/{inner_id}");
+        let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+        match out.content {
+            OutContent::DagCbor(ipld, _) => assert!(matches!(ipld, Ipld::Map(_))),
+            content => panic!("invalid content: {:?}", content),
+        }
+    }
+
     #[tokio::test]
     async fn test_resolve_txt_record() {
         let result = resolve_txt_record("_dnslink.ipfs.io.").await.unwrap();
@@ -2869,6 +3410,41 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_unixfs_hamt_lookup_loads() {
+        // same content as `test_unixfs_hamt_dir`
+        let root_cid_str = "QmUu8pzQ5yjhDrg4GiHYLeko2oT76vcmYX5bw6sjiEJ82k";
+
+        let reader = tokio::io::BufReader::new(
+            tokio::fs::File::open("./fixtures/big-foo.car")
+                .await
+                .unwrap(),
+        );
+        let car_reader = iroh_car::CarReader::new(reader).await.unwrap();
+        let store = crate::patch::tests::MemStore::default();
+        let blocks: HashMap<Cid, Bytes> = car_reader
+            .stream()
+            .map(|r| r.map(|(k, v)| (k, Bytes::from(v))))
+            .try_collect()
+            .await
+            .unwrap();
+        *store.blocks.lock().unwrap() = blocks;
+        let resolver = Resolver::new(store.clone());
+
+        for name in ["1.txt", "5000.txt", "9999.txt", "hello.txt"] {
+            store.loaded.store(0, std::sync::atomic::Ordering::SeqCst);
+            let path = format!("/ipfs/{root_cid_str}/{name}");
+            resolver.resolve(path.parse().unwrap()).await.unwrap();
+            // the root, the nested shards on the way and the entry itself,
+            // instead of all ~11k blocks of the directory
+            let loaded = store.loaded.load(std::sync::atomic::Ordering::SeqCst);
+            assert!(loaded <= 4, "{name} loaded {loaded} blocks");
+        }
+
+        let path = format!("/ipfs/{root_cid_str}/missing.txt");
+        assert!(resolver.resolve(path.parse().unwrap()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_resolve_recursive_with_path() {
         // Test content
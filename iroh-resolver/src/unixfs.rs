@@ -1,15 +1,20 @@
 use std::{
-    collections::VecDeque,
+    collections::{hash_map, HashMap, VecDeque},
     fmt::Debug,
     io::Cursor,
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 use bytes::{Buf, Bytes};
 use cid::{multihash::MultihashDigest, Cid};
-use futures::{future::BoxFuture, stream::BoxStream, FutureExt, Stream, StreamExt};
+use futures::{
+    future::{BoxFuture, RemoteHandle},
+    stream::BoxStream,
+    FutureExt, Stream, StreamExt,
+};
 use prost::Message;
 use tokio::io::{AsyncRead, AsyncSeek};
 
@@ -51,6 +56,8 @@ pub struct Unixfs {
 impl Unixfs {
     pub fn from_bytes<B: Buf>(bytes: B) -> Result<Self> {
         let proto = unixfs_pb::Data::decode(bytes)?;
+        // ensure the type is valid, so that `typ` can not fail later on
+        DataType::try_from(proto.r#type)?;
 
         Ok(Unixfs { inner: proto })
     }
@@ -64,6 +71,65 @@ impl Unixfs {
     }
 }
 
+/// Modification time of a UnixFS node, as defined in UnixFS 1.5.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixTime {
+    /// Seconds relative to the unix epoch, negative for times before it.
+    pub seconds: i64,
+    /// Fractional part of the timestamp, in nanoseconds.
+    pub nanos: u32,
+}
+
+impl UnixTime {
+    pub(crate) fn from_pb(mtime: &unixfs_pb::UnixTime) -> Self {
+        UnixTime {
+            seconds: mtime.seconds,
+            nanos: mtime.fractional_nanoseconds.unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn to_pb(self) -> unixfs_pb::UnixTime {
+        unixfs_pb::UnixTime {
+            seconds: self.seconds,
+            // the spec requires the fractional part to be omitted when it is zero
+            fractional_nanoseconds: (self.nanos != 0).then_some(self.nanos),
+        }
+    }
+}
+
+impl From<SystemTime> for UnixTime {
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => UnixTime {
+                seconds: d.as_secs() as i64,
+                nanos: d.subsec_nanos(),
+            },
+            Err(e) => {
+                // before the epoch, the nanoseconds always count forward
+                let d = e.duration();
+                let mut seconds = -(d.as_secs() as i64);
+                let mut nanos = d.subsec_nanos();
+                if nanos > 0 {
+                    seconds -= 1;
+                    nanos = 1_000_000_000 - nanos;
+                }
+                UnixTime { seconds, nanos }
+            }
+        }
+    }
+}
+
+impl From<UnixTime> for SystemTime {
+    fn from(time: UnixTime) -> Self {
+        let nanos = Duration::from_nanos(time.nanos as u64);
+        if time.seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(time.seconds as u64) + nanos
+        } else {
+            UNIX_EPOCH - Duration::from_secs(time.seconds.unsigned_abs()) + nanos
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Link {
     pub cid: Cid,
@@ -106,6 +172,8 @@ pub enum UnixfsNode {
     File(Node),
     Symlink(Node),
     HamtShard(Node, Hamt),
+    /// Legacy metadata node, wrapping a single link to the actual content.
+    Metadata(Node),
 }
 
 #[derive(
@@ -166,7 +234,7 @@ impl Node {
             DataType::File => Links::File(PbLinks::new(&self.outer)),
             DataType::Symlink => Links::Symlink(PbLinks::new(&self.outer)),
             DataType::HamtShard => Links::HamtShard(PbLinks::new(&self.outer)),
-            DataType::Metadata => unimplemented!(),
+            DataType::Metadata => Links::Metadata(PbLinks::new(&self.outer)),
         }
     }
 
@@ -179,6 +247,16 @@ impl Node {
     pub fn fanout(&self) -> Option<u32> {
         self.inner.fanout.and_then(|f| u32::try_from(f).ok())
     }
+
+    /// Returns the unix permission bits, if they were recorded.
+    pub fn mode(&self) -> Option<u32> {
+        self.inner.mode
+    }
+
+    /// Returns the modification time, if it was recorded.
+    pub fn mtime(&self) -> Option<UnixTime> {
+        self.inner.mtime.as_ref().map(UnixTime::from_pb)
+    }
 }
 
 impl UnixfsNode {
@@ -198,7 +276,7 @@ impl UnixfsNode {
 
                 // ensure correct unixfs type
                 match typ {
-                    DataType::Raw => todo!(),
+                    DataType::Raw => Ok(UnixfsNode::RawNode(node)),
                     DataType::Directory => Ok(UnixfsNode::Directory(node)),
                     DataType::File => Ok(UnixfsNode::File(node)),
                     DataType::Symlink => Ok(UnixfsNode::Symlink(node)),
@@ -206,7 +284,16 @@ impl UnixfsNode {
                         let hamt = Hamt::from_node(&node)?;
                         Ok(UnixfsNode::HamtShard(node, hamt))
                     }
-                    DataType::Metadata => bail!("unixfs metadata is not supported"),
+                    DataType::Metadata => {
+                        ensure!(
+                            node.outer.links.len() == 1,
+                            "unixfs metadata must link to exactly one node, found {}",
+                            node.outer.links.len()
+                        );
+                        // ensure the metadata itself is valid
+                        unixfs_pb::Metadata::decode(node.data().unwrap_or_default())?;
+                        Ok(UnixfsNode::Metadata(node))
+                    }
                 }
             }
         }
@@ -224,7 +311,8 @@ impl UnixfsNode {
             | UnixfsNode::Directory(node)
             | UnixfsNode::File(node)
             | UnixfsNode::Symlink(node)
-            | UnixfsNode::HamtShard(node, _) => {
+            | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node) => {
                 let out = node.encode()?;
                 let links = node
                     .links()
@@ -255,6 +343,7 @@ impl UnixfsNode {
             UnixfsNode::File(_) => Some(DataType::File),
             UnixfsNode::Symlink(_) => Some(DataType::Symlink),
             UnixfsNode::HamtShard(_, _) => Some(DataType::HamtShard),
+            UnixfsNode::Metadata(_) => Some(DataType::Metadata),
         }
     }
 
@@ -267,7 +356,8 @@ impl UnixfsNode {
             | UnixfsNode::RawNode(node)
             | UnixfsNode::File(node)
             | UnixfsNode::Symlink(node)
-            | UnixfsNode::HamtShard(node, _) => node.size(),
+            | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node) => node.size(),
         }
     }
 
@@ -280,7 +370,36 @@ impl UnixfsNode {
             | UnixfsNode::RawNode(node)
             | UnixfsNode::File(node)
             | UnixfsNode::Symlink(node)
-            | UnixfsNode::HamtShard(node, _) => node.filesize(),
+            | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node) => node.filesize(),
+        }
+    }
+
+    /// Returns the unix permission bits.
+    /// Raw nodes never carry any metadata.
+    pub fn mode(&self) -> Option<u32> {
+        match self {
+            UnixfsNode::Raw(_) => None,
+            UnixfsNode::Directory(node)
+            | UnixfsNode::RawNode(node)
+            | UnixfsNode::File(node)
+            | UnixfsNode::Symlink(node)
+            | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node) => node.mode(),
+        }
+    }
+
+    /// Returns the modification time.
+    /// Raw nodes never carry any metadata.
+    pub fn mtime(&self) -> Option<UnixTime> {
+        match self {
+            UnixfsNode::Raw(_) => None,
+            UnixfsNode::Directory(node)
+            | UnixfsNode::RawNode(node)
+            | UnixfsNode::File(node)
+            | UnixfsNode::Symlink(node)
+            | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node) => node.mtime(),
         }
     }
 
@@ -293,6 +412,7 @@ impl UnixfsNode {
             | UnixfsNode::RawNode(node)
             | UnixfsNode::Symlink(node)
             | UnixfsNode::HamtShard(node, _)
+            | UnixfsNode::Metadata(node)
             | UnixfsNode::File(node) => node.blocksizes(),
         }
     }
@@ -305,6 +425,7 @@ impl UnixfsNode {
             UnixfsNode::File(node) => Links::File(PbLinks::new(&node.outer)),
             UnixfsNode::Symlink(node) => Links::Symlink(PbLinks::new(&node.outer)),
             UnixfsNode::HamtShard(node, _) => Links::HamtShard(PbLinks::new(&node.outer)),
+            UnixfsNode::Metadata(node) => Links::Metadata(PbLinks::new(&node.outer)),
         }
     }
 
@@ -329,6 +450,29 @@ impl UnixfsNode {
             .transpose()
     }
 
+    /// If this is a metadata node, returns the mime type of the content it wraps.
+    pub fn mime_type(&self) -> Result<Option<String>> {
+        if let Self::Metadata(ref node) = self {
+            let metadata = unixfs_pb::Metadata::decode(node.data().unwrap_or_default())?;
+            Ok(metadata.mime_type)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// If this is a metadata node, returns the link to the content it wraps.
+    pub fn metadata_target(&self) -> Result<Option<LinkRef<'_>>> {
+        match self {
+            Self::Metadata(_) => self
+                .links()
+                .next()
+                .transpose()?
+                .map(Some)
+                .ok_or_else(|| anyhow!("unixfs metadata without link")),
+            _ => Ok(None),
+        }
+    }
+
     pub fn symlink(&self) -> Result<Option<&str>> {
         if let Self::Symlink(ref node) = self {
             let link = std::str::from_utf8(node.inner.data.as_deref().unwrap_or_default())?;
@@ -349,7 +493,8 @@ impl UnixfsNode {
             UnixfsNode::Raw(_)
             | UnixfsNode::RawNode(_)
             | UnixfsNode::File(_)
-            | UnixfsNode::Symlink(_) => Ok(None),
+            | UnixfsNode::Symlink(_)
+            | UnixfsNode::Metadata(_) => Ok(None),
             UnixfsNode::Directory(_) => {
                 let source = self.links().map(|l| l.map(|l| l.to_owned()));
                 let stream = futures::stream::iter(source).boxed();
@@ -378,16 +523,24 @@ impl UnixfsNode {
             UnixfsNode::Raw(_)
             | UnixfsNode::RawNode(_)
             | UnixfsNode::File(_)
-            | UnixfsNode::Symlink(_) => {
+            | UnixfsNode::Symlink(_)
+            | UnixfsNode::Metadata(_) => {
                 let current_links = vec![self.links_owned()?];
+                // the data of a metadata node describes the content, it is not part of it
+                let current_node = match self {
+                    UnixfsNode::Metadata(_) => CurrentNodeState::None,
+                    _ => CurrentNodeState::Outer,
+                };
 
+                let prefetch = Prefetch::new(loader.read_ahead());
                 Ok(Some(UnixfsContentReader::File {
                     root_node: self,
                     pos: 0,
                     skip_pos: 0,
                     pos_max,
-                    current_node: CurrentNodeState::Outer,
+                    current_node,
                     current_links,
+                    prefetch,
                     loader,
                     out_metrics: om,
                     ctx: std::sync::Arc::new(tokio::sync::Mutex::new(ctx)),
@@ -423,6 +576,8 @@ pub enum UnixfsContentReader<T: ContentLoader> {
         current_node: CurrentNodeState,
         /// Stack of links left to traverse.
         current_links: Vec<VecDeque<Link>>,
+        /// Nodes that are loaded ahead of reading them.
+        prefetch: Prefetch,
         loader: Resolver<T>,
         out_metrics: OutMetrics,
         ctx: std::sync::Arc<tokio::sync::Mutex<LoaderContext>>,
@@ -473,6 +628,7 @@ impl<T: ContentLoader + Unpin + 'static> AsyncRead for UnixfsContentReader<T> {
                 skip_pos,
                 current_node,
                 current_links,
+                prefetch,
                 loader,
                 out_metrics,
                 ctx,
@@ -484,7 +640,9 @@ impl<T: ContentLoader + Unpin + 'static> AsyncRead for UnixfsContentReader<T> {
                         let res = poll_read_buf_at_pos(pos, *pos_max, data, buf);
                         Poll::Ready(res)
                     }
-                    UnixfsNode::File(node) => poll_read_file_at(
+                    UnixfsNode::File(node)
+                    | UnixfsNode::RawNode(node)
+                    | UnixfsNode::Metadata(node) => poll_read_file_at(
                         cx,
                         node,
                         loader.clone(),
@@ -494,6 +652,7 @@ impl<T: ContentLoader + Unpin + 'static> AsyncRead for UnixfsContentReader<T> {
                         buf,
                         current_links,
                         current_node,
+                        prefetch,
                         ctx.clone(),
                     ),
                     UnixfsNode::Symlink(node) => {
@@ -524,6 +683,7 @@ impl<T: ContentLoader + Unpin + 'static> AsyncSeek for UnixfsContentReader<T> {
                 skip_pos,
                 current_node: _,
                 current_links: _,
+                prefetch: _,
                 loader: _,
                 out_metrics: _,
                 ctx: _,
@@ -593,6 +753,7 @@ impl<T: ContentLoader + Unpin + 'static> AsyncSeek for UnixfsContentReader<T> {
                 skip_pos: _,
                 current_node: _,
                 current_links: _,
+                prefetch: _,
                 loader: _,
                 out_metrics: _,
                 ctx: _,
@@ -644,9 +805,70 @@ impl Debug for CurrentNodeState {
     }
 }
 
+/// Loads the nodes of a file ahead of reading them, so they are fetched
+/// concurrently instead of one after the other.
+pub struct Prefetch {
+    limit: usize,
+    loading: HashMap<Cid, RemoteHandle<Result<UnixfsNode>>>,
+}
+
+impl Prefetch {
+    pub fn new(limit: usize) -> Self {
+        Prefetch {
+            limit,
+            loading: HashMap::new(),
+        }
+    }
+
+    /// Starts loading the next `limit` links, in the order they are read, and
+    /// stops loading the ones that are not among them anymore.
+    fn update<T: ContentLoader + 'static>(
+        &mut self,
+        current_links: &[VecDeque<Link>],
+        loader: &Resolver<T>,
+        ctx: &std::sync::Arc<tokio::sync::Mutex<LoaderContext>>,
+    ) {
+        if self.limit == 0 {
+            return;
+        }
+        // the innermost links are read first
+        let next: Vec<Cid> = current_links
+            .iter()
+            .rev()
+            .flatten()
+            .take(self.limit)
+            .map(|link| link.cid)
+            .collect();
+        // dropping the handle cancels the load
+        self.loading.retain(|cid, _| next.contains(cid));
+        for cid in next {
+            if let hash_map::Entry::Vacant(entry) = self.loading.entry(cid) {
+                let (load, handle) = load_node(cid, loader.clone(), ctx.clone()).remote_handle();
+                tokio::task::spawn(load);
+                entry.insert(handle);
+            }
+        }
+    }
+
+    fn take(&mut self, cid: &Cid) -> Option<RemoteHandle<Result<UnixfsNode>>> {
+        self.loading.remove(cid)
+    }
+}
+
+async fn load_node<T: ContentLoader + 'static>(
+    cid: Cid,
+    loader: Resolver<T>,
+    ctx: std::sync::Arc<tokio::sync::Mutex<LoaderContext>>,
+) -> Result<UnixfsNode> {
+    let ctx = ctx.lock().await.clone();
+    let loaded_cid = loader.loader().load_cid(&cid, &ctx).await?;
+    UnixfsNode::decode(&cid, loaded_cid.data)
+}
+
 fn load_next_node<T: ContentLoader + 'static>(
     current_node: &mut CurrentNodeState,
     current_links: &mut Vec<VecDeque<Link>>,
+    prefetch: &mut Prefetch,
     loader: Resolver<T>,
     ctx: std::sync::Arc<tokio::sync::Mutex<LoaderContext>>,
 ) -> bool {
@@ -670,14 +892,11 @@ fn load_next_node<T: ContentLoader + 'static>(
 
     let link = links.pop_front().unwrap();
 
-    let fut = async move {
-        let ctx = ctx.lock().await;
-        let loaded_cid = loader.loader().load_cid(&link.cid, &ctx).await?;
-        let node = UnixfsNode::decode(&link.cid, loaded_cid.data)?;
-
-        Ok(node)
-    }
-    .boxed();
+    let fut = match prefetch.take(&link.cid) {
+        Some(handle) => handle.boxed(),
+        None => load_node(link.cid, loader.clone(), ctx.clone()).boxed(),
+    };
+    prefetch.update(current_links, &loader, &ctx);
     *current_node = CurrentNodeState::Loading(fut);
     false
 }
@@ -693,6 +912,7 @@ fn poll_read_file_at<T: ContentLoader + 'static>(
     buf: &mut tokio::io::ReadBuf<'_>,
     current_links: &mut Vec<VecDeque<Link>>,
     current_node: &mut CurrentNodeState,
+    prefetch: &mut Prefetch,
     ctx: std::sync::Arc<tokio::sync::Mutex<LoaderContext>>,
 ) -> Poll<std::io::Result<()>> {
     loop {
@@ -714,12 +934,24 @@ fn poll_read_file_at<T: ContentLoader + 'static>(
                     }
                 }
                 *current_node = CurrentNodeState::None;
-                if load_next_node(current_node, current_links, loader.clone(), ctx.clone()) {
+                if load_next_node(
+                    current_node,
+                    current_links,
+                    prefetch,
+                    loader.clone(),
+                    ctx.clone(),
+                ) {
                     return Poll::Ready(Ok(()));
                 }
             }
             CurrentNodeState::None => {
-                if load_next_node(current_node, current_links, loader.clone(), ctx.clone()) {
+                if load_next_node(
+                    current_node,
+                    current_links,
+                    prefetch,
+                    loader.clone(),
+                    ctx.clone(),
+                ) {
                     return Poll::Ready(Ok(()));
                 }
             }
@@ -792,6 +1024,7 @@ fn poll_read_file_at<T: ContentLoader + 'static>(
                             if load_next_node(
                                 current_node,
                                 current_links,
+                                prefetch,
                                 loader.clone(),
                                 ctx.clone(),
                             ) {
@@ -832,8 +1065,13 @@ fn poll_read_file_at<T: ContentLoader + 'static>(
                             }
                         }
                         // follow links
-                        if load_next_node(current_node, current_links, loader.clone(), ctx.clone())
-                        {
+                        if load_next_node(
+                            current_node,
+                            current_links,
+                            prefetch,
+                            loader.clone(),
+                            ctx.clone(),
+                        ) {
                             return Poll::Ready(Ok(()));
                         }
                     }
@@ -857,6 +1095,7 @@ pub enum Links<'a> {
     File(PbLinks<'a>),
     Symlink(PbLinks<'a>),
     HamtShard(PbLinks<'a>),
+    Metadata(PbLinks<'a>),
 }
 
 #[derive(Debug)]
@@ -881,7 +1120,8 @@ impl<'a> Iterator for Links<'a> {
             | Links::RawNode(links)
             | Links::File(links)
             | Links::Symlink(links)
-            | Links::HamtShard(links) => links.next(),
+            | Links::HamtShard(links)
+            | Links::Metadata(links) => links.next(),
         }
     }
 
@@ -892,7 +1132,8 @@ impl<'a> Iterator for Links<'a> {
             | Links::RawNode(links)
             | Links::File(links)
             | Links::Symlink(links)
-            | Links::HamtShard(links) => links.size_hint(),
+            | Links::HamtShard(links)
+            | Links::Metadata(links) => links.size_hint(),
         }
     }
 }
@@ -1,24 +1,29 @@
 use std::{
     fmt::Debug,
+    iter::Peekable,
     path::{Path, PathBuf},
     pin::Pin,
+    str::FromStr,
 };
 
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use bytes::Bytes;
 use cid::Cid;
-use futures::{stream::LocalBoxStream, Stream, StreamExt};
+use futures::{stream::LocalBoxStream, Stream, StreamExt, TryStreamExt};
+use ignore::{gitignore::GitignoreBuilder, DirEntry, Walk, WalkBuilder};
 use iroh_rpc_client::Client;
 use prost::Message;
 use tokio::io::AsyncRead;
+use tracing::warn;
 
 use crate::{
     balanced_tree::{TreeBuilder, DEFAULT_DEGREE},
+    change_cache::{ChangeCache, PendingFile},
     chunker::{Chunker, DEFAULT_CHUNKS_SIZE, DEFAULT_CHUNK_SIZE_LIMIT},
     resolver::Block,
-    unixfs::{dag_pb, unixfs_pb, DataType, Node, UnixfsNode},
+    unixfs::{dag_pb, unixfs_pb, DataType, Node, UnixTime, UnixfsNode},
 };
 
 // The maximum number of links we allow in a directory
@@ -41,6 +46,8 @@ enum DirectoryType {
 pub struct Directory {
     name: String,
     entries: Vec<Entry>,
+    mode: Option<u32>,
+    mtime: Option<UnixTime>,
 }
 
 impl Directory {
@@ -54,6 +61,8 @@ impl Directory {
         Directory {
             name: "".into(),
             entries: vec![Entry::Directory(self)],
+            mode: None,
+            mtime: None,
         }
     }
 
@@ -70,47 +79,84 @@ impl Directory {
     }
 
     pub fn encode<'a>(self) -> LocalBoxStream<'a, Result<Block>> {
+        self.encode_parts(PathBuf::new())
+            .try_filter_map(|part| async move {
+                match part {
+                    Part::Block(block) => Ok(Some(block)),
+                    Part::File { .. } => Ok(None),
+                }
+            })
+            .boxed_local()
+    }
+
+    /// The total size of the files that are read when encoding the directory,
+    /// as far as it is known upfront.
+    pub fn estimate_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::File(file) => file.size().unwrap_or_default(),
+                Entry::Directory(dir) => dir.estimate_size(),
+                Entry::Symlink(_) | Entry::Link { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// Encodes the directory, announcing each file before its blocks. `path` is
+    /// the path of the directory relative to the one that is added.
+    fn encode_parts<'a>(self, path: PathBuf) -> LocalBoxStream<'a, Result<Part>> {
         async_stream::try_stream! {
             let mut links = Vec::new();
             for entry in self.entries {
                 let (name, root) = match entry {
                     Entry::File(file) => {
                         let name = file.name().to_string();
+                        let cache = file.cache.clone();
+                        yield Part::File {
+                            path: path.join(&name),
+                            size: file.size(),
+                        };
                         let parts = file.encode().await?;
                         tokio::pin!(parts);
                         let mut root = None;
                         while let Some(part) = parts.next().await {
                             let block = part?;
                             root = Some(block.clone());
-                            yield block;
+                            yield Part::Block(block);
                         }
-                         (name, root)
+                        if let (Some(cache), Some(root)) = (cache, &root) {
+                            cache.insert(*root.cid(), root.data().len() as u64);
+                        }
+                         (name, root.map(|root| (*root.cid(), root.data().len() as u64)))
                     }
                     Entry::Directory(dir) => {
                         let name = dir.name.clone();
-                        let parts = dir.encode();
+                        let parts = dir.encode_parts(path.join(&name));
                         tokio::pin!(parts);
                         let mut root = None;
                         while let Some(part) = parts.next().await {
-                            let block = part?;
-                            root = Some(block.clone());
-                            yield block;
+                            let part = part?;
+                            if let Part::Block(ref block) = part {
+                                root = Some(block.clone());
+                            }
+                            yield part;
                         }
-                         (name, root)
+                         (name, root.map(|root| (*root.cid(), root.data().len() as u64)))
                     }
                     Entry::Symlink(sym) => {
                         let name = sym.name().to_string();
                         let block = sym.encode()?;
-                        let root = Some(block.clone());
-                        yield block;
+                        let root = Some((*block.cid(), block.data().len() as u64));
+                        yield Part::Block(block);
                         (name, root)
                     }
+                    Entry::Link { name, cid, tsize } => (name, Some((cid, tsize))),
                 };
-                let root_block = root.expect("file must not be empty");
+                let (cid, tsize) = root.expect("file must not be empty");
                 links.push(dag_pb::PbLink {
-                    hash: Some(root_block.cid().to_bytes()),
+                    hash: Some(cid.to_bytes()),
                     name: Some(name),
-                    tsize: Some(root_block.data().len() as u64),
+                    tsize: Some(tsize),
                 });
 
             }
@@ -118,17 +164,29 @@ impl Directory {
             // directory itself comes last
             let inner = unixfs_pb::Data {
                 r#type: DataType::Directory as i32,
+                mode: self.mode,
+                mtime: self.mtime.map(UnixTime::to_pb),
                 ..Default::default()
             };
             let outer = encode_unixfs_pb(&inner, links)?;
 
             let node = UnixfsNode::Directory(Node { outer, inner });
-            yield node.encode()?;
+            yield Part::Block(node.encode()?);
         }
         .boxed_local()
     }
 }
 
+/// A part of an encoded directory.
+enum Part {
+    /// The blocks of the file at `path` follow.
+    File {
+        path: PathBuf,
+        size: Option<u64>,
+    },
+    Block(Block),
+}
+
 enum Content {
     Reader(Pin<Box<dyn AsyncRead>>),
     Path(PathBuf),
@@ -160,6 +218,12 @@ pub struct File {
     content: Content,
     tree_builder: TreeBuilder,
     chunker: Chunker,
+    mode: Option<u32>,
+    mtime: Option<UnixTime>,
+    /// The size of the content, if it is known before reading it.
+    size: Option<u64>,
+    /// Where to record the root of the file, if it is added with a change cache.
+    cache: Option<PendingFile>,
 }
 
 impl Debug for File {
@@ -169,6 +233,9 @@ impl Debug for File {
             .field("content", &self.content)
             .field("tree_builder", &self.tree_builder)
             .field("chunker", &self.chunker)
+            .field("mode", &self.mode)
+            .field("mtime", &self.mtime)
+            .field("size", &self.size)
             .finish()
     }
 }
@@ -178,10 +245,17 @@ impl File {
         &self.name
     }
 
+    /// The size of the content, if it is known before reading it.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
     pub fn wrap(self) -> Directory {
         Directory {
             name: "".into(),
             entries: vec![Entry::File(self)],
+            mode: None,
+            mtime: None,
         }
     }
 
@@ -207,7 +281,61 @@ impl File {
             Content::Reader(reader) => reader,
         };
         let chunks = self.chunker.chunks(reader);
-        Ok(self.tree_builder.stream_tree(chunks))
+        let blocks = self.tree_builder.stream_tree(chunks);
+        Ok(encode_root_metadata(blocks, self.mode, self.mtime))
+    }
+}
+
+/// Records `mode` and `mtime` on the root of a file, which is always the last block
+/// of the stream. Raw leaves can not carry any metadata, so a file that consists of a
+/// single raw leaf gets wrapped into a file node.
+fn encode_root_metadata(
+    blocks: impl Stream<Item = Result<Block>>,
+    mode: Option<u32>,
+    mtime: Option<UnixTime>,
+) -> impl Stream<Item = Result<Block>> {
+    async_stream::try_stream! {
+        tokio::pin!(blocks);
+        let mut root = None;
+        while let Some(block) = blocks.next().await {
+            let block = block?;
+            if mode.is_none() && mtime.is_none() {
+                yield block;
+            } else if let Some(prev) = root.replace(block) {
+                yield prev;
+            }
+        }
+
+        if let Some(root) = root {
+            let (inner, links) = match UnixfsNode::decode(root.cid(), root.data().clone())? {
+                UnixfsNode::Raw(data) => {
+                    let len = data.len() as u64;
+                    let inner = unixfs_pb::Data {
+                        r#type: DataType::File as i32,
+                        filesize: Some(len),
+                        blocksizes: vec![len],
+                        ..Default::default()
+                    };
+                    let links = vec![dag_pb::PbLink {
+                        hash: Some(root.cid().to_bytes()),
+                        name: None,
+                        tsize: Some(len),
+                    }];
+                    // the leaf stays part of the file
+                    yield root;
+                    (inner, links)
+                }
+                UnixfsNode::File(node) => (node.inner, node.outer.links),
+                node => Err(anyhow!("unexpected file root: {:?}", node.typ()))?,
+            };
+            let inner = unixfs_pb::Data {
+                mode,
+                mtime: mtime.map(UnixTime::to_pb),
+                ..inner
+            };
+            let outer = encode_unixfs_pb(&inner, links)?;
+            yield UnixfsNode::File(Node { outer, inner }).encode()?;
+        }
     }
 }
 
@@ -216,6 +344,7 @@ impl File {
 pub struct Symlink {
     name: String,
     target: PathBuf,
+    mtime: Option<UnixTime>,
 }
 
 impl Symlink {
@@ -228,6 +357,7 @@ impl Symlink {
                 .unwrap_or_default()
                 .to_string(),
             target: target.into(),
+            mtime: None,
         }
     }
 
@@ -235,6 +365,8 @@ impl Symlink {
         Directory {
             name: "".into(),
             entries: vec![Entry::Symlink(self)],
+            mode: None,
+            mtime: None,
         }
     }
 
@@ -251,6 +383,7 @@ impl Symlink {
         let inner = unixfs_pb::Data {
             r#type: DataType::Symlink as i32,
             data: Some(Bytes::from(target)),
+            mtime: self.mtime.map(UnixTime::to_pb),
             ..Default::default()
         };
         let outer = encode_unixfs_pb(&inner, Vec::new())?;
@@ -267,6 +400,10 @@ pub struct FileBuilder {
     reader: Option<Pin<Box<dyn AsyncRead>>>,
     chunk_size: Option<usize>,
     degree: Option<usize>,
+    mode: Option<u32>,
+    mtime: Option<UnixTime>,
+    preserve_mode: bool,
+    preserve_mtime: bool,
 }
 
 impl Debug for FileBuilder {
@@ -281,6 +418,10 @@ impl Debug for FileBuilder {
             .field("name", &self.name)
             .field("chunk_size", &self.chunk_size)
             .field("degree", &self.degree)
+            .field("mode", &self.mode)
+            .field("mtime", &self.mtime)
+            .field("preserve_mode", &self.preserve_mode)
+            .field("preserve_mtime", &self.preserve_mtime)
             .field("reader", &reader)
             .finish()
     }
@@ -312,6 +453,32 @@ impl FileBuilder {
         self
     }
 
+    /// Sets the unix permission bits.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the modification time.
+    pub fn mtime<T: Into<UnixTime>>(&mut self, mtime: T) -> &mut Self {
+        self.mtime = Some(mtime.into());
+        self
+    }
+
+    /// Records the permissions on disk when building from a path, unless a mode is
+    /// set. Off by default, so the cid only depends on the content.
+    pub fn preserve_mode(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_mode = preserve;
+        self
+    }
+
+    /// Records the modification time on disk when building from a path, unless an
+    /// mtime is set. Off by default, so the cid only depends on the content.
+    pub fn preserve_mtime(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
     pub fn content_bytes<B: Into<Bytes>>(&mut self, content: B) -> &mut Self {
         let bytes = content.into();
         self.reader = Some(Box::pin(std::io::Cursor::new(bytes)));
@@ -337,11 +504,19 @@ impl FileBuilder {
                     .unwrap_or_default()
                     .to_string(),
             };
+            let metadata = tokio::fs::metadata(&path).await?;
+            let (mode, mtime) = fs_attributes(&metadata);
+            let mode = mode.filter(|_| self.preserve_mode);
+            let mtime = mtime.filter(|_| self.preserve_mtime);
             return Ok(File {
                 content: Content::Path(path),
                 name,
                 chunker,
                 tree_builder,
+                mode: self.mode.or(mode),
+                mtime: self.mtime.or(mtime),
+                size: Some(metadata.len()),
+                cache: None,
             });
         }
 
@@ -355,6 +530,10 @@ impl FileBuilder {
                 name,
                 chunker,
                 tree_builder,
+                mode: self.mode,
+                mtime: self.mtime,
+                size: None,
+                cache: None,
             });
         }
         anyhow::bail!("must have a path to the content or a reader for the content");
@@ -368,6 +547,12 @@ enum Entry {
     File(File),
     Directory(Directory),
     Symlink(Symlink),
+    /// An entry that was added before, linked to by its root.
+    Link {
+        name: String,
+        cid: Cid,
+        tsize: u64,
+    },
 }
 
 /// Construct a UnixFS directory.
@@ -376,6 +561,8 @@ pub struct DirectoryBuilder {
     name: Option<String>,
     entries: Vec<Entry>,
     typ: DirectoryType,
+    mode: Option<u32>,
+    mtime: Option<UnixTime>,
 }
 
 impl Default for DirectoryBuilder {
@@ -384,6 +571,8 @@ impl Default for DirectoryBuilder {
             name: None,
             entries: Default::default(),
             typ: DirectoryType::Basic,
+            mode: None,
+            mtime: None,
         }
     }
 }
@@ -403,6 +592,16 @@ impl DirectoryBuilder {
         self
     }
 
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn mtime<T: Into<UnixTime>>(&mut self, mtime: T) -> &mut Self {
+        self.mtime = Some(mtime.into());
+        self
+    }
+
     pub fn add_dir(&mut self, dir: Directory) -> Result<&mut Self> {
         Ok(self.entry(Entry::Directory(dir)))
     }
@@ -415,6 +614,15 @@ impl DirectoryBuilder {
         self.entry(Entry::Symlink(symlink))
     }
 
+    /// Adds an entry that was added before, by the cid and tsize of its root.
+    pub fn add_link<N: Into<String>>(&mut self, name: N, cid: Cid, tsize: u64) -> &mut Self {
+        self.entry(Entry::Link {
+            name: name.into(),
+            cid,
+            tsize,
+        })
+    }
+
     fn entry(&mut self, entry: Entry) -> &mut Self {
         if self.typ == DirectoryType::Basic && self.entries.len() >= DIRECTORY_LINK_LIMIT {
             self.typ = DirectoryType::Hamt
@@ -425,14 +633,23 @@ impl DirectoryBuilder {
 
     pub fn build(self) -> Result<Directory> {
         let DirectoryBuilder {
-            name, entries, typ, ..
+            name,
+            entries,
+            typ,
+            mode,
+            mtime,
         } = self;
 
         ensure!(typ == DirectoryType::Basic, "too many links to fit into one chunk, must be encoded as a HAMT. However, HAMT creation has not yet been implemented.");
 
         let name = name.unwrap_or_default();
 
-        Ok(Directory { name, entries })
+        Ok(Directory {
+            name,
+            entries,
+            mode,
+            mtime,
+        })
     }
 }
 
@@ -441,6 +658,8 @@ impl DirectoryBuilder {
 pub struct SymlinkBuilder {
     path: PathBuf,
     target: Option<PathBuf>,
+    mtime: Option<UnixTime>,
+    preserve_mtime: bool,
 }
 
 impl SymlinkBuilder {
@@ -448,6 +667,8 @@ impl SymlinkBuilder {
         Self {
             path: path.into(),
             target: None,
+            mtime: None,
+            preserve_mtime: false,
         }
     }
 
@@ -456,6 +677,18 @@ impl SymlinkBuilder {
         self
     }
 
+    pub fn mtime<T: Into<UnixTime>>(&mut self, mtime: T) -> &mut Self {
+        self.mtime = Some(mtime.into());
+        self
+    }
+
+    /// Records the modification time of the symlink on disk when reading its target,
+    /// unless an mtime is set.
+    pub fn preserve_mtime(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
     pub async fn build(self) -> Result<Symlink> {
         let name = self
             .path
@@ -463,14 +696,43 @@ impl SymlinkBuilder {
             .and_then(|s| s.to_str())
             .unwrap_or_default()
             .to_string();
-        let target = match self.target {
-            Some(target) => target,
-            None => tokio::fs::read_link(&self.path).await?,
+        let (target, mtime) = match self.target {
+            Some(target) => (target, self.mtime),
+            None => {
+                let target = tokio::fs::read_link(&self.path).await?;
+                let mtime = match self.mtime {
+                    Some(mtime) => Some(mtime),
+                    None if self.preserve_mtime => {
+                        fs_attributes(&tokio::fs::symlink_metadata(&self.path).await?).1
+                    }
+                    None => None,
+                };
+                (target, mtime)
+            }
         };
-        Ok(Symlink { name, target })
+        Ok(Symlink {
+            name,
+            target,
+            mtime,
+        })
     }
 }
 
+/// Returns the unix permission bits and the modification time of a file on disk.
+fn fs_attributes(metadata: &std::fs::Metadata) -> (Option<u32>, Option<UnixTime>) {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        // only the permission bits are part of unixfs, not the file type
+        Some(metadata.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    let mtime = metadata.modified().ok().map(UnixTime::from);
+
+    (mode, mtime)
+}
+
 pub(crate) fn encode_unixfs_pb(
     inner: &unixfs_pb::Data,
     links: Vec<dag_pb::PbLink>,
@@ -491,6 +753,7 @@ pub(crate) fn encode_unixfs_pb(
 #[async_trait]
 pub trait Store {
     async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<()>;
+    async fn has(&self, cid: Cid) -> Result<bool>;
 }
 
 #[async_trait]
@@ -498,6 +761,10 @@ impl Store for &Client {
     async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<()> {
         self.try_store()?.put(cid, blob, links).await
     }
+
+    async fn has(&self, cid: Cid) -> Result<bool> {
+        self.try_store()?.has(cid).await
+    }
 }
 
 #[derive(Debug)]
@@ -511,6 +778,36 @@ impl Store for StoreAndProvideClient {
         self.client.try_store()?.put(cid, blob, links).await?;
         self.client.try_p2p()?.start_providing(&cid).await
     }
+
+    async fn has(&self, cid: Cid) -> Result<bool> {
+        self.client.try_store()?.has(cid).await
+    }
+}
+
+/// Like [`StoreAndProvideClient`], but providing is best effort: content is stored
+/// even if p2p is not running, failures to provide are only logged.
+#[derive(Debug)]
+pub struct StoreAndTryProvideClient {
+    pub client: Client,
+}
+
+#[async_trait]
+impl Store for StoreAndTryProvideClient {
+    async fn put(&self, cid: Cid, blob: Bytes, links: Vec<Cid>) -> Result<()> {
+        self.client.try_store()?.put(cid, blob, links).await?;
+        let res = match self.client.try_p2p() {
+            Ok(p2p) => p2p.start_providing(&cid).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            warn!("failed to provide {}: {:?}", cid, err);
+        }
+        Ok(())
+    }
+
+    async fn has(&self, cid: Cid) -> Result<bool> {
+        self.client.try_store()?.has(cid).await
+    }
 }
 
 #[async_trait]
@@ -519,66 +816,203 @@ impl Store for &tokio::sync::Mutex<std::collections::HashMap<Cid, Bytes>> {
         self.lock().await.insert(cid, blob);
         Ok(())
     }
+
+    async fn has(&self, cid: Cid) -> Result<bool> {
+        Ok(self.lock().await.contains_key(&cid))
+    }
+}
+
+/// Name of the files with gitignore-style rules that are applied to the
+/// directory they are in when adding a directory.
+pub const IGNORE_FILE_NAME: &str = ".irohignore";
+
+/// How symlinks are handled when adding a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkMode {
+    /// Add the file or directory the symlink points to.
+    Follow,
+    /// Add the symlink itself.
+    #[default]
+    Store,
+    /// Leave symlinks out.
+    Skip,
+}
+
+impl FromStr for SymlinkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "follow" => Ok(SymlinkMode::Follow),
+            "store" => Ok(SymlinkMode::Store),
+            "skip" => Ok(SymlinkMode::Skip),
+            _ => Err(anyhow!(
+                "invalid symlink mode {}, use follow, store or skip",
+                s
+            )),
+        }
+    }
+}
+
+/// Which entries are added when adding from disk, and which of their file system
+/// attributes are recorded.
+///
+/// Entries matching the rules in `.irohignore` files, or in the file at
+/// `ignore_rules_path`, are always left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// Leave out hidden files and directories, which are added by default.
+    pub skip_hidden: bool,
+    /// A file with gitignore-style rules that apply to the whole directory. The
+    /// rules are anchored at the added directory, wherever the file is.
+    pub ignore_rules_path: Option<PathBuf>,
+    pub symlinks: SymlinkMode,
+    /// Record the unix permissions of files and directories. This makes the cids
+    /// depend on them.
+    pub preserve_mode: bool,
+    /// Record the modification times of files, directories and symlinks. This makes
+    /// the cids depend on them.
+    pub preserve_mtime: bool,
+}
+
+/// Walks the directory at `path` depth first, yielding the directory itself
+/// first and leaving out the entries excluded by `options`.
+///
+/// Entries are sorted by name, so directories are always encoded the same way,
+/// whatever order the file system lists them in.
+pub fn walk_dir(path: &Path, options: &WalkOptions) -> Result<Walk> {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .standard_filters(false)
+        .hidden(options.skip_hidden)
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        .follow_links(options.symlinks == SymlinkMode::Follow)
+        .sort_by_file_name(|a, b| a.cmp(b));
+    if let Some(ref rules) = options.ignore_rules_path {
+        let context = || format!("failed to read ignore rules from {}", rules.display());
+        let mut ignore = GitignoreBuilder::new(path);
+        if let Some(err) = ignore.add(rules) {
+            return Err(anyhow::Error::new(err).context(context()));
+        }
+        let ignore = ignore.build().with_context(context)?;
+        // ignored directories are not entered, so their entries never get here
+        builder.filter_entry(move |entry| {
+            let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+            entry.depth() == 0 || !ignore.matched(entry.path(), is_dir).is_ignore()
+        });
+    }
+    Ok(builder.build())
 }
 
 /// Adds a single file.
-/// - storing the content using `rpc.store`
+/// - storing the content using `rpc.store`, or only computing the cids when
+///   `store` is `None`
 /// - returns a stream of AddEvent
 /// - optionally wraps into a UnixFs directory to preserve the filename
+/// - records the mode and mtime as selected by `options`
 pub async fn add_file<S: Store>(
     store: Option<S>,
     path: &Path,
     wrap: bool,
+    options: &WalkOptions,
 ) -> Result<impl Stream<Item = Result<AddEvent>>> {
     ensure!(path.is_file(), "provided path was not a file");
 
-    let file = FileBuilder::new().path(path).build().await?;
+    let mut file = FileBuilder::new().path(path);
+    file.preserve_mode(options.preserve_mode)
+        .preserve_mtime(options.preserve_mtime);
+    let file = file.build().await?;
+    let estimate = file.size();
 
-    let blocks = {
+    let parts = {
         if wrap {
             // wrap file in dir to preserve file name
-            file.wrap().encode()
+            file.wrap().encode_parts(PathBuf::new())
         } else {
-            Box::pin(file.encode().await?)
+            file_parts(file).await?
         }
     };
-    Ok(add_blocks_to_store(store, blocks).await)
+    Ok(add_parts_to_store(store, estimate, parts).await)
+}
+
+/// Adds the content of a reader as a single file.
+/// - storing the content using `rpc.store`, or only computing the cids when
+///   `store` is `None`
+/// - returns a stream of AddEvent
+/// - optionally wraps into a UnixFs directory to preserve the file name
+///
+/// The content is chunked, encoded and stored while it is read, so input of any
+/// size is added in bounded memory.
+pub async fn add_reader<S: Store, R: AsyncRead + 'static>(
+    store: Option<S>,
+    reader: R,
+    name: &str,
+    wrap: bool,
+) -> Result<impl Stream<Item = Result<AddEvent>>> {
+    let mut file = FileBuilder::new();
+    file.name(name).content_reader(reader);
+    let file = file.build().await?;
+
+    let parts = {
+        if wrap {
+            // wrap file in dir to preserve file name
+            file.wrap().encode_parts(PathBuf::new())
+        } else {
+            file_parts(file).await?
+        }
+    };
+    Ok(add_parts_to_store(store, None, parts).await)
 }
 
 /// Adds a directory.
-/// - storing the content using `rpc.store`
+/// - storing the content using `rpc.store`, or only computing the cids when
+///   `store` is `None`
 /// - returns a stream of AddEvent
 /// - optionally wraps into a UnixFs directory to preserve the directory name
+/// - reuses the roots of the files `cache` knows to be unchanged if they are still
+///   stored, and records the roots of the other files in it, unless they are not
+///   stored
 pub async fn add_dir<S: Store>(
     store: Option<S>,
     path: &Path,
     wrap: bool,
+    options: &WalkOptions,
+    cache: Option<&ChangeCache>,
 ) -> Result<impl Stream<Item = Result<AddEvent>>> {
     ensure!(path.is_dir(), "provided path was not a directory");
 
-    let dir = make_dir_from_path(path).await?;
+    let cache = cache.map(|cache| FileCache {
+        cache,
+        store: store.as_ref().map(|store| store as &dyn Store),
+    });
+    let dir = make_dir_from_path(path, options, cache).await?;
+    let estimate = dir.estimate_size();
 
     // encode and store
-    let blocks = {
+    let parts = {
         if wrap {
             // wrap dir in dir to preserve file name
-            dir.wrap().encode()
+            dir.wrap().encode_parts(PathBuf::new())
         } else {
-            dir.encode()
+            dir.encode_parts(PathBuf::new())
         }
     };
 
-    Ok(add_blocks_to_store(store, blocks).await)
+    Ok(add_parts_to_store(store, Some(estimate), parts).await)
 }
 
-/// Adds a symlink
+/// Adds a symlink, storing it using `rpc.store` unless `store` is `None`. Its mtime
+/// is only recorded with `options.preserve_mtime`.
 pub async fn add_symlink<S: Store>(
     store: Option<S>,
     path: &Path,
     wrap: bool,
+    options: &WalkOptions,
 ) -> Result<impl Stream<Item = Result<AddEvent>>> {
     ensure!(path.is_symlink(), "provided path was not a symlink");
-    let symlink = SymlinkBuilder::new(path).build().await?;
+    let mut symlink = SymlinkBuilder::new(path);
+    symlink.preserve_mtime(options.preserve_mtime);
+    let symlink = symlink.build().await?;
     if wrap {
         let dir = symlink.wrap();
         let blocks = dir.encode();
@@ -591,63 +1025,202 @@ pub async fn add_symlink<S: Store>(
 }
 
 /// An event on the add stream
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AddEvent {
-    /// Delta of progress in bytes
+    /// Estimated number of bytes that are read, produced first if it is known upfront
+    Estimate(u64),
+    /// The file at `path`, relative to the added directory, is read next
+    File { path: PathBuf, size: Option<u64> },
+    /// A block was stored, or only hashed when adding without a store
+    Block(Cid),
+    /// Delta of progress in bytes, produced after blocks holding file content
     ProgressDelta(u64),
     /// The root Cid of the added file, produced once in the end
     Done(Cid),
 }
 
+/// Stores `blocks` as they are produced.
+///
+/// A block is only pulled from `blocks` once the previous one was stored, so
+/// chunking and encoding never run ahead of the store.
 pub async fn add_blocks_to_store<S: Store>(
     store: Option<S>,
-    mut blocks: Pin<Box<dyn Stream<Item = Result<Block>>>>,
+    blocks: Pin<Box<dyn Stream<Item = Result<Block>>>>,
+) -> impl Stream<Item = Result<AddEvent>> {
+    add_parts_to_store(store, None, blocks.map_ok(Part::Block).boxed_local()).await
+}
+
+/// Stores the blocks of `parts` as they are produced, like [`add_blocks_to_store`],
+/// and reports the files they belong to.
+async fn add_parts_to_store<S: Store>(
+    store: Option<S>,
+    estimate: Option<u64>,
+    mut parts: LocalBoxStream<'static, Result<Part>>,
 ) -> impl Stream<Item = Result<AddEvent>> {
     async_stream::try_stream! {
+        if let Some(estimate) = estimate {
+            yield AddEvent::Estimate(estimate);
+        }
 
         let mut root = None;
-        while let Some(block) = blocks.next().await {
-            let block = block?;
-            let raw_data_size = block.raw_data_size();
-            let (cid, bytes, links) = block.into_parts();
-            if let Some(ref store) = store {
-                store.put(cid, bytes, links).await?;
-            }
-            if let Some(raw_data_size) = raw_data_size {
-                yield AddEvent::ProgressDelta(raw_data_size);
+        while let Some(part) = parts.next().await {
+            match part? {
+                Part::File { path, size } => {
+                    yield AddEvent::File { path, size };
+                }
+                Part::Block(block) => {
+                    let raw_data_size = block.raw_data_size();
+                    let (cid, data, links) = block.into_parts();
+                    if let Some(ref store) = store {
+                        store.put(cid, data, links).await?;
+                    }
+                    yield AddEvent::Block(cid);
+                    if let Some(raw_data_size) = raw_data_size {
+                        yield AddEvent::ProgressDelta(raw_data_size);
+                    }
+                    root = Some(cid);
+                }
             }
-            root = Some(cid);
         }
 
         yield AddEvent::Done(root.expect("missing root"))
     }
 }
 
-#[async_recursion(?Send)]
-async fn make_dir_from_path<P: Into<PathBuf>>(path: P) -> Result<Directory> {
+/// Encodes a single file, announcing it before its blocks.
+async fn file_parts(file: File) -> Result<LocalBoxStream<'static, Result<Part>>> {
+    let announce = Part::File {
+        path: PathBuf::from(file.name()),
+        size: file.size(),
+    };
+    let blocks = file.encode().await?.map_ok(Part::Block);
+    Ok(futures::stream::once(async move { Ok(announce) })
+        .chain(blocks)
+        .boxed_local())
+}
+
+/// A change cache used while adding a directory.
+#[derive(Clone, Copy)]
+struct FileCache<'a> {
+    cache: &'a ChangeCache,
+    /// Where the files are stored. Without a store, cached roots are used without
+    /// checking for their blocks, and files that are not in the cache yet are not
+    /// recorded, as the roots of files that are not stored must not be reused later.
+    store: Option<&'a dyn Store>,
+}
+
+impl FileCache<'_> {
+    /// Returns the cached root and tsize of the file at `path`, if it did not change
+    /// and its root is still stored.
+    async fn get(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        options: &WalkOptions,
+    ) -> Result<Option<(Cid, u64)>> {
+        let (cid, tsize) = match self.cache.get(path, metadata, options) {
+            Some(cached) => cached,
+            None => return Ok(None),
+        };
+        if let Some(store) = self.store {
+            if !store.has(cid).await? {
+                return Ok(None);
+            }
+        }
+        Ok(Some((cid, tsize)))
+    }
+}
+
+async fn make_dir_from_path<P: Into<PathBuf>>(
+    path: P,
+    options: &WalkOptions,
+    cache: Option<FileCache<'_>>,
+) -> Result<Directory> {
     let path = path.into();
-    let mut dir = DirectoryBuilder::new();
-    dir.name(
-        path.file_name()
+    let mut entries = walk_dir(&path, options)?.peekable();
+    let root = entries
+        .next()
+        .ok_or_else(|| anyhow!("missing directory {}", path.display()))??;
+    make_dir_from_walk(root, &mut entries, options, cache).await
+}
+
+/// Builds the directory `dir` from the entries following it in the walk, which
+/// are its descendants.
+#[async_recursion(?Send)]
+async fn make_dir_from_walk<'a>(
+    dir: DirEntry,
+    entries: &mut Peekable<Walk>,
+    options: &'a WalkOptions,
+    cache: Option<FileCache<'a>>,
+) -> Result<Directory> {
+    let mut builder = DirectoryBuilder::new();
+    builder.name(
+        dir.path()
+            .file_name()
             .and_then(|s| s.to_str())
             .unwrap_or_default(),
     );
-    let mut directory_reader = tokio::fs::read_dir(path.clone()).await?;
-    while let Some(entry) = directory_reader.next_entry().await? {
+    if options.preserve_mode || options.preserve_mtime {
+        let (mode, mtime) = fs_attributes(&tokio::fs::metadata(dir.path()).await?);
+        if let Some(mode) = mode.filter(|_| options.preserve_mode) {
+            builder.mode(mode);
+        }
+        if let Some(mtime) = mtime.filter(|_| options.preserve_mtime) {
+            builder.mtime(mtime);
+        }
+    }
+    let symlinks = options.symlinks;
+    loop {
+        match entries.peek() {
+            Some(Ok(entry)) if entry.depth() <= dir.depth() => break,
+            None => break,
+            _ => {}
+        }
+        let entry = entries.next().expect("just peeked")?;
         let path = entry.path();
-        if path.is_symlink() {
-            let s = SymlinkBuilder::new(path).build().await?;
-            dir.add_symlink(s);
-        } else if path.is_file() {
-            let f = FileBuilder::new().path(path).build().await?;
-            dir.add_file(f);
-        } else if path.is_dir() {
-            let d = make_dir_from_path(path).await?;
-            dir.add_dir(d)?;
-        } else {
-            anyhow::bail!("directory entry is neither file nor directory")
+        if entry.path_is_symlink() && symlinks != SymlinkMode::Follow {
+            if symlinks == SymlinkMode::Store {
+                let mut s = SymlinkBuilder::new(path);
+                s.preserve_mtime(options.preserve_mtime);
+                builder.add_symlink(s.build().await?);
+            }
+            continue;
+        }
+        match entry.file_type() {
+            Some(file_type) if file_type.is_file() => {
+                let metadata = entry.metadata()?;
+                let cached = match cache {
+                    Some(cache) => cache.get(path, &metadata, options).await?,
+                    None => None,
+                };
+                match cached {
+                    Some((cid, tsize)) => {
+                        let name = path
+                            .file_name()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or_default();
+                        builder.add_link(name, cid, tsize);
+                    }
+                    None => {
+                        let mut f = FileBuilder::new().path(path);
+                        f.preserve_mode(options.preserve_mode)
+                            .preserve_mtime(options.preserve_mtime);
+                        let mut f = f.build().await?;
+                        if let Some(cache) = cache.filter(|cache| cache.store.is_some()) {
+                            f.cache = Some(cache.cache.pending(path, &metadata, options));
+                        }
+                        builder.add_file(f);
+                    }
+                }
+            }
+            Some(file_type) if file_type.is_dir() => {
+                let d = make_dir_from_walk(entry, entries, options, cache).await?;
+                builder.add_dir(d)?;
+            }
+            _ => anyhow::bail!("directory entry is neither file nor directory"),
         }
     }
-    dir.build()
+    builder.build()
 }
 
 #[cfg(test)]
@@ -1134,6 +1707,300 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_unix_time_roundtrip() {
+        for time in [
+            UnixTime {
+                seconds: 1_234_567_890,
+                nanos: 0,
+            },
+            UnixTime {
+                seconds: 1_234_567_890,
+                nanos: 123,
+            },
+            UnixTime {
+                seconds: -2,
+                nanos: 500_000_000,
+            },
+        ] {
+            let system: std::time::SystemTime = time.into();
+            assert_eq!(UnixTime::from(system), time);
+            assert_eq!(UnixTime::from_pb(&time.to_pb()), time);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_metadata_roundtrip() -> Result<()> {
+        let mtime = UnixTime {
+            seconds: 1_600_000_000,
+            nanos: 42,
+        };
+        // a single chunk gets wrapped, multiple chunks get the metadata on the root
+        for content in [b"hello".to_vec(), vec![7u8; DEFAULT_CHUNKS_SIZE * 3]] {
+            let mut builder = FileBuilder::new();
+            builder
+                .name("file.bin")
+                .mode(0o755)
+                .mtime(mtime)
+                .content_bytes(content.clone());
+            let file = builder.build().await?;
+            let (root, resolver) = stream_to_resolver(file.encode().await?).await?;
+            let out = resolver
+                .resolve(crate::resolver::Path::from_cid(root))
+                .await?;
+            assert_eq!(out.metadata().mode, Some(0o755));
+            assert_eq!(out.metadata().mtime, Some(mtime));
+            assert_eq!(out.metadata().size, Some(content.len() as u64));
+            let data =
+                read_to_vec(out.pretty(resolver, OutMetrics::default(), ResponseClip::NoClip)?)
+                    .await?;
+            assert_eq!(data, content);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dir_metadata_roundtrip() -> Result<()> {
+        let mtime = UnixTime {
+            seconds: 1_600_000_000,
+            nanos: 0,
+        };
+        let mut builder = DirectoryBuilder::new();
+        builder.name("foo").mode(0o700).mtime(mtime);
+        let mut sym = SymlinkBuilder::new("link");
+        sym.target("target").mtime(mtime);
+        builder.add_symlink(sym.build().await?);
+        let dir = builder.build()?;
+        let (root, resolver) = stream_to_resolver(dir.encode()).await?;
+
+        let out = resolver
+            .resolve(crate::resolver::Path::from_cid(root))
+            .await?;
+        assert_eq!(out.metadata().mode, Some(0o700));
+        assert_eq!(out.metadata().mtime, Some(mtime));
+
+        let out = resolver
+            .resolve(format!("/ipfs/{root}/link").parse()?)
+            .await?;
+        assert_eq!(out.metadata().mode, None);
+        assert_eq!(out.metadata().mtime, Some(mtime));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_reader_streaming() -> Result<()> {
+        let store = tokio::sync::Mutex::new(std::collections::HashMap::new());
+        // an endless source can only be added piece by piece
+        let events = add_reader(Some(&store), tokio::io::repeat(1), "endless", false).await?;
+        let events: Vec<_> = events.take(21).try_collect().await?;
+
+        assert_eq!(
+            events[0],
+            AddEvent::File {
+                path: "endless".into(),
+                size: None
+            }
+        );
+        assert!(events[1..].iter().all(|event| matches!(
+            event,
+            AddEvent::ProgressDelta(bytes) if *bytes == DEFAULT_CHUNKS_SIZE as u64
+        ) || matches!(event, AddEvent::Block(_))));
+        assert_eq!(store.lock().await.len(), 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_reader() -> Result<()> {
+        let store = tokio::sync::Mutex::new(std::collections::HashMap::new());
+        let content = Bytes::from(vec![7u8; 1024 * 1024]);
+        let events = add_reader(
+            Some(&store),
+            std::io::Cursor::new(content.clone()),
+            "seven.bin",
+            true,
+        )
+        .await?;
+        let events: Vec<_> = events.try_collect().await?;
+        let root = match events.last() {
+            Some(AddEvent::Done(cid)) => *cid,
+            _ => panic!("missing root"),
+        };
+
+        let blocks = store.into_inner();
+        let resolver = Resolver::new(Arc::new(blocks));
+        let out = resolver
+            .resolve(format!("/ipfs/{}/seven.bin", root).parse()?)
+            .await?;
+        let reader = out.pretty(
+            resolver.clone(),
+            OutMetrics::default(),
+            ResponseClip::NoClip,
+        )?;
+        assert_eq!(read_to_vec(reader).await?, content);
+        Ok(())
+    }
+
+    async fn add_dir_root(
+        store: Option<&tokio::sync::Mutex<std::collections::HashMap<Cid, Bytes>>>,
+        path: &Path,
+    ) -> Result<Cid> {
+        let events: Vec<_> = add_dir(store, path, false, &WalkOptions::default(), None)
+            .await?
+            .try_collect()
+            .await?;
+        match events.last() {
+            Some(AddEvent::Done(cid)) => Ok(*cid),
+            _ => panic!("missing root"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_dir_only_hash() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let names = ["m.txt", "z.txt", "a.txt", "b.txt"];
+        // the same entries, created in a different order
+        let first = temp_dir.path().join("first");
+        let second = temp_dir.path().join("second");
+        std::fs::create_dir(&first)?;
+        std::fs::create_dir(&second)?;
+        for name in names {
+            std::fs::write(first.join(name), name)?;
+        }
+        for name in names.iter().rev() {
+            std::fs::write(second.join(name), name)?;
+        }
+
+        let store = tokio::sync::Mutex::new(std::collections::HashMap::new());
+        let stored = add_dir_root(Some(&store), &first).await?;
+        let hashed = add_dir_root(None, &first).await?;
+        assert_eq!(stored, hashed);
+        assert!(store.lock().await.contains_key(&stored));
+
+        let store = tokio::sync::Mutex::new(std::collections::HashMap::new());
+        let root = add_dir_root(Some(&store), &second).await?;
+        let dir = store.lock().await.get(&root).cloned().unwrap();
+        let node = UnixfsNode::decode(&root, dir)?;
+        let links: Vec<_> = node
+            .links()
+            .map(|l| l.map(|l| l.name.unwrap_or_default().to_string()))
+            .collect::<Result<_>>()?;
+        assert_eq!(links, vec!["a.txt", "b.txt", "m.txt", "z.txt"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_dir_events() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("data");
+        std::fs::create_dir_all(dir.join("sub"))?;
+        std::fs::write(dir.join("a.bin"), vec![1u8; 1024 * 1024])?;
+        std::fs::write(dir.join("sub").join("b.bin"), vec![2u8; 1000])?;
+
+        let events: Vec<_> = add_dir(None::<&Client>, &dir, true, &WalkOptions::default(), None)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(events[0], AddEvent::Estimate(1024 * 1024 + 1000));
+        let files: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                AddEvent::File { path, size } => Some((path.clone(), *size)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                (PathBuf::from("data/a.bin"), Some(1024 * 1024)),
+                (PathBuf::from("data/sub/b.bin"), Some(1000)),
+            ]
+        );
+        let bytes: u64 = events
+            .iter()
+            .map(|event| match event {
+                AddEvent::ProgressDelta(bytes) => *bytes,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(bytes, 1024 * 1024 + 1000);
+        assert!(matches!(events.last(), Some(AddEvent::Done(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_dir_change_cache() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("data");
+        std::fs::create_dir(&dir)?;
+        std::fs::write(dir.join("a.bin"), vec![1u8; 1024 * 1024])?;
+        std::fs::write(dir.join("b.bin"), vec![2u8; 1024 * 1024])?;
+        let cache = ChangeCache::load(temp_dir.path().join("cache")).await?;
+
+        /// Counts the blocks put into it.
+        #[derive(Default)]
+        struct CountingStore {
+            blocks: std::sync::Mutex<std::collections::HashMap<Cid, Bytes>>,
+            puts: std::sync::atomic::AtomicUsize,
+        }
+
+        #[async_trait]
+        impl Store for &CountingStore {
+            async fn put(&self, cid: Cid, blob: Bytes, _links: Vec<Cid>) -> Result<()> {
+                self.puts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                self.blocks.lock().unwrap().insert(cid, blob);
+                Ok(())
+            }
+
+            async fn has(&self, cid: Cid) -> Result<bool> {
+                Ok(self.blocks.lock().unwrap().contains_key(&cid))
+            }
+        }
+
+        async fn add(
+            path: &Path,
+            cache: &ChangeCache,
+            store: Option<&CountingStore>,
+        ) -> Result<(Cid, usize)> {
+            let puts = store.map_or(0, |s| s.puts.load(std::sync::atomic::Ordering::SeqCst));
+            let events: Vec<_> = add_dir(store, path, false, &WalkOptions::default(), Some(cache))
+                .await?
+                .try_collect()
+                .await?;
+            let root = match events.last() {
+                Some(AddEvent::Done(cid)) => *cid,
+                _ => panic!("missing root"),
+            };
+            let count =
+                store.map_or(0, |s| s.puts.load(std::sync::atomic::Ordering::SeqCst)) - puts;
+            Ok((root, count))
+        }
+
+        // files that are only hashed are not recorded
+        let store = CountingStore::default();
+        let (hashed, _) = add(&dir, &cache, None).await?;
+        let (root, count) = add(&dir, &cache, Some(&store)).await?;
+        assert_eq!(hashed, root);
+        assert!(count > 3);
+
+        // unchanged files are not chunked again
+        let (again, count) = add(&dir, &cache, Some(&store)).await?;
+        assert_eq!(again, root);
+        assert_eq!(count, 1);
+
+        // unless their blocks are missing from the store
+        let empty = CountingStore::default();
+        let (again, count) = add(&dir, &cache, Some(&empty)).await?;
+        assert_eq!(again, root);
+        assert!(count > 3);
+
+        std::fs::write(dir.join("b.bin"), vec![3u8; 1024 * 1024])?;
+        let (changed, count) = add(&dir, &cache, Some(&store)).await?;
+        assert_ne!(changed, root);
+        assert!(count > 1);
+        assert_eq!(add_dir_root(None, &dir).await?, changed);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_make_dir_from_path() -> Result<()> {
         let temp_dir = std::env::temp_dir();
@@ -1170,6 +2037,8 @@ mod tests {
                     .unwrap(),
             ),
             entries: vec![Entry::File(nested_file)],
+            mode: None,
+            mtime: None,
         };
 
         let file = FileBuilder::new().path(file_path).build().await?;
@@ -1177,9 +2046,11 @@ mod tests {
         let expected = Directory {
             name: String::from(dir.clone().file_name().and_then(|s| s.to_str()).unwrap()),
             entries: vec![Entry::File(file), Entry::Directory(nested_dir)],
+            mode: None,
+            mtime: None,
         };
 
-        let mut got = make_dir_from_path(dir).await?;
+        let mut got = make_dir_from_path(dir, &WalkOptions::default(), None).await?;
 
         // Before comparison sort entries to make test deterministic.
         // The readdir_r function is used in the underlying platform which
@@ -1189,9 +2060,163 @@ mod tests {
             Entry::Directory(dir) => dir.name.clone(),
             Entry::File(file) => file.name.clone(),
             Entry::Symlink(sym) => sym.name().to_string(),
+            Entry::Link { name, .. } => name.clone(),
         });
 
         assert_eq!(expected, got);
         Ok(())
     }
+
+    fn entry_names(dir: &Directory) -> Vec<String> {
+        let mut names: Vec<_> = dir
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Directory(dir) => format!("{}/", dir.name),
+                Entry::File(file) => file.name.clone(),
+                Entry::Symlink(sym) => format!("{}@", sym.name()),
+                Entry::Link { name, .. } => format!("{}~", name),
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_make_dir_from_path_walk_options() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("checkout");
+        std::fs::create_dir_all(dir.join("target"))?;
+        std::fs::create_dir_all(dir.join(".git"))?;
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::write(dir.join("target").join("out.bin"), b"out")?;
+        std::fs::write(dir.join(".git").join("HEAD"), b"head")?;
+        std::fs::write(dir.join("src").join("lib.rs"), b"lib")?;
+        std::fs::write(dir.join("src").join("debug.log"), b"log")?;
+        std::fs::write(dir.join(IGNORE_FILE_NAME), b"target/")?;
+        std::os::unix::fs::symlink("src", dir.join("link"))?;
+        let rules = temp_dir.path().join("rules");
+        std::fs::write(&rules, b"*.log")?;
+
+        let got = make_dir_from_path(&dir, &WalkOptions::default(), None).await?;
+        assert_eq!(
+            entry_names(&got),
+            vec![".git/", IGNORE_FILE_NAME, "link@", "src/"]
+        );
+
+        let options = WalkOptions {
+            skip_hidden: true,
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!(entry_names(&got), vec!["link@", "src/"]);
+
+        let options = WalkOptions {
+            ignore_rules_path: Some(rules),
+            symlinks: SymlinkMode::Follow,
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!(
+            entry_names(&got),
+            vec![".git/", IGNORE_FILE_NAME, "link/", "src/"]
+        );
+        for entry in &got.entries {
+            if let Entry::Directory(dir) = entry {
+                if dir.name == "src" || dir.name == "link" {
+                    assert_eq!(entry_names(dir), vec!["lib.rs"]);
+                }
+            }
+        }
+
+        let options = WalkOptions {
+            skip_hidden: true,
+            symlinks: SymlinkMode::Skip,
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!(entry_names(&got), vec!["src/"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ignore_rules_anchored_at_added_dir() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(dir.join("docs"))?;
+        std::fs::create_dir_all(dir.join("src").join("docs"))?;
+        std::fs::write(dir.join("docs").join("a.md"), b"a")?;
+        std::fs::write(dir.join("src").join("docs").join("b.md"), b"b")?;
+        std::fs::write(dir.join("src").join("lib.rs"), b"lib")?;
+        std::fs::write(dir.join("src").join("lib.rs.bak"), b"bak")?;
+        // the rules live outside of the added directory
+        let rules = temp_dir.path().join("rules").join("ignore");
+        std::fs::create_dir(temp_dir.path().join("rules"))?;
+        std::fs::write(&rules, b"/docs\n*.bak\n")?;
+
+        let options = WalkOptions {
+            ignore_rules_path: Some(rules),
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!(entry_names(&got), vec!["src/"]);
+        for entry in &got.entries {
+            if let Entry::Directory(src) = entry {
+                assert_eq!(entry_names(src), vec!["docs/", "lib.rs"]);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_make_dir_from_path_preserve() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("dir");
+        std::fs::create_dir(&dir)?;
+        let file_path = dir.join("foo.txt");
+        std::fs::write(&file_path, b"hello world")?;
+        std::fs::set_permissions(&file_path, std::fs::Permissions::from_mode(0o640))?;
+        std::os::unix::fs::symlink("foo.txt", dir.join("link"))?;
+
+        let file_attributes = fs_attributes(&std::fs::metadata(&file_path)?);
+        let dir_attributes = fs_attributes(&std::fs::metadata(&dir)?);
+        let link_mtime = fs_attributes(&std::fs::symlink_metadata(dir.join("link"))?).1;
+        assert_eq!(file_attributes.0, Some(0o640));
+
+        let options = WalkOptions {
+            preserve_mode: true,
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!((got.mode, got.mtime), (dir_attributes.0, None));
+        for entry in &got.entries {
+            match entry {
+                Entry::File(file) => {
+                    assert_eq!((file.mode, file.mtime), (Some(0o640), None));
+                }
+                Entry::Symlink(symlink) => assert_eq!(symlink.mtime, None),
+                _ => panic!("unexpected entry"),
+            }
+        }
+
+        let options = WalkOptions {
+            preserve_mode: true,
+            preserve_mtime: true,
+            ..Default::default()
+        };
+        let got = make_dir_from_path(&dir, &options, None).await?;
+        assert_eq!((got.mode, got.mtime), dir_attributes);
+        for entry in &got.entries {
+            match entry {
+                Entry::File(file) => assert_eq!((file.mode, file.mtime), file_attributes),
+                Entry::Symlink(symlink) => assert_eq!(symlink.mtime, link_mtime),
+                _ => panic!("unexpected entry"),
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{ensure, Context, Result};
 use bytes::Bytes;
@@ -8,9 +9,9 @@ use futures::{Stream, StreamExt};
 use iroh_rpc_types::p2p::p2p_client::P2pClient as GrpcP2pClient;
 use iroh_rpc_types::p2p::{
     BitswapBlock, BitswapRequest, ConnectRequest, DisconnectRequest, GossipsubPeerAndTopics,
-    GossipsubPeerIdMsg, GossipsubPublishRequest, GossipsubTopicHashMsg, Key,
-    NotifyNewBlocksBitswapRequest, P2p, P2pClientAddr, P2pClientBackend, Providers,
-    StopSessionBitswapRequest,
+    GossipsubPeerIdMsg, GossipsubPublishRequest, GossipsubTopicHashMsg, Key, LookupRequest,
+    LookupResponse, NotifyNewBlocksBitswapRequest, P2p, P2pClientAddr, P2pClientBackend, Providers,
+    PublishIpnsRequest, ReproviderStatusResponse, StopSessionBitswapRequest,
 };
 use iroh_rpc_types::Addr;
 use libp2p::gossipsub::{MessageId, TopicHash};
@@ -19,13 +20,94 @@ use libp2p::{Multiaddr, PeerId};
 use tonic::transport::Endpoint;
 #[cfg(feature = "grpc")]
 use tonic_health::proto::health_client::HealthClient;
-use tracing::{debug, warn};
+use tracing::debug;
 
 #[cfg(feature = "grpc")]
 use crate::status::{self, StatusRow};
 
 impl_client!(P2p);
 
+/// Identify info of a peer, together with the last measured round trip time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub peer_id: PeerId,
+    pub protocol_version: String,
+    pub agent_version: String,
+    pub listen_addrs: Vec<Multiaddr>,
+    pub protocols: Vec<String>,
+    /// Addresses of this node as observed by the peer.
+    pub observed_addrs: Vec<Multiaddr>,
+    pub rtt: Option<Duration>,
+}
+
+impl TryFrom<LookupResponse> for Lookup {
+    type Error = anyhow::Error;
+
+    fn try_from(res: LookupResponse) -> Result<Self> {
+        let observed_addrs = if res.observed_addr.is_empty() {
+            Vec::new()
+        } else {
+            vec![addr_from_bytes(res.observed_addr)?]
+        };
+        Ok(Lookup {
+            peer_id: peer_id_from_bytes(res.peer_id)?,
+            protocol_version: res.protocol_version,
+            agent_version: res.agent_version,
+            listen_addrs: addrs_from_bytes(res.listen_addrs)?,
+            protocols: res.protocols,
+            observed_addrs,
+            rtt: res.rtt.map(Duration::from_millis),
+        })
+    }
+}
+
+/// Progress and results of the reprovider of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReproviderStatus {
+    /// Which keys are reprovided: `all`, `pinned` or `roots`.
+    pub strategy: String,
+    pub running: bool,
+    /// Keys announced so far by the run in progress.
+    pub progress: u64,
+    pub last_run: Option<ReprovideRun>,
+}
+
+/// Results of a finished reprovider run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReprovideRun {
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub keys: u64,
+    /// Number of keys that could not be announced.
+    pub failures: u64,
+    /// Why the run was aborted, if it did not list all keys.
+    pub error: Option<String>,
+}
+
+impl From<ReproviderStatusResponse> for ReproviderStatus {
+    fn from(res: ReproviderStatusResponse) -> Self {
+        ReproviderStatus {
+            strategy: res.strategy,
+            running: res.running,
+            progress: res.progress,
+            last_run: res.last_run.map(|run| ReprovideRun {
+                started: UNIX_EPOCH + Duration::from_secs(run.started),
+                duration: Duration::from_millis(run.duration),
+                keys: run.keys,
+                failures: run.failures,
+                error: run.error,
+            }),
+        }
+    }
+}
+
+impl ReproviderStatus {
+    /// Converts a status response, `None` if the reprovider is disabled.
+    pub fn from_response(res: ReproviderStatusResponse) -> Option<Self> {
+        res.enabled.then(|| res.into())
+    }
+}
+
 impl P2pClient {
     #[tracing::instrument(skip(self))]
     pub async fn version(&self) -> Result<String> {
@@ -47,6 +129,14 @@ impl P2pClient {
         Ok(addrs)
     }
 
+    /// Returns the fingerprint of the pre-shared key when the node is part of a private
+    /// network, `None` on the public network.
+    #[tracing::instrument(skip(self))]
+    pub async fn private_network(&self) -> Result<Option<String>> {
+        let res = self.backend.network_info(()).await?;
+        Ok(res.psk_fingerprint)
+    }
+
     // Fetches a block directly from the network.
     #[tracing::instrument(skip(self))]
     pub async fn fetch_bitswap(
@@ -113,6 +203,33 @@ impl P2pClient {
         Ok(providers_stream)
     }
 
+    /// Fetches the values of the records stored under `key` in the DHT.
+    #[tracing::instrument(skip(self))]
+    pub async fn fetch_record_dht(&self, key: &[u8]) -> Result<Vec<Bytes>> {
+        let req = Key { key: key.to_vec() };
+        let res = self.backend.fetch_record_dht(req).await?;
+        Ok(res.records)
+    }
+
+    /// Signs an IPNS record pointing to `value` with the keychain key `key` and
+    /// publishes it to the DHT. Returns the name the record was published under.
+    #[tracing::instrument(skip(self))]
+    pub async fn publish_ipns(
+        &self,
+        key: &str,
+        value: &str,
+        lifetime: std::time::Duration,
+    ) -> Result<PeerId> {
+        let req = PublishIpnsRequest {
+            key: key.to_string(),
+            value: value.to_string(),
+            lifetime: lifetime.as_millis().try_into()?,
+        };
+        let res = self.backend.publish_ipns(req).await?;
+        let peer_id = PeerId::from_bytes(&res.peer_id[..])?;
+        Ok(peer_id)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn start_providing(&self, key: &Cid) -> Result<()> {
         let req = Key {
@@ -131,6 +248,13 @@ impl P2pClient {
         Ok(())
     }
 
+    /// Returns the status of the reprovider, `None` if it is disabled.
+    #[tracing::instrument(skip(self))]
+    pub async fn reprovider_status(&self) -> Result<Option<ReproviderStatus>> {
+        let res = self.backend.reprovider_status(()).await?;
+        Ok(ReproviderStatus::from_response(res))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn get_listening_addrs(&self) -> Result<(PeerId, Vec<Multiaddr>)> {
         let res = self.backend.get_listening_addrs(()).await?;
@@ -164,7 +288,6 @@ impl P2pClient {
 
     #[tracing::instrument(skip(self))]
     pub async fn disconnect(&self, peer_id: PeerId) -> Result<()> {
-        warn!("NetDisconnect not yet implemented on p2p node");
         let req = DisconnectRequest {
             peer_id: peer_id.to_bytes(),
         };
@@ -172,6 +295,18 @@ impl P2pClient {
         Ok(())
     }
 
+    /// Connects to `peer_id` and returns its identify info. Without `addrs` the peer
+    /// is looked up in the DHT.
+    #[tracing::instrument(skip(self))]
+    pub async fn lookup(&self, peer_id: PeerId, addrs: Vec<Multiaddr>) -> Result<Lookup> {
+        let req = LookupRequest {
+            peer_id: peer_id.to_bytes(),
+            addrs: addrs.iter().map(|a| a.to_vec()).collect(),
+        };
+        let res = self.backend.lookup(req).await?;
+        res.try_into()
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn shutdown(&self) -> Result<()> {
         self.backend.shutdown(()).await?;
@@ -293,8 +428,8 @@ mod tests {
     use iroh_rpc_types::p2p::{
         p2p_server, BitswapResponse, ConnectResponse, GetListeningAddrsResponse, GetPeersResponse,
         GossipsubAllPeersResponse, GossipsubPeersResponse, GossipsubPublishResponse,
-        GossipsubSubscribeResponse, GossipsubTopicsResponse, Multiaddrs, PeerIdResponse,
-        VersionResponse,
+        GossipsubSubscribeResponse, GossipsubTopicsResponse, Multiaddrs, NetworkInfoResponse,
+        PeerIdResponse, PublishIpnsResponse, Records, ReproviderStatusResponse, VersionResponse,
     };
     use libp2p::gossipsub::IdentTopic;
     use tokio::net::TcpListener;
@@ -374,6 +509,13 @@ mod tests {
             todo!()
         }
 
+        async fn network_info(
+            &self,
+            _request: Request<()>,
+        ) -> Result<tonic::Response<NetworkInfoResponse>, tonic::Status> {
+            todo!()
+        }
+
         async fn start_providing(
             &self,
             _request: Request<Key>,
@@ -388,6 +530,13 @@ mod tests {
             todo!()
         }
 
+        async fn reprovider_status(
+            &self,
+            _request: Request<()>,
+        ) -> Result<tonic::Response<ReproviderStatusResponse>, tonic::Status> {
+            todo!()
+        }
+
         async fn fetch_bitswap(
             &self,
             _request: Request<BitswapRequest>,
@@ -416,6 +565,20 @@ mod tests {
             todo!()
         }
 
+        async fn fetch_record_dht(
+            &self,
+            _request: Request<Key>,
+        ) -> Result<tonic::Response<Records>, tonic::Status> {
+            todo!()
+        }
+
+        async fn publish_ipns(
+            &self,
+            _request: Request<PublishIpnsRequest>,
+        ) -> Result<tonic::Response<PublishIpnsResponse>, tonic::Status> {
+            todo!()
+        }
+
         async fn get_listening_addrs(
             &self,
             _request: Request<()>,
@@ -442,6 +605,13 @@ mod tests {
             todo!()
         }
 
+        async fn lookup(
+            &self,
+            _request: Request<LookupRequest>,
+        ) -> Result<tonic::Response<LookupResponse>, tonic::Status> {
+            todo!()
+        }
+
         async fn shutdown(
             &self,
             _request: Request<()>,
@@ -2,13 +2,15 @@ use std::io::Cursor;
 
 use anyhow::{Context, Result};
 use bytes::Bytes;
+use cid::multihash::Multihash;
 use cid::Cid;
 #[cfg(feature = "grpc")]
 use futures::Stream;
 #[cfg(feature = "grpc")]
 use iroh_rpc_types::store::store_client::StoreClient as GrpcStoreClient;
 use iroh_rpc_types::store::{
-    GetLinksRequest, GetRequest, GetSizeRequest, HasRequest, PutRequest, Store, StoreClientAddr,
+    ExpectedRoot, GetBlockHashesRequest, GetLinksRequest, GetRequest, GetRootRequest,
+    GetSizeRequest, HasRequest, PutRequest, SetRootRequest, Store, StoreClientAddr,
     StoreClientBackend,
 };
 use iroh_rpc_types::Addr;
@@ -83,4 +85,77 @@ impl StoreClient {
         let size = self.backend.get_size(req).await?.size;
         Ok(size)
     }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_root(&self, name: &str) -> Result<Option<Cid>> {
+        let req = GetRootRequest {
+            name: name.to_string(),
+        };
+        let cid = self.backend.get_root(req).await?.cid;
+        cid.map(|c| Cid::read_bytes(Cursor::new(c)).context("invalid root cid"))
+            .transpose()
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_root(&self, name: &str, cid: Cid) -> Result<()> {
+        let req = SetRootRequest {
+            name: name.to_string(),
+            cid: cid.to_bytes(),
+            expected: None,
+        };
+        self.backend.set_root(req).await?;
+        Ok(())
+    }
+
+    /// Sets the root `name` to `cid` only if it currently is `expected`, where `None`
+    /// means that it is not set yet. Returns whether the root was set.
+    #[tracing::instrument(skip(self))]
+    pub async fn compare_and_set_root(
+        &self,
+        name: &str,
+        expected: Option<Cid>,
+        cid: Cid,
+    ) -> Result<bool> {
+        let req = SetRootRequest {
+            name: name.to_string(),
+            cid: cid.to_bytes(),
+            expected: Some(ExpectedRoot {
+                cid: expected.map(|cid| cid.to_bytes()),
+            }),
+        };
+        let updated = self.backend.set_root(req).await?.updated;
+        Ok(updated)
+    }
+
+    /// Returns all named roots.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_roots(&self) -> Result<Vec<(String, Cid)>> {
+        let roots = self.backend.get_roots(()).await?.roots;
+        roots
+            .into_iter()
+            .map(|root| {
+                let cid = Cid::read_bytes(Cursor::new(root.cid)).context("invalid root cid")?;
+                Ok((root.name, cid))
+            })
+            .collect()
+    }
+
+    /// Lists up to `limit` multihashes of the stored blocks, continuing after `after`.
+    /// Fewer hashes are returned once all are listed.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_block_hashes(
+        &self,
+        after: Option<Multihash>,
+        limit: u32,
+    ) -> Result<Vec<Multihash>> {
+        let req = GetBlockHashesRequest {
+            after: after.map(|hash| hash.to_bytes()),
+            limit,
+        };
+        let hashes = self.backend.get_block_hashes(req).await?.hashes;
+        hashes
+            .iter()
+            .map(|h| Multihash::from_bytes(h).context(format!("invalid multihash: {:?}", h)))
+            .collect()
+    }
 }
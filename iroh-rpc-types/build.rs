@@ -4,6 +4,7 @@ fn main() {
         ".p2p.BitswapBlock.data",
         ".p2p.BitswapResponse",
         ".p2p.GossipsubPublishRequest.data",
+        ".p2p.Records.records",
         ".store.PutRequest.blob",
         ".store.GetResponse.data",
     ]);
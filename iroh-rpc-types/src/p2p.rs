@@ -8,12 +8,15 @@ proxy!(
     fetch_provider_dht: Key =>
         std::pin::Pin<Box<dyn futures::Stream<Item = Result<Providers, tonic::Status>> + Send>> =>
         std::pin::Pin<Box<dyn futures::Stream<Item = anyhow::Result<Providers>> + Send>> [FetchProviderDhtStream],
+    fetch_record_dht: Key => Records => Records,
+    publish_ipns: PublishIpnsRequest => PublishIpnsResponse => PublishIpnsResponse,
     stop_session_bitswap: StopSessionBitswapRequest => () => (),
     notify_new_blocks_bitswap: NotifyNewBlocksBitswapRequest => () => (),
     get_listening_addrs: () => GetListeningAddrsResponse =>  GetListeningAddrsResponse,
     get_peers: () => GetPeersResponse =>  GetPeersResponse,
     peer_connect: ConnectRequest => ConnectResponse =>  ConnectResponse,
     peer_disconnect: DisconnectRequest => () =>  (),
+    lookup: LookupRequest => LookupResponse => LookupResponse,
     gossipsub_add_explicit_peer: GossipsubPeerIdMsg => () =>  (),
     gossipsub_all_mesh_peers: () => GossipsubPeersResponse =>  GossipsubPeersResponse,
     gossipsub_all_peers: () => GossipsubAllPeersResponse =>  GossipsubAllPeersResponse,
@@ -25,6 +28,8 @@ proxy!(
     gossipsub_unsubscribe: GossipsubTopicHashMsg => GossipsubSubscribeResponse => GossipsubSubscribeResponse,
     start_providing: Key => () => (),
     stop_providing: Key => () => (),
+    reprovider_status: () => ReproviderStatusResponse => ReproviderStatusResponse,
     local_peer_id: () => PeerIdResponse => PeerIdResponse,
-    external_addrs: () => Multiaddrs => Multiaddrs
+    external_addrs: () => Multiaddrs => Multiaddrs,
+    network_info: () => NetworkInfoResponse => NetworkInfoResponse
 );
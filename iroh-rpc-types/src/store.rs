@@ -7,5 +7,9 @@ proxy!(
     get: GetRequest => GetResponse => GetResponse,
     has: HasRequest => HasResponse => HasResponse,
     get_links: GetLinksRequest => GetLinksResponse => GetLinksResponse,
-    get_size: GetSizeRequest => GetSizeResponse => GetSizeResponse
+    get_size: GetSizeRequest => GetSizeResponse => GetSizeResponse,
+    get_root: GetRootRequest => GetRootResponse => GetRootResponse,
+    set_root: SetRootRequest => SetRootResponse => SetRootResponse,
+    get_roots: () => GetRootsResponse => GetRootsResponse,
+    get_block_hashes: GetBlockHashesRequest => GetBlockHashesResponse => GetBlockHashesResponse
 );
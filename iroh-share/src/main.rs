@@ -58,19 +58,12 @@ async fn main() -> Result<()> {
             ensure!(path.exists(), "provided file does not exist");
             ensure!(path.is_file(), "currently only supports files");
 
-            // TODO: streaming read
             let name = path
                 .file_name()
                 .ok_or_else(|| anyhow::anyhow!("missing file name"))?;
-            let name = name
-                .to_str()
-                .ok_or_else(|| anyhow::anyhow!("file name must be valid utf8"))?;
+            ensure!(name.to_str().is_some(), "file name must be valid utf8");
 
-            let data = tokio::fs::read(&path).await?;
-            let sender_transfer = sender
-                .transfer_from_data(name, data.into())
-                .await
-                .context("transfer")?;
+            let sender_transfer = sender.transfer_from_path(&path).await.context("transfer")?;
 
             let ticket = sender_transfer.ticket();
             let ticket_bytes = ticket.as_bytes();
@@ -144,7 +144,7 @@ impl P2pNode {
         };
         let config = config::Config {
             libp2p: config::Libp2pConfig {
-                listening_multiaddr: format!("/ip4/0.0.0.0/tcp/{port}").parse().unwrap(),
+                listening_multiaddrs: vec![format!("/ip4/0.0.0.0/tcp/{port}").parse().unwrap()],
                 mdns: false,
                 kademlia: true,
                 autonat: true,
@@ -158,6 +158,8 @@ impl P2pNode {
             rpc_client: rpc_p2p_client_config.clone(),
             metrics: Default::default(),
             key_store_path: db_path.parent().unwrap().to_path_buf(),
+            peer_store_path: None,
+            kad_store_path: None,
         };
 
         let rpc = Client::new(rpc_p2p_client_config).await?;
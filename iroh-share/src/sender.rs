@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use bytes::Bytes;
@@ -165,6 +165,19 @@ impl Sender {
         self.transfer_from_dir_builder(root_dir).await
     }
 
+    /// Transfers the file at `path`, reading it in chunks instead of loading it into memory.
+    pub async fn transfer_from_path(self, path: impl Into<PathBuf>) -> Result<Transfer> {
+        // wrap in directory to preserve the name
+        let mut root_dir = iroh_resolver::unixfs_builder::DirectoryBuilder::new();
+        let file = iroh_resolver::unixfs_builder::FileBuilder::new()
+            .path(path)
+            .build()
+            .await?;
+        root_dir.add_file(file);
+
+        self.transfer_from_dir_builder(root_dir).await
+    }
+
     fn next_id(&self) -> u64 {
         rand::thread_rng().gen()
     }
@@ -14,6 +14,9 @@ pub const CF_GRAPH_V0: &str = "graph-v0";
 ///
 /// By storing multihash first we can search for ids either by cid = (multihash, code) or by multihash.
 pub const CF_ID_V0: &str = "id-v0";
+/// Column family that stores named root cids, such as the root of the mutable file system.
+/// - indexed by name (utf8)
+pub const CF_ROOTS_V0: &str = "roots-v0";
 
 // This wrapper type serializes the contained value out-of-line so that newer
 // versions can be viewed as the older version.
@@ -5,9 +5,12 @@ use async_trait::async_trait;
 use bytes::BytesMut;
 use cid::Cid;
 use iroh_rpc_types::store::{
-    GetLinksRequest, GetLinksResponse, GetRequest, GetResponse, GetSizeRequest, GetSizeResponse,
-    HasRequest, HasResponse, PutRequest, Store as RpcStore, StoreServerAddr, VersionResponse,
+    GetBlockHashesRequest, GetBlockHashesResponse, GetLinksRequest, GetLinksResponse, GetRequest,
+    GetResponse, GetRootRequest, GetRootResponse, GetRootsResponse, GetSizeRequest,
+    GetSizeResponse, HasRequest, HasResponse, PutRequest, Root, SetRootRequest, SetRootResponse,
+    Store as RpcStore, StoreServerAddr, VersionResponse,
 };
+use multihash::Multihash;
 use tracing::info;
 
 use crate::store::Store;
@@ -77,6 +80,64 @@ impl RpcStore for Store {
             Ok(GetSizeResponse { size: None })
         }
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_root(&self, req: GetRootRequest) -> Result<GetRootResponse> {
+        let cid = self.get_root(&req.name).await?;
+        Ok(GetRootResponse {
+            cid: cid.map(|cid| cid.to_bytes()),
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_root(&self, req: SetRootRequest) -> Result<SetRootResponse> {
+        let cid = cid_from_bytes(req.cid)?;
+        let updated = match req.expected {
+            Some(expected) => {
+                let expected = expected.cid.map(cid_from_bytes).transpose()?;
+                self.compare_and_set_root(&req.name, expected, cid).await?
+            }
+            None => {
+                self.set_root(&req.name, cid).await?;
+                true
+            }
+        };
+
+        info!(
+            "store rpc call: set root {} to {}, updated: {}",
+            req.name, cid, updated
+        );
+        Ok(SetRootResponse { updated })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_roots(&self, _: ()) -> Result<GetRootsResponse> {
+        let roots = self
+            .roots()
+            .await?
+            .into_iter()
+            .map(|(name, cid)| Root {
+                name,
+                cid: cid.to_bytes(),
+            })
+            .collect();
+        Ok(GetRootsResponse { roots })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_block_hashes(&self, req: GetBlockHashesRequest) -> Result<GetBlockHashesResponse> {
+        let after = req
+            .after
+            .map(|hash| Multihash::from_bytes(&hash).context("invalid multihash"))
+            .transpose()?;
+        let hashes = self
+            .block_hashes(after.as_ref(), req.limit as usize)
+            .await?
+            .into_iter()
+            .map(|hash| hash.to_bytes())
+            .collect();
+        Ok(GetBlockHashesResponse { hashes })
+    }
 }
 
 #[tracing::instrument(skip(store))]
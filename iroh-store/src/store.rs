@@ -1,12 +1,12 @@
 use std::{
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread::available_parallelism,
 };
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use cid::Cid;
 use iroh_metrics::{
     core::{MObserver, MRecorder},
@@ -22,7 +22,9 @@ use rocksdb::{
 use smallvec::SmallVec;
 use tokio::task;
 
-use crate::cf::{GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0};
+use crate::cf::{
+    GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0, CF_ROOTS_V0,
+};
 use crate::Config;
 
 #[derive(Clone)]
@@ -33,6 +35,8 @@ pub struct Store {
 struct InnerStore {
     content: RocksDb,
     next_id: AtomicU64,
+    /// Serializes updates of the named roots.
+    roots_lock: Mutex<()>,
     _cache: Cache,
     _rpc_client: RpcClient,
 }
@@ -118,6 +122,10 @@ impl Store {
                 let opts = Options::default();
                 db.create_cf(CF_ID_V0, &opts)?;
             }
+            {
+                let opts = Options::default();
+                db.create_cf(CF_ROOTS_V0, &opts)?;
+            }
 
             Ok(db)
         })
@@ -131,6 +139,7 @@ impl Store {
             inner: Arc::new(InnerStore {
                 content: db,
                 next_id: 1.into(),
+                roots_lock: Mutex::new(()),
                 _cache: cache,
                 _rpc_client,
             }),
@@ -142,6 +151,8 @@ impl Store {
     pub async fn open(config: Config) -> Result<Self> {
         let (mut options, cache) = default_options();
         options.create_if_missing(false);
+        // databases created before the roots column family existed need it added
+        options.create_missing_column_families(true);
         // TODO: find a way to read existing options
 
         let path = config.path.clone();
@@ -149,7 +160,13 @@ impl Store {
             let db = RocksDb::open_cf(
                 &options,
                 path,
-                [CF_BLOBS_V0, CF_METADATA_V0, CF_GRAPH_V0, CF_ID_V0],
+                [
+                    CF_BLOBS_V0,
+                    CF_METADATA_V0,
+                    CF_GRAPH_V0,
+                    CF_ID_V0,
+                    CF_ROOTS_V0,
+                ],
             )?;
 
             // read last inserted id
@@ -183,6 +200,7 @@ impl Store {
             inner: Arc::new(InnerStore {
                 content: db,
                 next_id: next_id.into(),
+                roots_lock: Mutex::new(()),
                 _cache: cache,
                 _rpc_client,
             }),
@@ -347,6 +365,106 @@ impl Store {
         res
     }
 
+    /// Returns the root cid stored under the given name, if any.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_root(&self, name: &str) -> Result<Option<Cid>> {
+        let cf_roots = self.cf_roots()?;
+        match self.db().get_pinned_cf(cf_roots, name.as_bytes())? {
+            Some(bytes) => {
+                let cid = Cid::try_from(&bytes[..]).context("invalid root cid")?;
+                Ok(Some(cid))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `cid` as the root under the given name, replacing any previous root.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_root(&self, name: &str, cid: Cid) -> Result<()> {
+        let cf_roots = self.cf_roots()?;
+        let _guard = self.inner.roots_lock.lock().unwrap();
+        self.db()
+            .put_cf(cf_roots, name.as_bytes(), cid.to_bytes())?;
+        Ok(())
+    }
+
+    /// Stores `cid` as the root under the given name if the current root is `expected`,
+    /// where `None` means that no root is set yet. Returns whether the root was set.
+    #[tracing::instrument(skip(self))]
+    pub async fn compare_and_set_root(
+        &self,
+        name: &str,
+        expected: Option<Cid>,
+        cid: Cid,
+    ) -> Result<bool> {
+        let cf_roots = self.cf_roots()?;
+        let _guard = self.inner.roots_lock.lock().unwrap();
+        let current = match self.db().get_pinned_cf(cf_roots, name.as_bytes())? {
+            Some(bytes) => Some(Cid::try_from(&bytes[..]).context("invalid root cid")?),
+            None => None,
+        };
+        if current != expected {
+            return Ok(false);
+        }
+        self.db()
+            .put_cf(cf_roots, name.as_bytes(), cid.to_bytes())?;
+        Ok(true)
+    }
+
+    /// Returns all named roots.
+    #[tracing::instrument(skip(self))]
+    pub async fn roots(&self) -> Result<Vec<(String, Cid)>> {
+        let cf_roots = self.cf_roots()?;
+        self.db()
+            .iterator_cf(cf_roots, IteratorMode::Start)
+            .map(|elem| {
+                let (name, cid) = elem?;
+                let name = String::from_utf8(name.to_vec()).context("invalid root name")?;
+                let cid = Cid::try_from(&cid[..]).context("invalid root cid")?;
+                Ok((name, cid))
+            })
+            .collect()
+    }
+
+    /// Lists up to `limit` multihashes of the stored blocks, continuing after `after`.
+    ///
+    /// Cids only known as links of other blocks are skipped, and a multihash stored
+    /// with multiple codecs is only listed once.
+    #[tracing::instrument(skip(self))]
+    pub async fn block_hashes(
+        &self,
+        after: Option<&Multihash>,
+        limit: usize,
+    ) -> Result<Vec<Multihash>> {
+        let cf_id = self.cf_id()?;
+        let cf_blobs = self.cf_blobs()?;
+        let after = after.map(|hash| hash.to_bytes());
+        let mode = match after {
+            Some(ref after) => IteratorMode::From(after, Direction::Forward),
+            None => IteratorMode::Start,
+        };
+        let mut last = after.clone();
+
+        let mut hashes = Vec::new();
+        for elem in self.db().iterator_cf(cf_id, mode) {
+            if hashes.len() >= limit {
+                break;
+            }
+            let (key, id) = elem?;
+            ensure!(key.len() > 8, "invalid id key: {:?}", key);
+            // the key is the multihash followed by the code
+            let hash = &key[..key.len() - 8];
+            if last.as_deref() == Some(hash) {
+                continue;
+            }
+            if self.db().get_pinned_cf(cf_blobs, &id[..8])?.is_some() {
+                hashes.push(Multihash::from_bytes(hash)?);
+                last = Some(hash.to_vec());
+            }
+        }
+        Ok(hashes)
+    }
+
     #[tracing::instrument(skip(self))]
     async fn get_id(&self, cid: &Cid) -> Result<Option<u64>> {
         let cf_id = self.cf_id()?;
@@ -519,6 +637,12 @@ impl Store {
             .cf_handle(CF_GRAPH_V0)
             .context("missing column family: graph")
     }
+
+    fn cf_roots(&self) -> Result<&ColumnFamily> {
+        self.db()
+            .cf_handle(CF_ROOTS_V0)
+            .context("missing column family: roots")
+    }
 }
 
 #[cfg(test)]
@@ -657,6 +781,80 @@ mod tests {
         Ok((store, dir))
     }
 
+    #[tokio::test]
+    async fn test_roots() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = Config {
+            path: dir.path().into(),
+            rpc_client: RpcClientConfig::default(),
+            metrics: MetricsConfig::default(),
+        };
+        let cid1 = Cid::from_str("bafybeib4tddkl4oalrhe7q66rrz5dcpz4qwv5lmpstuqrls3djikw566y4")?;
+        let cid2 = Cid::from_str("QmcBphfXUFUNLcfAm31WEqYjrjEh19G5x4iAQANSK151DD")?;
+
+        let store = Store::create(config.clone()).await?;
+        assert_eq!(store.get_root("mfs").await?, None);
+        store.set_root("mfs", cid1).await?;
+        assert_eq!(store.get_root("mfs").await?, Some(cid1));
+        store.set_root("mfs", cid2).await?;
+        assert_eq!(store.get_root("mfs").await?, Some(cid2));
+        assert_eq!(store.get_root("other").await?, None);
+
+        assert!(!store.compare_and_set_root("mfs", Some(cid1), cid1).await?);
+        assert!(!store.compare_and_set_root("mfs", None, cid1).await?);
+        assert_eq!(store.get_root("mfs").await?, Some(cid2));
+        assert!(store.compare_and_set_root("mfs", Some(cid2), cid1).await?);
+        assert_eq!(store.get_root("mfs").await?, Some(cid1));
+        assert!(store.compare_and_set_root("other", None, cid2).await?);
+        assert_eq!(store.get_root("other").await?, Some(cid2));
+        store.set_root("mfs", cid2).await?;
+        drop(store);
+
+        let store = Store::open(config).await?;
+        assert_eq!(store.get_root("mfs").await?, Some(cid2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_roots_and_hashes() -> anyhow::Result<()> {
+        let link1 = Cid::from_str("bafybeib4tddkl4oalrhe7q66rrz5dcpz4qwv5lmpstuqrls3djikw566y4")?;
+        let link2 = Cid::from_str("QmcBphfXUFUNLcfAm31WEqYjrjEh19G5x4iAQANSK151DD")?;
+        let (store, _dir) = test_store().await?;
+        assert!(store.roots().await?.is_empty());
+        assert!(store.block_hashes(None, 10).await?.is_empty());
+
+        let mut hashes = Vec::new();
+        for i in 0..3u8 {
+            let blob = vec![i; 16];
+            let hash = Code::Sha2_256.digest(&blob);
+            // the links are not stored themselves and must not be listed
+            store
+                .put(Cid::new_v1(RAW, hash), &blob, vec![link1, link2])
+                .await?;
+            // same hash, different codec
+            store
+                .put(Cid::new_v1(IpldCodec::DagCbor.into(), hash), &blob, vec![])
+                .await?;
+            hashes.push(hash);
+        }
+        hashes.sort_by_key(|hash| hash.to_bytes());
+
+        assert_eq!(store.block_hashes(None, 10).await?, hashes);
+        let first = store.block_hashes(None, 2).await?;
+        assert_eq!(first, hashes[..2]);
+        let rest = store.block_hashes(first.last(), 2).await?;
+        assert_eq!(rest, hashes[2..]);
+
+        let root = Cid::new_v1(RAW, hashes[0]);
+        store.set_root("mfs", root).await?;
+        store.set_root("other", link1).await?;
+        assert_eq!(
+            store.roots().await?,
+            vec![("mfs".to_string(), root), ("other".to_string(), link1)]
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_multiple_cids_same_hash() -> anyhow::Result<()> {
         let link1 = Cid::from_str("bafybeib4tddkl4oalrhe7q66rrz5dcpz4qwv5lmpstuqrls3djikw566y4")?;
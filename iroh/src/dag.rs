@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::doc;
+use anyhow::{Error, Result};
+use clap::{Args, Subcommand};
+use iroh_api::{Api, Codec, IpfsPath};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Work with IPLD data")]
+#[clap(after_help = doc::DAG_LONG_DESCRIPTION)]
+pub struct Dag {
+    #[clap(subcommand)]
+    command: DagCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DagCommands {
+    #[clap(about = "Store JSON as an IPLD node")]
+    Put {
+        /// Local file to read the dag-json input from. Defaults to stdin
+        source: Option<PathBuf>,
+        /// Codec to store the node with: dag-cbor, dag-json, dag-pb or raw
+        #[clap(long, default_value = "dag-cbor")]
+        store_codec: CodecArg,
+    },
+    #[clap(about = "Get an IPLD node")]
+    Get {
+        /// CID or CID/with/path/qualifier of the node
+        ipfs_path: IpfsPath,
+        /// Codec to write the node with: dag-json, dag-cbor, dag-pb or raw
+        #[clap(long, default_value = "dag-json")]
+        output_codec: CodecArg,
+    },
+    #[clap(about = "Resolve an IPLD path to the block it ends in")]
+    Resolve {
+        /// CID or CID/with/path/qualifier to resolve
+        ipfs_path: IpfsPath,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CodecArg(Codec);
+
+impl FromStr for CodecArg {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dag-pb" => Ok(CodecArg(Codec::DagPb)),
+            "dag-cbor" => Ok(CodecArg(Codec::DagCbor)),
+            "dag-json" => Ok(CodecArg(Codec::DagJson)),
+            "raw" => Ok(CodecArg(Codec::Raw)),
+            _ => Err(anyhow::anyhow!("unsupported codec {}", s)),
+        }
+    }
+}
+
+pub async fn run_command(api: &impl Api, cmd: &Dag) -> Result<()> {
+    match &cmd.command {
+        DagCommands::Put {
+            source,
+            store_codec,
+        } => {
+            let mut input = Vec::new();
+            match source {
+                Some(source) => {
+                    tokio::fs::File::open(source)
+                        .await?
+                        .read_to_end(&mut input)
+                        .await?
+                }
+                None => tokio::io::stdin().read_to_end(&mut input).await?,
+            };
+            let cid = api.dag_put(input.into(), store_codec.0).await?;
+            println!("{}", cid);
+        }
+        DagCommands::Get {
+            ipfs_path,
+            output_codec,
+        } => {
+            let data = api.dag_get(ipfs_path, output_codec.0).await?;
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&data).await?;
+            stdout.flush().await?;
+        }
+        DagCommands::Resolve { ipfs_path } => {
+            let (cid, rem_path) = api.dag_resolve(ipfs_path).await?;
+            if rem_path.is_empty() {
+                println!("{}", cid);
+            } else {
+                println!("{}/{}", cid, rem_path.join("/"));
+            }
+        }
+    };
+    Ok(())
+}
@@ -35,6 +35,10 @@ Possible configurations fall into two buckets:
   cloud:   Iroh is running with services split into separate processes, which
            are speaking to each other via remote procedure calls.
 
+When the p2p service is serving, status reports whether it is part of the
+public network, or of a private network secured by a pre-shared swarm key,
+along with the fingerprint of that key.
+
 Use the --watch flag to continually poll for changes.
 
 Status reports no metrics about the running system aside from current service
@@ -43,6 +47,44 @@ reporting, which is intended to be consumed by tools like prometheus and
 grafana. For more info on metrics collection, see
 https://iroh.computer/docs/metrics";
 
+pub const ADD_LONG_DESCRIPTION: &str = "
+Adds a file or directory and prints the CID of its root. Pass - as the path to
+add the content of stdin as a single file, which is read and stored piece by
+piece, so it can be larger than the available memory:
+
+ > tar c photos | iroh add - --stdin-name photos.tar
+
+As content is wrapped in a directory by default, a name for stdin content has
+to be given with --stdin-name, unless --no-wrap is used.
+
+When adding a directory, entries matching the gitignore-style rules in
+.irohignore files are left out, and so are those matching the rules in the file
+given with --ignore-rules-path. Those rules are anchored at the added
+directory, wherever the file is, so /target only leaves out my-project/target:
+
+ > iroh add -r --ignore-rules-path .gitignore my-project
+
+Hidden files and directories are added like any other entry. Use --skip-hidden
+to leave them out, for instance .git in a source checkout.
+
+Symlinks are added as symlinks by default. Use --symlinks follow to add what
+they point to instead, or --symlinks skip to leave them out.
+
+With --only-hash the content is encoded as usual but not stored or provided, so
+the CID it would get can be computed without running the store or p2p services.
+Directory entries are always encoded sorted by name, which makes the CID only
+depend on the added content and its recorded metadata.
+
+Unix permissions and modification times are not recorded by default, so the
+CID does not change when only they do. Use --preserve-mode and --preserve-mtime
+to record them, like the ipfs add options of the same name.
+
+With --change-cache, the CIDs of the files in an added directory are recorded
+in the iroh data directory. Adding the directory again with --change-cache
+reuses them for the files whose size, modification time, inode and permissions
+did not change, instead of reading those files again, as long as the store still
+holds their content. Files added with --only-hash are not recorded.";
+
 pub const GET_LONG_DESCRIPTION: &str = "
 Download file or directory specified by <ipfs-path> from IPFS into [path]. If
 path already exists and is a file then it's overwritten with the new downloaded
@@ -54,6 +96,9 @@ By default, the output will be written to the working directory. If no file or
 directory name can be derived from the <ipfs-path>, the output will be written
 to the given path's CID.
 
+Unix file permissions and modification times are restored if they were
+recorded when the content was added, with --preserve-mode and --preserve-mtime.
+
 If <ipfs-path> is already present in the iroh store, no network call will
 be made.";
 
@@ -74,10 +119,84 @@ for more info on multiaddrs see https://iroh.computer/docs/concepts#multiaddr
 If a peer ID is provided, connect first perform a distribtued hash table (DHT)
 lookup to learn the address of the given peer ID before dialing.";
 
+pub const P2P_DISCONNECT_LONG_DESCRIPTION: &str = "
+Closes all connections to a peer, it is an error if p2p is not connected to it.
+p2p may connect to the peer again later on, for example when it is a provider of
+content being fetched.";
+
+pub const P2P_PEERS_LONG_DESCRIPTION: &str = "
+Lists the peer IDs of all peers p2p is currently connected to, each followed by
+the addresses known for that peer.";
+
 pub const P2P_LOOKUP_LONG_DESCRIPTION: &str = "
 Takes as input a peer ID or address and prints the output of the libp2p-identify
-protocol. When provided with a peer ID, the address is looked up on the 
+protocol: the protocol and agent versions, listening addresses, the address the
+peer observed for this node, the supported protocols, and the round trip time
+of the last ping. When provided with a peer ID, the address is looked up on the 
 Network's Distributed Hash Table (DHT) before connecting to the node. When 
 provided with a multiaddress, the connection is dialed directly.
 
 Providing no <ADDR> argument will return your local node information.";
+
+pub const FILES_LONG_DESCRIPTION: &str = "
+The mutable file system (MFS) is a namespace of files and directories that can
+be changed, unlike regular IPFS content which is immutable. Every change creates
+a new version of the modified directories up to the root, the CID of the root
+is stored by iroh and is updated after each command.
+
+Paths in the mutable file system are absolute, for example:
+
+ > iroh files mkdir /photos
+ > iroh files cp /ipfs/QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR /photos/cat.jpg
+
+Use flush to get the CID of a path, which can then be shared like any other
+IPFS content.";
+
+pub const FILES_WRITE_LONG_DESCRIPTION: &str = "
+Writes the content of [SOURCE], or stdin if no source is given, to <PATH>. The
+previous content of the file is replaced. Writing to a file that does not exist
+yet is an error, unless --create is given.";
+
+pub const FILES_CP_LONG_DESCRIPTION: &str = "
+Copies <SRC> to <DST>. The source can be a path in the mutable file system or an
+/ipfs/ or /ipns/ path, which makes cp the way to bring existing IPFS content
+into the mutable file system. No data is duplicated, only a new link is created.
+
+If <DST> ends with a slash, the source is copied into that directory under its
+own name. Copying onto an existing path is an error.";
+
+pub const OBJECT_PATCH_LONG_DESCRIPTION: &str = "
+Patch adds, replaces or removes a single link in an existing directory and
+prints the path of the resulting directory. Only the directories on the path to
+the link are rewritten, including the shards of large sharded directories, all
+other content is reused as is and does not have to be fetched.
+
+ > iroh object patch add-link QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn cat.jpg QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR
+ > iroh object patch rm-link QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn cat.jpg";
+
+pub const DAG_LONG_DESCRIPTION: &str = "
+The dag commands store and retrieve structured data as IPLD nodes, rather than
+files. Nodes are written as JSON, where links to other nodes are given as
+{\"/\": \"<CID>\"}, and can be stored as dag-cbor, dag-json, dag-pb or raw.
+
+ > echo '{\"name\": \"cat\", \"photo\": {\"/\": \"QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR\"}}' | iroh dag put
+ > iroh dag get <CID>/name
+
+Paths are resolved through links, also into other blocks. Links of dag-pb nodes
+can be addressed by name at the root of a block, below it the fields are
+addressed as in the other codecs, for instance <CID>/Links/0/Hash.";
+
+pub const NAME_LONG_DESCRIPTION: &str = "
+IPNS names are mutable pointers to IPFS content. A name is the peer ID of the
+key that signs its records, so only the owner of the key can update it. Names
+can be used in place of a CID in /ipns/ paths.";
+
+pub const NAME_PUBLISH_LONG_DESCRIPTION: &str = "
+Publish signs a record pointing to <PATH> with a key of the iroh-p2p keychain and
+puts it into the Distributed Hash Table (DHT). The node identity is used unless
+another key is given with --key. Each record is valid for --lifetime. While the
+node keeps running it republishes the record regularly with a fresh validity, so
+the name only expires once --lifetime has passed after the node stopped.
+
+ > iroh name publish /ipfs/QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR
+ > iroh name publish /ipfs/QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR --key id_ed25519_1 --lifetime 24h";
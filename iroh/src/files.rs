@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use crate::doc;
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use iroh_api::{FilesApi, UnixfsType};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Manipulate the mutable file system")]
+#[clap(after_help = doc::FILES_LONG_DESCRIPTION)]
+pub struct Files {
+    #[clap(subcommand)]
+    command: FilesCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FilesCommands {
+    #[clap(about = "Create a directory")]
+    Mkdir {
+        /// Path of the directory to create
+        path: String,
+        /// Create parent directories as needed, no error if the directory exists
+        #[clap(long, short)]
+        parents: bool,
+    },
+    #[clap(about = "Write a file")]
+    #[clap(after_help = doc::FILES_WRITE_LONG_DESCRIPTION)]
+    Write {
+        /// Path of the file to write to
+        path: String,
+        /// Local file to read the content from. Defaults to stdin
+        source: Option<PathBuf>,
+        /// Create the file if it does not exist
+        #[clap(long, short = 'e')]
+        create: bool,
+        /// Create parent directories as needed
+        #[clap(long, short)]
+        parents: bool,
+    },
+    #[clap(about = "Copy a file or directory")]
+    #[clap(after_help = doc::FILES_CP_LONG_DESCRIPTION)]
+    Cp {
+        /// Path in the mutable file system or /ipfs/ path to copy from
+        src: String,
+        /// Path to copy to
+        dst: String,
+    },
+    #[clap(about = "Move a file or directory")]
+    Mv {
+        /// Path to move from
+        src: String,
+        /// Path to move to
+        dst: String,
+    },
+    #[clap(about = "Remove a file or directory")]
+    Rm {
+        /// Path to remove
+        path: String,
+        /// Required to remove a directory
+        #[clap(long, short)]
+        recursive: bool,
+    },
+    #[clap(about = "List the entries of a directory")]
+    Ls {
+        /// Path of the directory to list
+        #[clap(default_value = "/")]
+        path: String,
+        /// Also print the CID of each entry
+        #[clap(long, short)]
+        long: bool,
+    },
+    #[clap(about = "Display information about a file or directory")]
+    Stat {
+        /// Path of the file or directory
+        path: String,
+    },
+    #[clap(about = "Persist the mutable file system and print the CID of a path")]
+    Flush {
+        /// Path to print the CID of
+        #[clap(default_value = "/")]
+        path: String,
+    },
+}
+
+pub async fn run_command(files: &impl FilesApi, cmd: &Files) -> Result<()> {
+    match &cmd.command {
+        FilesCommands::Mkdir { path, parents } => {
+            files.mkdir(path, *parents).await?;
+        }
+        FilesCommands::Write {
+            path,
+            source,
+            create,
+            parents,
+        } => {
+            let content: Box<dyn tokio::io::AsyncRead + Unpin> = match source {
+                Some(source) => Box::new(tokio::fs::File::open(source).await?),
+                None => Box::new(tokio::io::stdin()),
+            };
+            files.write(path, content, *create, *parents).await?;
+        }
+        FilesCommands::Cp { src, dst } => {
+            files.cp(src, dst).await?;
+        }
+        FilesCommands::Mv { src, dst } => {
+            files.mv(src, dst).await?;
+        }
+        FilesCommands::Rm { path, recursive } => {
+            files.rm(path, *recursive).await?;
+        }
+        FilesCommands::Ls { path, long } => {
+            for entry in files.ls(path).await? {
+                if *long {
+                    println!("{}\t{}", entry.name, entry.cid);
+                } else {
+                    println!("{}", entry.name);
+                }
+            }
+        }
+        FilesCommands::Stat { path } => {
+            let stat = files.stat(path).await?;
+            let typ = match stat.typ {
+                UnixfsType::Dir => "directory",
+                UnixfsType::File => "file",
+                UnixfsType::Symlink => "symlink",
+            };
+            println!("{}", stat.cid);
+            println!("Type: {}", typ);
+            if let Some(size) = stat.size {
+                println!("Size: {}", size);
+            }
+            if let Some(mode) = stat.mode {
+                println!("Mode: {:04o}", mode);
+            }
+            if let Some(mtime) = stat.mtime {
+                println!("Mtime: {}", mtime.seconds);
+            }
+        }
+        FilesCommands::Flush { path } => {
+            let cid = files.flush(path).await?;
+            println!("/ipfs/{}", cid);
+        }
+    };
+    Ok(())
+}
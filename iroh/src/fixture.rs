@@ -3,9 +3,13 @@ use std::env;
 use std::future;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use futures::StreamExt;
-use iroh_api::{AddEvent, Cid, Lookup, MockApi, MockP2p, OutType, PeerId};
+use iroh_api::{
+    AddEvent, Cid, Lookup, MfsEntry, MfsStat, MockApi, MockFiles, MockP2p, OutMetadata, OutType,
+    PeerId, UnixfsType,
+};
 use relative_path::RelativePathBuf;
 
 type GetFixture = fn() -> MockApi;
@@ -22,31 +26,180 @@ fn fixture_lookup() -> MockApi {
                 .unwrap();
             Ok(Lookup {
                 peer_id,
-                listen_addrs: vec![],
-                local_addrs: vec![],
+                protocol_version: "ipfs/0.1.0".to_string(),
+                agent_version: "iroh/0.1.0".to_string(),
+                listen_addrs: vec!["/ip4/192.168.1.10/tcp/4444".parse().unwrap()],
+                protocols: vec!["/ipfs/id/1.0.0".to_string(), "/ipfs/kad/1.0.0".to_string()],
+                observed_addrs: vec!["/ip4/203.0.113.7/tcp/4444".parse().unwrap()],
+                rtt: Some(Duration::from_millis(42)),
             })
         });
+        mock_p2p.expect_lookup_local().returning(|| {
+            let peer_id = "12D3KooWGQmdpzHXCqLno4mMxWXKNFQHASBeF99gTm2JR8Vu5Bdc"
+                .parse::<PeerId>()
+                .unwrap();
+            Ok(Lookup {
+                peer_id,
+                protocol_version: String::new(),
+                agent_version: "iroh/0.1.0".to_string(),
+                listen_addrs: vec!["/ip4/0.0.0.0/tcp/4444".parse().unwrap()],
+                protocols: vec![],
+                observed_addrs: vec![],
+                rtt: None,
+            })
+        });
+        Ok(mock_p2p)
+    });
+    api
+}
+
+fn fixture_p2p() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_p2p().returning(|| {
+        let mut mock_p2p = MockP2p::default();
+        mock_p2p.expect_connect().returning(|_addr| {
+            Ok("QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+                .parse::<PeerId>()
+                .unwrap())
+        });
+        mock_p2p.expect_disconnect().returning(|_peer_id| Ok(()));
+        mock_p2p.expect_peers().returning(|| {
+            let mut peers = HashMap::new();
+            peers.insert(
+                "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+                    .parse::<PeerId>()
+                    .unwrap(),
+                vec!["/ip4/104.131.131.82/tcp/4001".parse().unwrap()],
+            );
+            peers.insert(
+                "12D3KooWGQmdpzHXCqLno4mMxWXKNFQHASBeF99gTm2JR8Vu5Bdc"
+                    .parse::<PeerId>()
+                    .unwrap(),
+                vec![],
+            );
+            Ok(peers)
+        });
         Ok(mock_p2p)
     });
     api
 }
 
+fn fixture_name_publish() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_p2p().returning(|| {
+        let mut mock_p2p = MockP2p::default();
+        mock_p2p
+            .expect_publish_name()
+            .returning(|_key, _path, _lifetime| {
+                Ok("12D3KooWGQmdpzHXCqLno4mMxWXKNFQHASBeF99gTm2JR8Vu5Bdc"
+                    .parse::<PeerId>()
+                    .unwrap())
+            });
+        Ok(mock_p2p)
+    });
+    api
+}
+
+fn fixture_files_ls() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_files().returning(|| {
+        let mut mock_files = MockFiles::default();
+        mock_files.expect_ls().returning(|_path| {
+            Ok(vec![
+                MfsEntry {
+                    name: "a".to_string(),
+                    cid: Cid::from_str("QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn").unwrap(),
+                },
+                MfsEntry {
+                    name: "b.txt".to_string(),
+                    cid: Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR").unwrap(),
+                },
+            ])
+        });
+        Ok(mock_files)
+    });
+    api
+}
+
+fn fixture_files_stat() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_files().returning(|| {
+        let mut mock_files = MockFiles::default();
+        mock_files.expect_stat().returning(|_path| {
+            Ok(MfsStat {
+                cid: Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR").unwrap(),
+                typ: UnixfsType::File,
+                size: Some(5),
+                mode: Some(0o644),
+                mtime: None,
+            })
+        });
+        Ok(mock_files)
+    });
+    api
+}
+
+fn fixture_object_patch() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_object_patch_add_link()
+        .returning(|_root, _name, _target, _create| {
+            Box::pin(future::ready(Ok(Cid::from_str(
+                "QmNyLad1dWGS6mv2zno4iEviBSYSUR2SrQ8JoZNDz1UHYy",
+            )
+            .unwrap())))
+        });
+    api.expect_object_patch_rm_link().returning(|_root, _name| {
+        Box::pin(future::ready(Ok(Cid::from_str(
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn",
+        )
+        .unwrap())))
+    });
+    api
+}
+
+fn fixture_dag() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_dag_put().returning(|_input, _codec| {
+        Box::pin(future::ready(Ok(Cid::from_str(
+            "bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae",
+        )
+        .unwrap())))
+    });
+    api.expect_dag_resolve().returning(|_ipfs_path| {
+        Box::pin(future::ready(Ok((
+            Cid::from_str("bafyreidykglsfhoixmivffc5uwhcgshx4j465xwqntbmu43nb2dzqwfvae").unwrap(),
+            vec!["hello".to_string()],
+        ))))
+    });
+    api
+}
+
 fn fixture_get() -> MockApi {
     let mut api = MockApi::default();
     api.expect_get_stream().returning(|_ipfs_path| {
         futures::stream::iter(vec![
-            Ok((RelativePathBuf::from_path("").unwrap(), OutType::Dir)),
-            Ok((RelativePathBuf::from_path("a").unwrap(), OutType::Dir)),
+            Ok((
+                RelativePathBuf::from_path("").unwrap(),
+                OutType::Dir,
+                OutMetadata::default(),
+            )),
+            Ok((
+                RelativePathBuf::from_path("a").unwrap(),
+                OutType::Dir,
+                OutMetadata::default(),
+            )),
             // git doesn't like empty directories, nor does trycmd trip if it's missing
             // we rely on the unit test for save_get_stream elsewhere to check empty
             // directories are created
             Ok((
                 RelativePathBuf::from_path("a/exists").unwrap(),
                 OutType::Symlink(PathBuf::from("../b")),
+                OutMetadata::default(),
             )),
             Ok((
                 RelativePathBuf::from_path("b").unwrap(),
                 OutType::Reader(Box::new(std::io::Cursor::new("hello"))),
+                OutMetadata::default(),
             )),
         ])
         .boxed_local()
@@ -56,7 +209,7 @@ fn fixture_get() -> MockApi {
 
 fn fixture_add_file() -> MockApi {
     let mut api = MockApi::default();
-    api.expect_add_file().returning(|_ipfs_path, _| {
+    api.expect_add_file().returning(|_ipfs_path, _, _, _| {
         let add_event = Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR")
             .map(AddEvent::Done)
             .map_err(|e| e.into());
@@ -68,9 +221,24 @@ fn fixture_add_file() -> MockApi {
     api
 }
 
+fn fixture_add_stdin() -> MockApi {
+    let mut api = MockApi::default();
+    api.expect_add_reader()
+        .returning(|_reader, _name, _wrap, _only_hash| {
+            let add_event = Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR")
+                .map(AddEvent::Done)
+                .map_err(|e| e.into());
+
+            Box::pin(future::ready(Ok(
+                futures::stream::iter(vec![add_event]).boxed_local()
+            )))
+        });
+    api
+}
+
 fn fixture_add_directory() -> MockApi {
     let mut api = MockApi::default();
-    api.expect_add_dir().returning(|_ipfs_path, _| {
+    api.expect_add_dir().returning(|_ipfs_path, _, _, _, _| {
         let add_event = Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR")
             .map(AddEvent::Done)
             .map_err(|e| e.into());
@@ -86,10 +254,15 @@ fn fixture_get_wrapped_file() -> MockApi {
     let mut api = MockApi::default();
     api.expect_get_stream().returning(|_ipfs_path| {
         futures::stream::iter(vec![
-            Ok((RelativePathBuf::from_path("").unwrap(), OutType::Dir)),
+            Ok((
+                RelativePathBuf::from_path("").unwrap(),
+                OutType::Dir,
+                OutMetadata::default(),
+            )),
             Ok((
                 RelativePathBuf::from_path("file.txt").unwrap(),
                 OutType::Reader(Box::new(std::io::Cursor::new("hello"))),
+                OutMetadata::default(),
             )),
         ])
         .boxed_local()
@@ -103,6 +276,7 @@ fn fixture_get_unwrapped_file() -> MockApi {
         futures::stream::iter(vec![Ok((
             RelativePathBuf::from_path("").unwrap(),
             OutType::Reader(Box::new(std::io::Cursor::new("hello"))),
+            OutMetadata::default(),
         ))])
         .boxed_local()
     });
@@ -113,10 +287,15 @@ fn fixture_get_wrapped_symlink() -> MockApi {
     let mut api = MockApi::default();
     api.expect_get_stream().returning(|_ipfs_path| {
         futures::stream::iter(vec![
-            Ok((RelativePathBuf::from_path("").unwrap(), OutType::Dir)),
+            Ok((
+                RelativePathBuf::from_path("").unwrap(),
+                OutType::Dir,
+                OutMetadata::default(),
+            )),
             Ok((
                 RelativePathBuf::from_path("symlink.txt").unwrap(),
                 OutType::Symlink(PathBuf::from("target/path/foo.txt")),
+                OutMetadata::default(),
             )),
         ])
         .boxed_local()
@@ -130,6 +309,7 @@ fn fixture_get_unwrapped_symlink() -> MockApi {
         futures::stream::iter(vec![Ok((
             RelativePathBuf::from_path("").unwrap(),
             OutType::Symlink(PathBuf::from("target/path/foo.txt")),
+            OutMetadata::default(),
         ))])
         .boxed_local()
     });
@@ -139,7 +319,19 @@ fn fixture_get_unwrapped_symlink() -> MockApi {
 fn register_fixtures() -> FixtureRegistry {
     [
         ("lookup".to_string(), fixture_lookup as GetFixture),
+        (
+            "name_publish".to_string(),
+            fixture_name_publish as GetFixture,
+        ),
         ("get".to_string(), fixture_get as GetFixture),
+        ("files_ls".to_string(), fixture_files_ls as GetFixture),
+        ("files_stat".to_string(), fixture_files_stat as GetFixture),
+        (
+            "object_patch".to_string(),
+            fixture_object_patch as GetFixture,
+        ),
+        ("dag".to_string(), fixture_dag as GetFixture),
+        ("p2p".to_string(), fixture_p2p as GetFixture),
         (
             "get_wrapped_file".to_string(),
             fixture_get_wrapped_file as GetFixture,
@@ -149,6 +341,7 @@ fn register_fixtures() -> FixtureRegistry {
             fixture_get_unwrapped_file as GetFixture,
         ),
         ("add_file".to_string(), fixture_add_file as GetFixture),
+        ("add_stdin".to_string(), fixture_add_stdin as GetFixture),
         (
             "add_directory".to_string(),
             fixture_add_directory as GetFixture,
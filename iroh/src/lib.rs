@@ -1,8 +1,11 @@
+pub mod dag;
 pub mod doc;
+pub mod files;
 #[cfg(feature = "testing")]
 mod fixture;
 pub mod metrics;
+pub mod name;
+pub mod object;
 pub mod p2p;
 pub mod run;
-mod size;
 pub mod status;
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use crate::doc;
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use iroh_api::{IpfsPath, P2pApi};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Publish and resolve IPNS names")]
+#[clap(after_help = doc::NAME_LONG_DESCRIPTION)]
+pub struct Name {
+    #[clap(subcommand)]
+    command: NameCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum NameCommands {
+    #[clap(about = "Publish an IPNS record pointing to a path")]
+    #[clap(after_help = doc::NAME_PUBLISH_LONG_DESCRIPTION)]
+    Publish {
+        /// The /ipfs/ or /ipns/ path the name points to
+        path: IpfsPath,
+        /// Name of the key to sign the record with
+        #[clap(long, default_value = "self")]
+        key: String,
+        /// How long the record is valid for, e.g. 24h or 30m
+        #[clap(long, default_value = "24h", value_parser = humantime::parse_duration)]
+        lifetime: Duration,
+    },
+}
+
+pub async fn run_command(p2p: &impl P2pApi, cmd: &Name) -> Result<()> {
+    match &cmd.command {
+        NameCommands::Publish {
+            path,
+            key,
+            lifetime,
+        } => {
+            let name = p2p.publish_name(key, path, *lifetime).await?;
+            println!("Published to {}: {}", name, path);
+        }
+    };
+    Ok(())
+}
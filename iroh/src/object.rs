@@ -0,0 +1,67 @@
+use crate::doc;
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use iroh_api::{Api, Cid};
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Work with dag-pb objects")]
+pub struct Object {
+    #[clap(subcommand)]
+    command: ObjectCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ObjectCommands {
+    #[clap(about = "Create a new directory from an existing one")]
+    #[clap(after_help = doc::OBJECT_PATCH_LONG_DESCRIPTION)]
+    Patch {
+        #[clap(subcommand)]
+        command: PatchCommands,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PatchCommands {
+    #[clap(about = "Add or replace a link in a directory")]
+    AddLink {
+        /// CID of the directory to modify
+        root: Cid,
+        /// Name of the link, may contain slashes to add to a subdirectory
+        name: String,
+        /// CID the link points to
+        target: Cid,
+        /// Create intermediate directories as needed
+        #[clap(long, short = 'p')]
+        create: bool,
+    },
+    #[clap(about = "Remove a link from a directory")]
+    RmLink {
+        /// CID of the directory to modify
+        root: Cid,
+        /// Name of the link, may contain slashes to remove from a subdirectory
+        name: String,
+    },
+}
+
+pub async fn run_command(api: &impl Api, cmd: &Object) -> Result<()> {
+    match &cmd.command {
+        ObjectCommands::Patch { command } => {
+            let cid = match command {
+                PatchCommands::AddLink {
+                    root,
+                    name,
+                    target,
+                    create,
+                } => {
+                    api.object_patch_add_link(root, name, target, *create)
+                        .await?
+                }
+                PatchCommands::RmLink { root, name } => {
+                    api.object_patch_rm_link(root, name).await?
+                }
+            };
+            println!("/ipfs/{}", cid);
+        }
+    };
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use crate::doc;
 use anyhow::{Error, Result};
 use clap::{Args, Subcommand};
-use iroh_api::{Multiaddr, P2pApi, PeerId, PeerIdOrAddr};
+use iroh_api::{Lookup, Multiaddr, P2pApi, PeerId, PeerIdOrAddr};
 use std::str::FromStr;
 
 #[derive(Args, Debug, Clone)]
@@ -23,11 +23,20 @@ pub enum P2pCommands {
         /// Multiaddr or peer ID of a peer to connect to
         addr: PeerIdOrAddrArg,
     },
+    #[clap(about = "Disconnect from a peer")]
+    #[clap(after_help = doc::P2P_DISCONNECT_LONG_DESCRIPTION)]
+    Disconnect {
+        /// Peer ID of a connected peer
+        peer_id: PeerId,
+    },
+    #[clap(about = "List the connected peers")]
+    #[clap(after_help = doc::P2P_PEERS_LONG_DESCRIPTION)]
+    Peers,
     #[clap(about = "Retrieve info about a node")]
     #[clap(after_help = doc::P2P_LOOKUP_LONG_DESCRIPTION)]
     Lookup {
-        /// multiaddress or peer ID
-        addr: PeerIdOrAddrArg,
+        /// multiaddress or peer ID, defaults to the local node
+        addr: Option<PeerIdOrAddrArg>,
     },
 }
 
@@ -49,13 +58,62 @@ impl FromStr for PeerIdOrAddrArg {
 
 pub async fn run_command(p2p: &impl P2pApi, cmd: &P2p) -> Result<()> {
     match &cmd.command {
-        P2pCommands::Connect { .. } => {
-            todo!("`iroh p2p connect` is not yet implemented")
+        P2pCommands::Connect { addr } => {
+            let peer_id = p2p.connect(&addr.0).await?;
+            println!("Connected to {}", peer_id);
+        }
+        P2pCommands::Disconnect { peer_id } => {
+            p2p.disconnect(peer_id).await?;
+            println!("Disconnected from {}", peer_id);
+        }
+        P2pCommands::Peers => {
+            let mut peers: Vec<_> = p2p.peers().await?.into_iter().collect();
+            peers.sort_by_key(|(peer_id, _)| peer_id.to_string());
+            for (peer_id, addrs) in peers {
+                println!("{}", peer_id);
+                for addr in addrs {
+                    println!("  {}", addr);
+                }
+            }
         }
         P2pCommands::Lookup { addr } => {
-            let lookup = p2p.lookup(&addr.0).await?;
-            println!("peer id: {}", lookup.peer_id);
+            let lookup = match addr {
+                Some(addr) => p2p.lookup(&addr.0).await?,
+                None => p2p.lookup_local().await?,
+            };
+            print_lookup(&lookup);
         }
     };
     Ok(())
 }
+
+fn print_lookup(lookup: &Lookup) {
+    println!("peer id: {}", lookup.peer_id);
+    if !lookup.protocol_version.is_empty() {
+        println!("protocol version: {}", lookup.protocol_version);
+    }
+    if !lookup.agent_version.is_empty() {
+        println!("agent version: {}", lookup.agent_version);
+    }
+    if let Some(rtt) = lookup.rtt {
+        println!("rtt: {}ms", rtt.as_millis());
+    }
+    if !lookup.listen_addrs.is_empty() {
+        println!("listening addresses:");
+        for addr in &lookup.listen_addrs {
+            println!("  {}", addr);
+        }
+    }
+    if !lookup.observed_addrs.is_empty() {
+        println!("observed addresses:");
+        for addr in &lookup.observed_addrs {
+            println!("  {}", addr);
+        }
+    }
+    if !lookup.protocols.is_empty() {
+        println!("protocols:");
+        for protocol in &lookup.protocols {
+            println!("  {}", protocol);
+        }
+    }
+}
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use crate::dag::{run_command as run_dag_command, Dag};
 use crate::doc;
+use crate::files::{run_command as run_files_command, Files};
 #[cfg(feature = "testing")]
 use crate::fixture::get_fixture_api;
+use crate::name::{run_command as run_name_command, Name};
+use crate::object::{run_command as run_object_command, Object};
 use crate::p2p::{run_command as run_p2p_command, P2p};
-use crate::size::size_stream;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use iroh_api::{AddEvent, Api, ApiExt, IpfsPath, Iroh};
+use iroh_api::{AddEvent, Api, ApiExt, IpfsPath, Iroh, SymlinkMode, WalkOptions};
 use iroh_metrics::config::Config as MetricsConfig;
 
 #[derive(Parser, Debug, Clone)]
@@ -46,9 +49,14 @@ enum Commands {
         watch: bool,
     },
     P2p(P2p),
+    Files(Files),
+    Object(Object),
+    Dag(Dag),
+    Name(Name),
     #[clap(about = "Add a file or directory to iroh & make it available on IPFS")]
+    #[clap(after_help = doc::ADD_LONG_DESCRIPTION)]
     Add {
-        /// The path to a file or directory to be added
+        /// The path to a file or directory to be added, or - to read from stdin
         path: PathBuf,
         /// Required to add a directory
         #[clap(long, short)]
@@ -56,6 +64,31 @@ enum Commands {
         /// Do not wrap added content with a directory
         #[clap(long)]
         no_wrap: bool,
+        /// Only compute the CID, without storing or providing the content
+        #[clap(long)]
+        only_hash: bool,
+        /// Skip reading files that did not change since they were last added
+        #[clap(long)]
+        change_cache: bool,
+        /// Name of the file when adding from stdin
+        #[clap(long)]
+        stdin_name: Option<String>,
+        /// Leave out hidden files and directories, which are added by default
+        #[clap(long)]
+        skip_hidden: bool,
+        /// File with gitignore-style rules for entries to leave out, anchored at the
+        /// added directory
+        #[clap(long)]
+        ignore_rules_path: Option<PathBuf>,
+        /// How to add symlinks: follow, store or skip
+        #[clap(long, default_value = "store")]
+        symlinks: SymlinkMode,
+        /// Record the unix permissions of the added files and directories
+        #[clap(long)]
+        preserve_mode: bool,
+        /// Record the modification times of the added files and directories
+        #[clap(long)]
+        preserve_mtime: bool,
     },
     #[clap(about = "Fetch IPFS content and write it to disk")]
     #[clap(after_help = doc::GET_LONG_DESCRIPTION )]
@@ -110,12 +143,44 @@ impl Cli {
                 crate::status::status(api, *watch).await?;
             }
             Commands::P2p(p2p) => run_p2p_command(&api.p2p()?, p2p).await?,
+            Commands::Files(files) => run_files_command(&api.files()?, files).await?,
+            Commands::Object(object) => run_object_command(api, object).await?,
+            Commands::Dag(dag) => run_dag_command(api, dag).await?,
+            Commands::Name(name) => run_name_command(&api.p2p()?, name).await?,
             Commands::Add {
                 path,
                 recursive,
                 no_wrap,
+                only_hash,
+                change_cache,
+                stdin_name,
+                skip_hidden,
+                ignore_rules_path,
+                symlinks,
+                preserve_mode,
+                preserve_mtime,
             } => {
-                add(api, path, *no_wrap, *recursive).await?;
+                if path == Path::new("-") {
+                    add_stdin(api, stdin_name.as_deref(), *no_wrap, *only_hash).await?;
+                } else {
+                    let options = WalkOptions {
+                        skip_hidden: *skip_hidden,
+                        ignore_rules_path: ignore_rules_path.clone(),
+                        symlinks: *symlinks,
+                        preserve_mode: *preserve_mode,
+                        preserve_mtime: *preserve_mtime,
+                    };
+                    add(
+                        api,
+                        path,
+                        *no_wrap,
+                        *recursive,
+                        *only_hash,
+                        &options,
+                        *change_cache,
+                    )
+                    .await?;
+                }
             }
             Commands::Get {
                 ipfs_path: path,
@@ -130,7 +195,15 @@ impl Cli {
     }
 }
 
-async fn add(api: &impl Api, path: &Path, no_wrap: bool, recursive: bool) -> Result<()> {
+async fn add(
+    api: &impl Api,
+    path: &Path,
+    no_wrap: bool,
+    recursive: bool,
+    only_hash: bool,
+    options: &WalkOptions,
+    change_cache: bool,
+) -> Result<()> {
     if !path.exists() {
         anyhow::bail!("Path does not exist");
     }
@@ -143,27 +216,62 @@ async fn add(api: &impl Api, path: &Path, no_wrap: bool, recursive: bool) -> Res
             path.display()
         );
     }
-    let pb = ProgressBar::new_spinner();
-    pb.set_message("Calculating size...");
-    let mut total_size: u64 = 0;
-    let mut stream = Box::pin(size_stream(path));
-    while let Some(size_info) = stream.next().await {
-        total_size += size_info.size;
-        pb.inc(1);
+    // sized by the estimate that comes first on the add stream
+    let pb = ProgressBar::new(0);
+    pb.set_style(ProgressStyle::with_template(
+        "[{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}",
+    )?);
+    // show the progress bar right away, as `add` takes
+    // a while before it starts sending progress reports
+    pb.inc(0);
+
+    let mut progress = api
+        .add_stream(path, !no_wrap, only_hash, options, change_cache)
+        .await?;
+    while let Some(add_event) = progress.next().await {
+        match add_event? {
+            AddEvent::Estimate(size) => {
+                pb.set_length(size);
+            }
+            AddEvent::Block(_) => {}
+            AddEvent::File { path, .. } => {
+                pb.set_message(path.display().to_string());
+            }
+            AddEvent::ProgressDelta(size) => {
+                pb.inc(size);
+            }
+            AddEvent::Done(cid) => {
+                pb.finish_and_clear();
+                println!("/ipfs/{}", cid);
+            }
+        }
     }
-    pb.finish_and_clear();
+    Ok(())
+}
 
-    let pb = ProgressBar::new(total_size);
+async fn add_stdin(
+    api: &impl Api,
+    name: Option<&str>,
+    no_wrap: bool,
+    only_hash: bool,
+) -> Result<()> {
+    let name = match name {
+        Some(name) => name,
+        None if no_wrap => "",
+        None => anyhow::bail!("use --stdin-name to name the content, or --no-wrap"),
+    };
+    // the size of stdin is not known up front
+    let pb = ProgressBar::new_spinner();
     pb.set_style(ProgressStyle::with_template(
-        "[{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}) {msg}",
+        "[{elapsed_precise}] {spinner} {bytes} ({bytes_per_sec}) {msg}",
     )?);
-    // show the progress bar right away, as `add` takes
-    // a while before it starts ending progress reports
     pb.inc(0);
 
-    let mut progress = api.add_stream(path, !no_wrap).await?;
-    while let Some(Ok(add_event)) = progress.next().await {
-        match add_event {
+    let reader = Box::new(tokio::io::stdin());
+    let mut progress = api.add_reader(reader, name, !no_wrap, only_hash).await?;
+    while let Some(add_event) = progress.next().await {
+        match add_event? {
+            AddEvent::Estimate(_) | AddEvent::File { .. } | AddEvent::Block(_) => {}
             AddEvent::ProgressDelta(size) => {
                 pb.inc(size);
             }
@@ -4,7 +4,7 @@ use anyhow::Result;
 use crossterm::terminal::{Clear, ClearType};
 use crossterm::{cursor, style, style::Stylize, QueueableCommand};
 use futures::StreamExt;
-use iroh_api::{Api, ServiceStatus, StatusRow, StatusTable};
+use iroh_api::{Api, P2pApi, ServiceStatus, StatusRow, StatusTable};
 
 pub async fn status(api: &impl Api, watch: bool) -> Result<()> {
     let mut stdout = stdout();
@@ -18,6 +18,9 @@ pub async fn status(api: &impl Api, watch: bool) -> Result<()> {
                 .queue(Clear(ClearType::FromCursorUp))?
                 .queue(cursor::MoveTo(0, 1))?;
             queue_table(&table, &stdout)?;
+            if let Some(private) = private_network(api, &table).await {
+                queue_network(private.as_deref(), &stdout)?;
+            }
             stdout
                 .queue(cursor::SavePosition)?
                 .queue(style::Print("\n"))?
@@ -27,6 +30,9 @@ pub async fn status(api: &impl Api, watch: bool) -> Result<()> {
     } else {
         let table = api.check().await;
         queue_table(&table, &stdout)?;
+        if let Some(private) = private_network(api, &table).await {
+            queue_network(private.as_deref(), &stdout)?;
+        }
         stdout.flush()?;
         Ok(())
     }
@@ -47,6 +53,39 @@ where
     Ok(())
 }
 
+/// Asks the p2p node whether it is part of a private network, returning the fingerprint
+/// of its pre-shared key if so. `None` when p2p is not serving.
+async fn private_network(api: &impl Api, table: &StatusTable) -> Option<Option<String>> {
+    if !matches!(table.p2p.status(), ServiceStatus::Serving) {
+        return None;
+    }
+    let p2p = api.p2p().ok()?;
+    p2p.private_network().await.ok()
+}
+
+/// queues the network type, `psk_fingerprint` is set for private networks
+/// you must call `writer.flush()` to execute the queue
+pub fn queue_network<W>(psk_fingerprint: Option<&str>, mut w: W) -> Result<()>
+where
+    W: Write,
+{
+    w.queue(style::Print("\nNetwork\t\t\t"))?;
+    match psk_fingerprint {
+        Some(fingerprint) => {
+            w.queue(style::PrintStyledContent("Private".yellow()))?
+                .queue(style::Print(format!(
+                    "\tswarm key fingerprint {}",
+                    fingerprint
+                )))?;
+        }
+        None => {
+            w.queue(style::Print("Public"))?;
+        }
+    }
+    w.queue(style::Print("\n"))?;
+    Ok(())
+}
+
 // queue queues this row of the StatusRow to be written
 // You must call `writer.flush()` to actually write the content to the writer
 pub fn queue_row<W>(row: &StatusRow, w: &mut W) -> Result<()>
@@ -107,6 +146,21 @@ mod tests {
         assert_eq!(expect, got);
     }
 
+    #[test]
+    fn network_queue() {
+        let mut got = Vec::new();
+        queue_network(None, &mut got).unwrap();
+        assert_eq!(String::from_utf8(got).unwrap(), "\nNetwork\t\t\tPublic\n");
+
+        let mut got = Vec::new();
+        queue_network(Some("c0ffee"), &mut got).unwrap();
+        let expect = format!(
+            "\nNetwork\t\t\t{}\tswarm key fingerprint c0ffee\n",
+            "Private".yellow()
+        );
+        assert_eq!(String::from_utf8(got).unwrap(), expect);
+    }
+
     #[test]
     fn status_row_queue() {
         struct TestCase {
@@ -33,6 +33,46 @@ fn add_file_test() {
         .run();
 }
 
+#[test]
+fn add_stdin_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "add_stdin")
+        .case("tests/cmd/add_stdin.trycmd")
+        .run();
+}
+
+#[test]
+fn files_ls_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "files_ls")
+        .case("tests/cmd/files_ls.trycmd")
+        .run();
+}
+
+#[test]
+fn files_stat_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "files_stat")
+        .case("tests/cmd/files_stat.trycmd")
+        .run();
+}
+
+#[test]
+fn object_patch_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "object_patch")
+        .case("tests/cmd/object_patch.trycmd")
+        .run();
+}
+
+#[test]
+fn dag_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "dag")
+        .case("tests/cmd/dag.trycmd")
+        .run();
+}
+
 #[test]
 fn get_cid_directory_overwrite_explicit_failure_test() {
     trycmd::TestCases::new()
@@ -137,6 +177,22 @@ fn lookup_test() {
         .run();
 }
 
+#[test]
+fn p2p_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "p2p")
+        .case("tests/cmd/p2p.trycmd")
+        .run();
+}
+
+#[test]
+fn name_publish_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "name_publish")
+        .case("tests/cmd/name_publish.trycmd")
+        .run();
+}
+
 #[test]
 fn version_test() {
     trycmd::TestCases::new()